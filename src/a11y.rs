@@ -0,0 +1,93 @@
+use std::{
+    process::{Child, Command},
+    sync::Mutex,
+};
+
+use egui::{Response, WidgetInfo, WidgetType};
+
+use chrono::Datelike;
+
+use crate::{config::Identity, i18n, Message};
+
+/// Name an icon button like `×`, to be spoken when it's focused, and show
+/// the name when it's hovered
+pub fn icon(response: Response, name: &str) -> Response {
+    response.widget_info(|| WidgetInfo::labeled(WidgetType::Button, name));
+    response.on_hover_text(name)
+}
+
+/// What's spoken when a message bubble is focused: who sent it, when, and
+/// what's in it
+pub fn message(identity: &Identity, message: &Message) -> String {
+    let date = &message.date;
+    let from = format!(
+        "{}, {} {} {}",
+        identity.name(&message.sender),
+        date.day(),
+        i18n::month(date),
+        date.format("%Y %H:%M")
+    );
+
+    let mut parts = Vec::new();
+    match message.images.len() {
+        0 => {}
+        1 => parts.push(i18n::t("a photo").to_owned()),
+        n => parts.push(i18n::tf("{count} photos", &[("count", &n)])),
+    }
+    if !message.audio.is_empty() {
+        parts.push(i18n::t("a voice message").to_owned());
+    }
+    if let Some(transcription) = &message.transcription {
+        parts.push(transcription.clone());
+    }
+    if message.location.is_some() {
+        parts.push(i18n::t("a location").to_owned());
+    }
+    for contact in &message.contacts {
+        parts.push(i18n::tf(
+            "contact card for {name}",
+            &[("name", &contact.name)],
+        ));
+    }
+    if !message.text.is_empty() {
+        parts.push(message.text.clone());
+    }
+    if !message.reactions.is_empty() {
+        parts.push(i18n::tf(
+            "{count} reactions",
+            &[("count", &message.reactions.len())],
+        ));
+    }
+
+    if parts.is_empty() {
+        from
+    } else {
+        format!("{from}: {}", parts.join(", "))
+    }
+}
+
+/// Speak what was focused or clicked this frame through the system's
+/// text-to-speech (`say` on macOS, `spd-say` elsewhere), cutting off
+/// whatever was still being said. This is a side channel for the
+/// "speak focus changes" setting, not screen reader support: egui 0.19
+/// predates AccessKit, so VoiceOver and NVDA can't see inside the window.
+pub fn speak_focus_changes(ctx: &egui::Context) {
+    static SPEAKING: Mutex<Option<Child>> = Mutex::new(None);
+
+    let text = ctx.output().events_description();
+    if text.is_empty() {
+        return;
+    }
+
+    let mut speaking = SPEAKING.lock().unwrap();
+    if let Some(mut child) = speaking.take() {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+    let program = if cfg!(target_os = "macos") {
+        "say"
+    } else {
+        "spd-say"
+    };
+    *speaking = Command::new(program).arg(text).spawn().ok();
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use egui::{Sense, Stroke, Ui};
+use eyre::Result;
+use sqlx::SqlitePool;
+
+use crate::cache;
+
+/// How many weeks a sparkline covers
+pub const WEEKS: usize = 52;
+
+/// Nanoseconds in a week, the unit of `message.date`
+const WEEK: i64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+/// Messages per week in each chat, oldest week first, over the year before
+/// the newest message in the database (rather than today, so that an old
+/// backup isn't all flat lines). Keyed by chat identifier.
+pub type Activity = HashMap<String, Vec<u32>>;
+
+/// Count messages per chat per week, or reuse the counts from the last run
+/// if no messages have been added since
+pub async fn load(db: &SqlitePool, cache: &SqlitePool) -> Result<Activity> {
+    let (newest_id, newest_date): (i64, i64) =
+        sqlx::query_as("SELECT coalesce(max(ROWID), 0), coalesce(max(date), 0) from message;")
+            .fetch_one(db)
+            .await?;
+
+    if let Some(activity) = cache::load_activity(cache, newest_id).await? {
+        return Ok(activity);
+    }
+
+    let rows: Vec<(String, i64, i64)> = sqlx::query_as(
+        r#"
+            SELECT c.chat_identifier, ($1 - m.date) / $2, count(*)
+            from message m
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            where m.date > $1 - $2 * $3
+            group by 1, 2
+            ;
+        "#,
+    )
+    .bind(newest_date)
+    .bind(WEEK)
+    .bind(WEEKS as i64)
+    .fetch_all(db)
+    .await?;
+
+    let mut activity = Activity::new();
+    for (chat, weeks_ago, count) in rows {
+        let counts = activity.entry(chat).or_insert_with(|| vec![0; WEEKS]);
+        if let Some(slot) = (WEEKS - 1).checked_sub(weeks_ago as usize) {
+            counts[slot] += count as u32;
+        }
+    }
+
+    cache::save_activity(cache, newest_id, &activity).await?;
+
+    Ok(activity)
+}
+
+/// A small line chart of weekly message counts, scaled to the busiest week
+pub fn sparkline(ui: &mut Ui, counts: &[u32]) {
+    let (rect, response) = ui.allocate_exact_size(egui::vec2(52.0, 14.0), Sense::hover());
+    response.on_hover_text(format!(
+        "{} messages in the last year, busiest week {}",
+        counts.iter().sum::<u32>(),
+        counts.iter().max().unwrap_or(&0),
+    ));
+
+    let max = counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+    let step = rect.width() / (counts.len().max(2) - 1) as f32;
+    let points = counts
+        .iter()
+        .enumerate()
+        .map(|(i, count)| {
+            egui::pos2(
+                rect.left() + i as f32 * step,
+                rect.bottom() - *count as f32 / max * rect.height(),
+            )
+        })
+        .collect();
+
+    ui.painter().add(egui::Shape::line(
+        points,
+        Stroke::new(1.0, ui.visuals().text_color()),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[tokio::test]
+    async fn counts_weeks_before_the_newest_message() {
+        let db = test_util::memory().await;
+        let newest = 1000 * WEEK;
+        sqlx::query(
+            r#"
+                CREATE TABLE message (ROWID INTEGER PRIMARY KEY, date INTEGER);
+                CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, chat_identifier TEXT);
+                CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+                INSERT INTO chat VALUES (1, 'ana'), (2, 'bo');
+                INSERT INTO message VALUES
+                    (1, $1),
+                    (2, $1 - 1),
+                    (3, $1 - $2),
+                    (4, $1 - $2 * 51 - 1),
+                    -- more than a year before, so left out
+                    (5, $1 - $2 * 52),
+                    (6, $1 - $2 * 3);
+                INSERT INTO chat_message_join VALUES (1, 1), (1, 2), (1, 3), (1, 4), (1, 5), (2, 6);
+            "#,
+        )
+        .bind(newest)
+        .bind(WEEK)
+        .execute(&db)
+        .await
+        .unwrap();
+        let cache = test_util::cache().await;
+
+        let activity = load(&db, &cache).await.unwrap();
+        let ana = &activity["ana"];
+        assert_eq!(ana.len(), WEEKS);
+        assert_eq!(ana[WEEKS - 1], 2);
+        assert_eq!(ana[WEEKS - 2], 1);
+        assert_eq!(ana[0], 1);
+        assert_eq!(ana.iter().sum::<u32>(), 4);
+        assert_eq!(activity["bo"][WEEKS - 4], 1);
+
+        // reused until a message is added
+        sqlx::query("DELETE FROM chat_message_join where chat_id = 2;")
+            .execute(&db)
+            .await
+            .unwrap();
+        assert!(load(&db, &cache).await.unwrap().contains_key("bo"));
+        sqlx::query(
+            "INSERT INTO message VALUES (7, $1); INSERT INTO chat_message_join VALUES (1, 7);",
+        )
+        .bind(newest)
+        .execute(&db)
+        .await
+        .unwrap();
+        let activity = load(&db, &cache).await.unwrap();
+        assert!(!activity.contains_key("bo"));
+        assert_eq!(activity["ana"][WEEKS - 1], 3);
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+use egui::Ui;
+
+use crate::i18n;
+
+/// What a database's aliases are saved under in the config: its full path
+pub fn database_key(database_file: &str) -> String {
+    std::fs::canonicalize(database_file)
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| database_file.to_owned())
+}
+
+/// Contents of a right-click menu for naming `id`, a handle or chat
+/// identifier, e.g. "+15555550100" as "Landlord". `draft` is the name as
+/// typed. Returns true if `aliases` changed.
+pub fn edit(
+    ui: &mut Ui,
+    id: &str,
+    aliases: &mut HashMap<String, String>,
+    draft: &mut String,
+) -> bool {
+    let mut changed = false;
+
+    ui.label(i18n::tf("name {handle}", &[("handle", &id)]));
+    let hint = aliases
+        .get(id)
+        .map_or(i18n::t("e.g. Landlord"), String::as_str);
+    ui.add(egui::TextEdit::singleline(draft).hint_text(hint));
+
+    let name = draft.trim();
+    if !name.is_empty() && name != id && ui.button(i18n::t("save")).clicked() {
+        aliases.insert(id.to_owned(), name.to_owned());
+        draft.clear();
+        changed = true;
+        ui.close_menu();
+    }
+    if let Some(alias) = aliases.get(id) {
+        if ui
+            .small_button(i18n::tf("forget name: {name}", &[("name", alias)]))
+            .clicked()
+        {
+            aliases.remove(id);
+            changed = true;
+            ui.close_menu();
+        }
+    }
+
+    changed
+}
@@ -0,0 +1,332 @@
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use chrono::prelude::*;
+use eyre::Result;
+use futures_util::{stream::BoxStream, TryStreamExt};
+use sqlx::SqlitePool;
+
+use crate::{time, Handle, Sender};
+
+#[derive(Clone, Debug)]
+pub struct Attachment {
+    /// `attachment.guid`, which stays the same across copies of the database
+    pub guid: String,
+    pub path: PathBuf,
+    pub name: String,
+}
+
+/// An attachment along with the message it was sent in
+#[derive(Clone)]
+pub struct MediaItem {
+    pub attachment: Attachment,
+    /// ROWID of the message
+    pub message: i64,
+    pub mime_type: String,
+    pub sender: Sender,
+    pub date: DateTime<Utc>,
+}
+
+/// Where attachments live on the machine the database came from
+const ATTACHMENTS_DIR: &str = "Library/Messages/Attachments/";
+
+/// Attachments read at a time by [`stream_chat_media`] before they're put on
+/// screen
+const BATCH: usize = 500;
+
+/// Set by `--attachments-root`
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Read attachments from `root` instead of `~/Library/Messages/Attachments`,
+/// e.g. for a backup extracted somewhere else. `root` should contain the
+/// same two-level hashed directories as the original.
+pub fn set_root(root: &Path) {
+    let _ = ROOT.set(root.to_owned());
+}
+
+/// `attachment.filename` is stored relative to the home directory of the
+/// machine the database came from (`~/Library/Messages/Attachments/...`)
+pub fn expand_path(raw: &str) -> PathBuf {
+    if let Some(root) = ROOT.get() {
+        if let Some((_, rest)) = raw.split_once(ATTACHMENTS_DIR) {
+            return root.join(rest);
+        }
+    }
+
+    match raw.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None => PathBuf::from(raw),
+    }
+}
+
+/// Split the newline separated list of attachment names selected alongside
+/// messages
+pub fn names(concatenated: Option<String>) -> Vec<String> {
+    concatenated
+        .map(|names| names.lines().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// Split the newline separated list of `guid<tab>filename` pairs selected
+/// alongside messages
+pub fn attachments(concatenated: Option<String>) -> Vec<Attachment> {
+    names(concatenated)
+        .iter()
+        .filter_map(|line| {
+            let (guid, filename) = line.split_once('\t')?;
+            let path = expand_path(filename);
+            let name = path.file_name()?.to_string_lossy().into_owned();
+
+            Some(Attachment {
+                guid: guid.to_owned(),
+                path,
+                name,
+            })
+        })
+        .collect()
+}
+
+/// Whether an attachment is data for an iMessage app's balloon, like a link
+/// preview's image, rather than something that was sent
+pub fn is_app_payload(attachment: &Attachment) -> bool {
+    attachment
+        .path
+        .extension()
+        .is_some_and(|extension| extension == "pluginPayloadAttachment")
+}
+
+/// The text of an audio message as transcribed by Apple, which newer
+/// databases keep in the attachment's `user_info` plist
+pub fn transcription(user_info: Option<Vec<u8>>) -> Option<String> {
+    let value = plist::Value::from_reader(Cursor::new(user_info?)).ok()?;
+    let text = value
+        .as_dictionary()?
+        .get("audio-transcription")?
+        .as_string()?
+        .trim();
+
+    Some(text.to_owned()).filter(|t| !t.is_empty())
+}
+
+/// A row of [`media_rows`]
+type MediaRow = (String, String, String, String, i64, i64, String, bool);
+
+/// Every attachment in a chat whose mime type starts with `mime_prefix`
+/// (e.g. `image/`, or empty for everything), oldest first
+pub async fn chat_media(
+    db: &SqlitePool,
+    chat_id: &str,
+    mime_prefix: &str,
+) -> Result<Vec<MediaItem>> {
+    let media = media_rows(db, chat_id, mime_prefix)
+        .map_ok(media_item)
+        .try_collect()
+        .await?;
+
+    Ok(media)
+}
+
+/// Like [`chat_media`], but put into `handle` a batch at a time as they're
+/// read, so the first of a long chat's attachments show before the rest
+pub async fn stream_chat_media(
+    db: &SqlitePool,
+    chat_id: &str,
+    mime_prefix: &str,
+    handle: Handle<Vec<MediaItem>>,
+) -> Result<()> {
+    let mut batches = media_rows(db, chat_id, mime_prefix).try_chunks(BATCH);
+    while let Some(batch) = batches.try_next().await? {
+        handle.extend(|media| media.extend(batch.into_iter().map(media_item)));
+    }
+    // ready even if the chat has no attachments
+    handle.extend(|_| ());
+
+    Ok(())
+}
+
+fn media_rows<'a>(
+    db: &'a SqlitePool,
+    chat_id: &'a str,
+    mime_prefix: &'a str,
+) -> BoxStream<'a, sqlx::Result<MediaRow>> {
+    sqlx::query_as::<_, MediaRow>(
+        r#"
+            SELECT
+                a.guid, a.filename, coalesce(a.transfer_name, a.filename), coalesce(a.mime_type, ''),
+                m.ROWID, m.date, coalesce(h.id, ''), m.is_from_me
+            from attachment a
+            join message_attachment_join maj
+                on a.ROWID = maj.attachment_id
+            join message m
+                on maj.message_id = m.ROWID
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            left join handle h
+                on m.handle_id = h.ROWID
+            where c.chat_identifier=$1
+                and coalesce(a.mime_type, '') like $2 || '%'
+                and a.filename is not null
+            order by m.date
+            ;
+        "#,
+    )
+    .bind(chat_id)
+    .bind(mime_prefix)
+    .fetch(db)
+}
+
+fn media_item(
+    (guid, filename, name, mime_type, message, timestamp, sender, is_from_me): MediaRow,
+) -> MediaItem {
+    MediaItem {
+        attachment: Attachment {
+            guid,
+            path: expand_path(&filename),
+            name,
+        },
+        message,
+        mime_type,
+        date: time(timestamp),
+        sender: Sender::new(is_from_me, sender),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util, State};
+
+    /// A chat `a` with a photo, a video, a file with no type and one that
+    /// was never downloaded, and a chat `b` with another photo
+    async fn scratch_db() -> SqlitePool {
+        let db = test_util::memory().await;
+        sqlx::query(
+            r#"
+                CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, chat_identifier TEXT);
+                CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT);
+                CREATE TABLE message (
+                    ROWID INTEGER PRIMARY KEY, date INTEGER, handle_id INTEGER, is_from_me INTEGER
+                );
+                CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+                CREATE TABLE attachment (
+                    ROWID INTEGER PRIMARY KEY, guid TEXT, filename TEXT, transfer_name TEXT,
+                    mime_type TEXT
+                );
+                CREATE TABLE message_attachment_join (message_id INTEGER, attachment_id INTEGER);
+
+                INSERT INTO chat VALUES (1, 'a'), (2, 'b');
+                INSERT INTO handle VALUES (1, 'bob');
+                INSERT INTO message VALUES (1, 20, 1, 0), (2, 10, 0, 1), (3, 30, 1, 0);
+                INSERT INTO chat_message_join VALUES (1, 1), (1, 2), (2, 3);
+                INSERT INTO attachment VALUES
+                    (1, 'photo', '/a/IMG_1.jpeg', 'beach.jpeg', 'image/jpeg'),
+                    (2, 'video', '/a/IMG_2.mov', NULL, 'video/quicktime'),
+                    (3, 'file', '/a/notes', NULL, NULL),
+                    (4, 'missing', NULL, 'lost.jpeg', 'image/jpeg'),
+                    (5, 'other', '/b/IMG_3.jpeg', NULL, 'image/jpeg');
+                INSERT INTO message_attachment_join VALUES (1, 1), (2, 2), (2, 3), (1, 4), (3, 5);
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        db
+    }
+
+    fn guids(media: &[MediaItem]) -> Vec<&str> {
+        media
+            .iter()
+            .map(|item| item.attachment.guid.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn lists_chat_media() {
+        let db = scratch_db().await;
+
+        let media = chat_media(&db, "a", "").await.unwrap();
+        assert_eq!(guids(&media), ["video", "file", "photo"]);
+        assert_eq!(media[0].attachment.name, "/a/IMG_2.mov");
+        assert_eq!(media[0].sender, Sender::Me);
+        assert_eq!(media[2].attachment.name, "beach.jpeg");
+        assert_eq!(media[2].attachment.path, Path::new("/a/IMG_1.jpeg"));
+        assert_eq!(media[2].sender, Sender::SomeoneElse("bob".to_owned()));
+
+        let media = chat_media(&db, "a", "image/").await.unwrap();
+        assert_eq!(guids(&media), ["photo"]);
+        assert!(chat_media(&db, "c", "").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn streams_chat_media() {
+        let db = scratch_db().await;
+
+        let handle = Handle::new();
+        stream_chat_media(&db, "b", "image/", handle.clone())
+            .await
+            .unwrap();
+        assert!(matches!(&*handle.get(), State::Ready(media) if guids(media) == ["other"]));
+
+        // ready with nothing in it, rather than left loading
+        let handle = Handle::new();
+        stream_chat_media(&db, "b", "video/", handle.clone())
+            .await
+            .unwrap();
+        assert!(matches!(&*handle.get(), State::Ready(media) if media.is_empty()));
+    }
+
+    #[test]
+    fn splits_attachment_lists() {
+        assert!(names(None).is_empty());
+        assert_eq!(names(Some("a.jpeg\nb.mov".to_owned())), ["a.jpeg", "b.mov"]);
+
+        let attachments = attachments(Some(
+            "one\t/a/IMG_1.jpeg\nno tab\ntwo\t/b/link.pluginPayloadAttachment".to_owned(),
+        ));
+        assert_eq!(attachments.len(), 2);
+        assert_eq!(attachments[0].guid, "one");
+        assert_eq!(attachments[0].name, "IMG_1.jpeg");
+        assert!(!is_app_payload(&attachments[0]));
+        assert!(is_app_payload(&attachments[1]));
+    }
+
+    #[test]
+    fn expands_home() {
+        assert_eq!(expand_path("/abs/file"), Path::new("/abs/file"));
+        assert_eq!(
+            expand_path("~/Library/file"),
+            dirs::home_dir().unwrap_or_default().join("Library/file")
+        );
+    }
+
+    #[test]
+    fn reads_transcriptions() {
+        let plist = |text: &str| {
+            let info = plist::Value::Dictionary(
+                [(
+                    "audio-transcription".to_owned(),
+                    plist::Value::String(text.to_owned()),
+                )]
+                .into_iter()
+                .collect(),
+            );
+            let mut data = Vec::new();
+            info.to_writer_binary(&mut data).unwrap();
+            data
+        };
+
+        assert_eq!(
+            transcription(Some(plist(" call me back "))).as_deref(),
+            Some("call me back")
+        );
+        assert_eq!(transcription(Some(plist("  "))), None);
+        assert_eq!(transcription(Some(b"garbage".to_vec())), None);
+        assert_eq!(transcription(None), None);
+    }
+}
@@ -0,0 +1,487 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use egui::{Color32, Context, Sense, Ui};
+use tokio::runtime;
+
+use crate::{cache, error::Error, i18n, perf};
+
+/// Voice messages are decoded to mono at this rate, which is plenty for
+/// speech and keeps a minute of audio to about 2 MB
+const SAMPLE_RATE: u32 = 16000;
+
+/// Number of bars in a waveform
+const BARS: usize = 60;
+
+/// A decoded audio attachment
+pub struct Clip {
+    samples: Vec<i16>,
+    /// Loudest sample in each of [`BARS`] slices, scaled so the loudest slice
+    /// is 1
+    peaks: Vec<f32>,
+}
+
+impl Clip {
+    fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.samples.len() as f64 / f64::from(SAMPLE_RATE))
+    }
+}
+
+enum Entry {
+    Decoding,
+    Ready(Arc<Clip>),
+    Failed(Error),
+}
+
+struct Playing {
+    path: PathBuf,
+    player: Child,
+    started: Instant,
+    /// Where in the clip playback started
+    from: Duration,
+}
+
+/// Inline player for audio attachments. Clips are decoded to WAV with
+/// `afconvert` (macOS) or `ffmpeg` and played with whichever command line
+/// player is installed. Playback stops when this is dropped.
+pub struct Audio {
+    rt: runtime::Handle,
+    clips: Arc<Mutex<HashMap<PathBuf, Entry>>>,
+    playing: Option<Playing>,
+    /// Where each clip that isn't playing was paused or scrubbed to
+    paused: HashMap<PathBuf, Duration>,
+}
+
+impl Drop for Audio {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Audio {
+    pub fn new(rt: runtime::Handle) -> Self {
+        Self {
+            rt,
+            clips: Arc::default(),
+            playing: None,
+            paused: HashMap::new(),
+        }
+    }
+
+    /// Play/pause button, waveform (click or drag to scrub) and position for
+    /// the audio file at `path`
+    pub fn show(&mut self, ui: &mut Ui, path: &Path) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        let clip = match self.clip(ui.ctx(), path) {
+            Some(Ok(clip)) => clip,
+            Some(Err(e)) => {
+                let label = match e {
+                    Error::MissingAttachment(_) => format!(
+                        "🔈 {}",
+                        i18n::tf("{name} (attachment unavailable)", &[("name", &name)])
+                    ),
+                    _ => format!("🔈 {name}"),
+                };
+                ui.weak(label).on_hover_text(format!("{e}\n\n{}", e.hint()));
+                return;
+            }
+            None => {
+                ui.spinner();
+                return;
+            }
+        };
+
+        let position = self.position(path);
+        let is_playing = self.playing.as_ref().is_some_and(|p| p.path == path);
+        let duration = clip.duration();
+
+        ui.horizontal(|ui| {
+            if ui.button(if is_playing { "⏸" } else { "▶" }).clicked() {
+                if is_playing {
+                    self.pause();
+                } else {
+                    self.play(path, &clip, position);
+                }
+            }
+
+            let size = egui::vec2(BARS as f32 * 3.0, ui.spacing().interact_size.y);
+            let (response, painter) = ui.allocate_painter(size, Sense::click_and_drag());
+            let rect = response.rect;
+
+            let played = position.as_secs_f32() / duration.as_secs_f32().max(f32::EPSILON);
+            let bar_width = rect.width() / BARS as f32;
+            for (i, peak) in clip.peaks.iter().enumerate() {
+                let x = rect.left() + (i as f32 + 0.5) * bar_width;
+                let half = (peak * rect.height()).max(2.0) / 2.0;
+                let color = if (i as f32 + 0.5) / BARS as f32 <= played {
+                    ui.visuals().text_color()
+                } else {
+                    Color32::GRAY
+                };
+
+                painter.line_segment(
+                    [
+                        egui::pos2(x, rect.center().y - half),
+                        egui::pos2(x, rect.center().y + half),
+                    ],
+                    (bar_width * 0.6, color),
+                );
+            }
+
+            if response.clicked() || response.drag_released() {
+                if let Some(pointer) = response.interact_pointer_pos() {
+                    let fraction = ((pointer.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+                    let to = duration.mul_f32(fraction);
+
+                    if is_playing {
+                        self.play(path, &clip, to);
+                    } else {
+                        self.paused.insert(path.to_owned(), to);
+                    }
+                }
+            }
+
+            ui.label(format!("{} / {}", timestamp(position), timestamp(duration)));
+        });
+
+        if is_playing {
+            ui.ctx().request_repaint();
+        }
+    }
+
+    /// The decoded clip at `path`, or why it couldn't be decoded, or `None`
+    /// while it's still decoding
+    fn clip(&self, ctx: &Context, path: &Path) -> Option<Result<Arc<Clip>, Error>> {
+        let mut clips = self.clips.lock().unwrap();
+
+        let result = match clips.get(path) {
+            Some(Entry::Ready(clip)) => Some(Ok(clip.clone())),
+            Some(Entry::Failed(e)) => Some(Err(e.clone())),
+            Some(Entry::Decoding) => None,
+            None => {
+                clips.insert(path.to_owned(), Entry::Decoding);
+
+                let clips = self.clips.clone();
+                let path = path.to_owned();
+                let ctx = ctx.clone();
+                self.rt.spawn_blocking(move || {
+                    let entry = match decode(&path) {
+                        Ok(clip) => Entry::Ready(Arc::new(clip)),
+                        Err(e) => Entry::Failed(e),
+                    };
+                    clips.lock().unwrap().insert(path, entry);
+                    ctx.request_repaint();
+                });
+
+                None
+            }
+        };
+        perf::cache_lookup("audio clips", result.is_some());
+
+        result
+    }
+
+    /// How far into the clip at `path` playback is
+    fn position(&mut self, path: &Path) -> Duration {
+        if let Some(playing) = &mut self.playing {
+            if playing.path == path {
+                if let Ok(None) = playing.player.try_wait() {
+                    return playing.from + playing.started.elapsed();
+                }
+
+                // played to the end
+                self.playing = None;
+                self.paused.remove(path);
+                return Duration::ZERO;
+            }
+        }
+
+        self.paused.get(path).copied().unwrap_or_default()
+    }
+
+    fn play(&mut self, path: &Path, clip: &Clip, mut from: Duration) {
+        self.stop();
+        if from >= clip.duration() {
+            from = Duration::ZERO;
+        }
+
+        let start = (from.as_secs_f64() * f64::from(SAMPLE_RATE)) as usize;
+        let wav = cache::dir().join("audio").join("playing.wav");
+        if let Err(e) = write_wav(&wav, &clip.samples[start.min(clip.samples.len())..]) {
+            eprintln!("{e}");
+            return;
+        }
+
+        match spawn_player(&wav) {
+            Ok(player) => {
+                self.playing = Some(Playing {
+                    path: path.to_owned(),
+                    player,
+                    started: Instant::now(),
+                    from,
+                });
+            }
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+
+    fn pause(&mut self) {
+        if let Some(playing) = &self.playing {
+            let position = playing.from + playing.started.elapsed();
+            self.paused.insert(playing.path.clone(), position);
+        }
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        if let Some(mut playing) = self.playing.take() {
+            let _ = playing.player.kill();
+            let _ = playing.player.wait();
+        }
+    }
+}
+
+/// Decode an audio file, converting it to WAV in the cache directory the
+/// first time
+fn decode(path: &Path) -> Result<Clip, Error> {
+    puffin::profile_function!();
+
+    if !path.exists() {
+        return Err(Error::MissingAttachment(path.to_owned()));
+    }
+
+    let wav = cache::converted_path("audio", path, "wav");
+    if !wav.exists() {
+        convert(path, &wav).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => Error::UnsupportedFormat(e.to_string()),
+            _ => Error::Decode(e.to_string()),
+        })?;
+    }
+
+    let samples = read_wav(&wav).map_err(|e| Error::Decode(e.to_string()))?;
+
+    let slice_len = (samples.len() / BARS).max(1);
+    let mut peaks: Vec<f32> = (0..BARS)
+        .map(|i| {
+            samples
+                .iter()
+                .skip(i * slice_len)
+                .take(slice_len)
+                .map(|s| s.unsigned_abs())
+                .max()
+                .unwrap_or(0) as f32
+        })
+        .collect();
+    let loudest = peaks.iter().copied().fold(0.0, f32::max);
+    if loudest > 0.0 {
+        peaks.iter_mut().for_each(|p| *p /= loudest);
+    }
+
+    Ok(Clip { samples, peaks })
+}
+
+/// Convert to 16-bit mono WAV at [`SAMPLE_RATE`], with `afconvert` (which
+/// ships with macOS) or `ffmpeg`
+fn convert(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to.parent().unwrap())?;
+    // so that an interrupted conversion is never mistaken for a finished one
+    let partial = to.with_extension("partial.wav");
+
+    let mut afconvert = Command::new("afconvert");
+    afconvert
+        .args(["-f", "WAVE", "-c", "1", "-d"])
+        .arg(format!("LEI16@{SAMPLE_RATE}"))
+        .arg(from)
+        .arg(&partial);
+
+    let mut ffmpeg = Command::new("ffmpeg");
+    ffmpeg
+        .args(["-loglevel", "quiet", "-y", "-i"])
+        .arg(from)
+        .args(["-ac", "1", "-c:a", "pcm_s16le", "-ar"])
+        .arg(SAMPLE_RATE.to_string())
+        .arg(&partial);
+
+    for converter in [&mut afconvert, &mut ffmpeg] {
+        let status = converter
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+
+        match status {
+            Ok(status) if status.success() => return fs::rename(&partial, to),
+            Ok(status) => {
+                return Err(io::Error::other(format!(
+                    "{:?} {status}",
+                    converter.get_program()
+                )))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(io::Error::new(
+        ErrorKind::NotFound,
+        "can't decode audio messages, install ffmpeg",
+    ))
+}
+
+/// Play a WAV file with the first player that's installed
+fn spawn_player(wav: &Path) -> io::Result<Child> {
+    let players: [(&str, &[&str]); 4] = [
+        ("afplay", &[]),
+        ("paplay", &[]),
+        ("aplay", &["-q"]),
+        ("ffplay", &["-nodisp", "-autoexit", "-loglevel", "quiet"]),
+    ];
+
+    for (player, args) in players {
+        let spawned = Command::new(player)
+            .args(args)
+            .arg(wav)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        match spawned {
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            result => return result,
+        }
+    }
+
+    Err(io::Error::new(
+        ErrorKind::NotFound,
+        "no audio player found, install ffmpeg for ffplay",
+    ))
+}
+
+/// Samples from a 16-bit mono WAV file, as written by [`convert`]
+fn read_wav(path: &Path) -> io::Result<Vec<i16>> {
+    let data = fs::read(path)?;
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err(io::Error::new(ErrorKind::InvalidData, "not a WAV file"));
+    }
+
+    let mut chunks = &data[12..];
+    while chunks.len() >= 8 {
+        let len = u32::from_le_bytes(chunks[4..8].try_into().unwrap()) as usize;
+        let body = &chunks[8..chunks.len().min(8 + len)];
+
+        if &chunks[..4] == b"data" {
+            return Ok(body
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                .collect());
+        }
+
+        // chunks are padded to an even length
+        chunks = &chunks[chunks.len().min(8 + len + len % 2)..];
+    }
+
+    Err(io::Error::new(
+        ErrorKind::InvalidData,
+        "WAV file has no audio",
+    ))
+}
+
+/// Write 16-bit mono samples at [`SAMPLE_RATE`] as a WAV file
+pub fn write_wav(path: &Path, samples: &[i16]) -> io::Result<()> {
+    let data_len = (samples.len() * 2) as u32;
+
+    let mut wav = Vec::with_capacity(44 + samples.len() * 2);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes()); // bytes per second
+    wav.extend_from_slice(&2u16.to_le_bytes()); // bytes per frame
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, wav)
+}
+
+/// `m:ss`
+fn timestamp(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("imessage-viewer-audio-{}", std::process::id()))
+            .join(name)
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let path = scratch("round-trip.wav");
+        let samples = [0, 1, -1, i16::MAX, i16::MIN, 1234];
+        write_wav(&path, &samples).unwrap();
+
+        assert_eq!(read_wav(&path).unwrap(), samples);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 44 + 12);
+    }
+
+    #[test]
+    fn skips_other_chunks() {
+        // a LIST chunk of odd length, padded, before the audio
+        let mut wav = b"RIFF\0\0\0\0WAVE".to_vec();
+        wav.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+        wav.extend_from_slice(b"data\x04\0\0\0\x01\0\xff\xff");
+        let path = scratch("list.wav");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &wav).unwrap();
+        assert_eq!(read_wav(&path).unwrap(), [1, -1]);
+
+        // a data chunk cut short is read as far as it goes
+        wav.truncate(wav.len() - 1);
+        fs::write(&path, &wav).unwrap();
+        assert_eq!(read_wav(&path).unwrap(), [1]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = scratch("other.wav");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        for contents in [
+            &b"ID3 not a wav"[..],
+            b"RIFF",
+            b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0",
+        ] {
+            fs::write(&path, contents).unwrap();
+            assert_eq!(read_wav(&path).unwrap_err().kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(Duration::from_millis(999)), "0:00");
+        assert_eq!(timestamp(Duration::from_secs(65)), "1:05");
+        assert_eq!(timestamp(Duration::from_secs(3600)), "60:00");
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    ffi::CStr,
+    fs::{File, OpenOptions},
+    io::Write,
+    os::raw::{c_char, c_int, c_uint, c_void},
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+};
+
+use chrono::prelude::*;
+use eyre::Result;
+use libsqlite3_sys as ffi;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    SqlitePool,
+};
+
+use crate::search::fold;
+
+lazy_static::lazy_static! {
+    static ref LOG: Mutex<Option<File>> = Mutex::new(None);
+}
+
+/// How statements run against the source database are watched
+#[derive(Clone, Copy, Default)]
+pub struct Audit<'a> {
+    /// Append every statement (with its bound parameters) to this file
+    pub log: Option<&'a PathBuf>,
+    /// Also have SQLite refuse to prepare any statement that could modify
+    /// the database, on top of it being opened read-only
+    pub read_only: bool,
+}
+
+/// Prepared statements kept on each connection. Queries built for a set of
+/// chats differ in their number of parameters, so there are more distinct
+/// statements than the handful written out in the code.
+const STATEMENT_CACHE: usize = 256;
+
+/// What a pool of connections to the source database is for, which decides
+/// how many connections it has
+#[derive(Clone, Copy)]
+pub enum Pool {
+    /// Small queries the UI waits on: pages of messages, message info...
+    Interactive,
+    /// Long scans: exports, stats, searching without the index, building it
+    Bulk,
+}
+
+impl Pool {
+    pub const fn connections(self) -> u32 {
+        match self {
+            Self::Interactive => 4,
+            Self::Bulk => 2,
+        }
+    }
+}
+
+/// Connect to the source database, read-only, with auditing hooks installed
+/// on every connection in the pool. The hooks live inside SQLite itself, so
+/// they catch every statement regardless of which part of the app issued it.
+pub async fn connect(database_file: &str, audit: Audit<'_>, pool: Pool) -> Result<SqlitePool> {
+    if let Some(path) = audit.log {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        *LOG.lock().unwrap() = Some(file);
+    }
+
+    let log = audit.log.is_some();
+    let read_only = audit.read_only;
+
+    // whatever the flags, the viewer never changes the database it's showing
+    let options = SqliteConnectOptions::from_str(database_file)?
+        .read_only(true)
+        .statement_cache_capacity(STATEMENT_CACHE);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(pool.connections())
+        .after_connect(move |conn, _meta| {
+            Box::pin(async move {
+                let mut locked = conn.lock_handle().await?;
+                let handle = locked.as_raw_handle().as_ptr();
+
+                // SAFETY: the handle is locked away from sqlx's worker thread
+                // while the hooks are installed, and the callbacks only touch
+                // their arguments and `LOG`
+                unsafe {
+                    fold::register(handle);
+
+                    if log {
+                        ffi::sqlite3_trace_v2(
+                            handle,
+                            ffi::SQLITE_TRACE_STMT as c_uint,
+                            Some(trace),
+                            std::ptr::null_mut(),
+                        );
+                    }
+                    if read_only {
+                        ffi::sqlite3_set_authorizer(handle, Some(authorize), std::ptr::null_mut());
+                    }
+                }
+
+                Ok(())
+            })
+        })
+        .connect_with(options)
+        .await?;
+
+    Ok(pool)
+}
+
+/// SQLite authorizer allowing only the actions a read-only query needs
+unsafe extern "C" fn authorize(
+    _: *mut c_void,
+    action: c_int,
+    _: *const c_char,
+    pragma_value: *const c_char,
+    _: *const c_char,
+    _: *const c_char,
+) -> c_int {
+    match action {
+        ffi::SQLITE_SELECT | ffi::SQLITE_READ | ffi::SQLITE_FUNCTION | ffi::SQLITE_RECURSIVE => {
+            ffi::SQLITE_OK
+        }
+        // `PRAGMA x` reads a setting, `PRAGMA x = y` changes it
+        ffi::SQLITE_PRAGMA if pragma_value.is_null() => ffi::SQLITE_OK,
+        _ => ffi::SQLITE_DENY,
+    }
+}
+
+/// SQLite trace callback writing each statement as it starts to run
+unsafe extern "C" fn trace(_: c_uint, _: *mut c_void, stmt: *mut c_void, _: *mut c_void) -> c_int {
+    let sql = ffi::sqlite3_expanded_sql(stmt as *mut ffi::sqlite3_stmt);
+    if sql.is_null() {
+        return 0;
+    }
+
+    if let Some(file) = &mut *LOG.lock().unwrap() {
+        let text = CStr::from_ptr(sql).to_string_lossy();
+        let _ = writeln!(file, "{} {}", Utc::now().to_rfc3339(), text.trim());
+    }
+
+    ffi::sqlite3_free(sql as *mut c_void);
+
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch database with a single table, created outside of the audit
+    /// hooks
+    async fn scratch_db(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "imessage-viewer-audit-{}-{name}.db",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let pool = SqlitePool::connect_with(
+            SqliteConnectOptions::new()
+                .filename(&path)
+                .create_if_missing(true),
+        )
+        .await
+        .unwrap();
+        sqlx::query("CREATE TABLE message (text TEXT); INSERT INTO message VALUES ('hi');")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+
+        path
+    }
+
+    #[tokio::test]
+    async fn read_only_allows_reads() {
+        let path = scratch_db("reads").await;
+        let audit = Audit {
+            read_only: true,
+            ..Audit::default()
+        };
+        let db = connect(path.to_str().unwrap(), audit, Pool::Interactive)
+            .await
+            .unwrap();
+
+        let (text,) = sqlx::query_as::<_, (String,)>("SELECT text from message where text like $1")
+            .bind("h%")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(text, "hi");
+
+        sqlx::query("PRAGMA user_version;")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn always_opened_read_only() {
+        let path = scratch_db("always").await;
+        let db = connect(path.to_str().unwrap(), Audit::default(), Pool::Interactive)
+            .await
+            .unwrap();
+
+        for statement in [
+            "INSERT INTO message VALUES ('bye')",
+            "UPDATE message SET text = 'bye'",
+            "DELETE FROM message",
+        ] {
+            assert!(
+                sqlx::query(statement).execute(&db).await.is_err(),
+                "{statement} was allowed"
+            );
+        }
+
+        let (text,) = sqlx::query_as::<_, (String,)>("SELECT text from message")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(text, "hi");
+    }
+
+    #[tokio::test]
+    async fn every_connection_can_fold() {
+        let path = scratch_db("fold").await;
+        for pool in [Pool::Interactive, Pool::Bulk] {
+            let db = connect(path.to_str().unwrap(), Audit::default(), pool)
+                .await
+                .unwrap();
+
+            // hold them all at once so that none is handed out twice
+            let mut conns = Vec::new();
+            for _ in 0..pool.connections() {
+                conns.push(db.acquire().await.unwrap());
+            }
+            for conn in &mut conns {
+                let (folded,) = sqlx::query_as::<_, (String,)>("SELECT fold('Café')")
+                    .fetch_one(&mut **conn)
+                    .await
+                    .unwrap();
+                assert_eq!(folded, "cafe");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn read_only_rejects_writes() {
+        let path = scratch_db("writes").await;
+        let audit = Audit {
+            read_only: true,
+            ..Audit::default()
+        };
+        let db = connect(path.to_str().unwrap(), audit, Pool::Interactive)
+            .await
+            .unwrap();
+
+        for statement in [
+            "INSERT INTO message VALUES ('bye')",
+            "UPDATE message SET text = 'bye'",
+            "DELETE FROM message",
+            "DROP TABLE message",
+            "CREATE TABLE other (x)",
+            "ALTER TABLE message ADD COLUMN x",
+            "PRAGMA user_version = 5",
+            "ATTACH DATABASE ':memory:' AS other",
+        ] {
+            assert!(
+                sqlx::query(statement).execute(&db).await.is_err(),
+                "{statement} was allowed"
+            );
+        }
+
+        let (count,) = sqlx::query_as::<_, (i64,)>("SELECT count(*) from message")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+}
@@ -0,0 +1,57 @@
+use std::path::Path;
+
+use egui::{Color32, Response, Sense, Ui};
+
+use crate::images::Images;
+
+/// Draw someone's contact photo in a circle `size` across, or until there is
+/// one, their initials on the colour their `handle` gets in group chats
+pub fn show(
+    ui: &mut Ui,
+    images: &mut Images,
+    name: &str,
+    handle: &str,
+    photo: Option<&Path>,
+    size: f32,
+) -> Response {
+    let (rect, response) = ui.allocate_exact_size(egui::Vec2::splat(size), Sense::hover());
+    if !ui.is_rect_visible(rect) {
+        return response;
+    }
+
+    if let Some(photo) = photo {
+        if images.avatar(ui, photo, rect) {
+            return response;
+        }
+    }
+
+    ui.painter()
+        .circle_filled(rect.center(), size / 2.0, crate::sender_color(handle));
+    ui.painter().text(
+        rect.center(),
+        egui::Align2::CENTER_CENTER,
+        initials(name),
+        egui::FontId::proportional(size * 0.4),
+        Color32::WHITE,
+    );
+
+    response
+}
+
+/// The first letters of someone's first and last names, or "#" for a phone
+/// number or anything else without letters to go by
+fn initials(name: &str) -> String {
+    let mut words = name
+        .split_whitespace()
+        .filter_map(|word| word.chars().next())
+        .filter(|c| c.is_alphabetic());
+    let first = match words.next() {
+        Some(first) => first,
+        None => return "#".into(),
+    };
+
+    first
+        .to_uppercase()
+        .chain(words.next_back().into_iter().flat_map(char::to_uppercase))
+        .collect()
+}
@@ -0,0 +1,465 @@
+use std::{collections::HashMap, fs, io::Cursor};
+
+use egui::{Color32, Frame, RichText, Rounding, Ui};
+use plist::{Dictionary, Value};
+
+use crate::{
+    attachment::Attachment,
+    export, i18n,
+    images::Images,
+    payment::{self, Payment},
+};
+
+/// `balloon_bundle_id` of links, whose payload is the preview Messages
+/// fetched for them
+pub const LINK_PREVIEW: &str = "com.apple.messages.URLBalloonProvider";
+
+/// Characters of a link's summary shown on its card
+const SUMMARY_LEN: usize = 140;
+
+const HANDWRITING: &str = "com.apple.Handwriting.HandwritingProvider";
+const DIGITAL_TOUCH: &str = "com.apple.DigitalTouchBalloonProvider";
+
+/// A message drawn by an iMessage app (a game, a poll, a payment...) rather
+/// than as text
+#[derive(Clone, Debug)]
+pub enum Balloon {
+    Payment(Payment),
+    /// A handwritten or Digital Touch message, whose strokes aren't drawn
+    /// yet but can be saved for other tools
+    Drawing(DrawingKind, Vec<u8>),
+    Link(Link),
+    App(App),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawingKind {
+    Handwriting,
+    DigitalTouch,
+}
+
+impl DrawingKind {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Handwriting => "handwritten message",
+            Self::DigitalTouch => "Digital Touch message",
+        }
+    }
+
+    /// Start of the file name its payload is saved as
+    fn file_prefix(self) -> &'static str {
+        match self {
+            Self::Handwriting => "handwriting",
+            Self::DigitalTouch => "digital-touch",
+        }
+    }
+}
+
+/// A link with the preview Messages fetched when it was sent
+#[derive(Clone, Debug)]
+pub struct Link {
+    pub url: Option<String>,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    pub site: Option<String>,
+    /// The preview image, which is one of the message's attachments
+    pub image: Option<Attachment>,
+}
+
+/// What can be shown of a message from any other iMessage app
+#[derive(Clone, Debug)]
+pub struct App {
+    pub name: String,
+    pub caption: Option<String>,
+    pub subcaption: Option<String>,
+}
+
+/// Read `message.balloon_bundle_id` and `payload_data`, or `None` for a
+/// message that isn't drawn by an app. `payloads` are the message's
+/// `.pluginPayloadAttachment` files, which hold things like link previews'
+/// images.
+pub fn decode(
+    bundle_id: Option<String>,
+    payload: Option<Vec<u8>>,
+    payloads: Vec<Attachment>,
+) -> Option<Balloon> {
+    let bundle_id = bundle_id?;
+    match bundle_id.as_str() {
+        // a link still being fetched, or one without a preview, is just its
+        // URL as text
+        LINK_PREVIEW => return link(&payload?, payloads).map(Balloon::Link),
+        HANDWRITING => {
+            return Some(Balloon::Drawing(
+                DrawingKind::Handwriting,
+                payload.unwrap_or_default(),
+            ))
+        }
+        DIGITAL_TOUCH => {
+            return Some(Balloon::Drawing(
+                DrawingKind::DigitalTouch,
+                payload.unwrap_or_default(),
+            ))
+        }
+        _ => {}
+    }
+
+    let strings = payload
+        .and_then(|payload| archived_strings(&payload))
+        .unwrap_or_default();
+
+    if bundle_id.ends_with(payment::BUNDLE_ID) {
+        return Some(Balloon::Payment(payment::decode(&strings)));
+    }
+
+    let get = |keys: &[&str]| {
+        keys.iter()
+            .filter_map(|key| strings.get(*key))
+            .find(|value| !value.trim().is_empty())
+            .cloned()
+    };
+
+    Some(Balloon::App(App {
+        name: get(&["an"]).unwrap_or_else(|| app_name(&bundle_id)),
+        caption: get(&["caption", "ldtext", "title"]),
+        subcaption: get(&["subcaption", "subtitle"]),
+    }))
+}
+
+/// A readable name for an app from its bundle ID, for payloads without one,
+/// e.g. `...:com.gamerdelights.gamepigeon.ext` is "gamepigeon" and
+/// `...:com.apple.Polls.PollsMessagesExtension` is "Polls"
+fn app_name(bundle_id: &str) -> String {
+    let id = bundle_id.rsplit(':').next().unwrap_or(bundle_id);
+
+    id.split('.')
+        .rev()
+        .map(|part| {
+            part.trim_end_matches("Extension")
+                .trim_end_matches("Messages")
+        })
+        .find(|part| {
+            !part.is_empty() && !matches!(part.to_lowercase().as_str(), "ext" | "extension")
+        })
+        .unwrap_or(id)
+        .to_owned()
+}
+
+/// Read the `LPLinkMetadata` archived in a link's payload, or `None` if
+/// there's nothing to show beyond the URL
+fn link(payload: &[u8], mut payloads: Vec<Attachment>) -> Option<Link> {
+    let archive = Value::from_reader(Cursor::new(payload)).ok()?;
+    let archive = archive.as_dictionary()?;
+    let objects = archive.get("$objects")?.as_array()?;
+
+    let field = |dict: &Dictionary, key: &str| object(dict.get(key)?, objects);
+    let string = |dict: &Dictionary, key: &str| {
+        let value = field(dict, key)?.as_string()?.trim();
+        Some(value.to_owned()).filter(|value| !value.is_empty())
+    };
+
+    // `$top` only points at the archived payload, which holds the metadata
+    let top = archive.get("$top")?.as_dictionary()?;
+    let root = field(top, "root")?.as_dictionary()?;
+    let metadata = field(root, "richLinkMetadata")?.as_dictionary()?;
+
+    // an NSURL keeps its string as `NS.relative`
+    let url = |key: &str| string(field(metadata, key)?.as_dictionary()?, "NS.relative");
+    // images are attachments, numbered in the order they were attached
+    let image = |key: &str| {
+        let index = field(metadata, key)?
+            .as_dictionary()?
+            .get("richLinkImageAttachmentSubstituteIndex")?
+            .as_unsigned_integer()?;
+        Some(index as usize).filter(|index| *index < payloads.len())
+    };
+
+    let image = image("image").or_else(|| image("icon"));
+    let link = Link {
+        url: url("URL").or_else(|| url("originalURL")),
+        title: string(metadata, "title"),
+        summary: string(metadata, "summary"),
+        site: string(metadata, "siteName"),
+        image: image.map(|index| payloads.swap_remove(index)),
+    };
+
+    (link.title.is_some() || link.image.is_some()).then_some(link)
+}
+
+/// The archived object a `UID` refers to
+fn object<'a>(value: &Value, objects: &'a [Value]) -> Option<&'a Value> {
+    objects.get(value.as_uid()?.get() as usize)
+}
+
+/// Every string stored under a string key anywhere in an `NSKeyedArchiver`
+/// archive, whether in an archived dictionary or an archived object's fields
+fn archived_strings(payload: &[u8]) -> Option<HashMap<String, String>> {
+    let archive = Value::from_reader(Cursor::new(payload)).ok()?;
+    let objects = archive.as_dictionary()?.get("$objects")?.as_array()?;
+
+    let resolve = |value: &Value| -> Option<String> {
+        let uid = value.as_uid()?.get();
+        objects.get(uid as usize)?.as_string().map(str::to_owned)
+    };
+
+    let mut strings = HashMap::new();
+    for object in objects {
+        let object = match object.as_dictionary() {
+            Some(object) => object,
+            None => continue,
+        };
+
+        let keys = object.get("NS.keys").and_then(Value::as_array);
+        let values = object.get("NS.objects").and_then(Value::as_array);
+        if let (Some(keys), Some(values)) = (keys, values) {
+            for (key, value) in keys.iter().zip(values) {
+                if let (Some(key), Some(value)) = (resolve(key), resolve(value)) {
+                    strings.entry(key).or_insert(value);
+                }
+            }
+        } else {
+            for (key, value) in object {
+                if let Some(value) = resolve(value) {
+                    strings.entry(key.clone()).or_insert(value);
+                }
+            }
+        }
+    }
+
+    Some(strings)
+}
+
+/// Draw a balloon as a card inside the bubble of the message `guid`
+pub fn show(ui: &mut Ui, balloon: &Balloon, guid: &str, images: &mut Images) {
+    Frame::none()
+        .fill(Color32::from_black_alpha(60))
+        .rounding(Rounding::same(6.0))
+        .inner_margin(8.0)
+        .show(ui, |ui| match balloon {
+            Balloon::Payment(payment) => payment::show(ui, payment),
+            Balloon::Drawing(kind, payload) => show_drawing(ui, *kind, payload, guid),
+            Balloon::Link(link) => show_link(ui, link, images),
+            Balloon::App(app) => {
+                ui.label(RichText::new(&app.name).small().strong());
+                if let Some(caption) = &app.caption {
+                    ui.label(caption);
+                }
+                if let Some(subcaption) = &app.subcaption {
+                    ui.label(RichText::new(subcaption).small());
+                }
+            }
+        });
+}
+
+/// A link's preview image, site and title, opening the link when clicked
+fn show_link(ui: &mut Ui, link: &Link, images: &mut Images) {
+    let mut clicked = false;
+    if let Some(image) = &link.image {
+        clicked |= images.show_inline(ui, image);
+    }
+
+    let site = link.site.clone().or_else(|| {
+        let url = url::Url::parse(link.url.as_deref()?).ok()?;
+        Some(url.host_str()?.trim_start_matches("www.").to_owned())
+    });
+    if let Some(site) = site {
+        ui.label(RichText::new(site).small().weak());
+    }
+    if let Some(title) = &link.title {
+        let title = egui::Label::new(RichText::new(title).strong()).sense(egui::Sense::click());
+        clicked |= ui.add(title).clicked();
+    }
+    if let Some(summary) = &link.summary {
+        // Messages only shows the first couple of lines
+        let summary = match summary.char_indices().nth(SUMMARY_LEN) {
+            Some((end, _)) => format!("{}…", summary[..end].trim_end()),
+            None => summary.clone(),
+        };
+        ui.label(RichText::new(summary).small());
+    }
+
+    if let (true, Some(url)) = (clicked, &link.url) {
+        ui.ctx().output().open_url(url);
+    }
+}
+
+/// A placeholder for a drawing, with a button to save its raw payload
+fn show_drawing(ui: &mut Ui, kind: DrawingKind, payload: &[u8], guid: &str) {
+    ui.label(RichText::new(i18n::t(kind.label())).strong());
+    ui.label(RichText::new(i18n::t("not shown here yet")).small());
+
+    // the outcome of saving is kept in egui's memory, so the card can say
+    // where it went without any state of its own
+    let id = egui::Id::new(("saved drawing", guid));
+    if !payload.is_empty() && ui.small_button(i18n::t("save raw data")).clicked() {
+        let name = format!("{}-{}.bin", kind.file_prefix(), export::file_stem(guid));
+        let path = export::default_dir().join(name);
+        let outcome = match fs::write(&path, payload) {
+            Ok(()) => i18n::tf("saved to {path}", &[("path", &path.display())]),
+            Err(e) => i18n::tf("couldn't save: {error}", &[("error", &e)]),
+        };
+        ui.memory().data.insert_temp(id, outcome);
+    }
+    let outcome = ui.memory().data.get_temp::<String>(id);
+    if let Some(outcome) = outcome {
+        ui.label(RichText::new(outcome).small());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plist::Uid;
+
+    use super::*;
+
+    fn uid(i: u64) -> Value {
+        Value::Uid(Uid::new(i))
+    }
+
+    fn dict<const N: usize>(entries: [(&str, Value); N]) -> Value {
+        Value::Dictionary(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect(),
+        )
+    }
+
+    /// An `NSKeyedArchiver` archive of `objects`, whose root is the second
+    /// (the first is always `$null`)
+    fn archive(objects: Vec<Value>) -> Vec<u8> {
+        let archive = dict([
+            ("$archiver", Value::String("NSKeyedArchiver".to_owned())),
+            ("$objects", Value::Array(objects)),
+            ("$top", dict([("root", uid(1))])),
+        ]);
+        let mut data = Vec::new();
+        archive.to_writer_binary(&mut data).unwrap();
+        data
+    }
+
+    fn attachment(name: &str) -> Attachment {
+        Attachment {
+            guid: name.to_owned(),
+            path: name.into(),
+            name: name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn names_apps() {
+        assert_eq!(
+            app_name("com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.gamerdelights.gamepigeon.ext"),
+            "gamepigeon"
+        );
+        assert_eq!(
+            app_name("com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.apple.Polls.PollsMessagesExtension"),
+            "Polls"
+        );
+        assert_eq!(app_name("ext"), "ext");
+    }
+
+    #[test]
+    fn reads_app_captions() {
+        // a dictionary, and an object with fields
+        let payload = archive(vec![
+            Value::String("$null".to_owned()),
+            dict([
+                ("NS.keys", Value::Array(vec![uid(2), uid(3)])),
+                ("NS.objects", Value::Array(vec![uid(4), uid(5)])),
+            ]),
+            Value::String("caption".to_owned()),
+            Value::String("an".to_owned()),
+            Value::String("Your move!".to_owned()),
+            Value::String("GamePigeon".to_owned()),
+            dict([("subcaption", uid(7))]),
+            Value::String("8 ball".to_owned()),
+        ]);
+        let balloon = decode(
+            Some("com.apple.messages.MSMessageExtensionBalloonPlugin:0:com.gamerdelights.gamepigeon.ext".to_owned()),
+            Some(payload),
+            Vec::new(),
+        );
+        let Some(Balloon::App(app)) = balloon else {
+            panic!("{balloon:?}");
+        };
+        assert_eq!(app.name, "GamePigeon");
+        assert_eq!(app.caption.as_deref(), Some("Your move!"));
+        assert_eq!(app.subcaption.as_deref(), Some("8 ball"));
+
+        // an unreadable payload still shows which app it's from
+        let balloon = decode(
+            Some("com.apple.messages.MSMessageExtensionBalloonPlugin:0:com.apple.Polls.PollsMessagesExtension".to_owned()),
+            Some(b"not a plist".to_vec()),
+            Vec::new(),
+        );
+        let Some(Balloon::App(app)) = balloon else {
+            panic!("{balloon:?}");
+        };
+        assert_eq!(app.name, "Polls");
+        assert_eq!(app.caption, None);
+    }
+
+    #[test]
+    fn reads_link_previews() {
+        let payload = |metadata: Value| {
+            archive(vec![
+                Value::String("$null".to_owned()),
+                dict([("richLinkMetadata", uid(2))]),
+                metadata,
+                dict([("NS.relative", uid(4))]),
+                Value::String("https://example.com/post".to_owned()),
+                Value::String("  A post  ".to_owned()),
+                dict([(
+                    "richLinkImageAttachmentSubstituteIndex",
+                    Value::Integer(1.into()),
+                )]),
+                // a blank site name
+                Value::String(String::new()),
+            ])
+        };
+
+        let balloon = decode(
+            Some(LINK_PREVIEW.to_owned()),
+            Some(payload(dict([
+                ("URL", uid(3)),
+                ("title", uid(5)),
+                ("image", uid(6)),
+                ("siteName", uid(7)),
+            ]))),
+            vec![attachment("icon.png"), attachment("image.jpg")],
+        );
+        let Some(Balloon::Link(link)) = balloon else {
+            panic!("{balloon:?}");
+        };
+        assert_eq!(link.url.as_deref(), Some("https://example.com/post"));
+        assert_eq!(link.title.as_deref(), Some("A post"));
+        assert_eq!(link.site, None);
+        assert_eq!(
+            link.image.map(|image| image.name).as_deref(),
+            Some("image.jpg")
+        );
+
+        // nothing to show beyond the URL, or an image that isn't there
+        for metadata in [
+            dict([("URL", uid(3))]),
+            dict([("URL", uid(3)), ("image", uid(6))]),
+        ] {
+            let balloon = decode(
+                Some(LINK_PREVIEW.to_owned()),
+                Some(payload(metadata)),
+                vec![attachment("icon.png")],
+            );
+            assert!(balloon.is_none(), "{balloon:?}");
+        }
+        assert!(decode(Some(LINK_PREVIEW.to_owned()), None, Vec::new()).is_none());
+    }
+
+    #[test]
+    fn only_app_messages() {
+        assert!(decode(None, Some(Vec::new()), Vec::new()).is_none());
+        assert!(matches!(
+            decode(Some(HANDWRITING.to_owned()), None, Vec::new()),
+            Some(Balloon::Drawing(DrawingKind::Handwriting, payload)) if payload.is_empty()
+        ));
+    }
+}
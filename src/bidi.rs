@@ -0,0 +1,359 @@
+use egui::{
+    epaint::text::TextWrapping,
+    text::{LayoutJob, LayoutSection},
+    Align, Id, Response, Ui,
+};
+use unicode_bidi::{bidi_class, BidiClass, BidiInfo, Level};
+
+use crate::{config::Direction, i18n::system_language};
+
+/// Where `apply` keeps whether bubbles are mirrored in egui's memory
+const MIRRORED: &str = "mirrored";
+
+/// Languages written right to left, by their code in a locale like `he_IL`
+const RTL_LANGUAGES: [&str; 7] = ["ar", "fa", "he", "iw", "ps", "ur", "yi"];
+
+/// Whether `text` has any Hebrew, Arabic or other right-to-left letters
+pub fn has_rtl(text: &str) -> bool {
+    text.chars()
+        .any(|c| matches!(bidi_class(c), BidiClass::R | BidiClass::AL))
+}
+
+/// Whether `text` reads right to left, going by its first letter that has a
+/// direction
+pub fn is_rtl(text: &str) -> bool {
+    text.chars()
+        .find_map(|c| match bidi_class(c) {
+            BidiClass::L => Some(false),
+            BidiClass::R | BidiClass::AL => Some(true),
+            _ => None,
+        })
+        .unwrap_or(false)
+}
+
+/// Whether the system's language is written right to left
+fn locale_is_rtl() -> bool {
+    system_language().is_some_and(|language| RTL_LANGUAGES.contains(&language))
+}
+
+/// Swap which side of the chat my messages and everyone else's go on, if
+/// `direction` asks for it or the system's language is right to left
+pub fn apply(ctx: &egui::Context, direction: Direction) {
+    let mirrored = match direction {
+        Direction::Auto => locale_is_rtl(),
+        Direction::Ltr => false,
+        Direction::Rtl => true,
+    };
+    ctx.data().insert_temp(Id::new(MIRRORED), mirrored);
+}
+
+/// Whether my messages go on the left and everyone else's on the right, as
+/// last set by `apply`
+pub fn mirrored(ui: &Ui) -> bool {
+    ui.data().get_temp(Id::new(MIRRORED)).unwrap_or(false)
+}
+
+/// A label for `job`, which egui would lay out left to right as it's stored,
+/// with its Arabic letters joined up and each line put in the order it's
+/// read: wrapped first, then reordered line by line, and lined up on the
+/// right if it reads right to left
+pub fn label(ui: &mut Ui, job: LayoutJob) -> Response {
+    let LayoutJob { text, sections, .. } = job;
+
+    // each character, with the section of the job it's in
+    let mut chars = Vec::new();
+    for (i, section) in sections.iter().enumerate() {
+        chars.extend(text[section.byte_range.clone()].chars().map(|c| (c, i)));
+    }
+    let chars = shape(&chars);
+
+    let mut logical = LayoutJob {
+        wrap: TextWrapping {
+            max_width: ui.available_width(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    append(&mut logical, &chars, &sections);
+    let rows: Vec<_> = ui
+        .fonts()
+        .layout_job(logical)
+        .rows
+        .iter()
+        .map(|row| (row.char_count_excluding_newline(), row.ends_with_newline))
+        .collect();
+
+    let rtl = is_rtl(&text);
+    let base = if rtl { Level::rtl() } else { Level::ltr() };
+    let mut visual = Vec::with_capacity(chars.len() + rows.len());
+    let mut start = 0usize;
+    for (n, (count, newline)) in rows.into_iter().enumerate() {
+        if n > 0 {
+            let section = chars.get(start.saturating_sub(1)).map_or(0, |&(_, s)| s);
+            visual.push(('\n', section));
+        }
+        let end = (start + count).min(chars.len());
+        visual.extend(reorder(&chars[start..end], base));
+        start = end + newline as usize;
+    }
+
+    let mut job = LayoutJob {
+        halign: if rtl { Align::RIGHT } else { Align::LEFT },
+        ..Default::default()
+    };
+    append(&mut job, &visual, &sections);
+
+    let galley = ui.fonts().layout_job(job);
+    ui.label(galley)
+}
+
+/// Add `chars` to `job`, each in the format of the section of `sections`
+/// it's paired with
+fn append(job: &mut LayoutJob, chars: &[(char, usize)], sections: &[LayoutSection]) {
+    let mut run = String::new();
+    let mut section = None;
+    for &(c, i) in chars {
+        if section != Some(i) {
+            if let Some(section) = section {
+                job.append(&run, 0.0, sections[section].format.clone());
+                run.clear();
+            }
+            section = Some(i);
+        }
+        run.push(c);
+    }
+    if let Some(section) = section {
+        job.append(&run, 0.0, sections[section].format.clone());
+    }
+}
+
+/// One line of `chars` in the order they're shown, left to right, given the
+/// direction of the paragraph it's from
+fn reorder(chars: &[(char, usize)], base: Level) -> Vec<(char, usize)> {
+    let text: String = chars.iter().map(|(c, _)| c).collect();
+    let info = BidiInfo::new(&text, Some(base));
+    let Some(paragraph) = info.paragraphs.first() else {
+        return chars.to_vec();
+    };
+    let (levels, runs) = info.visual_runs(paragraph, paragraph.range.clone());
+
+    // runs are byte ranges of `text`, which is what these start at
+    let starts: Vec<usize> = text.char_indices().map(|(b, _)| b).collect();
+    let mut visual = Vec::with_capacity(chars.len());
+    for run in runs {
+        let from = starts.partition_point(|&b| b < run.start);
+        let to = starts.partition_point(|&b| b < run.end);
+        let run_chars = &chars[from..to];
+        if levels[run.start].is_rtl() {
+            visual.extend(run_chars.iter().rev().map(|&(c, s)| (mirror(c), s)));
+        } else {
+            visual.extend_from_slice(run_chars);
+        }
+    }
+
+    visual
+}
+
+/// The mirror image of a bracket, for brackets in right-to-left text
+fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        '«' => '»',
+        '»' => '«',
+        c => c,
+    }
+}
+
+/// Which sides an Arabic letter joins the letters next to it on
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Joining {
+    /// The letters before and after it
+    Dual,
+    /// Only the letter before it
+    Right,
+    /// Neither
+    Non,
+}
+
+/// Arabic letters, each with its isolated form in Arabic Presentation
+/// Forms-B. The final form follows it, and for letters that join both ways
+/// the initial and medial forms follow that.
+const LETTERS: [(char, u32, Joining); 36] = [
+    ('\u{621}', 0xfe80, Joining::Non),
+    ('\u{622}', 0xfe81, Joining::Right),
+    ('\u{623}', 0xfe83, Joining::Right),
+    ('\u{624}', 0xfe85, Joining::Right),
+    ('\u{625}', 0xfe87, Joining::Right),
+    ('\u{626}', 0xfe89, Joining::Dual),
+    ('\u{627}', 0xfe8d, Joining::Right),
+    ('\u{628}', 0xfe8f, Joining::Dual),
+    ('\u{629}', 0xfe93, Joining::Right),
+    ('\u{62a}', 0xfe95, Joining::Dual),
+    ('\u{62b}', 0xfe99, Joining::Dual),
+    ('\u{62c}', 0xfe9d, Joining::Dual),
+    ('\u{62d}', 0xfea1, Joining::Dual),
+    ('\u{62e}', 0xfea5, Joining::Dual),
+    ('\u{62f}', 0xfea9, Joining::Right),
+    ('\u{630}', 0xfeab, Joining::Right),
+    ('\u{631}', 0xfead, Joining::Right),
+    ('\u{632}', 0xfeaf, Joining::Right),
+    ('\u{633}', 0xfeb1, Joining::Dual),
+    ('\u{634}', 0xfeb5, Joining::Dual),
+    ('\u{635}', 0xfeb9, Joining::Dual),
+    ('\u{636}', 0xfebd, Joining::Dual),
+    ('\u{637}', 0xfec1, Joining::Dual),
+    ('\u{638}', 0xfec5, Joining::Dual),
+    ('\u{639}', 0xfec9, Joining::Dual),
+    ('\u{63a}', 0xfecd, Joining::Dual),
+    ('\u{641}', 0xfed1, Joining::Dual),
+    ('\u{642}', 0xfed5, Joining::Dual),
+    ('\u{643}', 0xfed9, Joining::Dual),
+    ('\u{644}', 0xfedd, Joining::Dual),
+    ('\u{645}', 0xfee1, Joining::Dual),
+    ('\u{646}', 0xfee5, Joining::Dual),
+    ('\u{647}', 0xfee9, Joining::Dual),
+    ('\u{648}', 0xfeed, Joining::Right),
+    ('\u{649}', 0xfeef, Joining::Right),
+    ('\u{64a}', 0xfef1, Joining::Dual),
+];
+
+const LAM: char = '\u{644}';
+const TATWEEL: char = '\u{640}';
+
+/// Lam followed by one of these alefs is written as a single ligature,
+/// whose isolated form is given; its final form follows it
+const LAM_ALEF: [(char, u32); 4] = [
+    ('\u{622}', 0xfef5),
+    ('\u{623}', 0xfef7),
+    ('\u{625}', 0xfef9),
+    ('\u{627}', 0xfefb),
+];
+
+fn letter(c: char) -> Option<(u32, Joining)> {
+    LETTERS
+        .binary_search_by_key(&c, |&(letter, ..)| letter)
+        .ok()
+        .map(|i| (LETTERS[i].1, LETTERS[i].2))
+}
+
+fn joining(c: char) -> Joining {
+    match letter(c) {
+        Some((_, joining)) => joining,
+        None if c == TATWEEL => Joining::Dual,
+        None => Joining::Non,
+    }
+}
+
+/// Vowel marks, which sit on a letter without getting in the way of it
+/// joining the next one
+fn is_transparent(c: char) -> bool {
+    matches!(c, '\u{64b}'..='\u{65f}' | '\u{670}')
+}
+
+/// `chars` with each Arabic letter swapped for the form it takes between
+/// the letters either side of it, since egui draws every letter on its own
+fn shape(chars: &[(char, usize)]) -> Vec<(char, usize)> {
+    // the nearest letter either side of `i`, skipping vowel marks
+    let before = |i: usize| chars[..i].iter().rev().find(|(c, _)| !is_transparent(*c));
+    let after = |i: usize| chars[i + 1..].iter().find(|(c, _)| !is_transparent(*c));
+
+    let mut shaped = Vec::with_capacity(chars.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let (c, section) = chars[i];
+        let joins_before = before(i).is_some_and(|&(b, _)| joining(b) == Joining::Dual)
+            && joining(c) != Joining::Non;
+
+        let alef = chars
+            .get(i + 1)
+            .and_then(|&(next, _)| LAM_ALEF.iter().find(|(alef, _)| *alef == next));
+        if let (LAM, Some(&(_, isolated))) = (c, alef) {
+            let form = isolated + joins_before as u32;
+            shaped.push((char::from_u32(form).unwrap_or(c), section));
+            i += 2;
+            continue;
+        }
+
+        let form = letter(c).map(|(isolated, kind)| {
+            let joins_after =
+                kind == Joining::Dual && after(i).is_some_and(|&(a, _)| joining(a) != Joining::Non);
+            match (joins_before, joins_after) {
+                (false, false) => isolated,
+                (true, false) => isolated + 1,
+                (false, true) => isolated + 2,
+                (true, true) => isolated + 3,
+            }
+        });
+        shaped.push((form.and_then(char::from_u32).unwrap_or(c), section));
+        i += 1;
+    }
+
+    shaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Vec<(char, usize)> {
+        text.chars().map(|c| (c, 0)).collect()
+    }
+
+    fn text(chars: &[(char, usize)]) -> String {
+        chars.iter().map(|(c, _)| c).collect()
+    }
+
+    #[test]
+    fn directions() {
+        assert!(!has_rtl("hello 123"));
+        assert!(has_rtl("hello שלום"));
+        assert!(is_rtl("123 שלום hello"));
+        assert!(!is_rtl("hello שלום"));
+        assert!(!is_rtl("123 !"));
+    }
+
+    #[test]
+    fn reorders_lines() {
+        assert_eq!(text(&reorder(&chars("abc אבג"), Level::ltr())), "abc גבא");
+        assert_eq!(
+            text(&reorder(&chars("שלום (abc)"), Level::rtl())),
+            "(abc) םולש"
+        );
+        assert_eq!(text(&reorder(&chars("(שלום)"), Level::rtl())), "(םולש)");
+        assert_eq!(text(&reorder(&[], Level::rtl())), "");
+    }
+
+    #[test]
+    fn letters_are_sorted() {
+        // `letter` binary searches them
+        assert!(LETTERS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn shapes_arabic() {
+        // beh, yeh and teh: initial, medial and final
+        assert_eq!(
+            text(&shape(&chars("\u{628}\u{64a}\u{62a}"))),
+            "\u{fe91}\u{fef4}\u{fe96}"
+        );
+        // seen, then lam alef as one final ligature, then an isolated meem
+        assert_eq!(
+            text(&shape(&chars("\u{633}\u{644}\u{627}\u{645}"))),
+            "\u{feb3}\u{fefc}\u{fee1}"
+        );
+        // a vowel mark doesn't stop beh joining teh
+        assert_eq!(
+            text(&shape(&chars("\u{628}\u{64e}\u{62a}"))),
+            "\u{fe91}\u{64e}\u{fe96}"
+        );
+        // nor does anything happen to text that isn't Arabic
+        assert_eq!(text(&shape(&chars("abc ب"))), "abc \u{fe8f}");
+    }
+}
@@ -0,0 +1,106 @@
+use egui::{
+    epaint::{tessellator::path, Mesh},
+    Color32, Pos2, Rect, Rounding, Shape,
+};
+
+/// Corner radius of a message bubble; a bubble a line high is a pill
+pub const ROUNDING: f32 = 14.0;
+
+/// Space left inside a bubble around what's in it
+pub const MARGIN: egui::Vec2 = egui::vec2(10.0, 6.0);
+
+/// Extra space above the first of a run of messages from the same sender
+pub const CLUSTER_SPACING: f32 = 6.0;
+
+/// How much lighter the top of a bubble is than its bottom
+const SHEEN: f32 = 0.08;
+
+/// Which bottom corner of a bubble its tail comes out of
+#[derive(Clone, Copy)]
+pub enum Tail {
+    Left,
+    Right,
+}
+
+/// Paint for the background of a bubble filling `rect`: `color`, a little
+/// lighter towards the top, with a tail on the `tail` side for the last of a
+/// run of messages
+pub fn background(rect: Rect, color: Color32, tail: Option<Tail>) -> Shape {
+    let mut outline = Vec::new();
+    path::rounded_rectangle(&mut outline, rect, Rounding::same(ROUNDING));
+
+    let top = lighten(color, SHEEN);
+    let shade = |pos: Pos2| {
+        let t = ((pos.y - rect.top()) / rect.height().max(1.0)).clamp(0.0, 1.0);
+        mix(top, color, t)
+    };
+
+    let mut mesh = Mesh::default();
+    fan(&mut mesh, rect.center(), &outline, shade);
+
+    if let Some(tail) = tail {
+        // mirrored for the left, so both curl away from the bubble
+        let side = match tail {
+            Tail::Left => -1.0,
+            Tail::Right => 1.0,
+        };
+        let corner = match tail {
+            Tail::Left => rect.left_bottom(),
+            Tail::Right => rect.right_bottom(),
+        };
+        let at = |x: f32, y: f32| corner + egui::vec2(side * x, y);
+
+        let tip = at(5.0, 0.0);
+        let mut points = Vec::new();
+        // down the bubble's side, curving out to the tip...
+        points.extend(quadratic(at(0.0, -ROUNDING), at(0.0, -2.0), tip));
+        // ...and back in along the bottom
+        points.extend(quadratic(tip, at(-4.0, 0.0), at(-8.0, -3.0)));
+        fan(&mut mesh, at(-6.0, -ROUNDING / 2.0), &points, |_| color);
+    }
+
+    Shape::mesh(mesh)
+}
+
+/// Fill the polygon `outline`, which every point of can be seen from
+/// `center`, as triangles fanning out from `center`
+fn fan(mesh: &mut Mesh, center: Pos2, outline: &[Pos2], shade: impl Fn(Pos2) -> Color32) {
+    let first = mesh.vertices.len() as u32;
+    mesh.colored_vertex(center, shade(center));
+    for pos in outline {
+        mesh.colored_vertex(*pos, shade(*pos));
+    }
+
+    let n = outline.len() as u32;
+    for i in 0..n {
+        mesh.add_triangle(first, first + 1 + i, first + 1 + (i + 1) % n);
+    }
+}
+
+/// Points along the quadratic curve from `from` to `to`, pulled towards
+/// `control`
+fn quadratic(from: Pos2, control: Pos2, to: Pos2) -> impl Iterator<Item = Pos2> {
+    const STEPS: usize = 6;
+    (0..=STEPS).map(move |i| {
+        let t = i as f32 / STEPS as f32;
+        let a = from + (control - from) * t;
+        let b = control + (to - control) * t;
+        a + (b - a) * t
+    })
+}
+
+/// `color` a fraction `amount` of the way to white
+fn lighten(color: Color32, amount: f32) -> Color32 {
+    mix(color, Color32::WHITE, amount)
+}
+
+/// The colour a fraction `t` of the way from `a` to `b`
+fn mix(a: Color32, b: Color32, t: f32) -> Color32 {
+    let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color32::from_rgba_premultiplied(
+        channel(a.r(), b.r()),
+        channel(a.g(), b.g()),
+        channel(a.b(), b.b()),
+        channel(a.a(), b.a()),
+    )
+}
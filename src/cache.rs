@@ -0,0 +1,370 @@
+use std::path::{Path, PathBuf};
+
+use eyre::Result;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    SqlitePool,
+};
+
+use chrono::prelude::*;
+
+use crate::{activity::Activity, search::fold, Chat};
+
+/// Open (creating if needed) the side cache database for the source database
+/// `db`, read from `database_file`. Anything derived from `chat.db` (search
+/// indexes etc.) lives here so that the source file itself is only ever read
+/// from.
+pub async fn open(db: &SqlitePool, database_file: &str) -> Result<SqlitePool> {
+    let dir = dir();
+    std::fs::create_dir_all(&dir)?;
+
+    let options = SqliteConnectOptions::new()
+        .filename(dir.join(file_name(db, database_file).await?))
+        .create_if_missing(true);
+
+    let pool = pool_options().connect_with(options).await?;
+
+    Ok(pool)
+}
+
+/// Options for a pool of connections to a cache database, registering the
+/// functions its queries use on each connection
+pub fn pool_options() -> SqlitePoolOptions {
+    SqlitePoolOptions::new().after_connect(|conn, _meta| {
+        Box::pin(async move {
+            let mut locked = conn.lock_handle().await?;
+
+            // SAFETY: the handle is locked away from sqlx's worker thread
+            unsafe { fold::register(locked.as_raw_handle().as_ptr()) };
+
+            Ok(())
+        })
+    })
+}
+
+/// Where cached files (databases, converted images) are kept
+pub fn dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("imessage-viewer")
+}
+
+/// Where a converted copy of `source` (e.g. a HEIC photo as JPEG) is kept,
+/// in a subdirectory named after the kind of conversion
+pub fn converted_path(kind: &str, source: &Path, extension: &str) -> PathBuf {
+    let hash = stable_hash(source.to_string_lossy().as_bytes());
+
+    dir().join(kind).join(format!("{hash:016x}.{extension}"))
+}
+
+/// FNV-1a of `bytes`, for names that have to come out the same on every run,
+/// which std's hasher doesn't promise between releases
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Where the thumbnail of an image attachment is kept, by its GUID
+pub fn thumbnail_path(guid: &str) -> PathBuf {
+    // GUIDs are letters, digits and dashes, but don't trust that for a path
+    let name: String = guid
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    dir().join("thumbnails").join(format!("{name}.png"))
+}
+
+/// One cache file per source database, named after where it is and the GUID
+/// of its first message, which stays the same as messages are added. That
+/// still leaves a backup restored over the same path, or a copy that has since
+/// gone its own way, sharing a cache with the original: what's cached is
+/// checked against the database before it's used, see
+/// [`crate::search::build_index`] and [`crate::summary::load`].
+async fn file_name(db: &SqlitePool, database_file: &str) -> Result<String> {
+    let first: Option<(String,)> =
+        sqlx::query_as("SELECT guid from message order by ROWID limit 1;")
+            .fetch_optional(db)
+            .await?;
+    let path = std::fs::canonicalize(database_file)
+        .unwrap_or_else(|_| PathBuf::from(database_file))
+        .to_string_lossy()
+        .into_owned();
+    let key = match first {
+        Some((guid,)) => format!("{path}\n{guid}"),
+        None => path,
+    };
+
+    Ok(format!("{:016x}.db", stable_hash(key.as_bytes())))
+}
+
+/// The chat list as of the last run, most recently active first
+pub async fn load_chats(cache: &SqlitePool) -> Result<Vec<Chat>> {
+    create_chat_summary(cache).await?;
+
+    let chats = sqlx::query_as::<_, (String, String, String, i64, i64, String)>(
+        r#"
+            SELECT
+                chat_identifier, display_name, participants, last_active, message_count,
+                preview
+            from chat_summary
+            order by last_active desc
+            ;
+        "#,
+    )
+    .fetch_all(cache)
+    .await?
+    .into_iter()
+    .map(
+        |(name, display_name, participants, last_active, message_count, preview)| Chat {
+            name,
+            display_name,
+            participants: participants
+                .split(' ')
+                .filter(|handle| !handle.is_empty())
+                .map(String::from)
+                .collect(),
+            last_active: Utc.timestamp(last_active, 0),
+            message_count,
+            preview,
+        },
+    )
+    .collect();
+
+    Ok(chats)
+}
+
+/// The newest message ROWID and the number of messages there were when the
+/// chat list was last saved, if it has been
+pub async fn summarized(cache: &SqlitePool) -> Result<Option<(i64, i64)>> {
+    create_chat_summary(cache).await?;
+
+    let state = sqlx::query_as("SELECT newest, messages from chat_summary_state;")
+        .fetch_optional(cache)
+        .await?;
+
+    Ok(state)
+}
+
+/// Replace the saved chat list, for [`load_chats`] to show on the next run
+/// and [`crate::summary::load`] to add newer messages to. `newest` and
+/// `messages` are as for [`summarized`].
+pub async fn save_chats(
+    cache: &SqlitePool,
+    chats: &[Chat],
+    newest: i64,
+    messages: i64,
+) -> Result<()> {
+    create_chat_summary(cache).await?;
+
+    let mut tx = cache.begin().await?;
+    sqlx::query("DELETE FROM chat_summary;")
+        .execute(&mut tx)
+        .await?;
+    for chat in chats {
+        sqlx::query(
+            r#"
+                INSERT INTO chat_summary (
+                    chat_identifier, display_name, participants, last_active,
+                    message_count, preview
+                )
+                values ($1, $2, $3, $4, $5, $6)
+                ;
+            "#,
+        )
+        .bind(&chat.name)
+        .bind(&chat.display_name)
+        .bind(chat.participants.join(" "))
+        .bind(chat.last_active.timestamp())
+        .bind(chat.message_count)
+        .bind(&chat.preview)
+        .execute(&mut tx)
+        .await?;
+    }
+    sqlx::query("DELETE FROM chat_summary_state;")
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("INSERT INTO chat_summary_state (newest, messages) values ($1, $2);")
+        .bind(newest)
+        .bind(messages)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn create_chat_summary(cache: &SqlitePool) -> Result<()> {
+    sqlx::query(
+        r#"
+            DROP TABLE IF EXISTS chat_list;
+            CREATE TABLE IF NOT EXISTS chat_summary (
+                chat_identifier TEXT PRIMARY KEY,
+                display_name TEXT NOT NULL,
+                participants TEXT NOT NULL,
+                last_active INTEGER NOT NULL,
+                message_count INTEGER NOT NULL,
+                preview TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS chat_summary_state (
+                newest INTEGER NOT NULL,
+                messages INTEGER NOT NULL
+            )
+            ;
+        "#,
+    )
+    .execute(cache)
+    .await?;
+
+    Ok(())
+}
+
+/// Sparkline counts saved by [`save_activity`], if they were counted when
+/// the newest message had ROWID `newest`
+pub async fn load_activity(cache: &SqlitePool, newest: i64) -> Result<Option<Activity>> {
+    create_chat_activity(cache).await?;
+
+    let rows: Vec<(String, Vec<u8>)> =
+        sqlx::query_as("SELECT chat_identifier, counts from chat_activity where newest = $1;")
+            .bind(newest)
+            .fetch_all(cache)
+            .await?;
+    if rows.is_empty() {
+        return Ok(None);
+    }
+
+    let activity = rows
+        .into_iter()
+        .map(|(chat, counts)| {
+            let counts = counts
+                .chunks_exact(4)
+                .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .collect();
+            (chat, counts)
+        })
+        .collect();
+
+    Ok(Some(activity))
+}
+
+/// Replace the saved sparkline counts
+pub async fn save_activity(cache: &SqlitePool, newest: i64, activity: &Activity) -> Result<()> {
+    create_chat_activity(cache).await?;
+
+    let mut tx = cache.begin().await?;
+    sqlx::query("DELETE FROM chat_activity;")
+        .execute(&mut tx)
+        .await?;
+    for (chat, counts) in activity {
+        let counts: Vec<u8> = counts.iter().flat_map(|c| c.to_le_bytes()).collect();
+        sqlx::query(
+            "INSERT INTO chat_activity (chat_identifier, newest, counts) values ($1, $2, $3);",
+        )
+        .bind(chat)
+        .bind(newest)
+        .bind(counts)
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn create_chat_activity(cache: &SqlitePool) -> Result<()> {
+    sqlx::query(
+        r#"
+            CREATE TABLE IF NOT EXISTS chat_activity (
+                chat_identifier TEXT PRIMARY KEY,
+                newest INTEGER NOT NULL,
+                counts BLOB NOT NULL
+            )
+            ;
+        "#,
+    )
+    .execute(cache)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// An in-memory database with `guids` as its messages, in order
+    async fn database(guids: &[&str]) -> SqlitePool {
+        let db = test_util::memory().await;
+        sqlx::query("CREATE TABLE message (ROWID INTEGER PRIMARY KEY, guid TEXT NOT NULL);")
+            .execute(&db)
+            .await
+            .unwrap();
+        for guid in guids {
+            sqlx::query("INSERT INTO message (guid) values ($1);")
+                .bind(guid)
+                .execute(&db)
+                .await
+                .unwrap();
+        }
+
+        db
+    }
+
+    #[test]
+    fn stable_hash_is_fnv_1a() {
+        assert_eq!(stable_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(stable_hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(stable_hash(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[tokio::test]
+    async fn named_after_the_path_and_first_message() {
+        let original = database(&["first", "second"]).await;
+        let grown = database(&["first", "second", "third"]).await;
+        let other = database(&["another"]).await;
+
+        let name = file_name(&original, "a/chat.db").await.unwrap();
+        assert_eq!(file_name(&grown, "a/chat.db").await.unwrap(), name);
+        assert_ne!(file_name(&other, "a/chat.db").await.unwrap(), name);
+        // e.g. a backup kept next to the database it was copied from
+        assert_ne!(file_name(&original, "b/chat.db").await.unwrap(), name);
+
+        // nothing to go by but where it is
+        let empty = database(&[]).await;
+        assert_eq!(
+            file_name(&empty, "a/chat.db").await.unwrap(),
+            file_name(&empty, "a/chat.db").await.unwrap()
+        );
+        assert_ne!(
+            file_name(&empty, "a/chat.db").await.unwrap(),
+            file_name(&empty, "b/chat.db").await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn chats_without_participants_load_back_without_any() {
+        let cache = database(&[]).await;
+        let chat = Chat {
+            name: "chat1".to_owned(),
+            display_name: String::new(),
+            participants: Vec::new(),
+            last_active: Utc.timestamp(1_600_000_000, 0),
+            message_count: 3,
+            preview: "hi".to_owned(),
+        };
+        save_chats(&cache, &[chat], 3, 3).await.unwrap();
+
+        let chats = load_chats(&cache).await.unwrap();
+        assert_eq!(chats.len(), 1);
+        assert!(chats[0].participants.is_empty());
+        assert_eq!(summarized(&cache).await.unwrap(), Some((3, 3)));
+    }
+}
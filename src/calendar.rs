@@ -0,0 +1,67 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use egui::Ui;
+
+use crate::{a11y, i18n};
+
+/// Width of a day's button in the month grid
+const DAY_WIDTH: f32 = 28.0;
+
+/// The first day of the month `date` is in
+pub fn month_of(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap_or(date)
+}
+
+/// The first day of the month after (`step` of 1) or before (-1) `month`
+fn step_month(month: NaiveDate, step: i32) -> NaiveDate {
+    let index = month.year() * 12 + month.month0() as i32 + step;
+    NaiveDate::from_ymd_opt(index.div_euclid(12), index.rem_euclid(12) as u32 + 1, 1)
+        .unwrap_or(month)
+}
+
+/// A month of days to pick from, starting on Monday, with arrows to move to
+/// the months either side. `month` is the first day of the month shown.
+/// Returns the day clicked, if any.
+pub fn show(ui: &mut Ui, month: &mut NaiveDate, selected: Option<NaiveDate>) -> Option<NaiveDate> {
+    let mut picked = None;
+
+    ui.horizontal(|ui| {
+        if a11y::icon(ui.small_button("◀"), i18n::t("previous month")).clicked() {
+            *month = step_month(*month, -1);
+        }
+        ui.add_sized(
+            [7.0 * DAY_WIDTH - 60.0, ui.spacing().interact_size.y],
+            egui::Label::new(i18n::month_year(month)),
+        );
+        if a11y::icon(ui.small_button("▶"), i18n::t("next month")).clicked() {
+            *month = step_month(*month, 1);
+        }
+    });
+
+    egui::Grid::new(("calendar", *month))
+        .min_col_width(DAY_WIDTH)
+        .max_col_width(DAY_WIDTH)
+        .spacing([0.0, 2.0])
+        .show(ui, |ui| {
+            for name in ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"] {
+                ui.weak(i18n::t(name));
+            }
+            ui.end_row();
+
+            for _ in 0..month.weekday().num_days_from_monday() {
+                ui.label("");
+            }
+            let mut day = *month;
+            while day.month() == month.month() {
+                let text = day.day().to_string();
+                if ui.selectable_label(selected == Some(day), text).clicked() {
+                    picked = Some(day);
+                }
+                if day.weekday().num_days_from_monday() == 6 {
+                    ui.end_row();
+                }
+                day += Duration::days(1);
+            }
+        });
+
+    picked
+}
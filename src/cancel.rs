@@ -0,0 +1,41 @@
+use std::future::Future;
+
+use eyre::Result;
+use tokio::sync::watch;
+
+/// Kept for as long as what's being loaded for a chat is wanted: dropping it,
+/// by opening another chat in its place or closing its tab, stops its loads
+/// that are still running, so they don't hold up the chat that's open now
+pub struct Loads(watch::Sender<()>);
+
+/// What a load stopped by dropping its [`Loads`] fails with
+#[derive(Debug, thiserror::Error)]
+#[error("cancelled")]
+pub struct Cancelled;
+
+impl Loads {
+    pub fn new() -> Self {
+        Self(watch::channel(()).0)
+    }
+
+    /// `f`, unless these loads are dropped before it's done, in which case
+    /// it's dropped too and fails with [`Cancelled`]
+    pub fn run<T>(&self, f: impl Future<Output = Result<T>>) -> impl Future<Output = Result<T>> {
+        let mut dropped = self.0.subscribe();
+
+        async move {
+            tokio::select! {
+                result = f => result,
+                // nothing is ever sent, so this only ends once the sender is
+                // gone
+                _ = async { while dropped.changed().await.is_ok() {} } => Err(Cancelled.into()),
+            }
+        }
+    }
+}
+
+impl Default for Loads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
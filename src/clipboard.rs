@@ -0,0 +1,124 @@
+use egui::Context;
+
+use crate::{
+    config::{ClipboardConfig, Identity},
+    i18n, Message,
+};
+
+/// Copying messages to the clipboard, which can ask before anything sensitive
+/// leaves the app
+#[derive(Default)]
+pub struct Clipboard {
+    /// A copy waiting to be confirmed
+    pending: Option<Pending>,
+}
+
+struct Pending {
+    text: String,
+    messages: usize,
+    attachments: usize,
+}
+
+impl Clipboard {
+    /// Copy `messages` as text, one per line like `[2021-03-02 14:05] Alice:
+    /// text`, with their attachments listed by name. If `settings` asks for
+    /// it, copies of more than a few messages or of anything with an
+    /// attachment wait for confirmation first.
+    pub fn copy(
+        &mut self,
+        ctx: &Context,
+        settings: &ClipboardConfig,
+        identity: &Identity,
+        messages: &[Message],
+    ) {
+        let mut text = String::new();
+        for message in messages {
+            if let Some(event) = &message.event {
+                text.push_str(&format!(
+                    "[{}] {}\n",
+                    message.date.format("%Y-%m-%d %H:%M"),
+                    event.describe(identity, &message.sender),
+                ));
+                continue;
+            }
+            text.push_str(&format!(
+                "[{}] {}: {}\n",
+                message.date.format("%Y-%m-%d %H:%M"),
+                identity.name(&message.sender),
+                message.text,
+            ));
+            if let Some(transcription) = &message.transcription {
+                text.push_str(&format!("    [voice message: {transcription}]\n"));
+            }
+            for name in &message.attachments {
+                text.push_str(&format!("    [attachment: {name}]\n"));
+            }
+        }
+
+        let pending = Pending {
+            text,
+            messages: messages.len(),
+            attachments: messages.iter().map(|m| m.attachments.len()).sum(),
+        };
+
+        let sensitive = pending.messages > settings.max_messages || pending.attachments > 0;
+        if settings.confirm && sensitive {
+            self.pending = Some(pending);
+        } else {
+            ctx.output().copied_text = pending.text;
+        }
+    }
+
+    /// Window asking to confirm a copy, if one is waiting
+    pub fn show(&mut self, ctx: &Context) {
+        let pending = match &self.pending {
+            Some(pending) => pending,
+            None => return,
+        };
+
+        let mut confirmed = None;
+        egui::Window::new(i18n::t("copy to clipboard?"))
+            .id(egui::Id::new("copy to clipboard?"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                let messages = ("messages", &pending.messages as &dyn std::fmt::Display);
+                ui.label(if pending.attachments > 0 {
+                    i18n::tf(
+                        "This copies {messages} messages and {attachments} attachments out of \
+                         the viewer.",
+                        &[messages, ("attachments", &pending.attachments)],
+                    )
+                } else {
+                    i18n::tf(
+                        "This copies {messages} messages out of the viewer.",
+                        &[messages],
+                    )
+                });
+                ui.label(i18n::t(
+                    "Anything on the clipboard can be pasted into any app, and clipboard \
+                     managers or sync may keep a copy.",
+                ));
+
+                ui.horizontal(|ui| {
+                    if ui.button(i18n::t("copy")).clicked() {
+                        confirmed = Some(true);
+                    }
+                    if ui.button(i18n::t("cancel")).clicked() {
+                        confirmed = Some(false);
+                    }
+                });
+            });
+
+        match confirmed {
+            Some(true) => {
+                if let Some(pending) = self.pending.take() {
+                    ctx.output().copied_text = pending.text;
+                }
+            }
+            Some(false) => self.pending = None,
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,672 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{people, Sender};
+
+/// User settings, stored as TOML in the platform config directory
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub me: Identity,
+    /// Searches pinned to the sidebar
+    pub saved_searches: Vec<SavedSearch>,
+    /// Colour/emoji labels for organising the sidebar, keyed by chat
+    /// identifier
+    pub labels: HashMap<String, ChatLabel>,
+    /// Named exports, run from the sidebar or with
+    /// `export --profile <name>`
+    pub export_profiles: Vec<ExportProfile>,
+    pub search: SearchConfig,
+    /// How HEIC photos are converted when exported
+    pub heic_export: HeicConversion,
+    pub clipboard: ClipboardConfig,
+    pub layout: LayoutConfig,
+    pub appearance: AppearanceConfig,
+    pub accessibility: AccessibilityConfig,
+    /// Automated messages (verification codes, delivery notices...) to
+    /// collapse or hide
+    pub ignore_rules: Vec<IgnoreRule>,
+    /// Dates marking the start of a new chapter of a conversation, keyed by
+    /// chat identifier
+    pub chapters: HashMap<String, Vec<Chapter>>,
+    /// People who changed their number or email, so their old and new
+    /// handles are treated as one person
+    pub handle_changes: Vec<HandleChange>,
+    /// People whose handles are merged by hand, on top of those merged from
+    /// Contacts. A handle listed here is taken out of whoever Contacts has
+    /// it under, so a person with a single handle keeps it apart.
+    pub people: Vec<Person>,
+    /// Contacts database to merge people's handles from, instead of the
+    /// macOS address book
+    pub contacts_database: Option<PathBuf>,
+    /// People read from Contacts at startup
+    #[serde(skip)]
+    pub contacts: Vec<Person>,
+    /// Set when the file couldn't be read and these are the defaults used in
+    /// its place, so that it isn't saved over
+    #[serde(skip)]
+    pub unreadable: bool,
+    /// Names given to handles and chats, by database (its full path), then by
+    /// handle or chat identifier
+    pub aliases: HashMap<String, HashMap<String, String>>,
+    pub notifications: NotificationConfig,
+    pub prefetch: PrefetchConfig,
+}
+
+/// Who "me" is, for labelling both sides of a conversation
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Identity {
+    /// Name shown for messages sent by you
+    pub display_name: String,
+    /// Picture to show alongside your messages
+    pub avatar: Option<PathBuf>,
+    /// Handles (phone numbers, emails) that are you. Only needed when viewing
+    /// a database that belongs to someone else - messages from these handles
+    /// are treated as yours, and the database owner's own messages are
+    /// labelled `owner_name` instead.
+    pub handles: Vec<String>,
+    pub owner_name: String,
+    /// Names given to handles and chats in the open database, from the
+    /// config's `aliases`
+    #[serde(skip)]
+    pub aliases: HashMap<String, String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub name: String,
+    /// The search box text, including any filters
+    pub query: String,
+    #[serde(default)]
+    pub regex: bool,
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatLabel {
+    pub color: Option<[u8; 3]>,
+    pub emoji: String,
+}
+
+impl ChatLabel {
+    pub fn is_empty(&self) -> bool {
+        self.color.is_none() && self.emoji.trim().is_empty()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ExportProfile {
+    pub name: String,
+    pub format: ExportFormat,
+    /// Chat identifiers to export, or every chat if empty
+    #[serde(default)]
+    pub chats: Vec<String>,
+    pub after: Option<NaiveDate>,
+    pub before: Option<NaiveDate>,
+    /// Patterns for text to blank out, e.g. phone numbers or a name
+    #[serde(default)]
+    pub redact: Vec<String>,
+    /// Directory to write to, defaults to the downloads directory
+    pub destination: Option<PathBuf>,
+}
+
+/// Messages matching `sender` and `text` (whichever are given) in `chat`, or
+/// in every chat if it's left out
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IgnoreRule {
+    pub chat: Option<String>,
+    /// Pattern for the sender's phone number, email or short code
+    pub sender: Option<String>,
+    /// Pattern for the text, e.g. `verification code is \d+`
+    pub text: Option<String>,
+    #[serde(default)]
+    pub action: IgnoreAction,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IgnoreAction {
+    /// Shrink to a line that can be clicked to show the message
+    #[default]
+    Collapse,
+    Hide,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportFormat {
+    /// Plain text, one message per line
+    Transcript,
+    /// HTML contact sheet of the photos
+    Photos,
+    /// iCalendar file of notable days
+    Timeline,
+    /// Every message, written as it's read so huge chats fit in memory
+    Json,
+    Csv,
+    Html,
+}
+
+/// How text is compared when searching. Changing these rebuilds the search
+/// index on the next launch.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Match "cafe" against "café"
+    pub ignore_diacritics: bool,
+    pub normalization: Normalization,
+    pub case_folding: CaseFolding,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalization {
+    /// Canonical equivalence only (precomposed vs combining accents)
+    Nfc,
+    /// Also treat compatibility forms alike, e.g. "ﬁ" and "fi", full width
+    /// and normal letters
+    Nfkc,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseFolding {
+    /// Lowercase each character
+    Simple,
+    /// Also fold characters that lowercase to more than one, e.g. "ß" and
+    /// "ss"
+    Full,
+    /// Full folding with Turkish and Azerbaijani dotted/dotless i
+    Turkic,
+}
+
+/// Where the panels go, kept so that the layout survives a restart
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    /// Width of the chat list while it's docked
+    pub sidebar_width: f32,
+    /// Chat list folded away to a single button
+    pub sidebar_collapsed: bool,
+    /// Leave chats from handles that aren't in Contacts out of the chat list,
+    /// instead of listing them under "unknown senders"
+    pub hide_unknown_senders: bool,
+    /// Panels shown in a floating window instead of docked
+    pub detached: Vec<Panel>,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            sidebar_width: 280.0,
+            sidebar_collapsed: false,
+            hide_unknown_senders: false,
+            detached: Vec::new(),
+        }
+    }
+}
+
+impl LayoutConfig {
+    pub fn is_detached(&self, panel: Panel) -> bool {
+        self.detached.contains(&panel)
+    }
+
+    pub fn set_detached(&mut self, panel: Panel, detached: bool) {
+        self.detached.retain(|p| *p != panel);
+        if detached {
+            self.detached.push(panel);
+        }
+    }
+}
+
+/// How the app looks
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppearanceConfig {
+    pub theme: Theme,
+    /// Colours to use in place of the theme's, in either theme
+    pub colors: ThemeColors,
+    /// Size of body text in points; the rest of the text scales with it
+    pub text_size: f32,
+    /// How much bigger than normal everything is drawn, 1 being normal
+    pub zoom: f32,
+    /// Which way round the chat goes: with right to left, my messages are
+    /// on the left and everyone else's on the right
+    pub direction: Direction,
+    /// Which language the app's text is in
+    pub language: Language,
+}
+
+impl Default for AppearanceConfig {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            colors: ThemeColors::default(),
+            text_size: 14.0,
+            zoom: 1.0,
+            direction: Direction::default(),
+            language: Language::default(),
+        }
+    }
+}
+
+/// Colours picked to replace the theme's; any left out keep the theme's own
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeColors {
+    /// My iMessages
+    pub me: Option<[u8; 3]>,
+    /// My text messages
+    pub sms: Option<[u8; 3]>,
+    /// Everyone else's messages
+    pub them: Option<[u8; 3]>,
+    /// Behind the panels and windows
+    pub background: Option<[u8; 3]>,
+    /// Selections and links
+    pub accent: Option<[u8; 3]>,
+}
+
+/// Light or dark colours, or whichever the system was using at startup
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+/// Help for people using assistive technology
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessibilityConfig {
+    /// Speak the chat, message or button that's focused or clicked through
+    /// the system's text-to-speech. Off unless asked for; it isn't a screen
+    /// reader, which egui 0.19 has no way to talk to.
+    #[serde(alias = "screen_reader")]
+    pub speak_focus: bool,
+    /// Black or white backgrounds, outlined bubbles and thicker outlines
+    pub high_contrast: bool,
+}
+
+/// Left to right or right to left, or whichever the system's language is
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    Auto,
+    Ltr,
+    Rtl,
+}
+
+/// A language the app's text can be shown in, or whichever the system's
+/// language is, falling back to English
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    Auto,
+    En,
+    De,
+    Es,
+}
+
+/// The parts of the window that can be moved around
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Panel {
+    Chats,
+    Media,
+    SearchResults,
+}
+
+/// When to ask before copying messages to the clipboard, e.g. on a shared
+/// machine
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipboardConfig {
+    /// Ask before copying more than `max_messages` messages, or any
+    /// attachment
+    pub confirm: bool,
+    pub max_messages: usize,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            confirm: false,
+            max_messages: 20,
+        }
+    }
+}
+
+/// Desktop notifications for new messages, shown in `--watch` mode
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// Chats to notify about, by chat identifier
+    pub chats: Vec<String>,
+    /// Include who sent the message and what it says, rather than only
+    /// which chat it's in
+    pub preview: bool,
+}
+
+/// Loading chats before they're opened, so they open straight away
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrefetchConfig {
+    /// How many of the most recently active chats to load the newest
+    /// messages of once the chat list is read; 0 turns it off
+    pub chats: usize,
+}
+
+impl Default for PrefetchConfig {
+    fn default() -> Self {
+        Self { chats: 5 }
+    }
+}
+
+/// What HEIC photos become, for people without an Apple device
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeicConversion {
+    pub format: HeicFormat,
+    /// JPEG quality, 1-100
+    pub quality: u8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeicFormat {
+    Jpeg,
+    /// Lossless, but much bigger
+    Png,
+}
+
+impl HeicFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+        }
+    }
+}
+
+impl Default for HeicConversion {
+    fn default() -> Self {
+        Self {
+            format: HeicFormat::Jpeg,
+            quality: 90,
+        }
+    }
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            ignore_diacritics: true,
+            normalization: Normalization::Nfkc,
+            case_folding: CaseFolding::Full,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Chapter {
+    pub date: NaiveDate,
+    pub title: String,
+}
+
+/// Someone who used `from` until `date`, and `to` from then on. Without a
+/// date, they use both at once (a phone number and an email), and `from` is
+/// shown as part of `to`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HandleChange {
+    pub from: String,
+    pub to: String,
+    pub date: Option<NaiveDate>,
+}
+
+/// Someone with all the handles they message from
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Person {
+    #[serde(default)]
+    pub name: String,
+    pub handles: Vec<String>,
+    /// Their picture from Contacts
+    #[serde(skip)]
+    pub photo: Option<PathBuf>,
+}
+
+impl Default for Identity {
+    fn default() -> Self {
+        Self {
+            display_name: "Me".into(),
+            avatar: None,
+            handles: Vec::new(),
+            owner_name: "Owner".into(),
+            aliases: HashMap::new(),
+        }
+    }
+}
+
+impl Identity {
+    /// True if the database belongs to someone other than the viewer
+    fn foreign_archive(&self) -> bool {
+        self.handles.iter().any(|h| !h.trim().is_empty())
+    }
+
+    pub fn is_me(&self, sender: &Sender) -> bool {
+        match sender {
+            Sender::Me => !self.foreign_archive(),
+            Sender::SomeoneElse(handle) => self.handles.iter().any(|h| h.trim() == handle),
+        }
+    }
+
+    /// Name to label a sender with
+    pub fn name<'a>(&'a self, sender: &'a Sender) -> &'a str {
+        if self.is_me(sender) {
+            return &self.display_name;
+        }
+
+        match sender {
+            Sender::Me => &self.owner_name,
+            Sender::SomeoneElse(handle) => self.aliases.get(handle).unwrap_or(handle),
+        }
+    }
+
+    /// Name to title a chat with: the name I gave it, or else the one its
+    /// members gave it, or else its identifier
+    pub fn chat_name<'a>(&'a self, chat_id: &'a str, display_name: &'a str) -> &'a str {
+        match self.aliases.get(chat_id) {
+            Some(alias) => alias,
+            None if !display_name.is_empty() => display_name,
+            None => chat_id,
+        }
+    }
+}
+
+impl Config {
+    /// Every handle change, plus each merged person's other handles joining
+    /// their first
+    pub fn changes(&self) -> Vec<HandleChange> {
+        let mut changes = self.handle_changes.clone();
+        for person in people::merged(&self.contacts, &self.people) {
+            if let Some((first, rest)) = person.handles.split_first() {
+                changes.extend(rest.iter().map(|handle| HandleChange {
+                    from: handle.clone(),
+                    to: first.clone(),
+                    date: None,
+                }));
+            }
+        }
+        changes
+    }
+
+    /// Whether `handle` is someone in Contacts, merged by hand or given a
+    /// name. Without any of those to go by, everyone is.
+    pub fn is_known(&self, handle: &str) -> bool {
+        if self.contacts.is_empty() && self.people.is_empty() && self.me.aliases.is_empty() {
+            return true;
+        }
+
+        self.me.aliases.contains_key(handle)
+            || self
+                .contacts
+                .iter()
+                .chain(&self.people)
+                .any(|person| person.handles.iter().any(|h| h == handle))
+    }
+
+    /// The picture Contacts has for whoever uses `handle`
+    pub fn photo(&self, handle: &str) -> Option<&Path> {
+        self.contacts
+            .iter()
+            .filter(|person| person.handles.iter().any(|h| h == handle))
+            .find_map(|person| person.photo.as_deref())
+    }
+
+    pub fn path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_default()
+            .join("imessage-viewer")
+            .join("config.toml")
+    }
+
+    /// Load the config file, falling back to defaults if there isn't one yet
+    pub fn load() -> Result<Self> {
+        match std::fs::read_to_string(Self::path()) {
+            Ok(raw) => Ok(toml::from_str(&raw)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if self.unreadable {
+            return Err(eyre!(
+                "not saving over {}, which couldn't be read; fix it and restart",
+                path.display()
+            ));
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        std::fs::write(path, self.to_toml()?)?;
+
+        Ok(())
+    }
+
+    fn to_toml(&self) -> Result<String> {
+        // going through a `Value` puts plain values ahead of tables, which
+        // TOML requires and serializing the structs directly doesn't do
+        let value = toml::Value::try_from(self)?;
+        Ok(toml::to_string_pretty(&value)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(handles: &[&str]) -> Identity {
+        Identity {
+            display_name: "Lily".to_owned(),
+            handles: handles.iter().map(|h| h.to_string()).collect(),
+            owner_name: "Mom".to_owned(),
+            aliases: HashMap::from([("+15555550142".to_owned(), "Sam".to_owned())]),
+            ..Identity::default()
+        }
+    }
+
+    #[test]
+    fn native_archive_labels_my_messages_as_me() {
+        let identity = identity(&[]);
+        let sam = Sender::SomeoneElse("+15555550142".to_owned());
+        let stranger = Sender::SomeoneElse("+15555550199".to_owned());
+
+        assert!(identity.is_me(&Sender::Me));
+        assert!(!identity.is_me(&sam));
+        assert_eq!(identity.name(&Sender::Me), "Lily");
+        assert_eq!(identity.name(&sam), "Sam");
+        assert_eq!(identity.name(&stranger), "+15555550199");
+    }
+
+    #[test]
+    fn foreign_archive_labels_the_owner_and_my_handles() {
+        let identity = identity(&[" +15555550100 "]);
+        let me = Sender::SomeoneElse("+15555550100".to_owned());
+        let sam = Sender::SomeoneElse("+15555550142".to_owned());
+
+        assert!(!identity.is_me(&Sender::Me));
+        assert!(identity.is_me(&me));
+        assert!(!identity.is_me(&sam));
+        assert_eq!(identity.name(&Sender::Me), "Mom");
+        assert_eq!(identity.name(&me), "Lily");
+        assert_eq!(identity.name(&sam), "Sam");
+    }
+
+    #[test]
+    fn saved_config_loads_back() {
+        let mut config = Config::default();
+        config.me.display_name = "Lily".to_owned();
+        config.me.handles.push("+15555550100".to_owned());
+        config.saved_searches.push(SavedSearch {
+            name: "ski trip photos".to_owned(),
+            query: "has:image ski".to_owned(),
+            regex: false,
+        });
+        config.labels.insert(
+            "+15555550100".to_owned(),
+            ChatLabel {
+                color: Some([235, 77, 75]),
+                emoji: "❤️".to_owned(),
+            },
+        );
+        config.ignore_rules.push(IgnoreRule {
+            text: Some(r"verification code is \d+".to_owned()),
+            ..IgnoreRule::default()
+        });
+        config.handle_changes.push(HandleChange {
+            from: "+15555550199".to_owned(),
+            to: "+15555550100".to_owned(),
+            date: NaiveDate::from_ymd_opt(2020, 8, 1),
+        });
+        config.contacts_database = Some(PathBuf::from("/tmp/AddressBook.abcddb"));
+        config.notifications.chats.push("+15555550100".to_owned());
+
+        let saved = config.to_toml().unwrap();
+        let loaded: Config = toml::from_str(&saved).unwrap();
+
+        assert_eq!(loaded.to_toml().unwrap(), saved);
+        assert_eq!(loaded.me.display_name, "Lily");
+        assert_eq!(loaded.saved_searches[0].query, "has:image ski");
+        assert!(loaded.labels == config.labels);
+        assert!(loaded.ignore_rules == config.ignore_rules);
+        assert_eq!(loaded.handle_changes, config.handle_changes);
+        assert_eq!(loaded.contacts_database, config.contacts_database);
+    }
+
+    #[test]
+    fn speaking_focus_keeps_its_old_name() {
+        let config: Config = toml::from_str("[accessibility]\nscreen_reader = true\n").unwrap();
+        assert!(config.accessibility.speak_focus);
+    }
+
+    #[test]
+    fn blank_handles_keep_the_archive_native() {
+        let identity = identity(&["", "  "]);
+
+        assert!(identity.is_me(&Sender::Me));
+        assert_eq!(identity.name(&Sender::Me), "Lily");
+    }
+}
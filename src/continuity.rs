@@ -0,0 +1,219 @@
+use chrono::prelude::*;
+use egui::Ui;
+
+use crate::{config::HandleChange, i18n};
+
+/// The handle someone with `handle` uses now, following any changes of
+/// number or email
+pub fn current<'a>(changes: &'a [HandleChange], handle: &'a str) -> &'a str {
+    let mut handle = handle;
+    // at most one step per change, in case the config loops back on itself
+    for _ in 0..changes.len() {
+        match changes.iter().find(|change| change.from == handle) {
+            Some(change) => handle = &change.to,
+            None => break,
+        }
+    }
+    handle
+}
+
+/// Every handle the person with `handle` has used, starting with `handle`
+/// itself. These are the chats shown and exported as one.
+pub fn handles(changes: &[HandleChange], handle: &str) -> Vec<String> {
+    let mut handles = vec![handle.to_owned()];
+    let mut i = 0;
+    while let Some(handle) = handles.get(i).cloned() {
+        for change in changes {
+            let other = if change.from == handle {
+                &change.to
+            } else if change.to == handle {
+                &change.from
+            } else {
+                continue;
+            };
+            if !handles.contains(other) {
+                handles.push(other.clone());
+            }
+        }
+        i += 1;
+    }
+    handles
+}
+
+/// The handle `handle` is shown as, following merges of handles used at the
+/// same time but not changes of number
+pub fn person<'a>(changes: &'a [HandleChange], handle: &'a str) -> &'a str {
+    let mut handle = handle;
+    for _ in 0..changes.len() {
+        match changes
+            .iter()
+            .find(|change| change.date.is_none() && change.from == handle)
+        {
+            Some(change) => handle = &change.to,
+            None => break,
+        }
+    }
+    handle
+}
+
+/// The changes between the handles of the person with `handle`, oldest
+/// first. Merged handles aren't changes, and are left out.
+pub fn history(changes: &[HandleChange], handle: &str) -> Vec<HandleChange> {
+    let handles = handles(changes, handle);
+    let mut history: Vec<_> = changes
+        .iter()
+        .filter(|change| change.date.is_some() && handles.contains(&change.from))
+        .cloned()
+        .collect();
+    history.sort_by_key(|change| change.date);
+    history
+}
+
+/// The changes that happened after `previous` and by `next`, to note
+/// between two messages
+pub fn between(
+    history: &[HandleChange],
+    previous: Option<DateTime<Utc>>,
+    next: DateTime<Utc>,
+) -> impl Iterator<Item = &HandleChange> {
+    history.iter().filter(move |change| {
+        change.date.is_some_and(|day| {
+            previous.is_none_or(|previous| previous.date_naive() < day) && day <= next.date_naive()
+        })
+    })
+}
+
+/// How a change is noted in chats and exports
+pub fn notice(change: &HandleChange) -> String {
+    match change.date {
+        Some(date) => i18n::tf(
+            "{from} became {to} on {date}",
+            &[
+                ("from", &change.from),
+                ("to", &change.to),
+                ("date", &date.format("%Y-%m-%d")),
+            ],
+        ),
+        None => i18n::tf(
+            "{from} is the same person as {to}",
+            &[("from", &change.from), ("to", &change.to)],
+        ),
+    }
+}
+
+/// Contents of a one on one chat's right-click menu for saying the person
+/// changed number. `draft` is the new handle and date as typed. Returns true
+/// if `changes` changed.
+pub fn edit(
+    ui: &mut Ui,
+    chat_id: &str,
+    changes: &mut Vec<HandleChange>,
+    draft: &mut (String, String),
+) -> bool {
+    let mut changed = false;
+
+    ui.label(i18n::t("changed number or email to"));
+    let (to, date) = draft;
+    ui.add(egui::TextEdit::singleline(to).hint_text(i18n::t("new handle")));
+    ui.add(egui::TextEdit::singleline(date).hint_text(i18n::t("on YYYY-MM-DD")));
+
+    let to = to.trim();
+    let date = date.trim();
+    match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        Ok(date) if !to.is_empty() && to != chat_id && ui.button(i18n::t("save")).clicked() => {
+            changes.retain(|change| change.from != chat_id);
+            changes.push(HandleChange {
+                from: chat_id.to_owned(),
+                to: to.to_owned(),
+                date: Some(date),
+            });
+            *draft = Default::default();
+            changed = true;
+            ui.close_menu();
+        }
+        Err(_) if !date.is_empty() => {
+            ui.weak(i18n::t("dates should look like 2020-12-31"));
+        }
+        _ => {}
+    }
+
+    let count = changes.len();
+    changes.retain(|change| {
+        let involved = change.from == chat_id || change.to == chat_id;
+        !involved
+            || !ui
+                .small_button(i18n::tf("forget: {change}", &[("change", &notice(change))]))
+                .clicked()
+    });
+    changed |= changes.len() != count;
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(from: &str, to: &str, date: Option<(i32, u32, u32)>) -> HandleChange {
+        HandleChange {
+            from: from.to_owned(),
+            to: to.to_owned(),
+            date: date.and_then(|(y, m, d)| NaiveDate::from_ymd_opt(y, m, d)),
+        }
+    }
+
+    /// `a` became `b` in 2020 and `c` in 2022, and `d` is an email `c` uses
+    /// alongside it
+    fn changes() -> Vec<HandleChange> {
+        vec![
+            change("b", "c", Some((2022, 6, 1))),
+            change("a", "b", Some((2020, 1, 1))),
+            change("d", "c", None),
+        ]
+    }
+
+    #[test]
+    fn follows_changes() {
+        let changes = changes();
+        assert_eq!(current(&changes, "a"), "c");
+        assert_eq!(current(&changes, "c"), "c");
+        assert_eq!(person(&changes, "d"), "c");
+        assert_eq!(person(&changes, "a"), "a");
+        assert_eq!(handles(&changes, "b"), ["b", "c", "a", "d"]);
+        assert_eq!(handles(&changes, "z"), ["z"]);
+    }
+
+    #[test]
+    fn stops_at_loops() {
+        let changes = vec![
+            change("a", "b", Some((2020, 1, 1))),
+            change("b", "a", Some((2021, 1, 1))),
+        ];
+        assert_eq!(current(&changes, "a"), "a");
+        assert_eq!(handles(&changes, "a"), ["a", "b"]);
+    }
+
+    #[test]
+    fn orders_history() {
+        let changes = changes();
+        let history = history(&changes, "d");
+        assert_eq!(history, vec![changes[1].clone(), changes[0].clone()]);
+
+        let at = |y, m, d| Utc.ymd(y, m, d).and_hms(12, 0, 0);
+        let noted = |previous, next| {
+            between(&history, previous, next)
+                .map(|change| change.to.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(noted(None, at(2023, 1, 1)), ["b", "c"]);
+        assert_eq!(noted(Some(at(2020, 1, 1)), at(2022, 6, 1)), ["c"]);
+        assert!(noted(Some(at(2022, 6, 1)), at(2023, 1, 1)).is_empty());
+    }
+
+    #[test]
+    fn notices() {
+        let changes = changes();
+        assert_eq!(notice(&changes[1]), "a became b on 2020-01-01");
+        assert_eq!(notice(&changes[2]), "d is the same person as c");
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use eyre::Result;
+use sqlx::SqlitePool;
+
+use crate::sql::Sql;
+
+/// Most message ids bound into one query, well under SQLite's limit on
+/// parameters
+const IDS_PER_QUERY: usize = 500;
+
+/// How many messages were sent in `chats` between `start` and `end` (in
+/// Apple's format), leaving out group events, counted by SQLite
+pub async fn messages(
+    db: &SqlitePool,
+    chats: &[String],
+    (start, end): (i64, i64),
+) -> Result<usize> {
+    let mut sql = Sql::new(
+        r#"
+            SELECT
+                count(*)
+            from message m
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            where m.item_type = 0
+                and m.date >= "#,
+    );
+    sql.bind(start)
+        .push(" and m.date < ")
+        .bind(end)
+        .push(" and c.chat_identifier in (");
+    for (i, chat) in chats.iter().enumerate() {
+        if i > 0 {
+            sql.push(", ");
+        }
+        sql.bind(chat.clone());
+    }
+    sql.push(");");
+
+    let (count,) = sqlx::query_as_with::<_, (i64,), _>(&sql.text, sql.args)
+        .fetch_one(db)
+        .await?;
+
+    Ok(count as usize)
+}
+
+/// How many attachments of each mime type were sent in `chats` between
+/// `start` and `end` (in Apple's format), counted by SQLite rather than read
+/// out one by one
+pub async fn attachment_types(
+    db: &SqlitePool,
+    chats: &[String],
+    (start, end): (i64, i64),
+) -> Result<HashMap<String, usize>> {
+    let mut sql = Sql::new(
+        r#"
+            SELECT
+                coalesce(a.mime_type, ''), count(*)
+            from attachment a
+            join message_attachment_join maj
+                on a.ROWID = maj.attachment_id
+            join message m
+                on maj.message_id = m.ROWID
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            where a.filename is not null
+                and m.date >= "#,
+    );
+    sql.bind(start)
+        .push(" and m.date < ")
+        .bind(end)
+        .push(" and c.chat_identifier in (");
+    for (i, chat) in chats.iter().enumerate() {
+        if i > 0 {
+            sql.push(", ");
+        }
+        sql.bind(chat.clone());
+    }
+    sql.push(") group by 1;");
+
+    types(db, sql).await
+}
+
+/// How many attachments of each mime type were sent in `messages`
+pub async fn message_attachment_types(
+    db: &SqlitePool,
+    messages: &[i64],
+) -> Result<HashMap<String, usize>> {
+    let mut counts = HashMap::new();
+
+    for ids in messages.chunks(IDS_PER_QUERY) {
+        let mut sql = Sql::new(
+            r#"
+                SELECT
+                    coalesce(a.mime_type, ''), count(*)
+                from attachment a
+                join message_attachment_join maj
+                    on a.ROWID = maj.attachment_id
+                where a.filename is not null
+                    and maj.message_id in ("#,
+        );
+        for (i, id) in ids.iter().enumerate() {
+            if i > 0 {
+                sql.push(", ");
+            }
+            sql.bind(*id);
+        }
+        sql.push(") group by 1;");
+
+        for (mime_type, count) in types(db, sql).await? {
+            *counts.entry(mime_type).or_default() += count;
+        }
+    }
+
+    Ok(counts)
+}
+
+/// Run `sql`, which selects a mime type and a count
+async fn types(db: &SqlitePool, sql: Sql) -> Result<HashMap<String, usize>> {
+    let rows = sqlx::query_as_with::<_, (String, i64), _>(&sql.text, sql.args)
+        .fetch_all(db)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(mime_type, count)| (mime_type, count as usize))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// Two chats: `a` with three messages, one of them a group event and
+    /// one with a photo and a video, and `b` with one message and a photo
+    async fn scratch_db() -> SqlitePool {
+        let db = test_util::memory().await;
+        sqlx::query(
+            r#"
+                CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, chat_identifier TEXT);
+                CREATE TABLE message (ROWID INTEGER PRIMARY KEY, date INTEGER, item_type INTEGER);
+                CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+                CREATE TABLE attachment (ROWID INTEGER PRIMARY KEY, filename TEXT, mime_type TEXT);
+                CREATE TABLE message_attachment_join (message_id INTEGER, attachment_id INTEGER);
+
+                INSERT INTO chat VALUES (1, 'a'), (2, 'b');
+                INSERT INTO message VALUES (1, 10, 0), (2, 20, 0), (3, 30, 1), (4, 40, 0);
+                INSERT INTO chat_message_join VALUES (1, 1), (1, 2), (1, 3), (2, 4);
+                INSERT INTO attachment VALUES
+                    (1, 'a.jpg', 'image/jpeg'), (2, 'b.mov', 'video/quicktime'),
+                    (3, 'c.jpg', 'image/jpeg'), (4, NULL, 'image/jpeg');
+                INSERT INTO message_attachment_join VALUES (2, 1), (2, 2), (4, 3), (1, 4);
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        db
+    }
+
+    fn chats(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn counts_messages() {
+        let db = scratch_db().await;
+        let all = (i64::MIN, i64::MAX);
+
+        assert_eq!(messages(&db, &chats(&["a"]), all).await.unwrap(), 2);
+        assert_eq!(messages(&db, &chats(&["a", "b"]), all).await.unwrap(), 3);
+        assert_eq!(
+            messages(&db, &chats(&["a", "b"]), (20, 40)).await.unwrap(),
+            1
+        );
+        assert_eq!(messages(&db, &chats(&["c"]), all).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn counts_attachment_types() {
+        let db = scratch_db().await;
+
+        let types = attachment_types(&db, &chats(&["a", "b"]), (i64::MIN, i64::MAX))
+            .await
+            .unwrap();
+        assert_eq!(types.get("image/jpeg"), Some(&2));
+        assert_eq!(types.get("video/quicktime"), Some(&1));
+
+        let types = attachment_types(&db, &chats(&["b"]), (0, 40))
+            .await
+            .unwrap();
+        assert!(types.is_empty());
+
+        let types = message_attachment_types(&db, &[2]).await.unwrap();
+        assert_eq!(types.get("image/jpeg"), Some(&1));
+        assert_eq!(types.get("video/quicktime"), Some(&1));
+    }
+}
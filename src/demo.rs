@@ -0,0 +1,1216 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::prelude::*;
+use eyre::Result;
+use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
+
+use crate::{apple_time, audio, balloon, cache, payment, people};
+
+const SCHEMA: &str = r#"
+    CREATE TABLE handle (
+        ROWID INTEGER PRIMARY KEY AUTOINCREMENT,
+        id TEXT NOT NULL,
+        service TEXT NOT NULL
+    );
+    CREATE TABLE chat (
+        ROWID INTEGER PRIMARY KEY AUTOINCREMENT,
+        guid TEXT UNIQUE NOT NULL,
+        chat_identifier TEXT,
+        service_name TEXT,
+        display_name TEXT
+    );
+    CREATE TABLE message (
+        ROWID INTEGER PRIMARY KEY AUTOINCREMENT,
+        guid TEXT UNIQUE NOT NULL,
+        text TEXT,
+        handle_id INTEGER DEFAULT 0,
+        service TEXT,
+        date INTEGER,
+        is_from_me INTEGER DEFAULT 0,
+        cache_has_attachments INTEGER DEFAULT 0,
+        associated_message_guid TEXT,
+        associated_message_type INTEGER DEFAULT 0,
+        expressive_send_style_id TEXT,
+        balloon_bundle_id TEXT,
+        payload_data BLOB,
+        attributedBody BLOB,
+        item_type INTEGER DEFAULT 0,
+        other_handle INTEGER DEFAULT 0,
+        group_title TEXT,
+        group_action_type INTEGER DEFAULT 0
+    );
+    CREATE TABLE chat_message_join (
+        chat_id INTEGER REFERENCES chat (ROWID) ON DELETE CASCADE,
+        message_id INTEGER REFERENCES message (ROWID) ON DELETE CASCADE,
+        message_date INTEGER DEFAULT 0,
+        PRIMARY KEY (chat_id, message_id)
+    );
+    CREATE INDEX chat_message_join_idx_message_date_id_chat_id
+        ON chat_message_join (chat_id, message_date, message_id);
+    CREATE TABLE chat_handle_join (
+        chat_id INTEGER REFERENCES chat (ROWID) ON DELETE CASCADE,
+        handle_id INTEGER REFERENCES handle (ROWID) ON DELETE CASCADE,
+        UNIQUE(chat_id, handle_id)
+    );
+    CREATE TABLE attachment (
+        ROWID INTEGER PRIMARY KEY AUTOINCREMENT,
+        guid TEXT UNIQUE NOT NULL,
+        filename TEXT,
+        uti TEXT,
+        mime_type TEXT,
+        user_info BLOB,
+        transfer_name TEXT,
+        total_bytes INTEGER DEFAULT 0,
+        is_sticker INTEGER DEFAULT 0
+    );
+    CREATE TABLE message_attachment_join (
+        message_id INTEGER REFERENCES message (ROWID) ON DELETE CASCADE,
+        attachment_id INTEGER REFERENCES attachment (ROWID) ON DELETE CASCADE,
+        UNIQUE(message_id, attachment_id)
+    );
+"#;
+
+/// (id, service)
+const HANDLES: &[(&str, &str)] = &[
+    ("+15555550100", "iMessage"),
+    ("+15555550100", "SMS"),
+    ("alice@example.com", "iMessage"),
+    ("bob@example.com", "iMessage"),
+    ("+15555550123", "SMS"),
+    ("demo@example.com", "iMessage"),
+];
+
+/// (guid, chat_identifier, service, display name, indices into HANDLES)
+const CHATS: &[(&str, &str, &str, &str, &[usize])] = &[
+    (
+        "iMessage;-;+15555550100",
+        "+15555550100",
+        "iMessage",
+        "",
+        &[0],
+    ),
+    ("SMS;-;+15555550100", "+15555550100", "SMS", "", &[1]),
+    (
+        "iMessage;+;chat100",
+        "chat100",
+        "iMessage",
+        "Demo Ski Trip",
+        &[2, 3],
+    ),
+    ("SMS;-;+15555550123", "+15555550123", "SMS", "", &[4]),
+    (
+        "iMessage;-;demo@example.com",
+        "demo@example.com",
+        "iMessage",
+        "",
+        &[5],
+    ),
+];
+
+/// The address book: each contact's name, phone numbers and emails. The demo
+/// contact texts from both their number and their email.
+const CONTACTS: &[(&str, &str, &[&str], &[&str])] = &[
+    (
+        "Demo",
+        "Contact",
+        &["(555) 555-0100"],
+        &["demo@example.com"],
+    ),
+    ("Alice", "Example", &[], &["alice@example.com"]),
+];
+
+/// Contacts with a photo, by index in [`CONTACTS`], and the photo's hue
+const CONTACT_PHOTOS: &[(usize, f32)] = &[(1, 300.0)];
+
+enum Attachment {
+    /// A generated photo, tinted with this hue (0-360)
+    Photo(&'static str, f32),
+    /// A generated voice message with Apple's transcription
+    Voice(&'static str),
+    File(&'static str, &'static str),
+    /// A shared location: the pin's name, latitude and longitude
+    Location(&'static str, f64, f64),
+    /// A shared contact: their name, number and email
+    Contact(&'static str, &'static str, &'static str),
+    /// A preview of the link that's the message's text: its title, summary
+    /// and the hue of its image
+    Link(&'static str, &'static str, f32),
+    /// A sticker stuck onto the message at this index in [`messages`]
+    Sticker(usize),
+}
+
+/// A message: index into CHATS, sender (index into HANDLES, `None` for me),
+/// when it was sent, its text, any attachment, any send effect and whether
+/// it's from an iMessage app
+struct Demo {
+    chat: usize,
+    from: Option<usize>,
+    sent: (i32, u32, u32, u32, u32),
+    text: &'static str,
+    attachment: Option<Attachment>,
+    effect: Option<&'static str>,
+    balloon: Option<Balloon>,
+}
+
+/// A message from an iMessage app: its bundle ID (after the team ID) and
+/// the strings in its payload
+type Balloon = (&'static str, &'static [(&'static str, &'static str)]);
+
+/// Group events: index into CHATS, who did it (index into HANDLES, `None` for
+/// me), when, `item_type`, `group_action_type`, who it was done to (index into
+/// HANDLES) and the new name
+type Event = (
+    usize,
+    Option<usize>,
+    (i32, u32, u32, u32, u32),
+    i64,
+    i64,
+    Option<usize>,
+    Option<&'static str>,
+);
+
+const EVENTS: &[Event] = &[
+    (
+        2,
+        None,
+        (2021, 1, 7, 20, 0),
+        2,
+        0,
+        None,
+        Some("Demo Ski Trip"),
+    ),
+    (2, None, (2021, 1, 7, 20, 1), 1, 0, Some(3), None),
+    (2, Some(3), (2021, 1, 17, 10, 0), 3, 0, None, None),
+];
+
+/// @mentions: index into [`messages`], and each name mentioned in its text
+/// with the handle it mentions. `jamie@example.com` is me.
+const MENTIONS: &[(usize, &[(&str, &str)])] = &[(
+    16,
+    &[
+        ("Alice", "alice@example.com"),
+        ("Jamie", "jamie@example.com"),
+    ],
+)];
+
+/// Tapbacks: index into [`messages`] of the message reacted to, who reacted
+/// (index into HANDLES, `None` for me), when, `associated_message_type`
+/// (2000 and up to add a tapback, 3000 and up to take it off) and the text
+/// Messages shows for it on devices without tapbacks
+type Tapback = (
+    usize,
+    Option<usize>,
+    (i32, u32, u32, u32, u32),
+    i64,
+    &'static str,
+);
+
+const TAPBACKS: &[Tapback] = &[
+    (
+        12,
+        Some(2),
+        (2021, 1, 8, 9, 11),
+        2001,
+        "Liked “beach towel?? it's a ski trip”",
+    ),
+    (
+        12,
+        None,
+        (2021, 1, 8, 9, 12),
+        2003,
+        "Laughed at “beach towel?? it's a ski trip”",
+    ),
+    (
+        12,
+        Some(2),
+        (2021, 1, 8, 9, 13),
+        2003,
+        "Laughed at “beach towel?? it's a ski trip”",
+    ),
+    (
+        11,
+        Some(3),
+        (2021, 1, 8, 9, 11),
+        2000,
+        "Loved “I will bring the skis and the beach towel”",
+    ),
+    (
+        11,
+        Some(3),
+        (2021, 1, 8, 9, 14),
+        3000,
+        "Removed a heart from “I will bring the skis and the beach towel”",
+    ),
+];
+
+const LONG_MESSAGE: &str = "This is a deliberately long demo message, standing in for the \
+    pasted articles and rambling updates that real chats are full of. ";
+
+fn messages() -> Vec<Demo> {
+    use Attachment::*;
+
+    let msg = |chat, from, sent, text| Demo {
+        chat,
+        from,
+        sent,
+        text,
+        attachment: None,
+        effect: None,
+        balloon: None,
+    };
+    let with = |chat, from, sent, text, attachment| Demo {
+        chat,
+        from,
+        sent,
+        text,
+        attachment: Some(attachment),
+        effect: None,
+        balloon: None,
+    };
+
+    vec![
+        msg(
+            1,
+            Some(1),
+            (2019, 3, 2, 18, 4),
+            "hi! this is the demo contact, texting over SMS",
+        ),
+        msg(1, None, (2019, 3, 2, 18, 6), "hello demo contact 👋"),
+        msg(
+            0,
+            Some(0),
+            (2019, 6, 14, 19, 30),
+            "want to grab a café au lait tomorrow?",
+        ),
+        msg(0, None, (2019, 6, 14, 19, 32), "sure! the beach place?"),
+        with(
+            0,
+            Some(0),
+            (2019, 6, 15, 10, 5),
+            "",
+            Photo("beach.png", 200.0),
+        ),
+        msg(0, None, (2019, 6, 15, 10, 7), "what a view"),
+        with(0, Some(0), (2019, 6, 15, 10, 8), "", Sticker(5)),
+        msg(
+            1,
+            Some(1),
+            (2020, 1, 6, 8, 15),
+            "no data on this train, back to SMS 🙃",
+        ),
+        Demo {
+            chat: 0,
+            from: Some(0),
+            sent: (2020, 11, 20, 21, 0),
+            text: "CAFE tomorrow? same time",
+            attachment: None,
+            effect: Some("com.apple.MobileSMS.expressivesend.impact"),
+            balloon: None,
+        },
+        with(
+            0,
+            None,
+            (2020, 11, 20, 21, 3),
+            "",
+            Voice("remember to pack the avalanche beacon"),
+        ),
+        msg(2, Some(2), (2021, 1, 8, 9, 0), "who is bringing the skis"),
+        msg(
+            2,
+            None,
+            (2021, 1, 8, 9, 4),
+            "I will bring the skis and the beach towel",
+        ),
+        msg(
+            2,
+            Some(3),
+            (2021, 1, 8, 9, 10),
+            "beach towel?? it's a ski trip",
+        ),
+        Demo {
+            chat: 2,
+            from: Some(2),
+            sent: (2021, 1, 8, 9, 20),
+            text: "",
+            attachment: None,
+            effect: None,
+            balloon: Some((
+                payment::BUNDLE_ID,
+                &[
+                    ("ldtext", "Requested $40.00 with Apple Cash"),
+                    ("status", "Pending"),
+                ],
+            )),
+        },
+        Demo {
+            chat: 2,
+            from: None,
+            sent: (2021, 1, 8, 9, 25),
+            text: "",
+            attachment: None,
+            effect: None,
+            balloon: Some((
+                payment::BUNDLE_ID,
+                &[
+                    ("ldtext", "Sent $40.00 with Apple Cash"),
+                    ("status", "Completed"),
+                ],
+            )),
+        },
+        Demo {
+            chat: 2,
+            from: Some(3),
+            sent: (2021, 1, 8, 21, 0),
+            text: "",
+            attachment: None,
+            effect: None,
+            balloon: Some((
+                "com.gamerdelights.gamepigeon.ext",
+                &[("an", "GamePigeon"), ("caption", "Let's play 8 Ball!")],
+            )),
+        },
+        msg(
+            2,
+            Some(3),
+            (2021, 1, 9, 7, 30),
+            "Alice can you drive? Jamie has the roof rack",
+        ),
+        with(
+            2,
+            Some(3),
+            (2021, 1, 9, 7, 45),
+            "itinerary attached",
+            File("itinerary.txt", "text/plain"),
+        ),
+        with(
+            2,
+            Some(3),
+            (2021, 1, 9, 8, 0),
+            "https://www.example.com/snow-report",
+            Link(
+                "Snow Report: 40cm of Fresh Powder",
+                "Every lift is open this weekend after the biggest storm of the season. \
+                 Expect long lines at the gondola, and get up early for first tracks.",
+                190.0,
+            ),
+        ),
+        with(
+            2,
+            Some(2),
+            (2021, 1, 16, 15, 30),
+            "",
+            Photo("ski.png", 30.0),
+        ),
+        with(2, None, (2021, 1, 16, 15, 31), "", Photo("lift.png", 280.0)),
+        with(
+            2,
+            Some(3),
+            (2021, 1, 16, 16, 2),
+            "meet here for après-ski",
+            Location("Base Lodge", 39.6061, -106.3550),
+        ),
+        with(
+            2,
+            Some(2),
+            (2021, 1, 16, 16, 5),
+            "the instructor I told you about",
+            Contact("Sam Powder", "+1 (970) 555-0142", "sam@example.com"),
+        ),
+        msg(
+            4,
+            Some(5),
+            (2021, 3, 3, 19, 0),
+            "sending this from my laptop, same demo contact",
+        ),
+        msg(
+            3,
+            Some(4),
+            (2022, 5, 1, 12, 0),
+            "Your Demo verification code is 123456",
+        ),
+        Demo {
+            chat: 0,
+            from: Some(0),
+            sent: (2023, 2, 14, 8, 0),
+            text: "happy valentine's ❤️",
+            attachment: None,
+            effect: Some("com.apple.messages.effect.CKHeartEffect"),
+            balloon: None,
+        },
+        Demo {
+            chat: 0,
+            from: None,
+            sent: (2023, 2, 14, 8, 30),
+            text: "",
+            attachment: None,
+            effect: None,
+            balloon: None,
+        },
+    ]
+}
+
+/// `message.handle_id`: who sent it, or for messages I sent, who they went to
+/// in one on one chats and 0 in group chats
+fn handle_id(message: &Demo) -> i64 {
+    let participants = CHATS[message.chat].4;
+
+    match message.from {
+        Some(from) => from as i64 + 1,
+        None if participants.len() == 1 => participants[0] as i64 + 1,
+        None => 0,
+    }
+}
+
+/// Where `--demo` keeps its database
+pub fn default_path() -> PathBuf {
+    cache::dir().join("demo").join("chat.db")
+}
+
+/// Write a small, obviously fake message database to `path`, replacing
+/// anything there, with its attachments in an `Attachments` directory next
+/// to it, and an address book. It has a little of everything: one on one
+/// and group chats, the same person over iMessage and SMS and from their
+/// email, photos, a voice message, a file, a sticker, send effects,
+/// payments, a game, a link preview, a group being renamed and joined, a
+/// long message and accented text.
+pub async fn generate(path: &Path) -> Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let attachments = dir.join("Attachments");
+    fs::create_dir_all(&attachments)?;
+    let _ = fs::remove_file(path);
+
+    let db = SqlitePool::connect_with(
+        SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true),
+    )
+    .await?;
+    let mut tx = db.begin().await?;
+
+    sqlx::query(SCHEMA).execute(&mut tx).await?;
+
+    for (id, service) in HANDLES {
+        sqlx::query("INSERT INTO handle (id, service) values ($1, $2);")
+            .bind(id)
+            .bind(service)
+            .execute(&mut tx)
+            .await?;
+    }
+
+    for (i, (guid, identifier, service, display_name, handles)) in CHATS.iter().enumerate() {
+        sqlx::query(
+            r#"
+                INSERT INTO chat (ROWID, guid, chat_identifier, service_name, display_name)
+                values ($1, $2, $3, $4, $5)
+                ;
+            "#,
+        )
+        .bind(i as i64 + 1)
+        .bind(guid)
+        .bind(identifier)
+        .bind(service)
+        .bind(display_name)
+        .execute(&mut tx)
+        .await?;
+
+        for handle in *handles {
+            sqlx::query("INSERT INTO chat_handle_join (chat_id, handle_id) values ($1, $2);")
+                .bind(i as i64 + 1)
+                .bind(*handle as i64 + 1)
+                .execute(&mut tx)
+                .await?;
+        }
+    }
+
+    for (i, message) in messages().into_iter().enumerate() {
+        let (year, month, day, hour, minute) = message.sent;
+        let date = apple_time(Utc.ymd(year, month, day).and_hms(hour, minute, 0));
+
+        let mut text = message.text.to_owned();
+        if text.is_empty() && message.attachment.is_none() && message.balloon.is_none() {
+            text = LONG_MESSAGE.repeat(40);
+        }
+        // links are only their URL, with the preview as an attachment
+        let is_link = matches!(message.attachment, Some(Attachment::Link(..)));
+        if (message.attachment.is_some() || message.balloon.is_some()) && !is_link {
+            text.insert(0, '\u{fffc}');
+        }
+
+        // stickers are their own message, pointing at the one they're on
+        let (associated_guid, associated_type) = match message.attachment {
+            Some(Attachment::Sticker(target)) => {
+                (Some(format!("p:0/demo-message-{}", target + 1)), 1000)
+            }
+            _ => (None, 0),
+        };
+
+        let (bundle_id, payload) = match (message.balloon, &message.attachment) {
+            (Some((bundle_id, strings)), _) => (
+                Some(format!(
+                    "com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:{bundle_id}"
+                )),
+                Some(keyed_archive(strings)?),
+            ),
+            (None, Some(Attachment::Link(title, summary, _))) => (
+                Some(balloon::LINK_PREVIEW.to_owned()),
+                Some(link_archive(message.text, title, summary)?),
+            ),
+            _ => (None, None),
+        };
+
+        let message_id = i as i64 + 1;
+        sqlx::query(
+            r#"
+                INSERT INTO message (
+                    ROWID, guid, text, handle_id, service, date, is_from_me,
+                    cache_has_attachments, associated_message_guid, associated_message_type,
+                    expressive_send_style_id, balloon_bundle_id, payload_data
+                )
+                values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+                ;
+            "#,
+        )
+        .bind(message_id)
+        .bind(format!("demo-message-{message_id}"))
+        .bind(text)
+        .bind(handle_id(&message))
+        .bind(CHATS[message.chat].2)
+        .bind(date)
+        .bind(message.from.is_none())
+        .bind(message.attachment.is_some())
+        .bind(associated_guid)
+        .bind(associated_type)
+        .bind(message.effect)
+        .bind(bundle_id)
+        .bind(payload)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO chat_message_join (chat_id, message_id, message_date) values ($1, $2, $3);",
+        )
+        .bind(message.chat as i64 + 1)
+        .bind(message_id)
+        .bind(date)
+        .execute(&mut tx)
+        .await?;
+
+        let attachment = match message.attachment {
+            Some(attachment) => attachment,
+            None => continue,
+        };
+
+        let is_sticker = matches!(attachment, Attachment::Sticker(_));
+        let (name, uti, mime_type, user_info) = match attachment {
+            Attachment::Sticker(_) => {
+                write_sticker(&attachments.join("sticker.png"))?;
+                ("sticker.png", "public.png", Some("image/png"), None)
+            }
+            Attachment::Photo(name, hue) => {
+                write_photo(&attachments.join(name), hue)?;
+                (name, "public.png", Some("image/png"), None)
+            }
+            Attachment::Voice(transcription) => {
+                write_voice(&attachments.join("Audio Message.wav"))?;
+                let mut user_info = Vec::new();
+                plist::Value::Dictionary(
+                    [(
+                        "audio-transcription".to_owned(),
+                        plist::Value::from(transcription),
+                    )]
+                    .into_iter()
+                    .collect(),
+                )
+                .to_writer_binary(&mut user_info)?;
+                (
+                    "Audio Message.wav",
+                    "com.microsoft.waveform-audio",
+                    Some("audio/wav"),
+                    Some(user_info),
+                )
+            }
+            Attachment::File(name, mime_type) => {
+                fs::write(
+                    attachments.join(name),
+                    "Day 1: drive up, rent skis\nDay 2: ski\nDay 3: ski, drive home\n",
+                )?;
+                (name, "public.plain-text", Some(mime_type), None)
+            }
+            Attachment::Location(pin, lat, lng) => {
+                let name = "Shared Location.loc.vcf";
+                fs::write(attachments.join(name), location_card(pin, lat, lng))?;
+                (name, "public.vlocation", Some("text/x-vlocation"), None)
+            }
+            Attachment::Contact(contact, phone, email) => {
+                let name = "Contact.vcf";
+                fs::write(attachments.join(name), contact_card(contact, phone, email))?;
+                (name, "public.vcard", Some("text/vcard"), None)
+            }
+            Attachment::Link(.., hue) => {
+                // the image Messages fetched, saved without a file type
+                let name = "Link Preview.pluginPayloadAttachment";
+                write_photo(&attachments.join(name), hue)?;
+                (name, "dyn.age80q2pssvu1g8pftb4gn5xxrf106", None, None)
+            }
+        };
+
+        let file = attachments.join(name);
+        sqlx::query(
+            r#"
+                INSERT INTO attachment (
+                    guid, filename, uti, mime_type, user_info, transfer_name, total_bytes,
+                    is_sticker
+                )
+                values ($1, $2, $3, $4, $5, $6, $7, $8)
+                ;
+            "#,
+        )
+        .bind(format!("demo-attachment-{message_id}"))
+        .bind(file.to_string_lossy())
+        .bind(uti)
+        .bind(mime_type)
+        .bind(user_info)
+        .bind(name)
+        .bind(fs::metadata(&file)?.len() as i64)
+        .bind(is_sticker)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(
+            r#"
+                INSERT INTO message_attachment_join (message_id, attachment_id)
+                values ($1, last_insert_rowid())
+                ;
+            "#,
+        )
+        .bind(message_id)
+        .execute(&mut tx)
+        .await?;
+    }
+
+    for (index, mentions) in MENTIONS {
+        let text = messages()[*index].text;
+        sqlx::query("UPDATE message SET attributedBody = $1 WHERE ROWID = $2;")
+            .bind(attributed_body(text, mentions))
+            .bind(*index as i64 + 1)
+            .execute(&mut tx)
+            .await?;
+    }
+
+    let first_event = messages().len() as i64 + 1;
+    for (i, (chat, from, sent, item_type, action, other, title)) in EVENTS.iter().enumerate() {
+        let (year, month, day, hour, minute) = *sent;
+        let date = apple_time(Utc.ymd(year, month, day).and_hms(hour, minute, 0));
+        let message_id = first_event + i as i64;
+
+        sqlx::query(
+            r#"
+                INSERT INTO message (
+                    ROWID, guid, handle_id, service, date, is_from_me, item_type,
+                    group_action_type, other_handle, group_title
+                )
+                values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                ;
+            "#,
+        )
+        .bind(message_id)
+        .bind(format!("demo-message-{message_id}"))
+        .bind(from.map_or(0, |from| from as i64 + 1))
+        .bind(CHATS[*chat].2)
+        .bind(date)
+        .bind(from.is_none())
+        .bind(item_type)
+        .bind(action)
+        .bind(other.map_or(0, |other| other as i64 + 1))
+        .bind(title)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO chat_message_join (chat_id, message_id, message_date) values ($1, $2, $3);",
+        )
+        .bind(*chat as i64 + 1)
+        .bind(message_id)
+        .bind(date)
+        .execute(&mut tx)
+        .await?;
+    }
+
+    let first_tapback = first_event + EVENTS.len() as i64;
+    for (i, (target, from, sent, kind, text)) in TAPBACKS.iter().enumerate() {
+        let (year, month, day, hour, minute) = *sent;
+        let date = apple_time(Utc.ymd(year, month, day).and_hms(hour, minute, 0));
+        let message_id = first_tapback + i as i64;
+        let chat = messages()[*target].chat;
+
+        sqlx::query(
+            r#"
+                INSERT INTO message (
+                    ROWID, guid, text, handle_id, service, date, is_from_me,
+                    associated_message_guid, associated_message_type
+                )
+                values ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                ;
+            "#,
+        )
+        .bind(message_id)
+        .bind(format!("demo-message-{message_id}"))
+        .bind(text)
+        .bind(from.map_or(0, |from| from as i64 + 1))
+        .bind(CHATS[chat].2)
+        .bind(date)
+        .bind(from.is_none())
+        .bind(format!("p:0/demo-message-{}", target + 1))
+        .bind(kind)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO chat_message_join (chat_id, message_id, message_date) values ($1, $2, $3);",
+        )
+        .bind(chat as i64 + 1)
+        .bind(message_id)
+        .bind(date)
+        .execute(&mut tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    db.close().await;
+
+    write_address_book(&dir.join(people::ADDRESS_BOOK)).await?;
+
+    Ok(())
+}
+
+/// An address book with [`CONTACTS`] in it, laid out like the one macOS keeps
+async fn write_address_book(path: &Path) -> Result<()> {
+    let _ = fs::remove_file(path);
+    let db = SqlitePool::connect_with(
+        SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true),
+    )
+    .await?;
+    let mut tx = db.begin().await?;
+
+    sqlx::query(
+        r#"
+            CREATE TABLE ZABCDRECORD (
+                Z_PK INTEGER PRIMARY KEY,
+                ZFIRSTNAME VARCHAR,
+                ZLASTNAME VARCHAR,
+                ZORGANIZATION VARCHAR,
+                ZTHUMBNAILIMAGEDATA BLOB
+            );
+            CREATE TABLE ZABCDPHONENUMBER (
+                Z_PK INTEGER PRIMARY KEY,
+                ZOWNER INTEGER,
+                ZFULLNUMBER VARCHAR
+            );
+            CREATE TABLE ZABCDEMAILADDRESS (
+                Z_PK INTEGER PRIMARY KEY,
+                ZOWNER INTEGER,
+                ZADDRESS VARCHAR
+            );
+        "#,
+    )
+    .execute(&mut tx)
+    .await?;
+
+    for (i, (first, last, numbers, emails)) in CONTACTS.iter().enumerate() {
+        let owner = i as i64 + 1;
+        // small thumbnails are kept inline, after a marker byte
+        let thumbnail = match CONTACT_PHOTOS.iter().find(|(contact, _)| *contact == i) {
+            Some((_, hue)) => {
+                let mut png = std::io::Cursor::new(Vec::new());
+                image::DynamicImage::ImageRgb8(photo(96, 96, *hue))
+                    .write_to(&mut png, image::ImageOutputFormat::Png)?;
+                Some([&[1], png.get_ref().as_slice()].concat())
+            }
+            None => None,
+        };
+        sqlx::query(
+            r#"
+                INSERT INTO ZABCDRECORD (Z_PK, ZFIRSTNAME, ZLASTNAME, ZTHUMBNAILIMAGEDATA)
+                values ($1, $2, $3, $4)
+                ;
+            "#,
+        )
+        .bind(owner)
+        .bind(first)
+        .bind(last)
+        .bind(thumbnail)
+        .execute(&mut tx)
+        .await?;
+        for number in *numbers {
+            sqlx::query("INSERT INTO ZABCDPHONENUMBER (ZOWNER, ZFULLNUMBER) values ($1, $2);")
+                .bind(owner)
+                .bind(number)
+                .execute(&mut tx)
+                .await?;
+        }
+        for email in *emails {
+            sqlx::query("INSERT INTO ZABCDEMAILADDRESS (ZOWNER, ZADDRESS) values ($1, $2);")
+                .bind(owner)
+                .bind(email)
+                .execute(&mut tx)
+                .await?;
+        }
+    }
+
+    tx.commit().await?;
+    db.close().await;
+
+    Ok(())
+}
+
+/// `message.attributedBody`: `text` as an NSAttributedString in a
+/// typedstream, with each of `mentions` (a name in the text and the handle
+/// it mentions) marked the way Messages marks @mentions
+fn attributed_body(text: &str, mentions: &[(&str, &str)]) -> Vec<u8> {
+    // runs of (attributes id, text), where id 1 is no attributes and each
+    // mentioned handle gets an id of its own
+    let mut runs: Vec<(i64, &str)> = Vec::new();
+    let mut handles: Vec<&str> = Vec::new();
+    let mut rest = text;
+    for (name, handle) in mentions {
+        let start = match rest.find(name) {
+            Some(start) => start,
+            None => continue,
+        };
+        if start > 0 {
+            runs.push((1, &rest[..start]));
+        }
+        let id = match handles.iter().position(|h| h == handle) {
+            Some(i) => i,
+            None => {
+                handles.push(handle);
+                handles.len() - 1
+            }
+        };
+        runs.push((id as i64 + 2, name));
+        rest = &rest[start + name.len()..];
+    }
+    if !rest.is_empty() {
+        runs.push((1, rest));
+    }
+
+    let mut stream = Typedstream::default();
+    stream.out.extend_from_slice(b"\x04\x0bstreamtyped");
+    stream.int(1000);
+
+    stream.shared(b"@");
+    stream.start(&["NSAttributedString", "NSObject"]);
+    stream.string(text);
+    let mut written = Vec::new();
+    for (id, run) in runs {
+        stream.shared(b"iI");
+        stream.int(id);
+        stream.int(run.encode_utf16().count() as i64);
+        if written.contains(&id) {
+            continue;
+        }
+        written.push(id);
+
+        stream.shared(b"@");
+        stream.start(&["NSDictionary", "NSObject"]);
+        stream.shared(b"i");
+        match id {
+            1 => stream.int(0),
+            id => {
+                stream.int(1);
+                stream.string("__kIMMentionConfirmedMention");
+                stream.string(handles[id as usize - 2]);
+            }
+        }
+        stream.end();
+    }
+    stream.end();
+
+    stream.out
+}
+
+/// Writes the parts of a typedstream [`attributed_body`] needs, the reverse
+/// of [`crate::typedstream`]
+#[derive(Default)]
+struct Typedstream {
+    out: Vec<u8>,
+    /// Type encodings and class names written so far
+    strings: Vec<Vec<u8>>,
+    /// Objects and classes written so far, with the names of the classes
+    entries: Vec<Option<&'static str>>,
+}
+
+impl Typedstream {
+    fn int(&mut self, value: i64) {
+        match value {
+            0..=0x7f => self.out.push(value as u8),
+            _ => match i16::try_from(value) {
+                Ok(value) => {
+                    self.out.push(0x81);
+                    self.out.extend_from_slice(&value.to_le_bytes());
+                }
+                Err(_) => {
+                    self.out.push(0x82);
+                    self.out.extend_from_slice(&(value as i32).to_le_bytes());
+                }
+            },
+        }
+    }
+
+    /// A type encoding or class name, referring back to it if it's been
+    /// written before
+    fn shared(&mut self, string: &[u8]) {
+        match self.strings.iter().position(|s| s == string) {
+            Some(index) => self.out.push(0x92 + index as u8),
+            None => {
+                self.out.push(0x84);
+                self.int(string.len() as i64);
+                self.out.extend_from_slice(string);
+                self.strings.push(string.to_vec());
+            }
+        }
+    }
+
+    /// Start an object of the first of `classes`, each of which is the
+    /// subclass of the next
+    fn start(&mut self, classes: &[&'static str]) {
+        self.out.push(0x84);
+        self.entries.push(None);
+        self.class(classes);
+    }
+
+    fn class(&mut self, classes: &[&'static str]) {
+        let (name, superclasses) = match classes.split_first() {
+            Some(split) => split,
+            None => {
+                self.out.push(0x85);
+                return;
+            }
+        };
+        match self.entries.iter().position(|e| *e == Some(*name)) {
+            Some(index) => self.out.push(0x92 + index as u8),
+            None => {
+                self.out.push(0x84);
+                self.shared(name.as_bytes());
+                self.entries.push(Some(name));
+                self.int(0);
+                self.class(superclasses);
+            }
+        }
+    }
+
+    fn end(&mut self) {
+        self.out.push(0x86);
+    }
+
+    /// An NSString object, as the value of an `@` group
+    fn string(&mut self, text: &str) {
+        self.shared(b"@");
+        self.start(&["NSString", "NSObject"]);
+        self.shared(b"+");
+        self.int(text.len() as i64);
+        self.out.extend_from_slice(text.as_bytes());
+        self.end();
+    }
+}
+
+/// A gradient "photo" in the given hue
+fn write_photo(path: &Path, hue: f32) -> Result<()> {
+    // link previews are saved without an extension to go by
+    photo(480, 360, hue).save_with_format(path, image::ImageFormat::Png)?;
+
+    Ok(())
+}
+
+fn photo(width: u32, height: u32, hue: f32) -> image::RgbImage {
+    image::RgbImage::from_fn(width, height, |x, y| {
+        let light = 0.3 + 0.6 * (1.0 - y as f32 / height as f32);
+        let shift = 40.0 * x as f32 / width as f32;
+        let channel = |offset: f32| {
+            let angle = (hue + shift + offset).to_radians();
+            (255.0 * light * (0.5 + 0.5 * angle.cos())) as u8
+        };
+        image::Rgb([channel(0.0), channel(240.0), channel(120.0)])
+    })
+}
+
+/// A yellow disc on a transparent background, as stickers have
+fn write_sticker(path: &Path) -> Result<()> {
+    let side = 160;
+    let radius = side as f32 / 2.0;
+
+    let image = image::RgbaImage::from_fn(side, side, |x, y| {
+        let distance = (x as f32 - radius).hypot(y as f32 - radius);
+        match distance {
+            d if d < radius - 12.0 => image::Rgba([250, 204, 21, 255]),
+            d if d < radius - 2.0 => image::Rgba([217, 119, 6, 255]),
+            _ => image::Rgba([0, 0, 0, 0]),
+        }
+    });
+    image.save(path)?;
+
+    Ok(())
+}
+
+/// A `.loc.vcf` like the ones Maps sends, linking to a pin at `lat`, `lng`
+fn location_card(pin: &str, lat: f64, lng: f64) -> String {
+    format!(
+        "BEGIN:VCARD\r\n\
+         VERSION:3.0\r\n\
+         N:;{pin};;;\r\n\
+         FN:{pin}\r\n\
+         item1.URL;type=pref:http://maps.apple.com/?ll={lat}\\,{lng}&q={q}\r\n\
+         item1.X-ABLabel:map url\r\n\
+         END:VCARD\r\n",
+        q = pin.replace(' ', "%20"),
+    )
+}
+
+/// A `.vcf` like the ones Contacts sends when sharing a contact
+fn contact_card(name: &str, phone: &str, email: &str) -> String {
+    let (given, family) = name.split_once(' ').unwrap_or((name, ""));
+    format!(
+        "BEGIN:VCARD\r\n\
+         VERSION:3.0\r\n\
+         N:{family};{given};;;\r\n\
+         FN:{name}\r\n\
+         TEL;type=CELL;type=VOICE;type=pref:{phone}\r\n\
+         EMAIL;type=INTERNET;type=HOME;type=pref:{email}\r\n\
+         END:VCARD\r\n"
+    )
+}
+
+/// A few seconds of warbling tones standing in for a voice message
+fn write_voice(path: &Path) -> Result<()> {
+    let rate = 16000.0;
+
+    let samples: Vec<i16> = (0..(rate as usize * 4))
+        .map(|i| {
+            let t = i as f32 / rate;
+            let envelope = (t * 3.0 * std::f32::consts::PI).sin().abs();
+            let pitch = 220.0 + 60.0 * (t * 1.5).sin();
+            (envelope * 8000.0 * (t * pitch * std::f32::consts::TAU).sin()) as i16
+        })
+        .collect();
+    audio::write_wav(path, &samples)?;
+
+    Ok(())
+}
+
+/// `payload_data` for a link: an `NSKeyedArchiver` archive of the
+/// `LPLinkMetadata` Messages fetched, whose image is the message's first
+/// attachment
+fn link_archive(url: &str, title: &str, summary: &str) -> Result<Vec<u8>> {
+    use plist::{Uid, Value};
+
+    let uid = |index: u64| Value::Uid(Uid::new(index));
+    let object = |fields: Vec<(&str, Value)>| {
+        Value::Dictionary(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect(),
+        )
+    };
+    let class = |name: &str| {
+        object(vec![
+            ("$classname", Value::from(name)),
+            (
+                "$classes",
+                Value::Array(vec![Value::from(name), Value::from("NSObject")]),
+            ),
+        ])
+    };
+
+    let objects = vec![
+        Value::from("$null"),
+        object(vec![
+            ("$class", uid(2)),
+            ("URL", uid(3)),
+            ("originalURL", uid(3)),
+            ("title", uid(6)),
+            ("summary", uid(7)),
+            ("image", uid(8)),
+        ]),
+        class("LPLinkMetadata"),
+        object(vec![
+            ("$class", uid(4)),
+            ("NS.base", uid(0)),
+            ("NS.relative", uid(5)),
+        ]),
+        class("NSURL"),
+        Value::from(url),
+        Value::from(title),
+        Value::from(summary),
+        object(vec![
+            ("$class", uid(9)),
+            ("richLinkImageAttachmentSubstituteIndex", Value::from(0)),
+            ("MIMEType", uid(10)),
+        ]),
+        class("RichLinkImageAttachmentSubstitute"),
+        Value::from("image/png"),
+        // the archive's root, which the metadata hangs off
+        object(vec![("$class", uid(12)), ("richLinkMetadata", uid(1))]),
+        class("LPMessagesPayload"),
+    ];
+
+    let archive = object(vec![
+        ("$archiver", Value::from("NSKeyedArchiver")),
+        ("$version", Value::from(100000)),
+        ("$top", object(vec![("root", uid(11))])),
+        ("$objects", Value::Array(objects)),
+    ]);
+
+    let mut payload = Vec::new();
+    archive.to_writer_binary(&mut payload)?;
+    Ok(payload)
+}
+
+/// `payload_data` for an app message: an `NSKeyedArchiver` archive of a
+/// dictionary of strings
+fn keyed_archive(strings: &[(&str, &str)]) -> Result<Vec<u8>> {
+    use plist::{Uid, Value};
+
+    // objects are referred to by index, after the `$null` at 0 and the
+    // dictionary itself at 1
+    let mut objects = vec![Value::from("$null"), Value::from("placeholder")];
+    let mut keys = Vec::new();
+    let mut values = Vec::new();
+    for (key, value) in strings {
+        keys.push(Value::Uid(Uid::new(objects.len() as u64)));
+        objects.push(Value::from(*key));
+        values.push(Value::Uid(Uid::new(objects.len() as u64)));
+        objects.push(Value::from(*value));
+    }
+    objects[1] = Value::Dictionary(
+        [
+            ("NS.keys".to_owned(), Value::Array(keys)),
+            ("NS.objects".to_owned(), Value::Array(values)),
+        ]
+        .into_iter()
+        .collect(),
+    );
+
+    let archive = Value::Dictionary(
+        [
+            ("$archiver".to_owned(), Value::from("NSKeyedArchiver")),
+            ("$version".to_owned(), Value::from(100000)),
+            (
+                "$top".to_owned(),
+                Value::Dictionary(
+                    [("root".to_owned(), Value::Uid(Uid::new(1)))]
+                        .into_iter()
+                        .collect(),
+                ),
+            ),
+            ("$objects".to_owned(), Value::Array(objects)),
+        ]
+        .into_iter()
+        .collect(),
+    );
+
+    let mut payload = Vec::new();
+    archive.to_writer_binary(&mut payload)?;
+    Ok(payload)
+}
@@ -0,0 +1,121 @@
+/// Most emoji a message can have and still be shown jumbo
+const MAX_JUMBO: usize = 3;
+
+/// The text size to show `text` at without a bubble, if it's nothing but
+/// one to three emoji: the fewer there are, the bigger
+pub fn jumbo_size(text: &str) -> Option<f32> {
+    match count(text)? {
+        1 => Some(48.0),
+        2 => Some(40.0),
+        3 => Some(32.0),
+        _ => None,
+    }
+}
+
+/// How many emoji `text` is, if it's only emoji and whitespace, and no more
+/// than `MAX_JUMBO` of them
+fn count(text: &str) -> Option<usize> {
+    let mut count = 0;
+    let mut chars = text.chars().filter(|c| !c.is_whitespace()).peekable();
+
+    while let Some(c) = chars.next() {
+        if is_regional_indicator(c) {
+            // flags are pairs of these
+            chars.next_if(|c| is_regional_indicator(*c));
+        } else if c.is_ascii_digit() || c == '#' || c == '*' {
+            // only an emoji as a keycap, e.g. 1️⃣
+            chars.next_if_eq(&'\u{fe0f}');
+            chars.next_if_eq(&'\u{20e3}')?;
+        } else if !is_emoji(c) {
+            return None;
+        }
+
+        // everything that joins onto or changes the emoji just read
+        loop {
+            if chars.next_if(|c| is_modifier(*c)).is_some() {
+                continue;
+            }
+            if chars.next_if_eq(&'\u{200d}').is_some() {
+                chars.next_if(|c| is_emoji(*c))?;
+                continue;
+            }
+            break;
+        }
+
+        count += 1;
+        if count > MAX_JUMBO {
+            return None;
+        }
+    }
+
+    (count > 0).then_some(count)
+}
+
+/// A character that starts an emoji
+fn is_emoji(c: char) -> bool {
+    matches!(c,
+        '\u{a9}' | '\u{ae}' | '\u{203c}' | '\u{2049}' | '\u{2122}' | '\u{2139}'
+        | '\u{2194}'..='\u{21aa}'
+        | '\u{231a}'..='\u{23ff}'
+        | '\u{24c2}'
+        | '\u{25aa}'..='\u{25fe}'
+        | '\u{2600}'..='\u{27bf}'
+        | '\u{2934}' | '\u{2935}'
+        | '\u{2b05}'..='\u{2b55}'
+        | '\u{3030}' | '\u{303d}' | '\u{3297}' | '\u{3299}'
+        | '\u{1f000}'..='\u{1faff}')
+}
+
+/// Flags are written as two of these, one per letter of the country code
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1f1e6}'..='\u{1f1ff}').contains(&c)
+}
+
+/// A character that changes the emoji before it: skin tones, the
+/// presentation selector, keycaps, and the tags subdivision flags use
+fn is_modifier(c: char) -> bool {
+    matches!(c,
+        '\u{1f3fb}'..='\u{1f3ff}'
+        | '\u{fe0f}'
+        | '\u{20e3}'
+        | '\u{e0020}'..='\u{e007f}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_emoji() {
+        assert_eq!(count("😂"), Some(1));
+        assert_eq!(count(" 😂 👍 "), Some(2));
+        // a skin tone, a family joined with zero width joiners, a flag, a
+        // keycap and a subdivision flag are one each
+        assert_eq!(count("👍🏽"), Some(1));
+        assert_eq!(count("👨\u{200d}👩\u{200d}👧"), Some(1));
+        assert_eq!(count("🇬🇧🇫🇷"), Some(2));
+        assert_eq!(count("1\u{fe0f}\u{20e3}"), Some(1));
+        assert_eq!(
+            count("🏴\u{e0067}\u{e0062}\u{e0073}\u{e0063}\u{e0074}\u{e007f}"),
+            Some(1)
+        );
+        assert_eq!(count("❤\u{fe0f}"), Some(1));
+    }
+
+    #[test]
+    fn only_a_few_emoji() {
+        assert_eq!(count(""), None);
+        assert_eq!(count("  "), None);
+        assert_eq!(count("hi 👋"), None);
+        assert_eq!(count("1"), None);
+        assert_eq!(count("👨\u{200d}"), None);
+        assert_eq!(count("😂😂😂😂"), None);
+    }
+
+    #[test]
+    fn fewer_are_bigger() {
+        assert_eq!(jumbo_size("😂"), Some(48.0));
+        assert_eq!(jumbo_size("😂😂😂"), Some(32.0));
+        assert_eq!(jumbo_size("ok"), None);
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use egui::{Color32, RichText};
+use libsqlite3_sys as ffi;
+
+use crate::{cancel::Cancelled, i18n, repaint};
+
+/// Failures the user can do something about, each with its own hint and
+/// exit code. These travel inside eyre reports like any other error; use
+/// [`classify`] to get them back out.
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the database doesn't have the expected layout: {0}")]
+    SchemaMismatch(String),
+    #[error("the database is locked by another program")]
+    Locked,
+    #[error("the database is damaged: {0}")]
+    Corrupt(String),
+    #[error("unsupported format: {0}")]
+    UnsupportedFormat(String),
+    #[error("attachment isn't on this computer: {}", .0.display())]
+    MissingAttachment(PathBuf),
+    #[error("couldn't decode: {0}")]
+    Decode(String),
+}
+
+impl Error {
+    /// Recognise the SQLite failures that have a variant of their own
+    pub fn from_sqlx(e: &sqlx::Error) -> Option<Self> {
+        let db = e.as_database_error()?;
+        let code: i32 = db.code()?.parse().ok()?;
+
+        match code & 0xff {
+            ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED => Some(Self::Locked),
+            ffi::SQLITE_CORRUPT | ffi::SQLITE_NOTADB => {
+                Some(Self::Corrupt(db.message().to_owned()))
+            }
+            ffi::SQLITE_ERROR if db.message().starts_with("no such ") => {
+                Some(Self::SchemaMismatch(db.message().to_owned()))
+            }
+            _ => None,
+        }
+    }
+
+    /// What the user can try next
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::SchemaMismatch(_) => {
+                "Make sure this is ~/Library/Messages/chat.db (or a copy of it). Databases \
+                 from much older or newer versions of macOS may not be supported yet."
+            }
+            Self::Locked => {
+                "Quit Messages, or copy chat.db (along with chat.db-wal and chat.db-shm) \
+                 somewhere else and open the copy."
+            }
+            Self::Corrupt(_) => {
+                "Copy the database again, including chat.db-wal and chat.db-shm if they \
+                 exist. A copy taken while Messages was writing to it can be incomplete."
+            }
+            Self::UnsupportedFormat(_) => {
+                "HEIC photos need sips (macOS) or heif-convert (libheif), and audio messages \
+                 need afconvert (macOS) or ffmpeg."
+            }
+            Self::MissingAttachment(_) => {
+                "Attachments are read from ~/Library/Messages/Attachments. Copy that folder \
+                 along with the database and pass --attachments-root with where it was copied \
+                 to, or turn off \"Optimize Mac Storage\" for Messages so they're downloaded \
+                 from iCloud."
+            }
+            Self::Decode(_) => "The file may be damaged or only partially downloaded.",
+        }
+    }
+
+    /// Exit code for the command line; anything without a variant exits 1
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::SchemaMismatch(_) => 3,
+            Self::Locked => 4,
+            Self::Corrupt(_) => 5,
+            Self::UnsupportedFormat(_) => 6,
+            Self::MissingAttachment(_) => 7,
+            Self::Decode(_) => 8,
+        }
+    }
+}
+
+/// The typed error behind `report`, if any. SQLite errors that were passed
+/// along as plain sqlx errors are recognised too.
+pub fn classify(report: &eyre::Report) -> Option<Error> {
+    report.chain().find_map(|cause| {
+        if let Some(e) = cause.downcast_ref::<Error>() {
+            return Some(e.clone());
+        }
+        cause
+            .downcast_ref::<sqlx::Error>()
+            .and_then(Error::from_sqlx)
+    })
+}
+
+/// The most recent failure of a background task, shown in a bar at the
+/// bottom of the window until it's dismissed
+#[derive(Clone, Default)]
+pub struct LastError(Arc<Mutex<Option<eyre::Report>>>);
+
+impl LastError {
+    pub fn set(&self, report: eyre::Report) {
+        // a load stopped because its chat was closed, not a failure
+        if report.is::<Cancelled>() {
+            return;
+        }
+        eprintln!("{report}");
+        *self.0.lock().unwrap() = Some(report);
+        repaint();
+    }
+
+    pub fn show(&self, ctx: &egui::Context) {
+        let mut last = self.0.lock().unwrap();
+        let report = match &*last {
+            Some(report) => report,
+            None => return,
+        };
+
+        let mut dismissed = false;
+        egui::TopBottomPanel::bottom("last error").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .small_button("x")
+                    .on_hover_text(i18n::t("dismiss"))
+                    .clicked()
+                {
+                    dismissed = true;
+                }
+                ui.label(RichText::new(report.to_string()).color(Color32::LIGHT_RED));
+            });
+            if let Some(error) = classify(report) {
+                ui.label(error.hint());
+            }
+        });
+
+        if dismissed {
+            *last = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eyre::WrapErr;
+
+    use super::*;
+    use crate::test_util;
+
+    #[tokio::test]
+    async fn recognises_sqlite_failures() {
+        let db = test_util::memory().await;
+
+        let e = sqlx::query("SELECT * from message;")
+            .execute(&db)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            Error::from_sqlx(&e),
+            Some(Error::SchemaMismatch(what)) if what == "no such table: message"
+        ));
+
+        // other SQL errors aren't the user's to fix
+        let e = sqlx::query("SELEC 1;").execute(&db).await.unwrap_err();
+        assert!(Error::from_sqlx(&e).is_none());
+        assert!(Error::from_sqlx(&sqlx::Error::RowNotFound).is_none());
+    }
+
+    #[tokio::test]
+    async fn classifies_through_context() {
+        let db = test_util::memory().await;
+        let e = sqlx::query("SELECT text from message;")
+            .execute(&db)
+            .await
+            .unwrap_err();
+        let report = eyre::Report::from(e).wrap_err("loading a chat");
+        let error = classify(&report).unwrap();
+        assert_eq!(error.exit_code(), 3);
+
+        let report: eyre::Report = Err::<(), _>(Error::Decode("truncated plist".to_owned()))
+            .wrap_err("reading a balloon")
+            .unwrap_err();
+        assert!(matches!(classify(&report), Some(Error::Decode(_))));
+
+        assert!(classify(&eyre::eyre!("something else")).is_none());
+    }
+}
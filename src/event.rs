@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use eyre::Result;
+use sqlx::SqlitePool;
+
+use crate::{config::Identity, Sender};
+
+/// `message.item_type` of someone being added to or removed from a group
+const MEMBERSHIP: i64 = 1;
+/// `message.item_type` of a group being renamed
+const NAME: i64 = 2;
+/// `message.item_type` of someone leaving, or the group photo changing
+const GROUP_ACTION: i64 = 3;
+
+/// Something that happened to a group chat rather than being said in it,
+/// stored as a message with a non-zero `item_type`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// The sender added someone
+    Added(Sender),
+    /// The sender removed someone
+    Removed(Sender),
+    /// The sender renamed the chat, or cleared its name
+    Named(Option<String>),
+    Left,
+    PhotoChanged,
+    PhotoRemoved,
+    /// An `item_type` or `group_action_type` this doesn't know
+    Other,
+}
+
+impl Event {
+    fn decode(item_type: i64, action: i64, other: String, title: Option<String>) -> Self {
+        let other = Sender::SomeoneElse(other);
+        match (item_type, action) {
+            (MEMBERSHIP, 0) => Self::Added(other),
+            (MEMBERSHIP, 1) => Self::Removed(other),
+            (NAME, _) => Self::Named(title.filter(|title| !title.is_empty())),
+            (GROUP_ACTION, 0) => Self::Left,
+            (GROUP_ACTION, 1) => Self::PhotoChanged,
+            (GROUP_ACTION, 2) => Self::PhotoRemoved,
+            _ => Self::Other,
+        }
+    }
+
+    /// What happened, e.g. `Alice named the conversation “Ski Trip”`
+    pub fn describe(&self, identity: &Identity, sender: &Sender) -> String {
+        let sender = identity.name(sender);
+        match self {
+            Self::Added(other) => {
+                format!(
+                    "{sender} added {} to the conversation",
+                    identity.name(other)
+                )
+            }
+            Self::Removed(other) => format!(
+                "{sender} removed {} from the conversation",
+                identity.name(other)
+            ),
+            Self::Named(Some(title)) => format!("{sender} named the conversation “{title}”"),
+            Self::Named(None) => format!("{sender} removed the name from the conversation"),
+            Self::Left => format!("{sender} left the conversation"),
+            Self::PhotoChanged => format!("{sender} changed the group photo"),
+            Self::PhotoRemoved => format!("{sender} removed the group photo"),
+            Self::Other => format!("{sender} changed the conversation"),
+        }
+    }
+}
+
+/// Every group event in a chat, keyed by the ROWID of its message
+pub async fn chat_events(db: &SqlitePool, chat_id: &str) -> Result<HashMap<i64, Event>> {
+    let rows = sqlx::query_as::<_, (i64, i64, i64, String, Option<String>)>(
+        r#"
+            SELECT
+                m.ROWID, m.item_type, coalesce(m.group_action_type, 0), coalesce(h.id, ''),
+                m.group_title
+            from message m
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            left join handle h
+                on m.other_handle = h.ROWID
+            where c.chat_identifier=$1
+                and m.item_type != 0
+            ;
+        "#,
+    )
+    .bind(chat_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(id, item_type, action, other, title)| {
+            (id, Event::decode(item_type, action, other, title))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// A chat `a` where someone is added, the group is named and its name
+    /// cleared, someone leaves, and something unknown happens
+    async fn scratch_db() -> SqlitePool {
+        let db = test_util::memory().await;
+        sqlx::query(
+            r#"
+                CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, chat_identifier TEXT);
+                CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT);
+                CREATE TABLE message (
+                    ROWID INTEGER PRIMARY KEY, item_type INTEGER, group_action_type INTEGER,
+                    other_handle INTEGER, group_title TEXT
+                );
+                CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+
+                INSERT INTO chat VALUES (1, 'a'), (2, 'b');
+                INSERT INTO handle VALUES (1, '+15555550100');
+                INSERT INTO message VALUES
+                    (1, 0, 0, 0, NULL), (2, 1, 0, 1, NULL), (3, 2, 0, 0, 'Ski Trip'),
+                    (4, 2, 0, 0, ''), (5, 3, NULL, 0, NULL), (6, 7, 0, 0, NULL),
+                    (7, 1, 1, 1, NULL);
+                INSERT INTO chat_message_join VALUES
+                    (1, 1), (1, 2), (1, 3), (1, 4), (1, 5), (1, 6), (2, 7);
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        db
+    }
+
+    #[tokio::test]
+    async fn reads_events() {
+        let db = scratch_db().await;
+
+        let events = chat_events(&db, "a").await.unwrap();
+        let someone = Sender::SomeoneElse("+15555550100".to_owned());
+        assert_eq!(
+            events,
+            HashMap::from([
+                (2, Event::Added(someone.clone())),
+                (3, Event::Named(Some("Ski Trip".to_owned()))),
+                (4, Event::Named(None)),
+                (5, Event::Left),
+                (6, Event::Other),
+            ])
+        );
+
+        let events = chat_events(&db, "b").await.unwrap();
+        assert_eq!(events, HashMap::from([(7, Event::Removed(someone))]));
+    }
+
+    #[test]
+    fn describes_events() {
+        let identity = Identity {
+            display_name: "Me".to_owned(),
+            aliases: HashMap::from([("bob".to_owned(), "Bob".to_owned())]),
+            ..Identity::default()
+        };
+        let bob = Sender::SomeoneElse("bob".to_owned());
+        let eve = Sender::SomeoneElse("eve".to_owned());
+
+        assert_eq!(
+            Event::Added(eve).describe(&identity, &Sender::Me),
+            "Me added eve to the conversation"
+        );
+        assert_eq!(
+            Event::Named(None).describe(&identity, &bob),
+            "Bob removed the name from the conversation"
+        );
+        assert_eq!(
+            Event::decode(GROUP_ACTION, 2, String::new(), None).describe(&identity, &bob),
+            "Bob removed the group photo"
+        );
+    }
+}
@@ -0,0 +1,391 @@
+mod attachments;
+mod capsule;
+mod ics;
+mod messages;
+pub mod profile;
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::prelude::*;
+use eyre::Result;
+use regex::Regex;
+use sqlx::SqlitePool;
+
+use crate::{
+    apple_time,
+    attachment::{self, MediaItem},
+    config::{HandleChange, HeicConversion, Identity},
+    continuity, heic, time, Sender,
+};
+
+pub use attachments::{attachments, Kind, Selection};
+pub use capsule::time_capsule;
+pub use ics::timeline;
+pub use messages::{messages, Format};
+
+/// Limits on what goes into an export, set by export profiles
+#[derive(Clone, Default)]
+pub struct Scope {
+    /// Only include messages on or after this day
+    pub after: Option<NaiveDate>,
+    /// Only include messages before this day
+    pub before: Option<NaiveDate>,
+    /// Text matching any of these is replaced with `[redacted]`
+    pub redact: Vec<Regex>,
+}
+
+impl Scope {
+    pub fn contains_day(&self, day: NaiveDate) -> bool {
+        self.after.is_none_or(|after| day >= after) && self.before.is_none_or(|before| day < before)
+    }
+
+    pub fn contains(&self, date: DateTime<Utc>) -> bool {
+        self.contains_day(date.date_naive())
+    }
+
+    /// The range of `message.date` values in scope, start inclusive
+    pub fn bounds(&self) -> (i64, i64) {
+        let day_start = |day: NaiveDate| apple_time(Utc.from_utc_datetime(&day.and_hms(0, 0, 0)));
+
+        (
+            self.after.map_or(i64::MIN, day_start),
+            self.before.map_or(i64::MAX, day_start),
+        )
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_owned();
+        for regex in &self.redact {
+            text = regex.replace_all(&text, "[redacted]").into_owned();
+        }
+        text
+    }
+}
+
+/// Directory exports are written to when the user doesn't pick one
+pub fn default_dir() -> PathBuf {
+    dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Turn a chat identifier (phone number, email, group id) into something safe
+/// to use as a file name
+pub fn file_stem(chat_id: &str) -> String {
+    chat_id
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+pub fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Write a printable HTML page with a grid of every photo in a chat, each
+/// captioned with its date and sender. HEIC photos are converted with `heic`.
+/// Returns the path that was written.
+pub async fn contact_sheet(
+    db: &SqlitePool,
+    identity: &Identity,
+    chat_id: &str,
+    scope: &Scope,
+    heic: HeicConversion,
+    dir: &Path,
+) -> Result<PathBuf> {
+    let media = photos(db, chat_id, scope, heic).await?;
+    let title = scope.redact(identity.chat_name(chat_id, ""));
+    let html = sheet(&title, identity, scope, &media, |item| {
+        format!("file://{}", item.attachment.path.to_string_lossy())
+    });
+
+    let path = dir.join(format!("{}-photos.html", file_stem(chat_id)));
+    tokio::fs::write(&path, html).await?;
+
+    Ok(path)
+}
+
+/// The photos in a chat within `scope`, with HEIC photos converted
+async fn photos(
+    db: &SqlitePool,
+    chat_id: &str,
+    scope: &Scope,
+    heic: HeicConversion,
+) -> Result<Vec<MediaItem>> {
+    let mut media = attachment::chat_media(db, chat_id, "image/").await?;
+    media.retain(|item| scope.contains(item.date));
+
+    // browsers other than Safari can't show HEIC
+    let media = tokio::task::spawn_blocking(move || {
+        for item in &mut media {
+            match heic::converted(&item.attachment.path, heic) {
+                Ok(path) => item.attachment.path = path,
+                Err(e) => eprintln!("{}: {e}", item.attachment.path.display()),
+            }
+        }
+        media
+    })
+    .await?;
+
+    Ok(media)
+}
+
+/// The contact sheet page, with `src` giving where each photo is linked from
+fn sheet(
+    title: &str,
+    identity: &Identity,
+    scope: &Scope,
+    media: &[MediaItem],
+    src: impl Fn(&MediaItem) -> String,
+) -> String {
+    let mut html = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; margin: 1cm; }}
+.sheet {{ display: grid; grid-template-columns: repeat(4, 1fr); gap: 0.5cm; }}
+figure {{ margin: 0; break-inside: avoid; }}
+img {{ width: 100%; aspect-ratio: 1; object-fit: cover; }}
+figcaption {{ font-size: 9pt; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>{count} photos</p>
+<div class="sheet">
+"#,
+        title = html_escape(title),
+        count = media.len(),
+    );
+
+    for item in media {
+        html.push_str(&format!(
+            "<figure><img src=\"{}\" alt=\"{}\"><figcaption>{}<br>{}</figcaption></figure>\n",
+            html_escape(&src(item)),
+            html_escape(&item.attachment.name),
+            item.date.format("%Y-%m-%d %H:%M"),
+            html_escape(&scope.redact(identity.name(&item.sender))),
+        ));
+    }
+
+    html.push_str("</div>\n</body>\n</html>\n");
+    html
+}
+
+/// Write a chat as plain text, one message per line. Returns the path that
+/// was written.
+pub async fn transcript(
+    db: &SqlitePool,
+    identity: &Identity,
+    changes: &[HandleChange],
+    chat_id: &str,
+    scope: &Scope,
+    dir: &Path,
+) -> Result<PathBuf> {
+    messages(db, identity, changes, chat_id, scope, Format::Text, dir).await
+}
+
+/// Write a transcript for every person messaged one on one, merging their
+/// iMessage and SMS threads, and the threads under any handle they had
+/// before, into a single file named after them, plus an `index.html` linking
+/// to each. Returns the directory that was written.
+pub async fn each_contact(
+    db: &SqlitePool,
+    identity: &Identity,
+    changes: &[HandleChange],
+    scope: &Scope,
+    dir: &Path,
+) -> Result<PathBuf> {
+    let (start, end) = scope.bounds();
+
+    // one on one chats are the ones with a single other participant
+    let messages = sqlx::query_as::<_, (String, String, i64, bool)>(
+        r#"
+            SELECT
+                h.id, coalesce(m.text, ''), m.date, m.is_from_me
+            from message m
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat_handle_join chj
+                on cmj.chat_id = chj.chat_id
+            join handle h
+                on chj.handle_id = h.ROWID
+            where (
+                    select count(*)
+                    from chat_handle_join other
+                    where other.chat_id = cmj.chat_id
+                ) = 1
+                and m.date >= $1 and m.date < $2
+            order by h.id, m.date
+            ;
+        "#,
+    )
+    .bind(start)
+    .bind(end)
+    .fetch_all(db)
+    .await?;
+
+    // someone who changed handle is filed under the one they use now, their
+    // messages from each in the order they were sent
+    let mut by_person: BTreeMap<&str, Vec<&(String, String, i64, bool)>> = BTreeMap::new();
+    for message in &messages {
+        let person = continuity::current(changes, &message.0);
+        by_person.entry(person).or_default().push(message);
+    }
+
+    let dir = dir.join("contacts");
+    tokio::fs::create_dir_all(&dir).await?;
+
+    // (person, file name, message count)
+    let mut people: Vec<(String, String, usize)> = Vec::new();
+    for (person, mut messages) in by_person {
+        messages.sort_by_key(|(_, _, timestamp, _)| *timestamp);
+        let mut history = continuity::history(changes, person);
+        history.retain(|change| change.date.is_some_and(|day| scope.contains_day(day)));
+
+        let mut text = String::new();
+        let mut previous = None;
+        for (_, body, timestamp, is_from_me) in &messages {
+            let date = time(*timestamp);
+            for change in continuity::between(&history, previous, date) {
+                let notice = scope.redact(&continuity::notice(change));
+                text.push_str(&format!("--- {notice} ---\n"));
+            }
+            previous = Some(date);
+
+            let sender = Sender::new(*is_from_me, person.to_owned());
+            transcript_line(&mut text, identity, scope, &sender, *timestamp, body);
+        }
+
+        let name = scope.redact(identity.name(&Sender::SomeoneElse(person.to_owned())));
+        let file = format!("{}.txt", file_stem(&name));
+        tokio::fs::write(dir.join(&file), text).await?;
+
+        people.push((name, file, messages.len()));
+    }
+
+    people.sort_by_key(|(name, ..)| name.to_lowercase());
+
+    let mut index = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>contacts</title>\n</head>\n<body>\n<ul>\n",
+    );
+    for (name, file, count) in &people {
+        index.push_str(&format!(
+            "<li><a href=\"{}\">{}</a> ({count} messages)</li>\n",
+            html_escape(file),
+            html_escape(name),
+        ));
+    }
+    index.push_str("</ul>\n</body>\n</html>\n");
+    tokio::fs::write(dir.join("index.html"), index).await?;
+
+    Ok(dir)
+}
+
+/// Append a message to a plain text transcript
+fn transcript_line(
+    text: &mut String,
+    identity: &Identity,
+    scope: &Scope,
+    sender: &Sender,
+    timestamp: i64,
+    body: &str,
+) {
+    text.push_str(&format!(
+        "{} {}: {}\n",
+        time(timestamp).format("%Y-%m-%d %H:%M"),
+        scope.redact(identity.name(sender)),
+        scope.redact(body),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Demo;
+
+    #[tokio::test]
+    async fn sheets_every_photo_in_scope() {
+        let demo = Demo::generate().await;
+        let db = demo.connect().await;
+        let dir = &demo.dir;
+        let identity = Identity::default();
+        let photos = attachment::chat_media(&db, "chat100", "image/")
+            .await
+            .unwrap();
+        assert!(!photos.is_empty());
+
+        let written = contact_sheet(
+            &db,
+            &identity,
+            "chat100",
+            &Scope::default(),
+            HeicConversion::default(),
+            dir,
+        )
+        .await
+        .unwrap();
+        assert_eq!(written, dir.join("chat100-photos.html"));
+        let html = std::fs::read_to_string(&written).unwrap();
+        assert_eq!(html.matches("<figure>").count(), photos.len());
+        assert!(html.contains(&format!("<p>{} photos</p>", photos.len())));
+        assert!(html.contains(&format!(
+            "src=\"file://{}",
+            html_escape(&photos[0].attachment.path.to_string_lossy())
+        )));
+
+        // nothing after the last photo
+        let scope = Scope {
+            after: Some(photos.last().unwrap().date.date_naive().succ()),
+            ..Default::default()
+        };
+        let written = contact_sheet(
+            &db,
+            &identity,
+            "chat100",
+            &scope,
+            HeicConversion::default(),
+            dir,
+        )
+        .await
+        .unwrap();
+        let html = std::fs::read_to_string(written).unwrap();
+        assert!(!html.contains("<figure>"));
+        assert!(html.contains("<p>0 photos</p>"));
+    }
+
+    #[test]
+    fn file_stems() {
+        assert_eq!(file_stem("+1 (555) 010-0100"), "_1__555__010_0100");
+        assert_eq!(file_stem("ana@example.com"), "ana_example_com");
+        assert_eq!(file_stem("chat100"), "chat100");
+        assert_eq!(file_stem("Zoë"), "Zoë");
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            html_escape(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use eyre::Result;
+use sqlx::SqlitePool;
+
+use crate::{
+    attachment,
+    config::{HeicConversion, Identity},
+    heic, Sender,
+};
+
+use super::{file_stem, Scope};
+
+/// Kinds of attachment, going by mime type
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Kind {
+    Photo,
+    Video,
+    Audio,
+    Other,
+}
+
+impl Kind {
+    pub const ALL: [Self; 4] = [Self::Photo, Self::Video, Self::Audio, Self::Other];
+
+    pub fn of(mime_type: &str) -> Self {
+        match mime_type.split('/').next() {
+            Some("image") => Self::Photo,
+            Some("video") => Self::Video,
+            Some("audio") => Self::Audio,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Photo => "photos",
+            Self::Video => "videos",
+            Self::Audio => "audio",
+            Self::Other => "other files",
+        }
+    }
+}
+
+/// Which attachments to copy out of a chat, and how
+#[derive(Clone, Default)]
+pub struct Selection {
+    /// Only these kinds, or everything if empty
+    pub kinds: Vec<Kind>,
+    /// Only attachments sent by this handle, or `me`
+    pub from: Option<String>,
+    /// Convert HEIC photos, for anyone without an Apple device
+    pub convert_heic: Option<HeicConversion>,
+    /// Start file names with when they were sent, so they sort by date
+    pub name_by_date: bool,
+}
+
+impl Selection {
+    fn sent_by(&self, identity: &Identity, sender: &Sender) -> bool {
+        match self.from.as_deref() {
+            None => true,
+            Some("me") => identity.is_me(sender),
+            Some(from) => match sender {
+                Sender::SomeoneElse(handle) => handle == from,
+                Sender::Me => identity.name(sender) == from,
+            },
+        }
+    }
+}
+
+/// Copy the attachments in a chat matching `selection` to a folder in `dir`,
+/// without a transcript. Returns the folder.
+pub async fn attachments(
+    db: &SqlitePool,
+    identity: &Identity,
+    chat_id: &str,
+    scope: &Scope,
+    selection: &Selection,
+    dir: &Path,
+) -> Result<PathBuf> {
+    let mut media = attachment::chat_media(db, chat_id, "").await?;
+    media.retain(|item| {
+        scope.contains(item.date)
+            && (selection.kinds.is_empty() || selection.kinds.contains(&Kind::of(&item.mime_type)))
+            && selection.sent_by(identity, &item.sender)
+    });
+
+    let out = dir.join(format!("{}-attachments", file_stem(chat_id)));
+    let selection = selection.clone();
+
+    tokio::task::spawn_blocking(move || -> io::Result<PathBuf> {
+        fs::create_dir_all(&out)?;
+
+        for item in &media {
+            let mut source = item.attachment.path.clone();
+            let mut name = PathBuf::from(&item.attachment.name);
+
+            if let Some(conversion) = selection.convert_heic.filter(|_| heic::is_heic(&source)) {
+                match heic::converted(&source, conversion) {
+                    Ok(converted) => {
+                        source = converted;
+                        name.set_extension(conversion.format.extension());
+                    }
+                    Err(e) => eprintln!("{}: {e}", source.display()),
+                }
+            }
+
+            let mut name = name.to_string_lossy().into_owned();
+            if selection.name_by_date {
+                name = format!("{} {name}", item.date.format("%Y-%m-%d %H.%M.%S"));
+            }
+
+            // missing files are skipped rather than failing the whole export
+            if let Err(e) = fs::copy(&source, unused_path(&out, &name)) {
+                eprintln!("{}: {e}", source.display());
+            }
+        }
+
+        Ok(out)
+    })
+    .await?
+    .map_err(Into::into)
+}
+
+/// `dir/name`, or `dir/name (2)` etc. if that is taken, since plenty of
+/// attachments are called IMG_0001.jpg
+fn unused_path(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+    if !path.exists() {
+        return path;
+    }
+
+    let name = Path::new(name);
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = name
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    (2..)
+        .map(|n| dir.join(format!("{stem} ({n}){extension}")))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Demo;
+
+    /// Names of the files in `dir`, sorted
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn copies_the_selected_attachments() {
+        let demo = Demo::generate().await;
+        let db = demo.connect().await;
+        let dir = &demo.dir;
+        let identity = Identity::default();
+        let media = attachment::chat_media(&db, "chat100", "").await.unwrap();
+        let photos: Vec<_> = media
+            .iter()
+            .filter(|item| {
+                Kind::of(&item.mime_type) == Kind::Photo && item.attachment.path.is_file()
+            })
+            .collect();
+        assert!(!photos.is_empty());
+
+        let selection = Selection {
+            kinds: vec![Kind::Photo],
+            name_by_date: true,
+            ..Default::default()
+        };
+        let out = attachments(
+            &db,
+            &identity,
+            "chat100",
+            &Scope::default(),
+            &selection,
+            &dir.join("photos"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(out, dir.join("photos/chat100-attachments"));
+        let mut expected: Vec<_> = photos
+            .iter()
+            .map(|item| {
+                format!(
+                    "{} {}",
+                    item.date.format("%Y-%m-%d %H.%M.%S"),
+                    item.attachment.name
+                )
+            })
+            .collect();
+        expected.sort();
+        assert_eq!(names(&out), expected);
+
+        // only mine
+        let selection = Selection {
+            from: Some("me".to_owned()),
+            ..Default::default()
+        };
+        let out = attachments(
+            &db,
+            &identity,
+            "chat100",
+            &Scope::default(),
+            &selection,
+            &dir.join("mine"),
+        )
+        .await
+        .unwrap();
+        let mine = media
+            .iter()
+            .filter(|item| item.sender == Sender::Me && item.attachment.path.is_file())
+            .count();
+        assert_eq!(names(&out).len(), mine);
+    }
+
+    #[test]
+    fn kinds_by_mime_type() {
+        assert_eq!(Kind::of("image/heic"), Kind::Photo);
+        assert_eq!(Kind::of("video/quicktime"), Kind::Video);
+        assert_eq!(Kind::of("audio/x-caf"), Kind::Audio);
+        assert_eq!(Kind::of("application/pdf"), Kind::Other);
+        assert_eq!(Kind::of(""), Kind::Other);
+    }
+
+    #[test]
+    fn numbers_taken_names() {
+        let dir = std::env::temp_dir().join(format!(
+            "imessage-viewer-attachments-{}-unused",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["IMG_0001.jpg", "IMG_0001 (2).jpg", "notes"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        assert_eq!(unused_path(&dir, "IMG_0002.jpg"), dir.join("IMG_0002.jpg"));
+        assert_eq!(
+            unused_path(&dir, "IMG_0001.jpg"),
+            dir.join("IMG_0001 (3).jpg")
+        );
+        assert_eq!(unused_path(&dir, "notes"), dir.join("notes (2)"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::prelude::*;
+use eyre::Result;
+use sqlx::SqlitePool;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{config::Config, ignore, stats};
+
+use super::{file_stem, html_escape, messages, photos, sheet, Format, Scope};
+
+/// Write a zip of everything about a chat between two dates, opening on a
+/// cover page: an HTML transcript, a contact sheet with the photos
+/// themselves, a stats summary and the raw messages as JSON. Returns the path
+/// of the zip.
+pub async fn time_capsule(
+    db: &SqlitePool,
+    config: &Config,
+    chat_id: &str,
+    scope: &Scope,
+    dir: &Path,
+) -> Result<PathBuf> {
+    let identity = &config.me;
+    let changes = &config.changes();
+    let rules = ignore::Rules::compile(&config.ignore_rules)?;
+
+    let (title,) = sqlx::query_as::<_, (String,)>(
+        r#"
+            SELECT coalesce(nullif(display_name, ''), chat_identifier)
+            from chat
+            where chat_identifier=$1
+            limit 1
+            ;
+        "#,
+    )
+    .bind(chat_id)
+    .fetch_optional(db)
+    .await?
+    .unwrap_or_else(|| (chat_id.to_owned(),));
+    let title = identity.chat_name(chat_id, &title).to_owned();
+
+    // the transcript and JSON can be huge, so they're written to files and
+    // streamed into the zip rather than built in memory
+    let staging =
+        std::env::temp_dir().join(format!("imessage-viewer-capsule-{}", file_stem(chat_id)));
+    let _ = tokio::fs::remove_dir_all(&staging).await;
+    tokio::fs::create_dir_all(&staging).await?;
+
+    let transcript = messages(
+        db,
+        identity,
+        changes,
+        chat_id,
+        scope,
+        Format::Html,
+        &staging,
+    )
+    .await?;
+    let json = messages(
+        db,
+        identity,
+        changes,
+        chat_id,
+        scope,
+        Format::Json,
+        &staging,
+    )
+    .await?;
+    let stats = stats::chat(db, identity, &rules, changes, scope, chat_id).await?;
+
+    let media = photos(db, chat_id, scope, config.heic_export).await?;
+    let photo_names: HashMap<String, String> = media
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let name = item.attachment.path.file_name().map_or_else(
+                || item.attachment.name.clone(),
+                |name| name.to_string_lossy().into_owned(),
+            );
+            (
+                item.attachment.guid.clone(),
+                format!("photos/{:04}-{name}", i + 1),
+            )
+        })
+        .collect();
+    let contact_sheet = sheet(&title, identity, scope, &media, |item| {
+        photo_names[&item.attachment.guid].clone()
+    });
+
+    let mut cover = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: Georgia, serif; max-width: 36em; margin: 3em auto; text-align: center; }}
+h1 {{ font-size: 3em; margin-bottom: 0.2em; }}
+.dates {{ color: gray; font-style: italic; }}
+ul {{ list-style: none; padding: 0; line-height: 2; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+"#,
+        title = html_escape(&title),
+    );
+    if let (Some(first), Some(last)) = (stats.first, stats.last) {
+        cover.push_str(&format!(
+            "<p class=\"dates\">{} &ndash; {}</p>\n",
+            first.format("%B %-d, %Y"),
+            last.format("%B %-d, %Y"),
+        ));
+    }
+    let people: Vec<_> = stats.senders.iter().map(|s| html_escape(&s.name)).collect();
+    cover.push_str(&format!(
+        "<p>{} messages and {} photos between {}</p>\n",
+        stats.messages,
+        media.len(),
+        people.join(", "),
+    ));
+    cover.push_str(
+        "<ul>\n\
+         <li><a href=\"transcript.html\">the conversation</a></li>\n\
+         <li><a href=\"photos.html\">photos</a></li>\n\
+         <li><a href=\"stats.html\">by the numbers</a></li>\n\
+         <li><a href=\"messages.json\">raw messages (JSON)</a></li>\n\
+         </ul>\n</body>\n</html>\n",
+    );
+
+    let root = format!("{}-time-capsule", file_stem(chat_id));
+    let path = dir.join(format!("{root}.zip"));
+    let pages = [
+        ("index.html", cover),
+        ("photos.html", contact_sheet),
+        ("stats.html", stats.html()),
+    ];
+    let files = [
+        ("transcript.html".to_owned(), transcript),
+        ("messages.json".to_owned(), json),
+    ]
+    .into_iter()
+    .chain(media.into_iter().map(|item| {
+        let name = photo_names[&item.attachment.guid].clone();
+        (name, item.attachment.path)
+    }))
+    .collect::<Vec<_>>();
+
+    let zip_path = path.clone();
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let mut zip = ZipWriter::new(File::create(&zip_path)?);
+        // without a date, every entry would be from 1980
+        let now = Local::now();
+        let modified = zip::DateTime::from_date_and_time(
+            now.year() as u16,
+            now.month() as u8,
+            now.day() as u8,
+            now.hour() as u8,
+            now.minute() as u8,
+            now.second() as u8,
+        )
+        .unwrap_or_default();
+        let deflated = FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .last_modified_time(modified);
+        // photos are compressed already
+        let stored = deflated.compression_method(CompressionMethod::Stored);
+
+        for (name, page) in pages {
+            zip.start_file(format!("{root}/{name}"), deflated)?;
+            zip.write_all(page.as_bytes())?;
+        }
+        for (name, file) in files {
+            let mut file = match File::open(&file) {
+                Ok(file) => file,
+                // a photo that was never downloaded from iCloud
+                Err(e) => {
+                    eprintln!("{}: {e}", file.display());
+                    continue;
+                }
+            };
+            let options = if name.starts_with("photos/") {
+                stored
+            } else {
+                deflated
+            };
+            zip.start_file(format!("{root}/{name}"), options)?;
+            io::copy(&mut file, &mut zip)?;
+        }
+
+        zip.finish()?;
+        Ok(())
+    })
+    .await??;
+
+    let _ = tokio::fs::remove_dir_all(&staging).await;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::test_util::Demo;
+
+    #[tokio::test]
+    async fn zips_everything_about_a_chat() {
+        let demo = Demo::generate().await;
+        let db = demo.connect().await;
+        let dir = &demo.dir;
+        let (photos,): (i64,) = sqlx::query_as(
+            r#"
+                SELECT count(*)
+                from attachment a
+                join message_attachment_join maj
+                    on a.ROWID = maj.attachment_id
+                join chat_message_join cmj
+                    on maj.message_id = cmj.message_id
+                join chat c
+                    on cmj.chat_id = c.ROWID
+                where c.chat_identifier = 'chat100' and a.mime_type like 'image/%'
+            "#,
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+        let zip = time_capsule(&db, &Config::default(), "chat100", &Scope::default(), dir)
+            .await
+            .unwrap();
+        assert_eq!(zip, dir.join("chat100-time-capsule.zip"));
+
+        let mut zip = zip::ZipArchive::new(File::open(zip).unwrap()).unwrap();
+        let mut names: Vec<_> = zip.file_names().map(String::from).collect();
+        names.sort();
+        let pages: Vec<_> = names
+            .iter()
+            .filter(|name| !name.contains("/photos/"))
+            .map(String::as_str)
+            .collect();
+        assert_eq!(
+            pages,
+            [
+                "chat100-time-capsule/index.html",
+                "chat100-time-capsule/messages.json",
+                "chat100-time-capsule/photos.html",
+                "chat100-time-capsule/stats.html",
+                "chat100-time-capsule/transcript.html",
+            ]
+        );
+        assert!(photos > 0);
+        assert_eq!(names.len() - pages.len(), photos as usize);
+        assert!(names
+            .iter()
+            .any(|name| name.starts_with("chat100-time-capsule/photos/0001-")));
+
+        let mut cover = String::new();
+        zip.by_name("chat100-time-capsule/index.html")
+            .unwrap()
+            .read_to_string(&mut cover)
+            .unwrap();
+        assert!(cover.contains("<h1>Demo Ski Trip</h1>"), "{cover}");
+        assert!(
+            cover.contains(&format!("and {photos} photos between")),
+            "{cover}"
+        );
+
+        // the sheet links to the photos in the zip
+        let mut sheet = String::new();
+        zip.by_name("chat100-time-capsule/photos.html")
+            .unwrap()
+            .read_to_string(&mut sheet)
+            .unwrap();
+        assert!(sheet.contains("photos/0001-"));
+    }
+}
@@ -0,0 +1,291 @@
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use eyre::Result;
+use sqlx::SqlitePool;
+
+use crate::{config::Chapter, time, APPLE_EPOCH};
+
+use super::{file_stem, Scope};
+
+/// How many of the busiest days in a chat become calendar events
+const BUSY_DAYS: i64 = 10;
+
+/// An all-day calendar event
+struct Event {
+    uid: String,
+    date: NaiveDate,
+    summary: String,
+    description: String,
+}
+
+/// Write an iCalendar file of the notable days in a chat: the first message,
+/// the busiest days, and any chapters marked in the config. Returns the path
+/// that was written.
+pub async fn timeline(
+    db: &SqlitePool,
+    chat_id: &str,
+    chapters: &[Chapter],
+    scope: &Scope,
+    dir: &Path,
+) -> Result<PathBuf> {
+    let stem = file_stem(chat_id);
+    let (start, end) = scope.bounds();
+    let mut events = Vec::new();
+
+    let first = sqlx::query_as::<_, (i64, String)>(
+        r#"
+            SELECT
+                m.date, m.text
+            from message m
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            where c.chat_identifier=$1
+                and m.date >= $2 and m.date < $3
+            order by m.date
+            limit 1
+            ;
+        "#,
+    )
+    .bind(chat_id)
+    .bind(start)
+    .bind(end)
+    .fetch_optional(db)
+    .await?;
+
+    if let Some((timestamp, text)) = first {
+        events.push(Event {
+            uid: format!("{stem}-first"),
+            date: time(timestamp).date_naive(),
+            summary: format!("First message with {chat_id}"),
+            description: scope.redact(&text),
+        });
+    }
+
+    let busy_days = sqlx::query_as::<_, (String, i64)>(
+        r#"
+            SELECT
+                date(m.date / 1000000000 + $1, 'unixepoch') as day, count(*)
+            from message m
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            where c.chat_identifier=$2
+                and m.date >= $4 and m.date < $5
+            group by day
+            order by count(*) desc
+            limit $3
+            ;
+        "#,
+    )
+    .bind(APPLE_EPOCH)
+    .bind(chat_id)
+    .bind(BUSY_DAYS)
+    .bind(start)
+    .bind(end)
+    .fetch_all(db)
+    .await?;
+
+    for (day, count) in busy_days {
+        let date = NaiveDate::parse_from_str(&day, "%Y-%m-%d")?;
+
+        events.push(Event {
+            uid: format!("{stem}-busy-{date}"),
+            date,
+            summary: format!("{count} messages with {chat_id}"),
+            description: String::new(),
+        });
+    }
+
+    for chapter in chapters.iter().filter(|c| scope.contains_day(c.date)) {
+        events.push(Event {
+            uid: format!("{stem}-chapter-{}", chapter.date),
+            date: chapter.date,
+            summary: scope.redact(&chapter.title),
+            description: format!("New chapter with {chat_id}"),
+        });
+    }
+
+    events.sort_by_key(|e| e.date);
+
+    let path = dir.join(format!("{stem}-timeline.ics"));
+    tokio::fs::write(&path, calendar(&events)).await?;
+
+    Ok(path)
+}
+
+fn calendar(events: &[Event]) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ");
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//imessage-viewer//EN".to_string(),
+    ];
+
+    for event in events {
+        lines.push("BEGIN:VEVENT".into());
+        lines.push(format!("UID:{}@imessage-viewer", event.uid));
+        lines.push(format!("DTSTAMP:{stamp}"));
+        lines.push(format!(
+            "DTSTART;VALUE=DATE:{}",
+            event.date.format("%Y%m%d")
+        ));
+        lines.push(format!("SUMMARY:{}", escape(&event.summary)));
+        if !event.description.is_empty() {
+            lines.push(format!("DESCRIPTION:{}", escape(&event.description)));
+        }
+        lines.push("END:VEVENT".into());
+    }
+
+    lines.push("END:VCALENDAR".into());
+
+    lines.iter().map(|l| fold(l)).collect()
+}
+
+/// Escape text for use in a property value (RFC 5545 3.3.11)
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Split a content line into 75 octet chunks (RFC 5545 3.1), terminated with
+/// CRLF
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut len = 0;
+
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            len = 1;
+        }
+        folded.push(c);
+        len += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apple_time, test_util};
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    /// A chat `a` whose first message, on 2020-01-01, has no text, with two
+    /// more messages on 2020-01-02, and a chat `b` with one message
+    async fn scratch_db() -> SqlitePool {
+        let db = test_util::memory().await;
+        sqlx::query(
+            r#"
+                CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, chat_identifier TEXT);
+                CREATE TABLE message (ROWID INTEGER PRIMARY KEY, date INTEGER, text TEXT);
+                CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+
+                INSERT INTO chat VALUES (1, 'a'), (2, 'b');
+                INSERT INTO chat_message_join VALUES (1, 1), (1, 2), (1, 3), (2, 4);
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let messages = [
+            (1, day(2020, 1, 1), None),
+            (2, day(2020, 1, 2), Some("hi, again")),
+            (3, day(2020, 1, 2), Some("bye")),
+            (4, day(2019, 1, 1), Some("elsewhere")),
+        ];
+        for (id, day, text) in messages {
+            sqlx::query("INSERT INTO message VALUES ($1, $2, $3);")
+                .bind(id)
+                .bind(apple_time(Utc.from_utc_datetime(&day.and_hms(12, 0, 0))))
+                .bind(text)
+                .execute(&db)
+                .await
+                .unwrap();
+        }
+
+        db
+    }
+
+    /// The lines of a calendar other than the time it was written
+    fn lines(calendar: &str) -> Vec<&str> {
+        calendar
+            .split("\r\n")
+            .filter(|line| !line.starts_with("DTSTAMP:"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn writes_notable_days() {
+        let db = scratch_db().await;
+        let dir = std::env::temp_dir().join(format!("imessage-viewer-ics-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let chapters = [Chapter {
+            date: day(2020, 1, 3),
+            title: "Moved; new city".to_owned(),
+        }];
+
+        let path = timeline(&db, "a", &chapters, &Scope::default(), &dir)
+            .await
+            .unwrap();
+        let calendar = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            lines(&calendar),
+            [
+                "BEGIN:VCALENDAR",
+                "VERSION:2.0",
+                "PRODID:-//imessage-viewer//EN",
+                "BEGIN:VEVENT",
+                "UID:a-first@imessage-viewer",
+                "DTSTART;VALUE=DATE:20200101",
+                "SUMMARY:First message with a",
+                "END:VEVENT",
+                "BEGIN:VEVENT",
+                "UID:a-busy-2020-01-01@imessage-viewer",
+                "DTSTART;VALUE=DATE:20200101",
+                "SUMMARY:1 messages with a",
+                "END:VEVENT",
+                "BEGIN:VEVENT",
+                "UID:a-busy-2020-01-02@imessage-viewer",
+                "DTSTART;VALUE=DATE:20200102",
+                "SUMMARY:2 messages with a",
+                "END:VEVENT",
+                "BEGIN:VEVENT",
+                "UID:a-chapter-2020-01-03@imessage-viewer",
+                "DTSTART;VALUE=DATE:20200103",
+                r"SUMMARY:Moved\; new city",
+                "DESCRIPTION:New chapter with a",
+                "END:VEVENT",
+                "END:VCALENDAR",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes_and_folds() {
+        assert_eq!(escape("a\\b; c,\nd"), r"a\\b\; c\,\nd");
+
+        let line = format!("SUMMARY:{}", "é".repeat(40));
+        let folded = fold(&line);
+        let lines: Vec<_> = folded.split_terminator("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.len() <= 75));
+        assert_eq!(lines.concat().replacen(" ", "", 1), line);
+        assert_eq!(fold("short"), "short\r\n");
+    }
+}
@@ -0,0 +1,570 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::prelude::*;
+use eyre::Result;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tokio::{
+    fs::File,
+    io::{AsyncWriteExt, BufWriter},
+};
+
+use crate::{
+    attachment,
+    config::{HandleChange, Identity},
+    continuity, event,
+    sql::Sql,
+    time, Sender,
+};
+
+use super::{file_stem, html_escape, transcript_line, Scope};
+
+/// Messages fetched per query. Each page is written out and dropped before
+/// the next is fetched, so a chat of any size is exported in this much memory.
+const PAGE: i64 = 1000;
+
+/// Formats a whole chat can be exported in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Plain text, one message per line
+    Text,
+    Json,
+    Csv,
+    Html,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Text => "txt",
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Html => "html",
+        }
+    }
+}
+
+/// One message as written to JSON, and the columns of CSV
+#[derive(Serialize)]
+struct Record {
+    guid: String,
+    date: DateTime<Utc>,
+    sender: String,
+    is_from_me: bool,
+    service: String,
+    text: String,
+    /// Apple's transcription of a voice message
+    transcription: Option<String>,
+    attachments: Vec<String>,
+    /// What happened, for a group event like a rename rather than a message
+    event: Option<String>,
+}
+
+/// Write every message in a chat, oldest first, a page at a time. The chats
+/// with anyone who changed handle are written as one, noting the change in
+/// text and HTML, as are group events. Returns the path that was written.
+pub async fn messages(
+    db: &SqlitePool,
+    identity: &Identity,
+    changes: &[HandleChange],
+    chat_id: &str,
+    scope: &Scope,
+    format: Format,
+    dir: &Path,
+) -> Result<PathBuf> {
+    let path = dir.join(format!("{}.{}", file_stem(chat_id), format.extension()));
+    let mut out = BufWriter::new(File::create(&path).await?);
+
+    let chats = continuity::handles(changes, chat_id);
+    let mut history = continuity::history(changes, chat_id);
+    history.retain(|change| change.date.is_some_and(|day| scope.contains_day(day)));
+    let mut events = HashMap::new();
+    for chat in &chats {
+        events.extend(event::chat_events(db, chat).await?);
+    }
+
+    let title = scope.redact(identity.chat_name(chat_id, ""));
+    out.write_all(header(format, chat_id, &title, &history).as_bytes())
+        .await?;
+
+    let (start, end) = scope.bounds();
+    // where the last page ended: pages are keyed on (date, ROWID) rather than
+    // an offset, so each query picks up where the last left off
+    let mut after = (start, i64::MIN);
+    let mut first = true;
+    let mut previous = None;
+
+    loop {
+        let mut sql = Sql::new(
+            r#"
+                SELECT
+                    m.ROWID, m.guid, m.date, coalesce(h.id, ''), m.is_from_me,
+                    coalesce(m.service, c.service_name, ''), coalesce(m.text, ''),
+                    (
+                        select group_concat(coalesce(a.transfer_name, a.filename), char(10))
+                        from message_attachment_join maj
+                        join attachment a
+                            on a.ROWID = maj.attachment_id
+                        where maj.message_id = m.ROWID
+                    ),
+                    (
+                        select a.user_info
+                        from message_attachment_join maj
+                        join attachment a
+                            on a.ROWID = maj.attachment_id
+                        where maj.message_id = m.ROWID
+                            and a.mime_type like 'audio/%'
+                        limit 1
+                    )
+                from message m
+                join chat_message_join cmj
+                    on m.ROWID = cmj.message_id
+                join chat c
+                    on cmj.chat_id = c.ROWID
+                left join handle h
+                    on m.handle_id = h.ROWID
+                where c.chat_identifier in ("#,
+        );
+        for (i, chat) in chats.iter().enumerate() {
+            if i > 0 {
+                sql.push(", ");
+            }
+            sql.bind(chat.clone());
+        }
+        sql.push(") and (m.date > ")
+            .bind(after.0)
+            .push(" or (m.date = ")
+            .bind(after.0)
+            .push(" and m.ROWID > ")
+            .bind(after.1)
+            .push(")) and m.date < ")
+            .bind(end)
+            .push(" order by m.date, m.ROWID limit ")
+            .bind(PAGE)
+            .push(";");
+
+        let page = sqlx::query_as_with::<
+            _,
+            (
+                i64,
+                String,
+                i64,
+                String,
+                bool,
+                String,
+                String,
+                Option<String>,
+                Option<Vec<u8>>,
+            ),
+            _,
+        >(&sql.text, sql.args)
+        .fetch_all(db)
+        .await?;
+
+        let last = match page.last() {
+            Some((id, _, timestamp, ..)) => (*timestamp, *id),
+            None => break,
+        };
+        let full = page.len() as i64 == PAGE;
+
+        let mut chunk = String::new();
+        for (id, guid, timestamp, handle, is_from_me, service, text, files, user_info) in page {
+            let date = time(timestamp);
+            for change in continuity::between(&history, previous, date) {
+                write_notice(
+                    &mut chunk,
+                    format,
+                    &scope.redact(&continuity::notice(change)),
+                );
+            }
+            previous = Some(date);
+
+            // someone who changed handle is named by the one they use now
+            let handle = continuity::current(changes, &handle).to_owned();
+            let sender = Sender::new(is_from_me, handle);
+            // attachments show up as U+FFFC in the text
+            let text = text.replace('\u{fffc}', "").trim().to_owned();
+            let transcription = attachment::transcription(user_info);
+            let event = events
+                .get(&id)
+                .map(|event| scope.redact(&event.describe(identity, &sender)));
+
+            if let (Some(event), Format::Text | Format::Html) = (&event, format) {
+                write_notice(&mut chunk, format, event);
+                continue;
+            }
+            if format == Format::Text {
+                // a voice message reads as what was said
+                let text = match &transcription {
+                    Some(transcription) if text.is_empty() => format!("🎤 {transcription}"),
+                    _ => text,
+                };
+                transcript_line(&mut chunk, identity, scope, &sender, timestamp, &text);
+                continue;
+            }
+
+            let record = Record {
+                guid,
+                date,
+                sender: scope.redact(identity.name(&sender)),
+                is_from_me,
+                service,
+                text: scope.redact(&text),
+                transcription: transcription.map(|t| scope.redact(&t)),
+                attachments: attachment::names(files),
+                event,
+            };
+            write_record(&mut chunk, format, &record, first)?;
+            first = false;
+        }
+        out.write_all(chunk.as_bytes()).await?;
+
+        if !full {
+            break;
+        }
+        after = last;
+    }
+
+    out.write_all(footer(format).as_bytes()).await?;
+    out.flush().await?;
+
+    Ok(path)
+}
+
+fn header(format: Format, chat_id: &str, title: &str, history: &[HandleChange]) -> String {
+    match format {
+        Format::Text => String::new(),
+        Format::Json => format!(
+            "{{\"chat\":{},\"handle_changes\":{},\"messages\":[\n",
+            serde_json::Value::from(chat_id),
+            serde_json::to_string(history).unwrap_or_else(|_| "[]".to_owned()),
+        ),
+        Format::Csv => {
+            "guid,date,sender,is_from_me,service,text,transcription,attachments,event\r\n"
+                .to_owned()
+        }
+        Format::Html => format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 40em; margin: auto; }}
+.message {{ margin: 0.5em 0; }}
+.meta {{ color: gray; font-size: small; }}
+.me {{ text-align: right; }}
+.notice {{ color: gray; font-size: small; text-align: center; margin: 1em 0; }}
+p {{ margin: 0; white-space: pre-wrap; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+"#,
+            title = html_escape(title),
+        ),
+    }
+}
+
+fn footer(format: Format) -> &'static str {
+    match format {
+        Format::Text | Format::Csv => "",
+        Format::Json => "\n]}\n",
+        Format::Html => "</body>\n</html>\n",
+    }
+}
+
+fn write_record(out: &mut String, format: Format, record: &Record, first: bool) -> Result<()> {
+    match format {
+        Format::Text => {}
+        Format::Json => {
+            if !first {
+                out.push_str(",\n");
+            }
+            out.push_str(&serde_json::to_string(record)?);
+        }
+        Format::Csv => {
+            let fields = [
+                csv_field(&record.guid),
+                record.date.to_rfc3339(),
+                csv_field(&record.sender),
+                record.is_from_me.to_string(),
+                csv_field(&record.service),
+                csv_field(&record.text),
+                csv_field(record.transcription.as_deref().unwrap_or_default()),
+                csv_field(&record.attachments.join("; ")),
+                csv_field(record.event.as_deref().unwrap_or_default()),
+            ];
+            out.push_str(&fields.join(","));
+            out.push_str("\r\n");
+        }
+        Format::Html => {
+            out.push_str(&format!(
+                "<div class=\"message{}\"><div class=\"meta\">{} {}</div><p>{}</p>",
+                if record.is_from_me { " me" } else { "" },
+                record.date.format("%Y-%m-%d %H:%M"),
+                html_escape(&record.sender),
+                html_escape(&record.text),
+            ));
+            if let Some(transcription) = &record.transcription {
+                out.push_str(&format!("<p><i>🎤 {}</i></p>", html_escape(transcription)));
+            }
+            for name in &record.attachments {
+                out.push_str(&format!(
+                    "<div class=\"meta\">📎 {}</div>",
+                    html_escape(name)
+                ));
+            }
+            out.push_str("</div>\n");
+        }
+    }
+    Ok(())
+}
+
+/// Note something that happened in the chat, like someone changing number or
+/// a group being renamed, in the formats that are read by people
+fn write_notice(out: &mut String, format: Format, notice: &str) {
+    match format {
+        Format::Text => out.push_str(&format!("--- {notice} ---\n")),
+        Format::Html => out.push_str(&format!(
+            "<div class=\"notice\">{}</div>\n",
+            html_escape(notice)
+        )),
+        Format::Json | Format::Csv => {}
+    }
+}
+
+/// Quote a CSV field if it needs it, per RFC 4180
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apple_time, test_util};
+    use regex::Regex;
+
+    fn at(day: u32, hour: u32) -> i64 {
+        apple_time(Utc.ymd(2020, 1, day).and_hms(hour, 0, 0))
+    }
+
+    /// Someone who messaged from `old` until 2020-01-02 and `new` after,
+    /// with a voice message, a group event and a message with a photo in
+    /// `new`
+    async fn scratch_db() -> SqlitePool {
+        let db = test_util::memory().await;
+        sqlx::query(
+            r#"
+                CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, chat_identifier TEXT, service_name TEXT);
+                CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT);
+                CREATE TABLE message (
+                    ROWID INTEGER PRIMARY KEY, guid TEXT, date INTEGER, handle_id INTEGER,
+                    is_from_me INTEGER, service TEXT, text TEXT, item_type INTEGER DEFAULT 0,
+                    group_action_type INTEGER, other_handle INTEGER, group_title TEXT
+                );
+                CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+                CREATE TABLE attachment (
+                    ROWID INTEGER PRIMARY KEY, filename TEXT, transfer_name TEXT, mime_type TEXT,
+                    user_info BLOB
+                );
+                CREATE TABLE message_attachment_join (message_id INTEGER, attachment_id INTEGER);
+
+                INSERT INTO chat VALUES (1, 'old', 'iMessage'), (2, 'new', 'SMS');
+                INSERT INTO handle VALUES (1, 'old'), (2, 'new');
+                INSERT INTO chat_message_join VALUES (1, 1), (2, 2), (2, 3), (2, 4);
+                INSERT INTO attachment VALUES
+                    (1, '~/a/Audio Message.caf', NULL, 'audio/x-caf', $1),
+                    (2, '~/a/IMG_0001.jpeg', 'beach.jpeg', 'image/jpeg', NULL);
+                INSERT INTO message_attachment_join VALUES (2, 1), (4, 2);
+            "#,
+        )
+        .bind({
+            let info = plist::Value::Dictionary(
+                [(
+                    "audio-transcription".to_owned(),
+                    plist::Value::String("call me back".to_owned()),
+                )]
+                .into_iter()
+                .collect(),
+            );
+            let mut data = Vec::new();
+            info.to_writer_binary(&mut data).unwrap();
+            data
+        })
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let messages = [
+            (1, at(1, 12), 1, false, None, "hello, \"you\"", 0, None),
+            (2, at(3, 9), 2, false, None, "\u{fffc}", 0, None),
+            (3, at(3, 10), 2, false, Some("SMS"), "", 2, Some("Trip")),
+            (
+                4,
+                at(3, 12),
+                0,
+                true,
+                None,
+                "\u{fffc}my secret beach",
+                0,
+                None,
+            ),
+        ];
+        for (id, date, handle, is_from_me, service, text, item_type, title) in messages {
+            sqlx::query(
+                "INSERT INTO message (ROWID, guid, date, handle_id, is_from_me, service, text, \
+                 item_type, group_title) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9);",
+            )
+            .bind(id)
+            .bind(format!("guid-{id}"))
+            .bind(date)
+            .bind(handle)
+            .bind(is_from_me)
+            .bind(service)
+            .bind(text)
+            .bind(item_type)
+            .bind(title)
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+
+        db
+    }
+
+    fn changes() -> Vec<HandleChange> {
+        vec![HandleChange {
+            from: "old".to_owned(),
+            to: "new".to_owned(),
+            date: Some(NaiveDate::from_ymd(2020, 1, 2)),
+        }]
+    }
+
+    fn scope() -> Scope {
+        Scope {
+            redact: vec![Regex::new("secret").unwrap()],
+            ..Scope::default()
+        }
+    }
+
+    /// Export `chat_id` and read back what was written
+    async fn export(db: &SqlitePool, chat_id: &str, scope: &Scope, format: Format) -> String {
+        let dir = std::env::temp_dir().join(format!(
+            "imessage-viewer-export-{}-{chat_id}-{format:?}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = messages(
+            db,
+            &Identity::default(),
+            &changes(),
+            chat_id,
+            scope,
+            format,
+            &dir,
+        )
+        .await
+        .unwrap();
+        let written = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        written
+    }
+
+    #[tokio::test]
+    async fn writes_transcripts() {
+        let db = scratch_db().await;
+
+        assert_eq!(
+            export(&db, "new", &scope(), Format::Text).await,
+            "2020-01-01 12:00 new: hello, \"you\"\n\
+             --- old became new on 2020-01-02 ---\n\
+             2020-01-03 09:00 new: 🎤 call me back\n\
+             --- new named the conversation “Trip” ---\n\
+             2020-01-03 12:00 Me: my [redacted] beach\n"
+        );
+
+        // only what's in scope
+        let scope = Scope {
+            before: Some(NaiveDate::from_ymd(2020, 1, 2)),
+            ..scope()
+        };
+        assert_eq!(
+            export(&db, "old", &scope, Format::Text).await,
+            "2020-01-01 12:00 new: hello, \"you\"\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_records() {
+        let db = scratch_db().await;
+
+        let json: serde_json::Value =
+            serde_json::from_str(&export(&db, "new", &scope(), Format::Json).await).unwrap();
+        assert_eq!(json["chat"], "new");
+        assert_eq!(json["handle_changes"][0]["from"], "old");
+        let messages = json["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0]["service"], "iMessage");
+        assert_eq!(messages[1]["transcription"], "call me back");
+        assert_eq!(messages[2]["event"], "new named the conversation “Trip”");
+        assert_eq!(messages[3]["sender"], "Me");
+        assert_eq!(messages[3]["text"], "my [redacted] beach");
+        assert_eq!(messages[3]["attachments"][0], "beach.jpeg");
+
+        let csv = export(&db, "new", &scope(), Format::Csv).await;
+        let rows: Vec<_> = csv.split_terminator("\r\n").collect();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(
+            rows[1],
+            "guid-1,2020-01-01T12:00:00+00:00,new,false,iMessage,\"hello, \"\"you\"\"\",,,"
+        );
+    }
+
+    #[tokio::test]
+    async fn pages_through_messages_sent_at_once() {
+        let db = scratch_db().await;
+        sqlx::query(
+            r#"
+                WITH RECURSIVE n(i) AS (SELECT 100 UNION ALL SELECT i + 1 FROM n WHERE i < 1100)
+                INSERT INTO message (ROWID, guid, date, handle_id, is_from_me, text)
+                SELECT i, 'guid-' || i, $1, 0, 1, 'again' FROM n;
+                INSERT INTO chat_message_join SELECT 1, ROWID FROM message WHERE ROWID >= 100;
+            "#,
+        )
+        .bind(at(1, 13))
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&export(&db, "old", &Scope::default(), Format::Json).await)
+                .unwrap();
+        let guids: Vec<_> = json["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["guid"].as_str().unwrap().to_owned())
+            .collect();
+        let expected: Vec<_> = std::iter::once("guid-1".to_owned())
+            .chain((100..=1100).map(|i| format!("guid-{i}")))
+            .chain((2..=4).map(|i| format!("guid-{i}")))
+            .collect();
+        assert_eq!(guids, expected);
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use eyre::{eyre, Result};
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use sqlx::SqlitePool;
+use tokio::sync::Semaphore;
+
+use crate::{
+    attachment::expand_path,
+    audit::Pool,
+    cancel,
+    config::{Config, ExportFormat, ExportProfile, HandleChange},
+    search, Handle, State,
+};
+
+use super::{contact_sheet, default_dir, messages, timeline, transcript, Format, Scope};
+
+/// Most chats exported at once: two for each connection of the pool exports
+/// run on. Each writes its own file, so while one waits on a query another
+/// can be formatting or writing.
+const CONCURRENCY: usize = 2 * Pool::Bulk.connections() as usize;
+
+/// Run an export profile over each of its chats, several at a time, counting
+/// them into `progress` as (chats exported, chats to export) as each is
+/// written. Returns the directory the files were written to.
+pub async fn run(
+    db: &SqlitePool,
+    config: &Config,
+    profile: &ExportProfile,
+    progress: &Handle<(usize, usize)>,
+) -> Result<PathBuf> {
+    let redact = profile
+        .redact
+        .iter()
+        .map(|pattern| {
+            search::compile_regex(pattern)
+                .map_err(|e| eyre!("invalid redaction pattern {pattern:?}: {e}"))
+        })
+        .collect::<Result<_>>()?;
+    let scope = Scope {
+        after: profile.after,
+        before: profile.before,
+        redact,
+    };
+
+    let dir = match &profile.destination {
+        Some(dir) => expand_path(&dir.to_string_lossy()),
+        None => default_dir(),
+    };
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let chats = if profile.chats.is_empty() {
+        sqlx::query_as::<_, (String,)>("SELECT DISTINCT chat_identifier from chat;")
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .map(|(chat,)| chat)
+            .collect()
+    } else {
+        profile.chats.clone()
+    };
+
+    let total = chats.len();
+    progress.set(State::Ready((0, total)));
+
+    let config = Arc::new(config.clone());
+    let changes: Arc<[HandleChange]> = config.changes().into();
+    let scope = Arc::new(scope);
+    let semaphore = Arc::new(Semaphore::new(CONCURRENCY));
+    // dropped when a chat fails, which stops the others rather than leaving
+    // them writing files after the export has been reported as failed
+    let loads = cancel::Loads::new();
+    let mut tasks = FuturesUnordered::new();
+
+    for chat in chats {
+        let db = db.clone();
+        let config = config.clone();
+        let changes = changes.clone();
+        let scope = scope.clone();
+        let dir = dir.clone();
+        let format = profile.format;
+        let semaphore = semaphore.clone();
+        let progress = progress.clone();
+
+        tasks.push(tokio::spawn(loads.run(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            export_chat(&db, &config, &changes, &chat, &scope, format, &dir).await?;
+            progress.update(|(done, _)| *done += 1);
+
+            Ok(())
+        })));
+    }
+
+    // as they finish, so the first to fail is noticed straight away
+    while let Some(task) = tasks.next().await {
+        task??;
+    }
+
+    Ok(dir)
+}
+
+/// Write one chat of a profile in its format
+async fn export_chat(
+    db: &SqlitePool,
+    config: &Config,
+    changes: &[HandleChange],
+    chat: &str,
+    scope: &Scope,
+    format: ExportFormat,
+    dir: &Path,
+) -> Result<()> {
+    match format {
+        ExportFormat::Transcript => {
+            transcript(db, &config.me, changes, chat, scope, dir).await?;
+        }
+        ExportFormat::Photos => {
+            contact_sheet(db, &config.me, chat, scope, config.heic_export, dir).await?;
+        }
+        ExportFormat::Timeline => {
+            let chapters = config.chapters.get(chat).cloned().unwrap_or_default();
+            timeline(db, chat, &chapters, scope, dir).await?;
+        }
+        ExportFormat::Json => {
+            messages(db, &config.me, changes, chat, scope, Format::Json, dir).await?;
+        }
+        ExportFormat::Csv => {
+            messages(db, &config.me, changes, chat, scope, Format::Csv, dir).await?;
+        }
+        ExportFormat::Html => {
+            messages(db, &config.me, changes, chat, scope, Format::Html, dir).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Look up a profile by the name given on the command line
+pub fn find<'a>(config: &'a Config, name: &str) -> Result<&'a ExportProfile> {
+    config
+        .export_profiles
+        .iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| {
+            eyre!(
+                "no export profile named {name:?} in {}",
+                Config::path().display()
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Demo;
+
+    fn profile(dir: &Path, chats: &[&str], redact: &[&str]) -> ExportProfile {
+        ExportProfile {
+            name: "backup".to_owned(),
+            format: ExportFormat::Json,
+            chats: chats.iter().map(|c| c.to_string()).collect(),
+            after: None,
+            before: None,
+            redact: redact.iter().map(|r| r.to_string()).collect(),
+            destination: Some(dir.to_owned()),
+        }
+    }
+
+    fn files(dir: &Path) -> usize {
+        std::fs::read_dir(dir).unwrap().count()
+    }
+
+    #[tokio::test]
+    async fn exports_every_chat() {
+        let demo = Demo::generate().await;
+        let db = demo.connect().await;
+        let dir = &demo.dir;
+        let (chats,): (i64,) = sqlx::query_as("SELECT count(DISTINCT chat_identifier) from chat;")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+
+        let config = Config::default();
+        let progress = Handle::new();
+        let out = dir.join("all");
+        let written = run(&db, &config, &profile(&out, &[], &[]), &progress)
+            .await
+            .unwrap();
+        assert_eq!(written, out);
+        assert_eq!(files(&out), chats as usize);
+        assert!(
+            matches!(*progress.get(), State::Ready((done, total)) if done == total && done == chats as usize)
+        );
+
+        let out = dir.join("one");
+        run(&db, &config, &profile(&out, &["chat100"], &[]), &progress)
+            .await
+            .unwrap();
+        assert_eq!(files(&out), 1);
+
+        let error = run(&db, &config, &profile(&out, &[], &["("]), &progress)
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("invalid redaction pattern \"(\""));
+    }
+
+    #[test]
+    fn finds_profiles_by_name() {
+        let config = Config {
+            export_profiles: vec![profile(Path::new("out"), &[], &[])],
+            ..Default::default()
+        };
+        assert_eq!(find(&config, "backup").unwrap().name, "backup");
+        assert!(find(&config, "Backup").is_err());
+    }
+}
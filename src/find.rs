@@ -0,0 +1,274 @@
+use std::ops::Range;
+
+use egui::{
+    text::{LayoutJob, TextFormat},
+    Color32, Stroke, TextStyle, Ui,
+};
+
+use crate::{perf, search::fold, Message};
+
+/// State of the find-in-chat bar (Cmd+F)
+#[derive(Default)]
+pub struct Find {
+    pub query: String,
+    /// Index into `hits` of the match currently scrolled to
+    pub current: usize,
+    /// Indices of the messages that contain `query`
+    pub hits: Vec<usize>,
+    /// Set when the view should scroll to the current match on the next frame
+    pub scroll_pending: bool,
+    /// Set when the query box should grab keyboard focus on the next frame
+    pub focus_pending: bool,
+    /// Ids of the messages in `hits`
+    hit_ids: Vec<i64>,
+    hits_for: Option<(String, usize)>,
+}
+
+impl Find {
+    pub fn open() -> Self {
+        Self {
+            focus_pending: true,
+            ..Self::default()
+        }
+    }
+
+    /// Recompute `hits` if the query or the message list changed since last
+    /// time
+    pub fn update_hits(&mut self, messages: &[Message]) {
+        let key = (self.query.clone(), messages.len());
+        let hit = self.hits_for.as_ref() == Some(&key);
+        perf::cache_lookup("find hits", hit);
+        if hit {
+            return;
+        }
+
+        puffin::profile_function!();
+
+        // a page loaded while finding shifts every index, so stay on the
+        // same message rather than going back to the first match
+        let same_query = self
+            .hits_for
+            .as_ref()
+            .is_some_and(|(query, _)| *query == self.query);
+        let current = self
+            .hit_ids
+            .get(self.current)
+            .filter(|_| same_query)
+            .copied();
+
+        self.hits = messages
+            .iter()
+            .enumerate()
+            .filter(|(_, msg)| !matches(&msg.text, &self.query).is_empty())
+            .map(|(i, _)| i)
+            .collect();
+        self.hit_ids = self.hits.iter().map(|&i| messages[i].id).collect();
+
+        match current.and_then(|id| self.hit_ids.iter().position(|&hit| hit == id)) {
+            Some(current) => self.current = current,
+            None => {
+                self.current = 0;
+                self.scroll_pending = !self.hits.is_empty();
+            }
+        }
+        self.hits_for = Some(key);
+    }
+
+    pub fn next(&mut self) {
+        if !self.hits.is_empty() {
+            self.current = (self.current + 1) % self.hits.len();
+            self.scroll_pending = true;
+        }
+    }
+
+    pub fn prev(&mut self) {
+        if !self.hits.is_empty() {
+            self.current = (self.current + self.hits.len() - 1) % self.hits.len();
+            self.scroll_pending = true;
+        }
+    }
+
+    /// Forget cached hits, e.g. because a different chat was opened
+    pub fn invalidate(&mut self) {
+        self.hits_for = None;
+    }
+
+    /// Index of the message holding the current match
+    pub fn current_message(&self) -> Option<usize> {
+        self.hits.get(self.current).copied()
+    }
+}
+
+/// Byte ranges of every occurrence of `needle` in `text`, compared the way
+/// search compares text (ignoring case, and accents if configured)
+pub fn matches(text: &str, needle: &str) -> Vec<Range<usize>> {
+    let needle = fold::fold(needle);
+    if needle.is_empty() {
+        return Vec::new();
+    }
+
+    let (folded, origins) = fold::mapped(text, true);
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+
+    for (start, m) in folded.match_indices(&needle) {
+        let range = origins[start].start..origins[start + m.len() - 1].end;
+
+        // a character that folds to several (ß -> ss) can match twice
+        if ranges.last().is_some_and(|last| range.start < last.end) {
+            continue;
+        }
+        ranges.push(range);
+    }
+
+    ranges
+}
+
+/// Lay out `text` with the given ranges highlighted, and `mentions` in blue
+/// and underlined (egui has no bold). A highlight wins where they overlap.
+pub fn highlighted(
+    ui: &Ui,
+    text: &str,
+    ranges: &[Range<usize>],
+    mentions: &[Range<usize>],
+) -> LayoutJob {
+    let normal = TextFormat {
+        font_id: TextStyle::Body.resolve(ui.style()),
+        color: ui.visuals().text_color(),
+        ..Default::default()
+    };
+    let highlight = TextFormat {
+        color: Color32::BLACK,
+        background: Color32::YELLOW,
+        ..normal.clone()
+    };
+    // a blue that stands out from the text, whichever bubble it's on
+    let blue = if normal.color.r() > 128 {
+        Color32::LIGHT_BLUE
+    } else {
+        Color32::DARK_BLUE
+    };
+    let mention = TextFormat {
+        color: blue,
+        underline: Stroke::new(1.0, blue),
+        ..normal.clone()
+    };
+
+    let mut bounds: Vec<usize> = ranges
+        .iter()
+        .chain(mentions)
+        .flat_map(|r| [r.start, r.end])
+        .chain([0, text.len()])
+        .collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut job = LayoutJob::default();
+    for span in bounds.windows(2) {
+        let inside = |ranges: &[Range<usize>]| {
+            ranges
+                .iter()
+                .any(|r| r.start <= span[0] && span[1] <= r.end)
+        };
+        let format = if inside(ranges) {
+            &highlight
+        } else if inside(mentions) {
+            &mention
+        } else {
+            &normal
+        };
+        job.append(&text[span[0]..span[1]], 0.0, format.clone());
+    }
+
+    job
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{time, Sender};
+
+    fn message(id: i64, text: &str) -> Message {
+        Message {
+            id,
+            guid: String::new(),
+            sticker_on: None,
+            tapback_on: None,
+            text: text.to_owned(),
+            attachments: Vec::new(),
+            images: Vec::new(),
+            audio: Vec::new(),
+            service: String::new(),
+            effect: None,
+            balloon: None,
+            location: None,
+            contacts: Vec::new(),
+            event: None,
+            mentions: Vec::new(),
+            collapsed: false,
+            stickers: Vec::new(),
+            reactions: Vec::new(),
+            transcription: None,
+            date: time(0),
+            sender: Sender::Me,
+        }
+    }
+
+    #[test]
+    fn matches_ignoring_case_and_accents() {
+        let text = "Café? CAFE! cafés";
+        let found: Vec<_> = matches(text, "cafe")
+            .into_iter()
+            .map(|r| &text[r])
+            .collect();
+        assert_eq!(found, ["Café", "CAFE", "café"]);
+
+        assert!(matches("anything", "").is_empty());
+        assert!(matches("anything", "nothing").is_empty());
+        // ranges are of whole characters in the original text
+        let text = "naïve";
+        let found: Vec<_> = matches(text, "I").into_iter().map(|r| &text[r]).collect();
+        assert_eq!(found, ["ï"]);
+    }
+
+    #[test]
+    fn stays_on_the_current_match() {
+        let mut messages: Vec<_> = ["lunch?", "sure", "lunch at 1", "ok", "LUNCH"]
+            .iter()
+            .enumerate()
+            .map(|(i, text)| message(i as i64 + 10, text))
+            .collect();
+
+        let mut find = Find::open();
+        find.query = "lunch".to_owned();
+        find.update_hits(&messages);
+        assert_eq!(find.hits, [0, 2, 4]);
+        assert_eq!(find.current_message(), Some(0));
+        assert!(find.scroll_pending);
+
+        find.prev();
+        assert_eq!(find.current_message(), Some(4));
+        find.next();
+        find.next();
+        assert_eq!(find.current_message(), Some(2));
+
+        // an older page loaded above
+        messages.splice(0..0, [message(1, "lunch tomorrow?"), message(2, "maybe")]);
+        find.scroll_pending = false;
+        find.update_hits(&messages);
+        assert_eq!(find.hits, [0, 2, 4, 6]);
+        assert_eq!(find.current_message(), Some(4));
+        assert!(!find.scroll_pending);
+
+        // a new query starts again from the first match
+        find.query = "ok".to_owned();
+        find.update_hits(&messages);
+        assert_eq!(find.hits, [5]);
+        assert_eq!(find.current_message(), Some(5));
+
+        find.query = "nowhere".to_owned();
+        find.update_hits(&messages);
+        assert_eq!(find.current_message(), None);
+        find.next();
+        assert_eq!(find.current, 0);
+    }
+}
@@ -0,0 +1,66 @@
+/// Score how well `pattern` fuzzily matches `candidate`: every letter and
+/// digit of the pattern has to appear in the candidate in order, ignoring
+/// case. Runs of consecutive characters and matches at the start of words
+/// score higher. Punctuation and spaces in the pattern are ignored, so
+/// "555 0100" matches "+15555550100".
+pub fn score(pattern: &str, candidate: &str) -> Option<i64> {
+    let mut score = 0;
+    let mut chars = candidate.chars().enumerate();
+    let mut prev: Option<char> = None;
+    let mut last_match = None;
+
+    for p in pattern
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+    {
+        loop {
+            let (i, c) = chars.next()?;
+            let word_start = !prev.is_some_and(char::is_alphanumeric);
+            prev = Some(c);
+
+            if c.to_lowercase().eq(std::iter::once(p)) {
+                score += 1;
+                if word_start {
+                    score += 8;
+                }
+                if last_match.is_some_and(|last| last + 1 == i) {
+                    score += 4;
+                }
+                last_match = Some(i);
+                break;
+            }
+        }
+    }
+
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_in_order() {
+        assert!(score("bob", "Bob Smith").is_some());
+        assert!(score("bsm", "Bob Smith").is_some());
+        assert_eq!(score("smb", "Bob Smith"), None);
+        assert_eq!(score("bobby", "Bob"), None);
+        assert_eq!(score("", "Bob"), Some(0));
+    }
+
+    #[test]
+    fn ignores_punctuation_in_the_pattern() {
+        assert!(score("555 0100", "+15555550100").is_some());
+        assert!(score("(555) 0100", "+15555550100").is_some());
+    }
+
+    #[test]
+    fn prefers_runs_and_word_starts() {
+        let word_start = score("sm", "Bob Smith").unwrap();
+        let scattered = score("sm", "Sam Mills").unwrap();
+        let middle = score("sm", "Rasmus").unwrap();
+        assert!(word_start > scattered, "{word_start} > {scattered}");
+        assert!(word_start > middle, "{word_start} > {middle}");
+    }
+}
@@ -0,0 +1,93 @@
+use chrono::prelude::*;
+use egui::{Sense, Ui};
+
+use crate::{attachment::MediaItem, config::Identity, i18n, images::Images};
+
+/// Width and height of each tile in the grid
+const TILE: f32 = 120.0;
+
+/// Grid of every attachment in a chat, grouped by month. Returns the ROWID of
+/// the message an attachment was sent in if it was clicked.
+pub fn show(
+    ui: &mut Ui,
+    identity: &Identity,
+    items: &[MediaItem],
+    images: &mut Images,
+) -> Option<i64> {
+    puffin::profile_function!();
+
+    if items.is_empty() {
+        ui.label(i18n::t("nothing has been shared in this chat"));
+        return None;
+    }
+
+    let mut clicked = None;
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        let same_month = |a: &MediaItem, b: &MediaItem| {
+            a.date.year() == b.date.year() && a.date.month() == b.date.month()
+        };
+
+        for month in items.chunk_by(same_month) {
+            ui.heading(i18n::month_year(&month[0].date));
+
+            ui.horizontal_wrapped(|ui| {
+                for item in month {
+                    let response = if item.mime_type.starts_with("image/") {
+                        images.tile(ui, &item.attachment, TILE)
+                    } else {
+                        file_tile(ui, item)
+                    };
+
+                    let response = response.on_hover_text(format!(
+                        "{}\n{} {}",
+                        item.attachment.name,
+                        identity.name(&item.sender),
+                        item.date.format("%Y-%m-%d %H:%M"),
+                    ));
+                    if response.clicked() {
+                        clicked = Some(item.message);
+                    }
+                }
+            });
+        }
+    });
+
+    clicked
+}
+
+/// Tile with an icon and the file name, for anything that isn't a photo
+fn file_tile(ui: &mut Ui, item: &MediaItem) -> egui::Response {
+    let icon = match item.mime_type.split('/').next() {
+        Some("video") => "🎞",
+        Some("audio") => "🎤",
+        _ => "📄",
+    };
+
+    let (rect, response) = ui.allocate_exact_size(egui::Vec2::splat(TILE), Sense::click());
+    if ui.is_rect_visible(rect) {
+        ui.painter()
+            .rect_filled(rect, 4.0, ui.visuals().faint_bg_color);
+        ui.painter().text(
+            rect.center() - egui::vec2(0.0, TILE / 8.0),
+            egui::Align2::CENTER_CENTER,
+            icon,
+            egui::FontId::proportional(TILE / 3.0),
+            ui.visuals().text_color(),
+        );
+
+        let galley = ui.painter().layout(
+            item.attachment.name.clone(),
+            egui::FontId::proportional(11.0),
+            ui.visuals().text_color(),
+            TILE - 8.0,
+        );
+        let name_pos = egui::pos2(
+            rect.left() + 4.0,
+            rect.bottom() - galley.size().y.min(TILE / 3.0) - 4.0,
+        );
+        ui.painter().with_clip_rect(rect).galley(name_pos, galley);
+    }
+
+    response
+}
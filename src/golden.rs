@@ -0,0 +1,274 @@
+//! Golden transcripts: each chat of a fixture database, written out as text
+//! and HTML exports and as the messages the viewer decodes for it, compared
+//! with known-good copies in `tests/golden`. A change to the queries or the
+//! decoding that changes any of them fails here.
+//!
+//! After an intended change, run `UPDATE_GOLDEN=1 cargo test golden` to
+//! rewrite the files, and review their diff.
+
+use std::{fs, path::Path};
+
+use egui::Align;
+use sqlx::SqlitePool;
+
+use crate::{
+    apple_time,
+    audit::{self, Audit},
+    config::{Config, ExportFormat, ExportProfile, Identity},
+    export::{self, Format, Scope},
+    ignore,
+    paging::{self, Page, Window},
+    tapback,
+    test_util::Demo,
+    Handle, Message, State,
+};
+
+/// The chats of the demo database
+const CHATS: &[&str] = &[
+    "+15555550100",
+    "chat100",
+    "+15555550123",
+    "demo@example.com",
+];
+
+/// Stands in for the fixture's directory, which is different every run
+const DIR: &str = "$FIXTURE";
+
+/// A freshly generated demo database, opened read-only like a real one
+async fn fixture() -> (Demo, SqlitePool) {
+    let demo = Demo::generate().await;
+    let audit = Audit {
+        read_only: true,
+        ..Audit::default()
+    };
+    let db = demo.connect_with(audit, audit::Pool::Interactive).await;
+
+    (demo, db)
+}
+
+/// Compare `actual` with the golden file `name`, or replace the file with it
+/// when `UPDATE_GOLDEN` is set
+fn check(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "{} is missing, run with UPDATE_GOLDEN=1 to create it",
+            path.display()
+        )
+    });
+    if expected == actual {
+        return;
+    }
+
+    let (line, (expected, actual)) = expected
+        .lines()
+        .chain(std::iter::repeat("<end of file>"))
+        .zip(actual.lines().chain(std::iter::repeat("<end of file>")))
+        .enumerate()
+        .find(|(_, (expected, actual))| expected != actual)
+        .unwrap();
+    panic!(
+        "{name} differs from its golden file at line {}:\n- {expected}\n+ {actual}\n\
+         run with UPDATE_GOLDEN=1 if the change is intended",
+        line + 1
+    );
+}
+
+/// A file name for the golden copy of a chat's output
+fn golden_name(chat: &str, extension: &str) -> String {
+    format!("{}.{extension}", export::file_stem(chat))
+}
+
+async fn check_export(format: Format) {
+    let (demo, db) = fixture().await;
+    let dir = &demo.dir;
+    let identity = Identity::default();
+    let out = dir.join("export");
+    fs::create_dir_all(&out).unwrap();
+
+    for chat in CHATS {
+        let scope = Scope::default();
+        let path = export::messages(&db, &identity, &[], chat, &scope, format, &out)
+            .await
+            .unwrap();
+        let written = fs::read_to_string(path).unwrap();
+        check(
+            &golden_name(chat, format.extension()),
+            &written.replace(dir.to_str().unwrap(), DIR),
+        );
+    }
+}
+
+#[tokio::test]
+async fn text_transcripts() {
+    check_export(Format::Text).await;
+}
+
+#[tokio::test]
+async fn html_transcripts() {
+    check_export(Format::Html).await;
+}
+
+/// One message as the viewer decoded it, with everything drawn in or around
+/// its bubble on lines of its own
+fn canonical(identity: &Identity, message: &Message) -> String {
+    let mut text = format!(
+        "{} {} [{}] {}: {}\n",
+        message.date.format("%Y-%m-%d %H:%M"),
+        message.guid,
+        message.service,
+        identity.name(&message.sender),
+        message.text,
+    );
+    let mut detail = |label: &str, value: String| text.push_str(&format!("    {label}: {value}\n"));
+
+    if let Some(event) = &message.event {
+        detail("event", event.describe(identity, &message.sender));
+    }
+    for mention in &message.mentions {
+        let name = &message.text[mention.range.clone()];
+        detail("mention", format!("{name} -> {}", mention.handle));
+    }
+    for name in &message.attachments {
+        detail("attachment", name.clone());
+    }
+    for image in &message.images {
+        detail("image", image.name.clone());
+    }
+    for path in &message.audio {
+        detail("audio", path.display().to_string());
+    }
+    if let Some(transcription) = &message.transcription {
+        detail("transcription", transcription.clone());
+    }
+    for sticker in &message.stickers {
+        detail("sticker", sticker.name.clone());
+    }
+    for (tapback, senders) in tapback::summarize(&message.reactions) {
+        let names: Vec<&str> = senders.iter().map(|s| identity.name(s)).collect();
+        detail(
+            "tapback",
+            format!("{} {}", tapback.emoji(), names.join(", ")),
+        );
+    }
+    if let Some(effect) = &message.effect {
+        detail("effect", effect.clone());
+    }
+    if let Some(balloon) = &message.balloon {
+        detail("balloon", format!("{balloon:?}"));
+    }
+    if let Some(location) = &message.location {
+        detail("location", format!("{location:?}"));
+    }
+    for contact in &message.contacts {
+        detail("contact", format!("{contact:?}"));
+    }
+    if message.collapsed {
+        detail("collapsed", "true".to_owned());
+    }
+
+    text
+}
+
+/// The window a chat opens to at `page`, once all of it has streamed in
+async fn window(db: &SqlitePool, rules: &ignore::Rules, chat: &str, page: Page) -> Window {
+    let handle = Handle::new();
+    paging::load(db, rules, &[], chat, page, &handle)
+        .await
+        .unwrap();
+    let state = handle.get();
+    match &*state {
+        State::Ready(window) => window.clone(),
+        _ => panic!("{chat} didn't load"),
+    }
+}
+
+#[tokio::test]
+async fn decoded_messages() {
+    let (demo, db) = fixture().await;
+    let dir = &demo.dir;
+    let identity = Identity::default();
+    let rules = ignore::Rules::compile(&[]).unwrap();
+
+    for chat in CHATS {
+        let window = window(&db, &rules, chat, Page::Newest).await;
+        assert!(!window.older, "{chat} is bigger than a page");
+
+        let decoded: String = window
+            .messages
+            .iter()
+            .map(|message| canonical(&identity, message))
+            .collect();
+        check(
+            &golden_name(chat, "messages.txt"),
+            &decoded.replace(dir.to_str().unwrap(), DIR),
+        );
+    }
+}
+
+/// Opening a chat part way through reads the messages either side of the
+/// one asked for in two halves, newer then older, which have to put
+/// together into the same run of messages as opening it at the end does
+#[tokio::test]
+async fn pages_around_a_message() {
+    let (_demo, db) = fixture().await;
+    let rules = ignore::Rules::compile(&[]).unwrap();
+
+    for chat in CHATS {
+        let newest = window(&db, &rules, chat, Page::Newest).await;
+        let ids: Vec<_> = newest.messages.iter().map(|m| m.id).collect();
+        let middle = &newest.messages[newest.messages.len() / 2];
+
+        let around = window(&db, &rules, chat, Page::Around(middle.id)).await;
+        let around_ids: Vec<_> = around.messages.iter().map(|m| m.id).collect();
+        assert_eq!(around_ids, ids, "{chat}");
+        assert_eq!(around.scroll_to, Some((middle.id, Align::Center)));
+
+        let date = apple_time(middle.date);
+        let on_day = window(&db, &rules, chat, Page::AroundDate(date)).await;
+        let on_day_ids: Vec<_> = on_day.messages.iter().map(|m| m.id).collect();
+        assert_eq!(on_day_ids, ids, "{chat}");
+        let (first_after, _) = on_day.scroll_to.unwrap();
+        let first_after = on_day.messages.iter().find(|m| m.id == first_after);
+        assert!(first_after.unwrap().date >= middle.date);
+    }
+}
+
+/// A chat of an export profile that can't be written fails the export, and
+/// stops the rest, rather than it being reported done with a file missing
+#[tokio::test]
+async fn profile_fails_on_a_chat_it_cannot_write() {
+    let (demo, db) = fixture().await;
+    let dir = &demo.dir;
+    let out = dir.join("profile");
+    // a directory where one chat's file would go
+    fs::create_dir_all(out.join(format!("{}.json", export::file_stem("chat100")))).unwrap();
+
+    let profile = ExportProfile {
+        name: "everything".to_owned(),
+        format: ExportFormat::Json,
+        chats: Vec::new(),
+        after: None,
+        before: None,
+        redact: Vec::new(),
+        destination: Some(out),
+    };
+    let progress = Handle::new();
+    let result = export::profile::run(&db, &Config::default(), &profile, &progress).await;
+    assert!(result.is_err());
+
+    let done = match &*progress.get() {
+        State::Ready((done, _)) => *done,
+        _ => panic!("no progress"),
+    };
+    assert!(done < CHATS.len());
+}
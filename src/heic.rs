@@ -0,0 +1,126 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    cache,
+    config::{HeicConversion, HeicFormat},
+};
+
+/// Whether `path` looks like a HEIC/HEIF image, which is how iPhones save
+/// photos by default
+pub fn is_heic(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("heic") || ext.eq_ignore_ascii_case("heif"))
+        .unwrap_or(false)
+}
+
+/// A version of the image at `path` that the image crate, browsers and
+/// tesseract can all read. HEIC files are converted to JPEG the first time
+/// they're asked for and the result is kept in the cache directory; anything
+/// else is returned as is. Blocks while converting.
+pub fn readable(path: &Path) -> io::Result<PathBuf> {
+    converted(path, HeicConversion::default())
+}
+
+/// Like [`readable`], converting HEIC files to the given format and quality
+pub fn converted(path: &Path, conversion: HeicConversion) -> io::Result<PathBuf> {
+    if !is_heic(path) {
+        return Ok(path.to_owned());
+    }
+
+    // each format and quality gets its own directory, keeping the default's
+    // where it has always been
+    let extension = conversion.format.extension();
+    let kind = match conversion.format {
+        HeicFormat::Jpeg if conversion != HeicConversion::default() => {
+            format!("heic-q{}", conversion.quality)
+        }
+        _ => "heic".to_owned(),
+    };
+
+    let converted = cache::converted_path(&kind, path, extension);
+    if converted.exists() {
+        return Ok(converted);
+    }
+
+    // convert next to the final name so that an interrupted conversion is
+    // never mistaken for a finished one
+    fs::create_dir_all(converted.parent().unwrap())?;
+    let partial = converted.with_extension(format!("partial.{extension}"));
+    convert(path, &partial, conversion)?;
+    fs::rename(&partial, &converted)?;
+
+    Ok(converted)
+}
+
+/// Convert with whichever tool is installed: `sips` ships with macOS, and
+/// `heif-convert` comes with libheif everywhere else (and picks the format
+/// from the extension of `to`)
+fn convert(from: &Path, to: &Path, conversion: HeicConversion) -> io::Result<()> {
+    let quality = conversion.quality.clamp(1, 100).to_string();
+
+    let mut sips = Command::new("sips");
+    match conversion.format {
+        HeicFormat::Jpeg => sips.args(["-s", "format", "jpeg", "-s", "formatOptions", &quality]),
+        HeicFormat::Png => sips.args(["-s", "format", "png"]),
+    };
+    sips.arg(from).arg("--out").arg(to);
+
+    let mut heif_convert = Command::new("heif-convert");
+    heif_convert.args(["-q", &quality]).arg(from).arg(to);
+
+    for converter in [&mut sips, &mut heif_convert] {
+        let status = converter
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+
+        match status {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => {
+                return Err(io::Error::other(format!(
+                    "{:?} {status}",
+                    converter.get_program()
+                )))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(io::Error::new(
+        ErrorKind::NotFound,
+        "can't convert HEIC images, install libheif for heif-convert",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_heic_by_extension() {
+        assert!(is_heic(Path::new("IMG_0001.HEIC")));
+        assert!(is_heic(Path::new("~/a/b/photo.heif")));
+        assert!(!is_heic(Path::new("IMG_0001.jpeg")));
+        assert!(!is_heic(Path::new("heic")));
+        assert!(!is_heic(Path::new("photo.heic.zip")));
+    }
+
+    #[test]
+    fn leaves_other_images_alone() {
+        let png = Path::new("/nowhere/IMG_0001.png");
+        assert_eq!(readable(png).unwrap(), png);
+        let conversion = HeicConversion {
+            format: HeicFormat::Png,
+            ..Default::default()
+        };
+        assert_eq!(converted(png, conversion).unwrap(), png);
+    }
+}
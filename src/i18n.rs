@@ -0,0 +1,969 @@
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        OnceLock,
+    },
+};
+
+use chrono::Datelike;
+
+use crate::config::Language;
+
+/// Which column of `STRINGS` the app's text comes from: 0 for English, as
+/// last set by `set`
+static CURRENT: AtomicU8 = AtomicU8::new(0);
+
+/// The app's text in English, then German, then Spanish. Anything not listed
+/// here is shown in English whatever the language, which is left to error
+/// messages and the performance overlay.
+const STRINGS: &[(&str, &str, &str)] = &[
+    // chat list
+    ("settings", "Einstellungen", "ajustes"),
+    ("filter chats", "Chats filtern", "filtrar chats"),
+    ("regex", "Regex", "regex"),
+    (
+        "no chats found",
+        "keine Chats gefunden",
+        "no se encontraron chats",
+    ),
+    ("loading...", "lädt...", "cargando..."),
+    (
+        "open in new tab",
+        "in neuem Tab öffnen",
+        "abrir en una pestaña nueva",
+    ),
+    (
+        "open beside this chat",
+        "neben diesem Chat öffnen",
+        "abrir junto a este chat",
+    ),
+    ("was {handles}", "früher {handles}", "antes {handles}"),
+    ("also {handles}", "auch {handles}", "también {handles}"),
+    ("show chats", "Chats anzeigen", "mostrar chats"),
+    ("close tab", "Tab schließen", "cerrar pestaña"),
+    (
+        "close split view",
+        "geteilte Ansicht schließen",
+        "cerrar vista dividida",
+    ),
+    (
+        "select a chat on the left",
+        "links einen Chat auswählen",
+        "elige un chat a la izquierda",
+    ),
+    ("chats", "Chats", "chats"),
+    (
+        "saved searches",
+        "gespeicherte Suchen",
+        "búsquedas guardadas",
+    ),
+    ("save search", "Suche speichern", "guardar búsqueda"),
+    (
+        "unknown senders ({count})",
+        "unbekannte Absender ({count})",
+        "remitentes desconocidos ({count})",
+    ),
+    (
+        "{date} · {count} messages",
+        "{date} · {count} Nachrichten",
+        "{date} · {count} mensajes",
+    ),
+    (
+        "notify about new messages",
+        "über neue Nachrichten benachrichtigen",
+        "avisar de mensajes nuevos",
+    ),
+    ("name {handle}", "Name für {handle}", "nombre de {handle}"),
+    ("e.g. Landlord", "z. B. Vermieter", "p. ej. casero"),
+    ("save", "speichern", "guardar"),
+    (
+        "forget name: {name}",
+        "Namen vergessen: {name}",
+        "olvidar nombre: {name}",
+    ),
+    (
+        "changed number or email to",
+        "Nummer oder E-Mail geändert zu",
+        "cambió de número o correo a",
+    ),
+    ("new handle", "neue Adresse", "identificador nuevo"),
+    ("on YYYY-MM-DD", "am JJJJ-MM-TT", "el AAAA-MM-DD"),
+    (
+        "forget: {change}",
+        "vergessen: {change}",
+        "olvidar: {change}",
+    ),
+    (
+        "{from} became {to} on {date}",
+        "{from} wurde am {date} zu {to}",
+        "{from} pasó a ser {to} el {date}",
+    ),
+    (
+        "{from} is the same person as {to}",
+        "{from} ist dieselbe Person wie {to}",
+        "{from} es la misma persona que {to}",
+    ),
+    (
+        "same person as {handles}",
+        "dieselbe Person wie {handles}",
+        "la misma persona que {handles}",
+    ),
+    (
+        "not the same person",
+        "nicht dieselbe Person",
+        "no es la misma persona",
+    ),
+    (
+        "same person as",
+        "dieselbe Person wie",
+        "la misma persona que",
+    ),
+    (
+        "their other handle",
+        "ihre andere Adresse",
+        "su otro identificador",
+    ),
+    ("merge", "zusammenführen", "unir"),
+    ("emoji", "Emoji", "emoji"),
+    ("clear label", "Label entfernen", "quitar etiqueta"),
+    ("all", "alle", "todos"),
+    ("search results", "Suchergebnisse", "resultados de búsqueda"),
+    (
+        "collapse chat list",
+        "Chatliste einklappen",
+        "contraer lista de chats",
+    ),
+    ("detach", "lösen", "separar"),
+    (
+        "move into a floating window inside the viewer",
+        "in ein schwebendes Fenster im Viewer verschieben",
+        "mover a una ventana flotante dentro del visor",
+    ),
+    ("dismiss", "schließen", "descartar"),
+    // searching
+    (
+        "search, e.g. from:me after:2021-01-01 has:image beach",
+        "suchen, z. B. from:me after:2021-01-01 has:image Strand",
+        "buscar, p. ej. from:me after:2021-01-01 has:image playa",
+    ),
+    (
+        "invalid search: {error}",
+        "ungültige Suche: {error}",
+        "búsqueda no válida: {error}",
+    ),
+    (
+        "name this search",
+        "diese Suche benennen",
+        "ponle nombre a esta búsqueda",
+    ),
+    ("forget", "vergessen", "olvidar"),
+    ("no results", "keine Ergebnisse", "sin resultados"),
+    (
+        "search took too long, showing partial results",
+        "Suche dauerte zu lange, zeige Teilergebnisse",
+        "la búsqueda tardó demasiado, se muestran resultados parciales",
+    ),
+    ("searching...", "suche...", "buscando..."),
+    (
+        "searched {done} of {total} chats...",
+        "{done} von {total} Chats durchsucht...",
+        "buscados {done} de {total} chats...",
+    ),
+    // a chat
+    ("messages", "Nachrichten", "mensajes"),
+    (
+        "{count} messages",
+        "{count} Nachrichten",
+        "{count} mensajes",
+    ),
+    ("media", "Medien", "multimedia"),
+    ("stats", "Statistik", "estadísticas"),
+    (
+        "no messages found",
+        "keine Nachrichten gefunden",
+        "no se encontraron mensajes",
+    ),
+    (
+        "load earlier messages",
+        "ältere Nachrichten laden",
+        "cargar mensajes anteriores",
+    ),
+    (
+        "load later messages",
+        "neuere Nachrichten laden",
+        "cargar mensajes posteriores",
+    ),
+    ("jump to latest ↓", "zur neuesten ↓", "ir al más reciente ↓"),
+    (
+        "show full message ({hidden} more characters)",
+        "ganze Nachricht anzeigen ({hidden} weitere Zeichen)",
+        "mostrar el mensaje completo ({hidden} caracteres más)",
+    ),
+    ("show less", "weniger anzeigen", "mostrar menos"),
+    ("find in chat", "im Chat suchen", "buscar en el chat"),
+    ("previous", "zurück", "anterior"),
+    ("next", "weiter", "siguiente"),
+    ("no matches", "keine Treffer", "sin coincidencias"),
+    (
+        "{current} of {total}",
+        "{current} von {total}",
+        "{current} de {total}",
+    ),
+    ("done", "fertig", "listo"),
+    (
+        "{count} selected",
+        "{count} ausgewählt",
+        "{count} seleccionados",
+    ),
+    ("copy", "kopieren", "copiar"),
+    ("clear", "aufheben", "quitar"),
+    ("get info", "Informationen", "ver información"),
+    ("copy selected", "Auswahl kopieren", "copiar selección"),
+    (
+        "copy from here to the end",
+        "von hier bis zum Ende kopieren",
+        "copiar desde aquí hasta el final",
+    ),
+    ("{count} people", "{count} Personen", "{count} personas"),
+    (
+        "{count} people - only showing {name}'s messages",
+        "{count} Personen – nur Nachrichten von {name}",
+        "{count} personas: solo mensajes de {name}",
+    ),
+    (
+        "only messages that mention me",
+        "nur Nachrichten, die mich erwähnen",
+        "solo mensajes que me mencionan",
+    ),
+    ("jump to date", "zu Datum springen", "saltar a una fecha"),
+    ("YYYY-MM-DD", "JJJJ-MM-TT", "AAAA-MM-DD"),
+    ("previous month", "vorheriger Monat", "mes anterior"),
+    ("next month", "nächster Monat", "mes siguiente"),
+    (
+        "ignored message from {name} - click to show",
+        "ignorierte Nachricht von {name} – zum Anzeigen klicken",
+        "mensaje ignorado de {name}: haz clic para verlo",
+    ),
+    (
+        "Sent with an effect",
+        "Mit einem Effekt gesendet",
+        "Enviado con un efecto",
+    ),
+    (
+        "Sent with {effect}",
+        "Gesendet mit {effect}",
+        "Enviado con {effect}",
+    ),
+    ("Slam", "Knall", "Portazo"),
+    ("Loud", "Laut", "Fuerte"),
+    ("Gentle", "Sanft", "Suave"),
+    ("Invisible Ink", "Unsichtbare Tinte", "Tinta invisible"),
+    ("Echo", "Echo", "Eco"),
+    ("Spotlight", "Scheinwerfer", "Foco"),
+    ("Balloons", "Luftballons", "Globos"),
+    ("Confetti", "Konfetti", "Confeti"),
+    ("Love", "Liebe", "Amor"),
+    ("Lasers", "Laser", "Láseres"),
+    ("Fireworks", "Feuerwerk", "Fuegos artificiales"),
+    ("Shooting Star", "Sternschnuppe", "Estrella fugaz"),
+    ("Celebration", "Feier", "Celebración"),
+    (
+        "sent as text message",
+        "als SMS gesendet",
+        "enviado como SMS",
+    ),
+    (
+        "sent as {service}",
+        "als {service} gesendet",
+        "enviado como {service}",
+    ),
+    (
+        "nothing has been shared in this chat",
+        "in diesem Chat wurde nichts geteilt",
+        "no se ha compartido nada en este chat",
+    ),
+    (
+        "open full size",
+        "in voller Größe öffnen",
+        "abrir a tamaño completo",
+    ),
+    (
+        "{name} (attachment unavailable)",
+        "{name} (Anhang nicht verfügbar)",
+        "{name} (adjunto no disponible)",
+    ),
+    ("close (esc)", "schließen (Esc)", "cerrar (esc)"),
+    ("previous photo", "vorheriges Foto", "foto anterior"),
+    ("next photo", "nächstes Foto", "foto siguiente"),
+    ("fit", "einpassen", "ajustar"),
+    ("save as", "speichern unter", "guardar como"),
+    (
+        "saved to {path}",
+        "gespeichert unter {path}",
+        "guardado en {path}",
+    ),
+    (
+        "couldn't save: {error}",
+        "konnte nicht speichern: {error}",
+        "no se pudo guardar: {error}",
+    ),
+    (
+        "handwritten message",
+        "handschriftliche Nachricht",
+        "mensaje escrito a mano",
+    ),
+    (
+        "Digital Touch message",
+        "Digital Touch-Nachricht",
+        "mensaje de Digital Touch",
+    ),
+    (
+        "not shown here yet",
+        "wird hier noch nicht angezeigt",
+        "aún no se muestra aquí",
+    ),
+    (
+        "save raw data",
+        "Rohdaten speichern",
+        "guardar datos sin procesar",
+    ),
+    (
+        "shared location",
+        "geteilter Standort",
+        "ubicación compartida",
+    ),
+    (
+        "Shared Location",
+        "Geteilter Standort",
+        "Ubicación compartida",
+    ),
+    ("open in Maps", "in Karten öffnen", "abrir en Mapas"),
+    ("contact", "Kontakt", "contacto"),
+    (
+        "sent with Apple Pay",
+        "mit Apple Pay gesendet",
+        "enviado con Apple Pay",
+    ),
+    (
+        "requested with Apple Pay",
+        "mit Apple Pay angefordert",
+        "solicitado con Apple Pay",
+    ),
+    (
+        "copy to clipboard?",
+        "in die Zwischenablage kopieren?",
+        "¿copiar al portapapeles?",
+    ),
+    (
+        "This copies {messages} messages out of the viewer.",
+        "Damit werden {messages} Nachrichten aus dem Viewer kopiert.",
+        "Esto copia {messages} mensajes fuera del visor.",
+    ),
+    (
+        "This copies {messages} messages and {attachments} attachments out of the viewer.",
+        "Damit werden {messages} Nachrichten und {attachments} Anhänge aus dem Viewer kopiert.",
+        "Esto copia {messages} mensajes y {attachments} adjuntos fuera del visor.",
+    ),
+    (
+        "Anything on the clipboard can be pasted into any app, and clipboard managers or sync \
+         may keep a copy.",
+        "Alles in der Zwischenablage lässt sich in jede App einfügen, und Zwischenablage-Manager \
+         oder Synchronisierung können eine Kopie behalten.",
+        "Cualquier app puede pegar lo que haya en el portapapeles, y los gestores del \
+         portapapeles o la sincronización pueden guardar una copia.",
+    ),
+    ("cancel", "abbrechen", "cancelar"),
+    // exporting
+    ("export", "exportieren", "exportar"),
+    ("exporting...", "exportiere...", "exportando..."),
+    (
+        "exported {done} of {total} chats...",
+        "{done} von {total} Chats exportiert...",
+        "exportados {done} de {total} chats...",
+    ),
+    ("comparing...", "vergleiche...", "comparando..."),
+    (
+        "exported to {path}",
+        "exportiert nach {path}",
+        "exportado a {path}",
+    ),
+    ("copy chat", "Chat kopieren", "copiar chat"),
+    (
+        "every contact as its own file",
+        "jeden Kontakt als eigene Datei",
+        "cada contacto en su propio archivo",
+    ),
+    (
+        "export every contact as its own file",
+        "jeden Kontakt als eigene Datei exportieren",
+        "exportar cada contacto en su propio archivo",
+    ),
+    (
+        "export this chat as {format}",
+        "diesen Chat als {format} exportieren",
+        "exportar este chat como {format}",
+    ),
+    (
+        "export profile {name}",
+        "Exportprofil {name}",
+        "perfil de exportación {name}",
+    ),
+    (
+        "export photo contact sheet",
+        "Foto-Kontaktbogen exportieren",
+        "exportar hoja de contactos de fotos",
+    ),
+    (
+        "export timeline (.ics)",
+        "Zeitleiste exportieren (.ics)",
+        "exportar cronología (.ics)",
+    ),
+    (
+        "make time capsule",
+        "Zeitkapsel erstellen",
+        "crear cápsula del tiempo",
+    ),
+    (
+        "a zip of this chat to keep or give",
+        "ein Zip dieses Chats zum Aufheben oder Verschenken",
+        "un zip de este chat para guardar o regalar",
+    ),
+    ("everything", "alles", "todo"),
+    ("go", "los", "ir"),
+    (
+        "dates should look like 2020-12-31",
+        "Daten sollten wie 2020-12-31 aussehen",
+        "las fechas deben tener la forma 2020-12-31",
+    ),
+    (
+        "export messages",
+        "Nachrichten exportieren",
+        "exportar mensajes",
+    ),
+    ("text", "Text", "texto"),
+    ("time capsule", "Zeitkapsel", "cápsula del tiempo"),
+    ("from", "von", "desde"),
+    ("until", "bis", "hasta"),
+    (
+        "export attachments",
+        "Anhänge exportieren",
+        "exportar adjuntos",
+    ),
+    ("photos", "Fotos", "fotos"),
+    ("videos", "Videos", "vídeos"),
+    ("audio", "Audio", "audio"),
+    ("other files", "andere Dateien", "otros archivos"),
+    // command palette and keyboard shortcuts
+    ("keyboard shortcuts", "Tastenkürzel", "atajos de teclado"),
+    ("jump to {date}", "zu {date} springen", "saltar a {date}"),
+    ("zoom in", "vergrößern", "acercar"),
+    ("zoom out", "verkleinern", "alejar"),
+    ("reset zoom", "Zoom zurücksetzen", "restablecer zoom"),
+    (
+        "go to a chat, export, settings, or a date (YYYY-MM-DD)",
+        "Chat, Export, Einstellungen oder Datum (JJJJ-MM-TT)",
+        "ir a un chat, exportar, ajustes o una fecha (AAAA-MM-DD)",
+    ),
+    ("nothing matches", "nichts gefunden", "no hay coincidencias"),
+    ("go to {name}", "zu {name}", "ir a {name}"),
+    (
+        "go to {name} ({handle})",
+        "zu {name} ({handle})",
+        "ir a {name} ({handle})",
+    ),
+    ("anywhere", "überall", "en cualquier lugar"),
+    ("search box", "Suchfeld", "cuadro de búsqueda"),
+    ("chat list", "Chatliste", "lista de chats"),
+    ("chat", "Chat", "chat"),
+    ("command palette", "Befehlspalette", "paleta de comandos"),
+    ("photo lightbox", "Fotoansicht", "visor de fotos"),
+    (
+        "show or hide this list",
+        "diese Liste ein- oder ausblenden",
+        "mostrar u ocultar esta lista",
+    ),
+    (
+        "performance overlay",
+        "Leistungsanzeige",
+        "panel de rendimiento",
+    ),
+    (
+        "close search results, or the open chat",
+        "Suchergebnisse oder den offenen Chat schließen",
+        "cerrar los resultados de búsqueda o el chat abierto",
+    ),
+    ("search", "suchen", "buscar"),
+    ("previous chat", "vorheriger Chat", "chat anterior"),
+    ("next chat", "nächster Chat", "chat siguiente"),
+    ("open the chat", "den Chat öffnen", "abrir el chat"),
+    (
+        "close the chat's tab",
+        "Tab des Chats schließen",
+        "cerrar la pestaña del chat",
+    ),
+    (
+        "scroll up a page",
+        "eine Seite nach oben",
+        "subir una página",
+    ),
+    (
+        "scroll down a page",
+        "eine Seite nach unten",
+        "bajar una página",
+    ),
+    ("scroll to the top", "ganz nach oben", "ir al principio"),
+    (
+        "jump to the latest message",
+        "zur neuesten Nachricht springen",
+        "ir al mensaje más reciente",
+    ),
+    ("next match", "nächster Treffer", "coincidencia siguiente"),
+    (
+        "previous match",
+        "vorheriger Treffer",
+        "coincidencia anterior",
+    ),
+    ("close", "schließen", "cerrar"),
+    ("previous command", "vorheriger Befehl", "comando anterior"),
+    ("next command", "nächster Befehl", "comando siguiente"),
+    ("run the command", "Befehl ausführen", "ejecutar el comando"),
+    // spoken when focus changes
+    ("a photo", "ein Foto", "una foto"),
+    ("{count} photos", "{count} Fotos", "{count} fotos"),
+    (
+        "a voice message",
+        "eine Sprachnachricht",
+        "un mensaje de voz",
+    ),
+    ("a location", "ein Standort", "una ubicación"),
+    (
+        "contact card for {name}",
+        "Kontaktkarte von {name}",
+        "tarjeta de contacto de {name}",
+    ),
+    (
+        "{count} reactions",
+        "{count} Reaktionen",
+        "{count} reacciones",
+    ),
+    // message info
+    (
+        "message info",
+        "Nachrichteninformationen",
+        "información del mensaje",
+    ),
+    ("handle", "Adresse", "identificador"),
+    (
+        "{guid} (not in the database)",
+        "{guid} (nicht in der Datenbank)",
+        "{guid} (no está en la base de datos)",
+    ),
+    (
+        "every other column ({count})",
+        "alle anderen Spalten ({count})",
+        "todas las demás columnas ({count})",
+    ),
+    ("copy all", "alles kopieren", "copiar todo"),
+    // stats
+    ("first", "erste", "primero"),
+    ("last", "letzte", "último"),
+    ("busiest day", "aktivster Tag", "día con más actividad"),
+    (
+        "{day} ({count} messages)",
+        "{day} ({count} Nachrichten)",
+        "{day} ({count} mensajes)",
+    ),
+    ("by person", "nach Person", "por persona"),
+    (
+        "{messages} messages, {words} words",
+        "{messages} Nachrichten, {words} Wörter",
+        "{messages} mensajes, {words} palabras",
+    ),
+    (
+        "who starts conversations",
+        "wer Gespräche beginnt",
+        "quién empieza las conversaciones",
+    ),
+    (
+        "{started} of {total} ({percent}%)",
+        "{started} von {total} ({percent} %)",
+        "{started} de {total} ({percent} %)",
+    ),
+    ("attachments", "Anhänge", "adjuntos"),
+    ("by hour (UTC)", "nach Stunde (UTC)", "por hora (UTC)"),
+    (
+        "by day of the week",
+        "nach Wochentag",
+        "por día de la semana",
+    ),
+    // merge preview
+    (
+        "merge preview",
+        "Zusammenführungsvorschau",
+        "vista previa de la fusión",
+    ),
+    (
+        "path to another copy of chat.db",
+        "Pfad zu einer anderen Kopie von chat.db",
+        "ruta a otra copia de chat.db",
+    ),
+    ("compare", "vergleichen", "comparar"),
+    (
+        "comparing with {path}",
+        "Vergleich mit {path}",
+        "comparando con {path}",
+    ),
+    (
+        "merging would add {added} messages, and {differing} have different text",
+        "die Zusammenführung würde {added} Nachrichten hinzufügen, und {differing} haben \
+         einen anderen Text",
+        "la fusión añadiría {added} mensajes, y {differing} tienen un texto distinto",
+    ),
+    (
+        "new messages ({count} chats)",
+        "neue Nachrichten ({count} Chats)",
+        "mensajes nuevos ({count} chats)",
+    ),
+    (
+        "conflicts ({count})",
+        "Konflikte ({count})",
+        "conflictos ({count})",
+    ),
+    ("none", "keine", "ninguno"),
+    ("current: {text}", "aktuell: {text}", "actual: {text}"),
+    ("incoming: {text}", "neu: {text}", "nueva: {text}"),
+    // notifications
+    ("(attachment)", "(Anhang)", "(adjunto)"),
+    (
+        "new message in {chat}",
+        "neue Nachricht in {chat}",
+        "mensaje nuevo en {chat}",
+    ),
+    // settings
+    ("me", "ich", "yo"),
+    ("display name", "Anzeigename", "nombre visible"),
+    ("avatar", "Profilbild", "avatar"),
+    ("my handles", "meine Adressen", "mis identificadores"),
+    (
+        "only needed for a database that belongs to someone else, one per line",
+        "nur nötig für die Datenbank einer anderen Person, eine pro Zeile",
+        "solo hace falta para la base de datos de otra persona, uno por línea",
+    ),
+    (
+        "database owner's name",
+        "Name des Datenbankbesitzers",
+        "nombre del dueño de la base de datos",
+    ),
+    ("exports", "Exporte", "exportaciones"),
+    (
+        "convert HEIC photos to",
+        "HEIC-Fotos umwandeln in",
+        "convertir fotos HEIC a",
+    ),
+    (
+        "for photo exports, so they open without an Apple device",
+        "für Fotoexporte, damit sie sich ohne Apple-Gerät öffnen lassen",
+        "para exportar fotos que se abran sin un dispositivo Apple",
+    ),
+    ("JPEG quality", "JPEG-Qualität", "calidad JPEG"),
+    ("appearance", "Darstellung", "apariencia"),
+    ("theme", "Design", "tema"),
+    ("system", "System", "sistema"),
+    (
+        "whichever the system was using when the app started",
+        "was das System beim Start der App verwendet hat",
+        "el que usaba el sistema al abrir la app",
+    ),
+    ("light", "hell", "claro"),
+    ("dark", "dunkel", "oscuro"),
+    ("direction", "Richtung", "dirección"),
+    (
+        "right to left for Arabic, Hebrew, Persian and Urdu",
+        "rechts nach links für Arabisch, Hebräisch, Persisch und Urdu",
+        "de derecha a izquierda para árabe, hebreo, persa y urdu",
+    ),
+    (
+        "left to right",
+        "links nach rechts",
+        "de izquierda a derecha",
+    ),
+    (
+        "right to left",
+        "rechts nach links",
+        "de derecha a izquierda",
+    ),
+    (
+        "my messages on the left, everyone else's on the right",
+        "meine Nachrichten links, alle anderen rechts",
+        "mis mensajes a la izquierda, los demás a la derecha",
+    ),
+    ("language", "Sprache", "idioma"),
+    (
+        "English unless the system's language is one listed here",
+        "Englisch, außer die Systemsprache ist hier aufgeführt",
+        "inglés, salvo que el idioma del sistema esté en esta lista",
+    ),
+    ("text size", "Schriftgröße", "tamaño del texto"),
+    ("zoom", "Zoom", "zoom"),
+    (
+        "also Cmd + and Cmd -, or from the command palette",
+        "auch Cmd + und Cmd - oder über die Befehlspalette",
+        "también Cmd + y Cmd -, o desde la paleta de comandos",
+    ),
+    ("my iMessages", "meine iMessages", "mis iMessages"),
+    ("my text messages", "meine SMS", "mis SMS"),
+    ("everyone else's", "die der anderen", "los de los demás"),
+    ("background", "Hintergrund", "fondo"),
+    ("accent", "Akzent", "acento"),
+    ("accessibility", "Bedienungshilfen", "accesibilidad"),
+    (
+        "speak focus changes",
+        "Fokuswechsel vorlesen",
+        "leer en voz alta los cambios de foco",
+    ),
+    (
+        "says what's focused or clicked through the system's text-to-speech; this isn't \
+         screen reader support. Tab moves between chats, messages and buttons.",
+        "sagt über die Sprachausgabe des Systems, was fokussiert oder angeklickt wird; das ist \
+         keine Unterstützung für Bildschirmleser. Tab wechselt zwischen Chats, Nachrichten und \
+         Schaltflächen.",
+        "dice con la voz del sistema lo que se enfoca o pulsa; no es compatibilidad con lectores \
+         de pantalla. Tab pasa entre chats, mensajes y botones.",
+    ),
+    ("high contrast", "hoher Kontrast", "alto contraste"),
+    ("layout", "Anordnung", "disposición"),
+    (
+        "dock every panel",
+        "alle Bereiche andocken",
+        "acoplar todos los paneles",
+    ),
+    (
+        "hide chats from unknown senders",
+        "Chats von unbekannten Absendern ausblenden",
+        "ocultar chats de remitentes desconocidos",
+    ),
+    (
+        "handles that aren't in Contacts and haven't been given a name",
+        "Adressen, die nicht in Kontakte stehen und keinen Namen bekommen haben",
+        "identificadores que no están en Contactos ni tienen nombre",
+    ),
+    (
+        "load the newest messages of the",
+        "die neuesten Nachrichten der",
+        "cargar los mensajes más nuevos de los",
+    ),
+    (
+        "most recent chats ahead of time",
+        "letzten Chats vorab laden",
+        "chats más recientes por adelantado",
+    ),
+    (
+        "so they open straight away; 0 to only load a chat when it's opened",
+        "damit sie sofort aufgehen; 0, um einen Chat erst beim Öffnen zu laden",
+        "para que se abran al instante; 0 para cargar un chat solo al abrirlo",
+    ),
+    ("ignore rules", "Ignorierregeln", "reglas para ignorar"),
+    (
+        "automated messages to collapse or hide; applies to chats opened after saving",
+        "automatische Nachrichten zum Einklappen oder Ausblenden; gilt für Chats, die nach dem \
+         Speichern geöffnet werden",
+        "mensajes automáticos que contraer u ocultar; se aplica a los chats abiertos después de \
+         guardar",
+    ),
+    ("in every chat", "in jedem Chat", "en todos los chats"),
+    ("from anyone", "von allen", "de cualquiera"),
+    ("any text", "beliebiger Text", "cualquier texto"),
+    ("collapse", "einklappen", "contraer"),
+    ("hide", "ausblenden", "ocultar"),
+    ("remove", "entfernen", "eliminar"),
+    ("add rule", "Regel hinzufügen", "añadir regla"),
+    ("clipboard", "Zwischenablage", "portapapeles"),
+    (
+        "ask before copying more than",
+        "nachfragen, bevor mehr als",
+        "preguntar antes de copiar más de",
+    ),
+    (
+        "messages or any attachment",
+        "Nachrichten oder ein Anhang kopiert werden",
+        "mensajes o algún adjunto",
+    ),
+    ("notifications", "Mitteilungen", "notificaciones"),
+    (
+        "show who sent new messages and what they say",
+        "zeigen, wer neue Nachrichten geschickt hat und was darin steht",
+        "mostrar quién envió mensajes nuevos y qué dicen",
+    ),
+    (
+        "right-click a chat to be notified about it while running with --watch",
+        "Rechtsklick auf einen Chat, um mit --watch Mitteilungen dafür zu bekommen",
+        "haz clic derecho en un chat para recibir avisos de él al usar --watch",
+    ),
+    ("reset", "zurücksetzen", "restablecer"),
+    // dates
+    ("January", "Januar", "enero"),
+    ("February", "Februar", "febrero"),
+    ("March", "März", "marzo"),
+    ("April", "April", "abril"),
+    ("May", "Mai", "mayo"),
+    ("June", "Juni", "junio"),
+    ("July", "Juli", "julio"),
+    ("August", "August", "agosto"),
+    ("September", "September", "septiembre"),
+    ("October", "Oktober", "octubre"),
+    ("November", "November", "noviembre"),
+    ("December", "Dezember", "diciembre"),
+    ("Mon", "Mo", "lun"),
+    ("Tue", "Di", "mar"),
+    ("Wed", "Mi", "mié"),
+    ("Thu", "Do", "jue"),
+    ("Fri", "Fr", "vie"),
+    ("Sat", "Sa", "sáb"),
+    ("Sun", "So", "dom"),
+    ("Mo", "Mo", "lu"),
+    ("Tu", "Di", "ma"),
+    ("We", "Mi", "mi"),
+    ("Th", "Do", "ju"),
+    ("Fr", "Fr", "vi"),
+    ("Sa", "Sa", "sá"),
+    ("Su", "So", "do"),
+];
+
+/// The language part of the system's locale, e.g. `de` for `de_DE.UTF-8`,
+/// from the first of `LC_ALL`, `LC_MESSAGES` and `LANG` that's set
+pub fn system_language() -> Option<&'static str> {
+    static LANGUAGE: OnceLock<Option<String>> = OnceLock::new();
+    LANGUAGE
+        .get_or_init(|| {
+            let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+                .iter()
+                .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))?;
+            let language = locale.split(['_', '-', '.']).next().unwrap_or_default();
+            Some(language.to_lowercase())
+        })
+        .as_deref()
+}
+
+/// Show the app's text in `language`, or the system's language if it's one
+/// with translations
+pub fn set(language: Language) {
+    let language = match language {
+        Language::Auto => match system_language() {
+            Some("de") => Language::De,
+            Some("es") => Language::Es,
+            _ => Language::En,
+        },
+        language => language,
+    };
+    let column = match language {
+        Language::Auto | Language::En => 0,
+        Language::De => 1,
+        Language::Es => 2,
+    };
+    CURRENT.store(column, Ordering::Relaxed);
+}
+
+/// `english` in the language last `set`, or as it is if it has no translation
+pub fn t(english: &'static str) -> &'static str {
+    let column = CURRENT.load(Ordering::Relaxed);
+    if column == 0 {
+        return english;
+    }
+
+    match STRINGS.iter().find(|(en, ..)| *en == english) {
+        Some((_, de, _)) if column == 1 => de,
+        Some((_, _, es)) => es,
+        None => english,
+    }
+}
+
+/// The name of `date`'s month, e.g. "March", in the language last `set`
+pub fn month(date: &impl Datelike) -> &'static str {
+    const MONTHS: [&str; 12] = [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ];
+    t(MONTHS[date.month0() as usize])
+}
+
+/// `date`'s month and year, e.g. "March 2021", in the language last `set`
+pub fn month_year(date: &impl Datelike) -> String {
+    format!("{} {}", month(date), date.year())
+}
+
+/// `english` translated as by `t`, with each `{name}` in it replaced by the
+/// value given for that name in `args`
+pub fn tf(english: &'static str, args: &[(&str, &dyn Display)]) -> String {
+    let mut text = t(english).to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{name}}}"), &value.to_string());
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `{name}`s in `text`, in order
+    fn placeholders(text: &str) -> Vec<&str> {
+        text.split('{')
+            .skip(1)
+            .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+            .collect()
+    }
+
+    // these don't `set` the language, since that's shared with every other
+    // test running at the same time
+
+    #[test]
+    fn translations_keep_placeholders() {
+        for (en, de, es) in STRINGS {
+            let mut names = placeholders(en);
+            names.sort_unstable();
+            for translation in [de, es] {
+                assert!(!translation.is_empty(), "{en:?} is blank");
+                let mut translated = placeholders(translation);
+                translated.sort_unstable();
+                assert_eq!(names, translated, "{translation:?} for {en:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn translated_once() {
+        for (i, (en, ..)) in STRINGS.iter().enumerate() {
+            assert!(
+                STRINGS[i + 1..].iter().all(|(other, ..)| other != en),
+                "{en:?} is listed twice"
+            );
+        }
+    }
+
+    #[test]
+    fn fills_in_placeholders() {
+        assert_eq!(
+            tf("{current} of {total}", &[("current", &3), ("total", &10)]),
+            "3 of 10"
+        );
+        assert_eq!(tf("not translated {x}", &[]), "not translated {x}");
+    }
+
+    #[test]
+    fn names_months() {
+        let date = chrono::NaiveDate::from_ymd(2021, 3, 14);
+        assert_eq!(month(&date), "March");
+        assert_eq!(month_year(&date), "March 2021");
+    }
+}
@@ -0,0 +1,147 @@
+use eyre::{eyre, Result};
+use regex::Regex;
+
+use crate::{
+    config::{IgnoreAction, IgnoreRule},
+    search, Sender,
+};
+
+struct Rule {
+    chat: Option<String>,
+    sender: Option<Regex>,
+    text: Option<Regex>,
+    action: IgnoreAction,
+}
+
+/// Ignore rules from the config file, ready to match messages against
+#[derive(Default)]
+pub struct Rules(Vec<Rule>);
+
+impl Rules {
+    pub fn compile(rules: &[IgnoreRule]) -> Result<Self> {
+        let compile = |pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .filter(|p| !p.is_empty())
+                .map(|p| {
+                    search::compile_regex(p)
+                        .map_err(|e| eyre!("invalid ignore rule pattern {p:?}: {e}"))
+                })
+                .transpose()
+        };
+
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                Ok(Rule {
+                    chat: rule.chat.clone().filter(|c| !c.is_empty()),
+                    sender: compile(&rule.sender)?,
+                    text: compile(&rule.text)?,
+                    action: rule.action,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self(rules))
+    }
+
+    /// What to do with a message in `chat`, if any rule matches it. Hiding
+    /// wins over collapsing when several do.
+    pub fn check(&self, chat: &str, sender: &Sender, text: &str) -> Option<IgnoreAction> {
+        self.0
+            .iter()
+            .filter(|rule| rule.matches(chat, sender, text))
+            .map(|rule| rule.action)
+            .max_by_key(|action| *action == IgnoreAction::Hide)
+    }
+}
+
+impl Rule {
+    fn matches(&self, chat: &str, sender: &Sender, text: &str) -> bool {
+        // a rule without any pattern would swallow the whole chat
+        if self.sender.is_none() && self.text.is_none() {
+            return false;
+        }
+        if self.chat.as_deref().is_some_and(|c| c != chat) {
+            return false;
+        }
+
+        let sender_matches = match (&self.sender, sender) {
+            (None, _) => true,
+            (Some(pattern), Sender::SomeoneElse(handle)) => pattern.is_match(handle),
+            (Some(_), Sender::Me) => false,
+        };
+
+        sender_matches && self.text.as_ref().is_none_or(|p| p.is_match(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(chat: Option<&str>, sender: Option<&str>, text: Option<&str>) -> IgnoreRule {
+        IgnoreRule {
+            chat: chat.map(str::to_owned),
+            sender: sender.map(str::to_owned),
+            text: text.map(str::to_owned),
+            action: IgnoreAction::Collapse,
+        }
+    }
+
+    fn someone(handle: &str) -> Sender {
+        Sender::SomeoneElse(handle.to_owned())
+    }
+
+    #[test]
+    fn matches_every_pattern_given() {
+        let rules = Rules::compile(&[rule(
+            Some("bank"),
+            Some(r"^\d{5}$"),
+            Some(r"verification code is \d+"),
+        )])
+        .unwrap();
+        let code = "Your VERIFICATION code is 123456";
+
+        assert_eq!(
+            rules.check("bank", &someone("12345"), code),
+            Some(IgnoreAction::Collapse)
+        );
+        assert_eq!(rules.check("other", &someone("12345"), code), None);
+        assert_eq!(rules.check("bank", &someone("+15555550100"), code), None);
+        assert_eq!(rules.check("bank", &someone("12345"), "hello"), None);
+        assert_eq!(rules.check("bank", &Sender::Me, code), None);
+    }
+
+    #[test]
+    fn blank_patterns_match_nothing() {
+        let rules = Rules::compile(&[rule(Some("bank"), Some(""), None)]).unwrap();
+        assert_eq!(rules.check("bank", &someone("12345"), "hello"), None);
+    }
+
+    #[test]
+    fn hiding_wins() {
+        let rules = Rules::compile(&[
+            rule(None, None, Some("code")),
+            IgnoreRule {
+                action: IgnoreAction::Hide,
+                ..rule(Some(""), Some("12345"), None)
+            },
+            rule(None, Some("12345"), None),
+        ])
+        .unwrap();
+        assert_eq!(
+            rules.check("any", &someone("12345"), "code"),
+            Some(IgnoreAction::Hide)
+        );
+        assert_eq!(
+            rules.check("any", &Sender::Me, "code"),
+            Some(IgnoreAction::Collapse)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_patterns() {
+        assert!(Rules::compile(&[rule(None, Some("(unclosed"), None)]).is_err());
+    }
+}
@@ -0,0 +1,824 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use egui::{Color32, ColorImage, Context, Response, Sense, TextureHandle, Ui};
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    AnimationDecoder, DynamicImage, ImageError, ImageFormat,
+};
+use tokio::runtime;
+
+use crate::{a11y, attachment::Attachment, cache, error::Error, heic, i18n, perf, shortcuts};
+
+/// Largest width or height an image is drawn at inside a bubble
+const MAX_INLINE: f32 = 240.0;
+
+/// Inline images are decoded at up to this size, leaving room for high DPI
+/// screens without keeping every photo in a chat at full resolution
+const THUMBNAIL_LEN: u32 = 640;
+
+/// Contact photos are decoded at up to this size, as they're only ever
+/// drawn small
+const AVATAR_LEN: u32 = 128;
+
+/// Larger than this and some GPUs refuse the texture
+const MAX_TEXTURE_LEN: u32 = 8192;
+
+/// Animations stop after this many bytes of decoded frames, and loop over
+/// what was decoded
+const MAX_ANIMATION_BYTES: usize = 64 * 1024 * 1024;
+
+/// Browsers play GIF frames with a delay shorter than this at 100ms, and so
+/// plenty of GIFs depend on that
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+
+/// How far the lightbox zooms in past fitting the image to the window
+const MAX_ZOOM: f32 = 16.0;
+
+/// A decoded image, or one frame of an animation along with how long it is
+/// shown for. Still images are a single frame.
+type Frame<T> = (T, Duration);
+
+enum Entry {
+    Decoding,
+    Decoded(Vec<Frame<ColorImage>>),
+    Ready(Vec<Frame<TextureHandle>>),
+    Failed(Error),
+}
+
+type Entries = Arc<Mutex<HashMap<PathBuf, Entry>>>;
+
+/// Decoded image attachments. Decoding happens on the blocking thread pool,
+/// and textures are uploaded the first frame after that finishes.
+pub struct Images {
+    rt: runtime::Handle,
+    thumbnails: Entries,
+    /// The image opened at full size, if any
+    lightbox: Option<Lightbox>,
+}
+
+/// Full window view of one of a chat's images
+struct Lightbox {
+    /// Every image in the chat, to step through with the arrow keys
+    paths: Vec<PathBuf>,
+    index: usize,
+    /// Full size textures, only kept for the current image and its neighbours
+    entries: Entries,
+    /// Relative to fitting the image in the window
+    zoom: f32,
+    /// How far the image has been dragged from the center
+    pan: egui::Vec2,
+    /// Where "save as" copies the current image to
+    save_path: String,
+    saved: Option<Result<PathBuf, String>>,
+}
+
+impl Images {
+    pub fn new(rt: runtime::Handle) -> Self {
+        Self {
+            rt,
+            thumbnails: Entries::default(),
+            lightbox: None,
+        }
+    }
+
+    /// Open `paths[index]` in the lightbox
+    pub fn open(&mut self, paths: Vec<PathBuf>, index: usize) {
+        let mut lightbox = Lightbox {
+            paths,
+            index: 0,
+            entries: Entries::default(),
+            zoom: 1.0,
+            pan: egui::Vec2::ZERO,
+            save_path: String::new(),
+            saved: None,
+        };
+        lightbox.go_to(index);
+
+        self.lightbox = Some(lightbox);
+    }
+
+    pub fn lightbox_open(&self) -> bool {
+        self.lightbox.is_some()
+    }
+
+    /// Rough number of bytes used by decoded thumbnails
+    pub fn size(&self) -> usize {
+        self.thumbnails
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| match entry {
+                Entry::Decoded(frames) => {
+                    frames.iter().map(|(image, _)| image.pixels.len() * 4).sum()
+                }
+                Entry::Ready(frames) => frames
+                    .iter()
+                    .map(|(texture, _)| texture.size()[0] * texture.size()[1] * 4)
+                    .sum(),
+                Entry::Decoding | Entry::Failed(_) => 0,
+            })
+            .sum()
+    }
+
+    /// Draw an image attachment scaled down to fit in a bubble. Returns
+    /// whether it was clicked, to open it with [`Images::open`].
+    pub fn show_inline(&mut self, ui: &mut Ui, attachment: &Attachment) -> bool {
+        let path = &attachment.path;
+
+        // don't decode every photo in a long chat, only the ones scrolled to
+        let placeholder = egui::vec2(MAX_INLINE, MAX_INLINE * 0.75);
+        let visible = ui.is_rect_visible(egui::Rect::from_min_size(ui.cursor().min, placeholder));
+        if !visible && !self.thumbnails.lock().unwrap().contains_key(path) {
+            ui.allocate_space(placeholder);
+            return false;
+        }
+
+        let texture = self.thumbnail(ui.ctx(), attachment);
+        perf::cache_lookup("thumbnails", texture.is_some());
+
+        match texture {
+            Some(Ok(texture)) => {
+                let size = texture.size_vec2();
+                let scale = (MAX_INLINE / size.x.max(size.y)).min(1.0);
+
+                ui.add(egui::ImageButton::new(&texture, size * scale).frame(false))
+                    .on_hover_text(i18n::t("open full size"))
+                    .clicked()
+            }
+            Some(Err(e)) => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let label = match e {
+                    Error::MissingAttachment(_) => format!(
+                        "🖼 {}",
+                        i18n::tf("{name} (attachment unavailable)", &[("name", &name)])
+                    ),
+                    _ => format!("🖼 {name}"),
+                };
+                ui.weak(label).on_hover_text(format!("{e}\n\n{}", e.hint()));
+                false
+            }
+            None => {
+                ui.spinner();
+                false
+            }
+        }
+    }
+
+    /// Draw an image attachment cropped to a `size` square, e.g. for a grid
+    /// of photos
+    pub fn tile(&mut self, ui: &mut Ui, attachment: &Attachment, size: f32) -> Response {
+        let (rect, mut response) = ui.allocate_exact_size(egui::Vec2::splat(size), Sense::click());
+        if !ui.is_rect_visible(rect) {
+            return response;
+        }
+
+        let texture = self.thumbnail(ui.ctx(), attachment);
+        perf::cache_lookup("thumbnails", texture.is_some());
+
+        match texture {
+            Some(Ok(texture)) => {
+                // the middle square of the image
+                let [width, height] = texture.size().map(|side| side as f32);
+                let side = width.min(height);
+                let uv = egui::Rect::from_center_size(
+                    egui::pos2(0.5, 0.5),
+                    egui::vec2(side / width, side / height),
+                );
+                ui.painter()
+                    .add(egui::Shape::image(texture.id(), rect, uv, Color32::WHITE));
+            }
+            Some(Err(e)) => {
+                ui.painter()
+                    .rect_filled(rect, 0.0, ui.visuals().faint_bg_color);
+                ui.painter().text(
+                    rect.center(),
+                    egui::Align2::CENTER_CENTER,
+                    "🖼",
+                    egui::FontId::proportional(size / 3.0),
+                    ui.visuals().weak_text_color(),
+                );
+                response = response.on_hover_text(format!("{e}\n\n{}", e.hint()));
+            }
+            None => {
+                ui.painter()
+                    .rect_filled(rect, 0.0, ui.visuals().faint_bg_color);
+            }
+        }
+
+        response
+    }
+
+    /// Paint an image attachment over whatever is already at `rect`, fitted
+    /// inside it, e.g. a sticker on a bubble. Nothing is drawn until it has
+    /// been decoded.
+    pub fn overlay(&mut self, ui: &Ui, attachment: &Attachment, rect: egui::Rect) {
+        if !ui.is_rect_visible(rect) {
+            return;
+        }
+
+        if let Some(Ok(texture)) = self.thumbnail(ui.ctx(), attachment) {
+            let size = texture.size_vec2();
+            let scale = (rect.width() / size.x).min(rect.height() / size.y);
+            let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+
+            ui.painter().add(egui::Shape::image(
+                texture.id(),
+                egui::Rect::from_center_size(rect.center(), size * scale),
+                uv,
+                Color32::WHITE,
+            ));
+        }
+    }
+
+    /// Paint a picture cropped to the circle inside `rect`, e.g. a contact
+    /// photo. Returns false, drawing nothing, until it has been decoded or if
+    /// it can't be.
+    pub fn avatar(&mut self, ui: &Ui, path: &Path, rect: egui::Rect) -> bool {
+        let texture = texture(ui.ctx(), &self.rt, &self.thumbnails, path, None, AVATAR_LEN);
+        let texture = match texture {
+            Some(Ok(texture)) => texture,
+            _ => return false,
+        };
+        if !ui.is_rect_visible(rect) {
+            return true;
+        }
+
+        // a fan of triangles around the middle of the image's middle square
+        let [width, height] = texture.size().map(|side| side as f32);
+        let side = width.min(height);
+        let uv_radius = egui::vec2(side / width, side / height) / 2.0;
+        let radius = rect.width().min(rect.height()) / 2.0;
+
+        let mut mesh = egui::Mesh::with_texture(texture.id());
+        mesh.vertices.push(egui::epaint::Vertex {
+            pos: rect.center(),
+            uv: egui::pos2(0.5, 0.5),
+            color: Color32::WHITE,
+        });
+        const SEGMENTS: u32 = 32;
+        for i in 0..=SEGMENTS {
+            let angle = i as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+            let direction = egui::vec2(angle.cos(), angle.sin());
+            mesh.vertices.push(egui::epaint::Vertex {
+                pos: rect.center() + direction * radius,
+                uv: egui::pos2(0.5, 0.5) + direction * uv_radius,
+                color: Color32::WHITE,
+            });
+            if i > 0 {
+                mesh.add_triangle(0, i, i + 1);
+            }
+        }
+        ui.painter().add(egui::Shape::mesh(mesh));
+
+        true
+    }
+
+    fn thumbnail(
+        &self,
+        ctx: &Context,
+        attachment: &Attachment,
+    ) -> Option<Result<TextureHandle, Error>> {
+        let guid = Some(attachment.guid.as_str()).filter(|guid| !guid.is_empty());
+        texture(
+            ctx,
+            &self.rt,
+            &self.thumbnails,
+            &attachment.path,
+            guid,
+            THUMBNAIL_LEN,
+        )
+    }
+
+    /// The lightbox opened with [`Images::open`], covering the whole window.
+    /// Drag to pan, scroll to zoom, arrow keys for the previous or next image
+    /// and escape to close.
+    pub fn show_lightbox(&mut self, ctx: &Context) {
+        let lightbox = match &mut self.lightbox {
+            Some(lightbox) => lightbox,
+            None => return,
+        };
+
+        // arrow keys move the cursor while typing a path to save to
+        let typing = ctx.wants_keyboard_input();
+        let (mut close, step) = {
+            let input = ctx.input();
+            let step = if typing {
+                0
+            } else if shortcuts::LIGHTBOX_PREV.pressed(&input) {
+                -1
+            } else if shortcuts::LIGHTBOX_NEXT.pressed(&input) {
+                1
+            } else {
+                0
+            };
+            (shortcuts::LIGHTBOX_CLOSE.pressed(&input), step)
+        };
+        lightbox.step(step);
+
+        let screen = ctx.input().screen_rect();
+        egui::Area::new("lightbox")
+            .order(egui::Order::Foreground)
+            .fixed_pos(screen.min)
+            .show(ctx, |ui| {
+                ui.painter()
+                    .rect_filled(screen, 0.0, Color32::from_black_alpha(230));
+                ui.set_min_size(screen.size());
+                ui.visuals_mut().override_text_color = Some(Color32::WHITE);
+
+                let path = lightbox.paths[lightbox.index].clone();
+                ui.horizontal(|ui| {
+                    if a11y::icon(ui.button("✕"), i18n::t("close (esc)")).clicked() {
+                        close = true;
+                    }
+                    let last = lightbox.paths.len() - 1;
+                    let previous = ui.add_enabled(lightbox.index > 0, egui::Button::new("⏴"));
+                    if a11y::icon(previous, i18n::t("previous photo")).clicked() {
+                        lightbox.step(-1);
+                    }
+                    ui.label(format!("{} / {}", lightbox.index + 1, last + 1));
+                    let next = ui.add_enabled(lightbox.index < last, egui::Button::new("⏵"));
+                    if a11y::icon(next, i18n::t("next photo")).clicked() {
+                        lightbox.step(1);
+                    }
+                    ui.label(path.file_name().unwrap_or_default().to_string_lossy());
+
+                    ui.separator();
+                    if a11y::icon(ui.button("−"), i18n::t("zoom out")).clicked() {
+                        lightbox.zoom_by(0.5);
+                    }
+                    ui.label(format!("{:.0}%", lightbox.zoom * 100.0));
+                    if a11y::icon(ui.button("+"), i18n::t("zoom in")).clicked() {
+                        lightbox.zoom_by(2.0);
+                    }
+                    if ui.button(i18n::t("fit")).clicked() {
+                        lightbox.zoom = 1.0;
+                        lightbox.pan = egui::Vec2::ZERO;
+                    }
+
+                    ui.separator();
+                    ui.add(
+                        egui::TextEdit::singleline(&mut lightbox.save_path).desired_width(280.0),
+                    );
+                    if ui.button(i18n::t("save as")).clicked() {
+                        lightbox.saved = Some(save_as(&path, Path::new(&lightbox.save_path)));
+                    }
+                    match &lightbox.saved {
+                        Some(Ok(saved)) => {
+                            ui.label(i18n::tf("saved to {path}", &[("path", &saved.display())]));
+                        }
+                        Some(Err(e)) => {
+                            ui.colored_label(Color32::LIGHT_RED, e);
+                        }
+                        None => {}
+                    }
+                });
+
+                let path = lightbox.paths[lightbox.index].clone();
+                let area = ui.available_rect_before_wrap();
+                let response = ui.allocate_rect(area, Sense::click_and_drag());
+
+                match texture(
+                    ctx,
+                    &self.rt,
+                    &lightbox.entries,
+                    &path,
+                    None,
+                    MAX_TEXTURE_LEN,
+                ) {
+                    Some(Ok(texture)) => {
+                        if response.hovered() {
+                            let scroll = ctx.input().scroll_delta.y;
+                            if scroll != 0.0 {
+                                lightbox.zoom_by((scroll / 200.0).exp());
+                            }
+                        }
+                        if response.dragged() {
+                            lightbox.pan += response.drag_delta();
+                        }
+                        if response.double_clicked() {
+                            lightbox.zoom = 1.0;
+                            lightbox.pan = egui::Vec2::ZERO;
+                        }
+
+                        let size = texture.size_vec2();
+                        let fit = (area.width() / size.x).min(area.height() / size.y).min(1.0);
+                        let rect = egui::Rect::from_center_size(
+                            area.center() + lightbox.pan,
+                            size * fit * lightbox.zoom,
+                        );
+                        let uv =
+                            egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                        ui.painter().with_clip_rect(area).add(egui::Shape::image(
+                            texture.id(),
+                            rect,
+                            uv,
+                            Color32::WHITE,
+                        ));
+                    }
+                    Some(Err(e)) => {
+                        ui.painter().text(
+                            area.center(),
+                            egui::Align2::CENTER_CENTER,
+                            format!("{e}\n{}", e.hint()),
+                            egui::FontId::proportional(16.0),
+                            Color32::WHITE,
+                        );
+                    }
+                    None => {
+                        ui.put(
+                            egui::Rect::from_center_size(area.center(), egui::Vec2::splat(32.0)),
+                            egui::Spinner::new(),
+                        );
+                    }
+                }
+            });
+
+        if close {
+            self.lightbox = None;
+        }
+    }
+}
+
+impl Lightbox {
+    fn step(&mut self, step: isize) {
+        let index = self.index as isize + step;
+        if step != 0 && (0..self.paths.len() as isize).contains(&index) {
+            self.go_to(index as usize);
+        }
+    }
+
+    fn go_to(&mut self, index: usize) {
+        self.index = index;
+        self.zoom = 1.0;
+        self.pan = egui::Vec2::ZERO;
+        self.saved = None;
+
+        let path = &self.paths[index];
+        let file_name = path.file_name().unwrap_or_default();
+        self.save_path = dirs::download_dir()
+            .or_else(dirs::home_dir)
+            .unwrap_or_default()
+            .join(file_name)
+            .to_string_lossy()
+            .into_owned();
+
+        // full size photos are big, drop any that aren't next to this one
+        let nearby = &self.paths[index.saturating_sub(1)..(index + 2).min(self.paths.len())];
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|path, _| nearby.contains(path));
+    }
+
+    fn zoom_by(&mut self, factor: f32) {
+        let zoom = (self.zoom * factor).clamp(1.0 / MAX_ZOOM, MAX_ZOOM);
+        self.pan *= zoom / self.zoom;
+        self.zoom = zoom;
+    }
+}
+
+/// Copy the original attachment (not the decoded image) to `to`, refusing to
+/// overwrite anything
+fn save_as(path: &Path, to: &Path) -> Result<PathBuf, String> {
+    if to.exists() {
+        return Err(format!("{} already exists", to.display()));
+    }
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::copy(path, to).map_err(|e| e.to_string())?;
+
+    Ok(to.to_owned())
+}
+
+/// The texture for `path` once it has been decoded (or why it couldn't be),
+/// or `None` while it is still decoding. For animations this is whichever
+/// frame is showing now, and a repaint is scheduled for the next one. With
+/// the attachment's `guid`, still images go through the on-disk cache.
+fn texture(
+    ctx: &Context,
+    rt: &runtime::Handle,
+    entries: &Entries,
+    path: &Path,
+    guid: Option<&str>,
+    max_len: u32,
+) -> Option<Result<TextureHandle, Error>> {
+    let mut locked = entries.lock().unwrap();
+
+    if let Some(Entry::Decoded(_)) = locked.get(path) {
+        if let Some(Entry::Decoded(frames)) = locked.remove(path) {
+            let name = path.to_string_lossy();
+            let textures = frames
+                .into_iter()
+                .enumerate()
+                .map(|(i, (image, delay))| {
+                    let texture =
+                        ctx.load_texture(format!("{name}#{i}"), image, Default::default());
+                    (texture, delay)
+                })
+                .collect();
+            locked.insert(path.to_owned(), Entry::Ready(textures));
+        }
+    }
+
+    match locked.get(path) {
+        Some(Entry::Ready(frames)) => Some(Ok(current_frame(ctx, frames).clone())),
+        Some(Entry::Failed(e)) => Some(Err(e.clone())),
+        Some(Entry::Decoding | Entry::Decoded(_)) => None,
+        None => {
+            locked.insert(path.to_owned(), Entry::Decoding);
+
+            let entries = entries.clone();
+            let path = path.to_owned();
+            let guid = guid.map(str::to_owned);
+            let ctx = ctx.clone();
+            rt.spawn_blocking(move || {
+                let decoded = match &guid {
+                    Some(guid) => decode_cached(&path, guid, max_len),
+                    None => decode(&path, max_len),
+                };
+                let entry = match decoded {
+                    Ok(frames) => Entry::Decoded(frames),
+                    Err(e) => Entry::Failed(e),
+                };
+
+                // the entry is gone if the cache was cleared while decoding
+                if let Some(slot) = entries.lock().unwrap().get_mut(&path) {
+                    *slot = entry;
+                }
+                ctx.request_repaint();
+            });
+
+            None
+        }
+    }
+}
+
+/// The frame of a looping animation to show now, asking for a repaint when
+/// it is time for the next one
+fn current_frame<'a>(ctx: &Context, frames: &'a [Frame<TextureHandle>]) -> &'a TextureHandle {
+    let total = frames.iter().map(|(_, delay)| *delay).sum::<Duration>();
+    if frames.len() < 2 || total.is_zero() {
+        return &frames[0].0;
+    }
+
+    let mut into = Duration::from_secs_f64(ctx.input().time % total.as_secs_f64());
+    for (texture, delay) in frames {
+        if into < *delay {
+            ctx.request_repaint_after(*delay - into);
+            return texture;
+        }
+        into -= *delay;
+    }
+
+    &frames[0].0
+}
+
+/// [`decode`], reading the result from the thumbnail cache if it has been
+/// decoded before, and saving it there if not. Animations aren't cached, as
+/// only their first frame would be.
+fn decode_cached(path: &Path, guid: &str, max_len: u32) -> Result<Vec<Frame<ColorImage>>, Error> {
+    let cached = cache::thumbnail_path(guid);
+
+    let hit = image::open(&cached).ok();
+    perf::cache_lookup("thumbnails on disk", hit.is_some());
+    if let Some(image) = hit {
+        return Ok(vec![(color_image(image, max_len), Duration::ZERO)]);
+    }
+
+    let frames = decode(path, max_len)?;
+    if let [(image, _)] = frames.as_slice() {
+        if let Err(e) = save_thumbnail(&cached, image) {
+            eprintln!("{}: {e}", cached.display());
+        }
+    }
+
+    Ok(frames)
+}
+
+/// Write a thumbnail as PNG, via a temporary file so that a half written one
+/// is never read back
+fn save_thumbnail(to: &Path, image: &ColorImage) -> Result<(), ImageError> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let rgba: Vec<u8> = image
+        .pixels
+        .iter()
+        .flat_map(|pixel| pixel.to_srgba_unmultiplied())
+        .collect();
+    let [width, height] = image.size.map(|side| side as u32);
+
+    let partial = to.with_extension("partial.png");
+    image::save_buffer(&partial, &rgba, width, height, image::ColorType::Rgba8)?;
+    fs::rename(&partial, to)?;
+
+    Ok(())
+}
+
+/// Decode an image file (converting HEIC first), scaled down so neither side
+/// is over `max_len`. Animated GIF, PNG and WebP files are decoded to every
+/// frame, up to [`MAX_ANIMATION_BYTES`].
+fn decode(path: &Path, max_len: u32) -> Result<Vec<Frame<ColorImage>>, Error> {
+    puffin::profile_function!();
+
+    if !path.exists() {
+        return Err(Error::MissingAttachment(path.to_owned()));
+    }
+
+    let readable = heic::readable(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => Error::UnsupportedFormat(e.to_string()),
+        _ => Error::Decode(e.to_string()),
+    })?;
+
+    let reader = image::io::Reader::open(&readable)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| Error::Decode(e.to_string()))?;
+
+    let open = || {
+        File::open(&readable)
+            .map(BufReader::new)
+            .map_err(|e| Error::Decode(e.to_string()))
+    };
+    let animation = match reader.format() {
+        Some(ImageFormat::Gif) => Some(frames(
+            GifDecoder::new(open()?).map_err(image_error)?,
+            max_len,
+        )),
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(open()?).map_err(image_error)?;
+            decoder.is_apng().then(|| frames(decoder.apng(), max_len))
+        }
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(open()?).map_err(image_error)?;
+            decoder.has_animation().then(|| frames(decoder, max_len))
+        }
+        _ => None,
+    };
+    if let Some(animation) = animation {
+        return animation;
+    }
+
+    let image = reader.decode().map_err(image_error)?;
+
+    Ok(vec![(color_image(image, max_len), Duration::ZERO)])
+}
+
+/// Every frame of an animation, scaled down, and cut short if the decoded
+/// frames would take up too much memory
+fn frames<'a>(
+    decoder: impl AnimationDecoder<'a>,
+    max_len: u32,
+) -> Result<Vec<Frame<ColorImage>>, Error> {
+    let mut frames = Vec::new();
+    let mut bytes = 0;
+
+    for frame in decoder.into_frames() {
+        let frame = frame.map_err(image_error)?;
+        let delay = match Duration::from(frame.delay()) {
+            delay if delay < MIN_FRAME_DELAY => Duration::from_millis(100),
+            delay => delay,
+        };
+
+        let image = color_image(DynamicImage::ImageRgba8(frame.into_buffer()), max_len);
+        bytes += image.pixels.len() * 4;
+        frames.push((image, delay));
+
+        if bytes > MAX_ANIMATION_BYTES {
+            break;
+        }
+    }
+
+    if frames.is_empty() {
+        return Err(Error::Decode("animation has no frames".into()));
+    }
+
+    Ok(frames)
+}
+
+fn color_image(mut image: DynamicImage, max_len: u32) -> ColorImage {
+    if image.width() > max_len || image.height() > max_len {
+        image = image.thumbnail(max_len, max_len);
+    }
+
+    let image = image.to_rgba8();
+    let size = [image.width() as usize, image.height() as usize];
+
+    ColorImage::from_rgba_unmultiplied(size, image.as_raw())
+}
+
+fn image_error(e: ImageError) -> Error {
+    match e {
+        ImageError::Unsupported(e) => Error::UnsupportedFormat(e.to_string()),
+        e => Error::Decode(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{codecs::gif::GifEncoder, Delay, Frame, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("imessage-viewer-images-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn scales_down_large_images() {
+        let path = scratch("wide.png");
+        RgbaImage::from_pixel(400, 100, Rgba([255, 0, 0, 255]))
+            .save(&path)
+            .unwrap();
+
+        let frames = decode(&path, 200).unwrap();
+        assert_eq!(frames.len(), 1);
+        let (image, delay) = &frames[0];
+        assert_eq!(image.size, [200, 50]);
+        assert_eq!(image.pixels[0], Color32::RED);
+        assert_eq!(*delay, Duration::ZERO);
+
+        // small ones are left as they are
+        assert_eq!(decode(&path, 1000).unwrap()[0].0.size, [400, 100]);
+    }
+
+    #[test]
+    fn decodes_every_frame_of_an_animation() {
+        let path = scratch("blink.gif");
+        let frame = |color, millis| {
+            Frame::from_parts(
+                RgbaImage::from_pixel(8, 8, Rgba(color)),
+                0,
+                0,
+                Delay::from_numer_denom_ms(millis, 1),
+            )
+        };
+        let mut gif = Vec::new();
+        GifEncoder::new(&mut gif)
+            .encode_frames([frame([0, 0, 0, 255], 500), frame([255, 255, 255, 255], 0)])
+            .unwrap();
+        fs::write(&path, gif).unwrap();
+
+        let delays: Vec<_> = decode(&path, 100)
+            .unwrap()
+            .into_iter()
+            .map(|(_, delay)| delay)
+            .collect();
+        // too short a delay is played the way browsers play it
+        assert_eq!(
+            delays,
+            [Duration::from_millis(500), Duration::from_millis(100)]
+        );
+    }
+
+    #[test]
+    fn reports_what_went_wrong() {
+        assert!(matches!(
+            decode(&scratch("missing.png"), 100),
+            Err(Error::MissingAttachment(_))
+        ));
+
+        let path = scratch("notes.png");
+        fs::write(&path, "not an image").unwrap();
+        assert!(matches!(
+            decode(&path, 100),
+            Err(Error::Decode(_) | Error::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn thumbnails_round_trip() {
+        let image = ColorImage::new([3, 2], Color32::from_rgb(10, 20, 30));
+        let path = scratch("thumbnails/guid.png");
+        save_thumbnail(&path, &image).unwrap();
+
+        let read = color_image(image::open(&path).unwrap(), 100);
+        assert_eq!(read.size, image.size);
+        assert_eq!(read.pixels, image.pixels);
+    }
+
+    #[test]
+    fn saves_a_copy_without_overwriting() {
+        let original = scratch("original.jpeg");
+        fs::write(&original, "jpeg").unwrap();
+        let copy = scratch("saved/copy.jpeg");
+        let _ = fs::remove_file(&copy);
+
+        assert_eq!(save_as(&original, &copy).unwrap(), copy);
+        assert_eq!(fs::read(&copy).unwrap(), b"jpeg");
+        assert!(save_as(&original, &copy)
+            .unwrap_err()
+            .ends_with("already exists"));
+    }
+}
@@ -0,0 +1,344 @@
+use eyre::Result;
+use sqlx::{sqlite::SqliteRow, Column, Row, SqlitePool, TypeInfo, ValueRef};
+
+use crate::{associated_target, i18n, time};
+
+/// Columns shown first, when the database has them: what identifies the
+/// message, every date, the delivery flags and links to other messages.
+/// Everything else in the row follows.
+const SUMMARY: &[&str] = &[
+    "ROWID",
+    "guid",
+    "service",
+    "handle_id",
+    "is_from_me",
+    "date",
+    "date_read",
+    "date_delivered",
+    "date_played",
+    "date_edited",
+    "date_retracted",
+    "is_read",
+    "is_delivered",
+    "is_sent",
+    "is_finished",
+    "error",
+    "item_type",
+    "associated_message_guid",
+    "associated_message_type",
+    "reply_to_guid",
+    "thread_originator_guid",
+];
+
+/// Columns holding the GUID of another message
+const LINKS: &[&str] = &[
+    "associated_message_guid",
+    "reply_to_guid",
+    "thread_originator_guid",
+];
+
+/// Everything the database has on one message, for working out why a row
+/// looks the way it does
+#[derive(Clone)]
+pub struct Info {
+    pub id: i64,
+    /// Every column of the row as text: the summary ones, then the rest
+    pub summary: Vec<(String, String)>,
+    pub rest: Vec<(String, String)>,
+    /// `handle.id` of the sender, or for messages I sent, who they went to
+    pub handle: Option<String>,
+    /// Every chat the message is in
+    pub chats: Vec<String>,
+    pub links: Vec<Link>,
+}
+
+/// A message this one points at, e.g. what a reaction or reply is to
+#[derive(Clone)]
+pub struct Link {
+    pub column: String,
+    pub guid: String,
+    /// Its ROWID and chat, if it's in the database
+    pub target: Option<(i64, String)>,
+}
+
+pub async fn load(db: &SqlitePool, id: i64) -> Result<Info> {
+    let row = sqlx::query("SELECT * from message where ROWID=$1;")
+        .bind(id)
+        .fetch_one(db)
+        .await?;
+
+    let mut summary = Vec::new();
+    let mut rest = Vec::new();
+    let mut links = Vec::new();
+    for column in row.columns() {
+        let name = column.name();
+        let value = value(&row, column.ordinal())?;
+
+        if LINKS.contains(&name) && !value.is_empty() && value != "null" {
+            let guid = associated_target(&value).to_owned();
+            let target = sqlx::query_as::<_, (i64, String)>(
+                r#"
+                    SELECT m.ROWID, c.chat_identifier
+                    from message m
+                    join chat_message_join cmj
+                        on m.ROWID = cmj.message_id
+                    join chat c
+                        on cmj.chat_id = c.ROWID
+                    where m.guid=$1
+                    ;
+                "#,
+            )
+            .bind(&guid)
+            .fetch_optional(db)
+            .await?;
+            links.push(Link {
+                column: name.to_owned(),
+                guid,
+                target,
+            });
+        }
+
+        if SUMMARY.contains(&name) {
+            summary.push((name.to_owned(), value));
+        } else {
+            rest.push((name.to_owned(), value));
+        }
+    }
+    summary.sort_by_key(|(name, _)| SUMMARY.iter().position(|s| s == name));
+
+    let handle = sqlx::query_as::<_, (String,)>(
+        "SELECT h.id from message m join handle h on m.handle_id = h.ROWID where m.ROWID=$1;",
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await?
+    .map(|(handle,)| handle);
+
+    let chats = sqlx::query_as::<_, (String,)>(
+        r#"
+            SELECT c.chat_identifier
+            from chat_message_join cmj
+            join chat c
+                on cmj.chat_id = c.ROWID
+            where cmj.message_id=$1
+            ;
+        "#,
+    )
+    .bind(id)
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|(chat,)| chat)
+    .collect();
+
+    Ok(Info {
+        id,
+        summary,
+        rest,
+        handle,
+        chats,
+        links,
+    })
+}
+
+/// A column's value as text. Dates are followed by what they mean, and blobs
+/// are only described, since they're usually archived plists.
+fn value(row: &SqliteRow, index: usize) -> Result<String> {
+    let raw = row.try_get_raw(index)?;
+    if raw.is_null() {
+        return Ok("null".to_owned());
+    }
+
+    let name = row.column(index).name();
+    let value = match raw.type_info().name() {
+        "INTEGER" => {
+            let value: i64 = row.try_get_unchecked(index)?;
+            if name.starts_with("date") && value != 0 {
+                format!("{value} ({})", time(value).format("%Y-%m-%d %H:%M:%S UTC"))
+            } else {
+                value.to_string()
+            }
+        }
+        "REAL" => row.try_get_unchecked::<f64, _>(index)?.to_string(),
+        "BLOB" => {
+            let bytes: Vec<u8> = row.try_get_unchecked(index)?;
+            format!("{} bytes", bytes.len())
+        }
+        _ => row.try_get_unchecked(index)?,
+    };
+
+    Ok(value)
+}
+
+impl Info {
+    /// Everything as `column: value` lines, for pasting into a bug report
+    fn text(&self) -> String {
+        self.summary
+            .iter()
+            .chain(&self.rest)
+            .map(|(name, value)| format!("{name}: {value}\n"))
+            .collect()
+    }
+}
+
+/// The info window. Returns the message and chat of a link that was clicked.
+pub fn show(ctx: &egui::Context, info: &Info, open: &mut bool) -> Option<(i64, String)> {
+    let mut clicked = None;
+
+    egui::Window::new(i18n::t("message info"))
+        .id(egui::Id::new("message info"))
+        .open(open)
+        .default_width(420.0)
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("message_info")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label(i18n::t("handle"));
+                        ui.label(info.handle.as_deref().unwrap_or("-"));
+                        ui.end_row();
+                        ui.label(i18n::t("chats"));
+                        ui.label(info.chats.join(", "));
+                        ui.end_row();
+                        for (name, value) in &info.summary {
+                            ui.label(name);
+                            ui.add(egui::Label::new(value).wrap(true));
+                            ui.end_row();
+                        }
+                    });
+
+                if !info.links.is_empty() {
+                    ui.separator();
+                    for link in &info.links {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{} →", link.column));
+                            match &link.target {
+                                Some(target) => {
+                                    if ui.link(&link.guid).clicked() {
+                                        clicked = Some(target.clone());
+                                    }
+                                }
+                                None => {
+                                    ui.weak(i18n::tf(
+                                        "{guid} (not in the database)",
+                                        &[("guid", &link.guid)],
+                                    ));
+                                }
+                            }
+                        });
+                    }
+                }
+
+                ui.separator();
+                egui::CollapsingHeader::new(i18n::tf(
+                    "every other column ({count})",
+                    &[("count", &info.rest.len())],
+                ))
+                .id_source(("message_info_rest", info.id))
+                .show(ui, |ui| {
+                    egui::Grid::new("message_info_rest")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (name, value) in &info.rest {
+                                ui.label(name);
+                                ui.add(egui::Label::new(value).wrap(true));
+                                ui.end_row();
+                            }
+                        });
+                });
+
+                if ui.button(i18n::t("copy all")).clicked() {
+                    ui.output().copied_text = info.text();
+                }
+            });
+        });
+
+    clicked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[tokio::test]
+    async fn loads_every_column() {
+        let db = test_util::memory().await;
+        sqlx::query(
+            r#"
+                CREATE TABLE message (
+                    ROWID INTEGER PRIMARY KEY, text TEXT, guid TEXT, payload_data BLOB,
+                    date_read INTEGER, date INTEGER, handle_id INTEGER, ck_sync_state REAL,
+                    associated_message_guid TEXT, reply_to_guid TEXT, thread_originator_guid TEXT
+                );
+                CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT);
+                CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, chat_identifier TEXT);
+                CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+                INSERT INTO handle VALUES (1, 'ana');
+                INSERT INTO chat VALUES (1, 'ana'), (2, 'chat100');
+                INSERT INTO message VALUES
+                    (1, 'hi', 'first', NULL, 0, 0, 1, NULL, NULL, NULL, NULL),
+                    (2, NULL, 'love', x'0102', 0, 631152000000000000, 1, 1.5,
+                        'p:0/first', 'missing', '');
+                INSERT INTO chat_message_join VALUES (1, 1), (1, 2), (2, 2);
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let info = load(&db, 2).await.unwrap();
+        assert_eq!(info.handle.as_deref(), Some("ana"));
+        assert_eq!(info.chats, ["ana", "chat100"]);
+
+        let columns = |columns: &[(String, String)]| {
+            columns
+                .iter()
+                .map(|(name, value)| format!("{name}: {value}"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            columns(&info.summary),
+            [
+                "ROWID: 2",
+                "guid: love",
+                "handle_id: 1",
+                "date: 631152000000000000 (2021-01-01 00:00:00 UTC)",
+                "date_read: 0",
+                "associated_message_guid: p:0/first",
+                "reply_to_guid: missing",
+                "thread_originator_guid: ",
+            ]
+        );
+        assert_eq!(
+            columns(&info.rest),
+            ["text: null", "payload_data: 2 bytes", "ck_sync_state: 1.5"]
+        );
+
+        let links: Vec<_> = info
+            .links
+            .iter()
+            .map(|link| {
+                (
+                    link.column.as_str(),
+                    link.guid.as_str(),
+                    link.target.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            links,
+            [
+                (
+                    "associated_message_guid",
+                    "first",
+                    Some((1, "ana".to_owned()))
+                ),
+                ("reply_to_guid", "missing", None),
+            ]
+        );
+
+        assert!(info.text().starts_with("ROWID: 2\nguid: love\n"));
+        assert!(load(&db, 3).await.is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use egui::{Color32, Sense, Ui};
+
+use crate::{config::ChatLabel, i18n};
+
+/// Colours offered when labelling a chat
+const PALETTE: [[u8; 3]; 6] = [
+    [235, 77, 75],
+    [240, 147, 43],
+    [246, 229, 141],
+    [106, 176, 76],
+    [72, 126, 176],
+    [165, 94, 234],
+];
+
+/// Draw a chat's label: a colour dot followed by its emoji
+pub fn badge(ui: &mut Ui, label: &ChatLabel) {
+    if let Some([r, g, b]) = label.color {
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(10.0, 10.0), Sense::hover());
+        ui.painter()
+            .circle_filled(rect.center(), 5.0, Color32::from_rgb(r, g, b));
+    }
+
+    if !label.emoji.trim().is_empty() {
+        ui.label(label.emoji.trim());
+    }
+}
+
+/// Contents of a chat's right-click menu for changing its label. Returns
+/// true if the label changed.
+pub fn edit(ui: &mut Ui, label: &mut ChatLabel) -> bool {
+    let mut changed = false;
+
+    ui.horizontal(|ui| {
+        for rgb in PALETTE {
+            let [r, g, b] = rgb;
+            let selected = label.color == Some(rgb);
+            let text = egui::RichText::new(if selected { "●" } else { "○" })
+                .color(Color32::from_rgb(r, g, b));
+
+            if ui.selectable_label(selected, text).clicked() {
+                label.color = if selected { None } else { Some(rgb) };
+                changed = true;
+            }
+        }
+    });
+
+    ui.horizontal(|ui| {
+        ui.label(i18n::t("emoji"));
+        changed |= ui
+            .add(egui::TextEdit::singleline(&mut label.emoji).desired_width(40.0))
+            .changed();
+    });
+
+    if ui.button(i18n::t("clear label")).clicked() {
+        *label = ChatLabel::default();
+        changed = true;
+        ui.close_menu();
+    }
+
+    changed
+}
+
+/// A row of the labels in use, for showing only the chats with one of them.
+/// Clicking the active label again clears the filter.
+pub fn filter_bar(
+    ui: &mut Ui,
+    labels: &HashMap<String, ChatLabel>,
+    filter: &mut Option<ChatLabel>,
+) {
+    let mut in_use: Vec<&ChatLabel> = Vec::new();
+    for label in labels.values() {
+        if !label.is_empty() && !in_use.contains(&label) {
+            in_use.push(label);
+        }
+    }
+    if in_use.is_empty() {
+        *filter = None;
+        return;
+    }
+    in_use.sort_by(|a, b| (a.color, &a.emoji).cmp(&(b.color, &b.emoji)));
+
+    ui.horizontal_wrapped(|ui| {
+        if ui
+            .selectable_label(filter.is_none(), i18n::t("all"))
+            .clicked()
+        {
+            *filter = None;
+        }
+
+        for label in in_use {
+            let selected = filter.as_ref() == Some(label);
+            let response = ui
+                .scope(|ui| {
+                    ui.spacing_mut().item_spacing.x = 2.0;
+                    egui::Frame::none()
+                        .inner_margin(2.0)
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| badge(ui, label));
+                        })
+                        .response
+                })
+                .inner
+                .interact(Sense::click());
+
+            if selected {
+                ui.painter()
+                    .rect_stroke(response.rect, 2.0, ui.visuals().selection.stroke);
+            }
+            if response.clicked() {
+                *filter = if selected { None } else { Some(label.clone()) };
+            }
+        }
+    });
+}
@@ -0,0 +1,48 @@
+use egui::Ui;
+
+use crate::{
+    a11y,
+    config::{LayoutConfig, Panel},
+    i18n,
+};
+
+impl Panel {
+    fn title(self) -> &'static str {
+        match self {
+            Self::Chats => "chats",
+            Self::Media => "media",
+            Self::SearchResults => "search results",
+        }
+    }
+}
+
+/// Buttons for moving a docked panel: collapsing it (the chat list only) and
+/// detaching it into a floating window. Returns whether the layout changed.
+pub fn controls(ui: &mut Ui, layout: &mut LayoutConfig, panel: Panel) -> bool {
+    let mut changed = false;
+
+    if panel == Panel::Chats
+        && a11y::icon(ui.small_button("«"), i18n::t("collapse chat list")).clicked()
+    {
+        layout.sidebar_collapsed = true;
+        changed = true;
+    }
+    if ui
+        .small_button(i18n::t("detach"))
+        .on_hover_text(i18n::t("move into a floating window inside the viewer"))
+        .clicked()
+    {
+        layout.set_detached(panel, true);
+        changed = true;
+    }
+
+    changed
+}
+
+/// Window for a detached panel. Closing it docks the panel again.
+pub fn window(panel: Panel, open: &mut bool) -> egui::Window<'_> {
+    egui::Window::new(i18n::t(panel.title()))
+        .id(egui::Id::new(("detached", panel.title())))
+        .open(open)
+        .default_size([360.0, 480.0])
+}
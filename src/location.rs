@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use egui::{Color32, Frame, RichText, Rounding, Ui};
+use eyre::Result;
+use sqlx::SqlitePool;
+use url::Url;
+
+use crate::{attachment::expand_path, i18n, vcard};
+
+/// A place shared from Maps or with "Send My Current Location", which
+/// arrives as a `.loc.vcf` attachment
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    /// The card's name, e.g. "Current Location" or the name of a dropped pin
+    pub name: Option<String>,
+    /// Latitude and longitude
+    pub coordinates: Option<(f64, f64)>,
+    /// Street address, if Maps put one in the link
+    pub address: Option<String>,
+}
+
+/// The shared location in every `.loc.vcf` attachment of a chat, keyed by
+/// the ROWID of the message it was sent in. Files are read on the blocking
+/// thread pool.
+pub async fn chat_locations(db: &SqlitePool, chat_id: &str) -> Result<HashMap<i64, Location>> {
+    let attachments = sqlx::query_as::<_, (i64, String)>(
+        r#"
+            SELECT
+                maj.message_id, a.filename
+            from attachment a
+            join message_attachment_join maj
+                on a.ROWID = maj.attachment_id
+            join chat_message_join cmj
+                on maj.message_id = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            where c.chat_identifier=$1
+                and (a.mime_type = 'text/x-vlocation' or a.filename like '%.loc.vcf')
+                and a.filename is not null
+            ;
+        "#,
+    )
+    .bind(chat_id)
+    .fetch_all(db)
+    .await?;
+
+    let locations = tokio::task::spawn_blocking(move || {
+        attachments
+            .into_iter()
+            .filter_map(|(message_id, filename)| {
+                // the file may not have been downloaded from iCloud
+                let card = std::fs::read_to_string(expand_path(&filename)).ok()?;
+                Some((message_id, parse(&card)?))
+            })
+            .collect()
+    })
+    .await?;
+
+    Ok(locations)
+}
+
+/// Read a location card, which is a vCard whose URL is a Maps link like
+/// `http://maps.apple.com/?ll=51.5,-0.12&q=Dropped%20Pin`
+pub fn parse(card: &str) -> Option<Location> {
+    let properties = vcard::cards(card).into_iter().next()?;
+    let name = properties
+        .iter()
+        .find(|p| p.name == "FN")
+        .map(|p| vcard::unescape(&p.value));
+    let link = properties
+        .iter()
+        .find(|p| p.name == "URL")
+        .and_then(|p| Url::parse(&vcard::unescape(&p.value)).ok())?;
+
+    let query: HashMap<_, _> = link.query_pairs().into_owned().collect();
+    let coordinates = ["ll", "q", "sll"]
+        .iter()
+        .find_map(|key| query.get(*key).and_then(|value| coordinates(value)));
+    let address = query.get("address").cloned();
+
+    if coordinates.is_none() && address.is_none() {
+        return None;
+    }
+
+    Some(Location {
+        name: name.filter(|name| !name.is_empty()),
+        coordinates,
+        address,
+    })
+}
+
+/// `51.5,-0.12` as a latitude and longitude
+fn coordinates(value: &str) -> Option<(f64, f64)> {
+    let (lat, lng) = value.split_once(',')?;
+    let lat: f64 = lat.trim().parse().ok()?;
+    let lng: f64 = lng.trim().parse().ok()?;
+    ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lng)).then_some((lat, lng))
+}
+
+impl Location {
+    /// A link to the place in the system's maps app: Apple Maps on macOS,
+    /// OpenStreetMap in the browser anywhere else
+    pub fn maps_url(&self) -> Option<String> {
+        let url = if cfg!(target_os = "macos") {
+            let mut url = Url::parse("https://maps.apple.com/").ok()?;
+            match (self.coordinates, &self.address) {
+                (Some((lat, lng)), _) => {
+                    url.query_pairs_mut()
+                        .append_pair("ll", &format!("{lat},{lng}"))
+                        .append_pair("q", self.name.as_deref().unwrap_or("Shared Location"));
+                }
+                (None, Some(address)) => {
+                    url.query_pairs_mut().append_pair("address", address);
+                }
+                (None, None) => return None,
+            }
+            url
+        } else {
+            match (self.coordinates, &self.address) {
+                (Some((lat, lng)), _) => {
+                    let mut url = Url::parse("https://www.openstreetmap.org/").ok()?;
+                    url.query_pairs_mut()
+                        .append_pair("mlat", &lat.to_string())
+                        .append_pair("mlon", &lng.to_string());
+                    url.set_fragment(Some(&format!("map=16/{lat}/{lng}")));
+                    url
+                }
+                (None, Some(address)) => {
+                    let mut url = Url::parse("https://www.openstreetmap.org/search").ok()?;
+                    url.query_pairs_mut().append_pair("query", address);
+                    url
+                }
+                (None, None) => return None,
+            }
+        };
+        Some(url.into())
+    }
+}
+
+/// A card with the place's name, address and coordinates, and a button to
+/// open it in a maps app
+pub fn show(ui: &mut Ui, location: &Location) {
+    Frame::none()
+        .fill(Color32::from_black_alpha(60))
+        .rounding(Rounding::same(6.0))
+        .inner_margin(8.0)
+        .show(ui, |ui| {
+            ui.label(
+                RichText::new(format!("📍 {}", i18n::t("shared location")))
+                    .small()
+                    .strong(),
+            );
+            ui.label(
+                location
+                    .name
+                    .as_deref()
+                    .unwrap_or(i18n::t("Shared Location")),
+            );
+            if let Some(address) = &location.address {
+                ui.label(RichText::new(address).small());
+            }
+            if let Some((lat, lng)) = location.coordinates {
+                ui.label(RichText::new(format!("{lat:.5}, {lng:.5}")).small().weak());
+            }
+            if let Some(url) = location.maps_url() {
+                if ui
+                    .small_button(i18n::t("open in Maps"))
+                    .on_hover_text(&url)
+                    .clicked()
+                {
+                    ui.ctx().output().open_url(url);
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(name: &str, url: &str) -> String {
+        format!(
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nFN:{name}\r\n\
+             item1.URL;type=pref:{url}\r\nitem1.X-ABLabel:map url\r\nEND:VCARD\r\n"
+        )
+    }
+
+    #[test]
+    fn reads_pins() {
+        let location = parse(&card(
+            "Dropped Pin",
+            "http://maps.apple.com/?ll=51.5\\,-0.12&q=Dropped%20Pin",
+        ))
+        .unwrap();
+        assert_eq!(
+            location,
+            Location {
+                name: Some("Dropped Pin".to_owned()),
+                coordinates: Some((51.5, -0.12)),
+                address: None,
+            }
+        );
+    }
+
+    #[test]
+    fn reads_addresses() {
+        let location = parse(&card(
+            "",
+            "https://maps.apple.com/?address=1%20Infinite%20Loop,%20Cupertino&q=Apple",
+        ))
+        .unwrap();
+        assert_eq!(location.name, None);
+        assert_eq!(location.coordinates, None);
+        assert_eq!(
+            location.address.as_deref(),
+            Some("1 Infinite Loop, Cupertino")
+        );
+    }
+
+    #[test]
+    fn needs_somewhere() {
+        assert_eq!(parse(&card("Home", "https://maps.apple.com/?q=Home")), None);
+        assert_eq!(parse(&card("Home", "not a link")), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn checks_coordinates() {
+        assert_eq!(coordinates(" 51.5 , -0.12"), Some((51.5, -0.12)));
+        assert_eq!(coordinates("91,0"), None);
+        assert_eq!(coordinates("0,181"), None);
+        assert_eq!(coordinates("Dropped Pin"), None);
+    }
+}
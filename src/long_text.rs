@@ -0,0 +1,143 @@
+use std::ops::Range;
+
+use egui::Ui;
+
+use crate::{bidi, find, i18n};
+
+/// Messages longer than this many bytes (pasted articles, logs...) are
+/// collapsed to a preview until the user asks for the rest
+const PREVIEW_LEN: usize = 2000;
+
+/// Expanded messages are laid out as separate labels of about this many
+/// bytes, so each one is cheap to lay out and off-screen ones aren't painted
+const CHUNK_LEN: usize = 4000;
+
+/// Draw a message body with `ranges` highlighted and `mentions` picked out.
+/// Long messages only show a preview unless `expanded` is set, which the
+/// expander button toggles.
+pub fn show(
+    ui: &mut Ui,
+    text: &str,
+    ranges: &[Range<usize>],
+    mentions: &[Range<usize>],
+    expanded: &mut bool,
+) {
+    if text.len() <= PREVIEW_LEN {
+        label(ui, text, 0, ranges, mentions);
+        return;
+    }
+
+    if !*expanded {
+        let end = floor_char_boundary(text, PREVIEW_LEN);
+        label(ui, &text[..end], 0, ranges, mentions);
+
+        let hidden = text[end..].chars().count();
+        if ui
+            .button(i18n::tf(
+                "show full message ({hidden} more characters)",
+                &[("hidden", &hidden)],
+            ))
+            .clicked()
+        {
+            *expanded = true;
+        }
+        return;
+    }
+
+    for chunk in chunks(text, CHUNK_LEN) {
+        label(ui, &text[chunk.clone()], chunk.start, ranges, mentions);
+    }
+
+    if ui.button(i18n::t("show less")).clicked() {
+        *expanded = false;
+    }
+}
+
+/// Label for the part of a message starting at byte `offset`, with the parts
+/// of `ranges` and `mentions` that fall inside it highlighted
+fn label(
+    ui: &mut Ui,
+    text: &str,
+    offset: usize,
+    ranges: &[Range<usize>],
+    mentions: &[Range<usize>],
+) {
+    let end = offset + text.len();
+    let inside = |ranges: &[Range<usize>]| -> Vec<_> {
+        ranges
+            .iter()
+            .filter(|r| r.start < end && r.end > offset)
+            .map(|r| r.start.max(offset) - offset..r.end.min(end) - offset)
+            .collect()
+    };
+    let ranges = inside(ranges);
+    let mentions = inside(mentions);
+
+    if bidi::has_rtl(text) {
+        bidi::label(ui, find::highlighted(ui, text, &ranges, &mentions));
+    } else if ranges.is_empty() && mentions.is_empty() {
+        ui.label(text);
+    } else {
+        ui.label(find::highlighted(ui, text, &ranges, &mentions));
+    }
+}
+
+/// Split `text` into byte ranges of at most about `len` bytes, breaking after
+/// a newline where there is one
+fn chunks(text: &str, len: usize) -> Vec<Range<usize>> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while text.len() - start > len {
+        let mut end = floor_char_boundary(text, start + len);
+        if let Some(newline) = text[start..end].rfind('\n').filter(|&n| n > 0) {
+            end = start + newline + 1;
+        }
+
+        chunks.push(start..end);
+        start = end;
+    }
+    chunks.push(start..text.len());
+
+    chunks
+}
+
+/// The largest char boundary in `text` that isn't after `index`
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn chunks_at_newlines() {
+        assert_eq!(chunks("one\ntwo three\nfour", 10), [0..4, 4..14, 14..18]);
+
+        // no newline to break at
+        assert_eq!(chunks("abcdefghij", 4), [0..4, 4..8, 8..10]);
+        assert_eq!(chunks("short", 10), [0..5]);
+        assert_eq!(chunks("", 10), [0..0]);
+    }
+
+    #[test]
+    fn chunks_between_chars() {
+        let text = "é".repeat(10);
+        let ranges = chunks(&text, 5);
+        assert!(ranges.iter().all(|range| range.len() <= 5));
+        let joined: String = ranges.into_iter().map(|range| &text[range]).collect();
+        assert_eq!(joined, text);
+    }
+
+    #[test]
+    fn floors_to_char_boundaries() {
+        assert_eq!(floor_char_boundary("aé", 2), 1);
+        assert_eq!(floor_char_boundary("aé", 3), 3);
+        assert_eq!(floor_char_boundary("aé", 10), 3);
+    }
+}
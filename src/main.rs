@@ -1,56 +1,477 @@
+mod a11y;
+mod activity;
+mod alias;
+mod attachment;
+mod audio;
+mod audit;
+mod avatar;
+mod balloon;
+mod bidi;
+mod bubble;
+mod cache;
+mod calendar;
+mod cancel;
+mod clipboard;
+mod config;
+mod continuity;
+mod counts;
+mod demo;
+mod emoji;
+mod error;
+mod event;
+mod export;
+mod find;
+mod fuzzy;
+mod gallery;
+#[cfg(test)]
+mod golden;
+mod heic;
+mod i18n;
+mod ignore;
+mod images;
+mod info;
+mod labels;
+mod layout;
+mod location;
+mod long_text;
+mod media_info;
+mod mention;
+mod merge;
+mod minimap;
+mod ocr;
+mod open;
+mod paging;
+mod palette;
+mod participants;
+mod payment;
+mod people;
+mod perf;
+mod schema;
+mod search;
+mod settings;
+mod shortcuts;
+mod sql;
+mod stats;
+mod summary;
+mod tabs;
+mod tapback;
+#[cfg(test)]
+mod test_util;
+mod theme;
+mod typedstream;
+mod vcard;
+mod watch;
+
 use std::{
+    collections::{HashMap, HashSet},
     future::Future,
-    sync::{Arc, Mutex, MutexGuard},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, OnceLock,
+    },
+    time::Instant,
 };
 
 use chrono::prelude::*;
 use clap::Parser;
-use egui::{Color32, Frame, Rgba, Rounding, Stroke, Ui};
+use egui::{
+    text::{LayoutJob, TextFormat},
+    Color32, Frame, Rounding, Stroke, Ui,
+};
 use eyre::Result;
 use sqlx::SqlitePool;
 use tokio::runtime::Runtime;
 
-lazy_static::lazy_static! {
-    static ref BLUE: Color32 = Rgba::from_srgba_premultiplied(65, 136, 247, 255).into();
-    static ref GREY: Color32 = Rgba::from_srgba_premultiplied(59, 59, 61, 255).into();
-}
+use attachment::{Attachment, MediaItem};
+use audio::Audio;
+use clipboard::Clipboard;
+use config::{ChatLabel, Config, ExportProfile, HandleChange, Identity, Panel, SavedSearch};
+use error::LastError;
+use find::Find;
+use images::Images;
+use media_info::MediaInfo;
+use paging::{Edge, Page, Window};
+use palette::{Command as PaletteCommand, Palette};
+use search::{Query, SearchResults, Terms};
 
 #[derive(Parser)]
 /// View historical iMessage chats based on a `chat.db` file
 struct Options {
     /// Path to the database file to load - do not use the main chat.db file
-    /// directly, make a copy before feeding it to this program.
-    database_file: String,
+    /// directly, make a copy before feeding it to this program. Can also be a
+    /// folder with one inside, like a copy of ~/Library/Messages or an iPhone
+    /// backup.
+    #[clap(required_unless_present = "demo")]
+    database_file: Option<String>,
+
+    /// Open a generated database of made up chats instead, to try things out
+    /// without real data
+    #[clap(long, conflicts_with = "database-file")]
+    demo: bool,
+
+    /// Read attachments from this directory instead of
+    /// ~/Library/Messages/Attachments, e.g. when they were copied out of a
+    /// backup along with the database
+    #[clap(long)]
+    attachments_root: Option<PathBuf>,
+
+    /// Append every SQL statement run against the database to this file
+    #[clap(long)]
+    audit_log: Option<PathBuf>,
+
+    /// Refuse to run any SQL statement that could modify the database
+    #[clap(long)]
+    assert_read_only: bool,
+
+    /// Don't run text recognition over photos to make them searchable
+    #[clap(long)]
+    no_ocr: bool,
+
+    /// Keep checking the database for new messages, adding them to the open
+    /// chat and notifying about chats set to notify
+    #[clap(long)]
+    watch: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Run an export profile from the config file, or export every contact,
+    /// then exit
+    Export {
+        #[clap(long, required_unless_present = "each-contact")]
+        profile: Option<String>,
+        /// Write one transcript per person to a `contacts` folder
+        #[clap(long, conflicts_with = "profile")]
+        each_contact: bool,
+    },
+    /// Write every message in a chat to a file, then exit. Messages are
+    /// read and written a page at a time, so chats of any size can be
+    /// exported.
+    ExportChat {
+        /// Chat identifier (phone number, email or chatNNN) to export
+        chat: String,
+        #[clap(long, value_enum, default_value = "json")]
+        format: export::Format,
+        /// Only messages sent on or after this day (YYYY-MM-DD)
+        #[clap(long)]
+        after: Option<NaiveDate>,
+        /// Only messages sent before this day (YYYY-MM-DD)
+        #[clap(long)]
+        before: Option<NaiveDate>,
+    },
+    /// Zip up a chat between two dates as a keepsake: a cover page, HTML
+    /// transcript, photo contact sheet, stats and raw JSON, then exit
+    TimeCapsule {
+        /// Chat identifier (phone number, email or chatNNN)
+        chat: String,
+        /// Only messages sent on or after this day (YYYY-MM-DD)
+        #[clap(long)]
+        after: Option<NaiveDate>,
+        /// Only messages sent before this day (YYYY-MM-DD)
+        #[clap(long)]
+        before: Option<NaiveDate>,
+    },
+    /// Copy the attachments out of a chat, without a transcript, then exit
+    ExportAttachments {
+        /// Chat identifier (phone number, email or chatNNN) to export from
+        chat: String,
+        /// Only attachments sent by this handle, or "me"
+        #[clap(long)]
+        from: Option<String>,
+        /// Only these kinds of attachment; may be given more than once
+        #[clap(long = "type", value_enum)]
+        kinds: Vec<export::Kind>,
+        /// Only attachments sent on or after this day (YYYY-MM-DD)
+        #[clap(long)]
+        after: Option<NaiveDate>,
+        /// Only attachments sent before this day (YYYY-MM-DD)
+        #[clap(long)]
+        before: Option<NaiveDate>,
+        /// Convert HEIC photos, to JPEG unless `heic_export` in the config
+        /// file says otherwise
+        #[clap(long)]
+        convert_heic: bool,
+        /// Start file names with the date they were sent
+        #[clap(long)]
+        name_by_date: bool,
+    },
+    /// Write a report of a chat's statistics, the same as its stats tab, then
+    /// exit
+    Stats {
+        /// Chat identifier (phone number, email or chatNNN)
+        #[clap(long)]
+        chat: String,
+        #[clap(long, value_enum, default_value = "text")]
+        format: stats::Format,
+        /// Where to write the report, instead of the downloads folder
+        #[clap(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+fn main() {
+    if let Err(report) = run() {
+        eprintln!("Error: {report:?}");
+
+        let error = error::classify(&report);
+        if let Some(error) = &error {
+            eprintln!("\n{}", error.hint());
+        }
+        std::process::exit(error.map_or(1, |e| e.exit_code()));
+    }
 }
 
-fn main() -> Result<()> {
-    let options = Options::parse();
+fn run() -> Result<()> {
+    let options = Options::parse_from(open::args());
+    // a mistake in the file shouldn't stop the viewer opening
+    let mut config = Config::load().unwrap_or_else(|e| {
+        eprintln!(
+            "couldn't read {}, using the defaults: {e}",
+            Config::path().display()
+        );
+        Config {
+            unreadable: true,
+            ..Config::default()
+        }
+    });
+    search::fold::configure(&config.search);
+
+    let mut attachments_root = options.attachments_root.clone();
+    let database_file = match &options.database_file {
+        Some(database_file) => {
+            let (database_file, attachments) = open::resolve(Path::new(database_file))?;
+            attachments_root = attachments_root.or(attachments);
+            Some(database_file.to_string_lossy().into_owned())
+        }
+        None => None,
+    };
+    if let Some(root) = &attachments_root {
+        attachment::set_root(root);
+    }
 
-    let native_options = eframe::NativeOptions::default();
+    let native_options = eframe::NativeOptions {
+        follow_system_theme: true,
+        ..Default::default()
+    };
 
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()?;
 
-    let db = rt.block_on(async { SqlitePool::connect(&options.database_file).await })?;
+    let audit = audit::Audit {
+        log: options.audit_log.as_ref(),
+        read_only: options.assert_read_only,
+    };
+    // the demo has an address book of its own
+    let mut contacts = config.contacts_database.clone();
+    let database_file = match database_file {
+        Some(database_file) => database_file,
+        None => {
+            let path = demo::default_path();
+            rt.block_on(demo::generate(&path))?;
+            contacts = Some(path.with_file_name(people::ADDRESS_BOOK));
+            path.to_string_lossy().into_owned()
+        }
+    };
+
+    let db = rt.block_on(audit::connect(
+        &database_file,
+        audit,
+        audit::Pool::Interactive,
+    ))?;
+    rt.block_on(schema::check(&db))?;
+    match rt.block_on(people::contacts(&db, contacts.as_deref())) {
+        Ok(contacts) => config.contacts = contacts,
+        Err(e) => eprintln!("couldn't read Contacts: {e}"),
+    }
+    let database_key = alias::database_key(&database_file);
+    config.me.aliases = config
+        .aliases
+        .get(&database_key)
+        .cloned()
+        .unwrap_or_default();
+
+    if let Some(Command::Export { profile, .. }) = &options.command {
+        let dir = match profile {
+            Some(profile) => {
+                let profile = export::profile::find(&config, profile)?;
+                rt.block_on(export::profile::run(&db, &config, profile, &Handle::new()))?
+            }
+            // clap makes sure --each-contact was passed instead
+            None => rt.block_on(export::each_contact(
+                &db,
+                &config.me,
+                &config.changes(),
+                &export::Scope::default(),
+                &export::default_dir(),
+            ))?,
+        };
+        println!("exported to {}", dir.display());
+        return Ok(());
+    }
+
+    if let Some(Command::ExportChat {
+        chat,
+        format,
+        after,
+        before,
+    }) = &options.command
+    {
+        let scope = export::Scope {
+            after: *after,
+            before: *before,
+            ..Default::default()
+        };
+        let path = rt.block_on(export::messages(
+            &db,
+            &config.me,
+            &config.changes(),
+            chat,
+            &scope,
+            *format,
+            &export::default_dir(),
+        ))?;
+        println!("exported to {}", path.display());
+        return Ok(());
+    }
+
+    if let Some(Command::TimeCapsule {
+        chat,
+        after,
+        before,
+    }) = &options.command
+    {
+        let scope = export::Scope {
+            after: *after,
+            before: *before,
+            ..Default::default()
+        };
+        let path = rt.block_on(export::time_capsule(
+            &db,
+            &config,
+            chat,
+            &scope,
+            &export::default_dir(),
+        ))?;
+        println!("exported to {}", path.display());
+        return Ok(());
+    }
+
+    if let Some(Command::ExportAttachments {
+        chat,
+        from,
+        kinds,
+        after,
+        before,
+        convert_heic,
+        name_by_date,
+    }) = &options.command
+    {
+        let scope = export::Scope {
+            after: *after,
+            before: *before,
+            ..Default::default()
+        };
+        let selection = export::Selection {
+            kinds: kinds.clone(),
+            from: from.clone(),
+            convert_heic: convert_heic.then_some(config.heic_export),
+            name_by_date: *name_by_date,
+        };
+        let dir = rt.block_on(export::attachments(
+            &db,
+            &config.me,
+            chat,
+            &scope,
+            &selection,
+            &export::default_dir(),
+        ))?;
+        println!("exported to {}", dir.display());
+        return Ok(());
+    }
+
+    if let Some(Command::Stats {
+        chat,
+        format,
+        output,
+    }) = &options.command
+    {
+        let rules = ignore::Rules::compile(&config.ignore_rules)?;
+        let stats = rt.block_on(stats::chat(
+            &db,
+            &config.me,
+            &rules,
+            &config.changes(),
+            &export::Scope::default(),
+            chat,
+        ))?;
+        let path = output.clone().unwrap_or_else(|| {
+            export::default_dir().join(format!(
+                "{}-stats.{}",
+                export::file_stem(chat),
+                format.extension()
+            ))
+        });
+        std::fs::write(&path, stats.render(*format)?)?;
+        println!("exported to {}", path.display());
+        return Ok(());
+    }
+
+    let cache = rt.block_on(cache::open(&db, &database_file))?;
+    let bulk = rt.block_on(audit::connect(&database_file, audit, audit::Pool::Bulk))?;
 
-    let app = MyEguiApp::new(rt, db);
+    let mut app = MyEguiApp::new(rt, db, bulk, cache, config);
+    app.database_file = database_file;
+    app.database_key = database_key;
+    if options.watch {
+        app.watch = Some(app.rt.block_on(watch::Watch::start(&app.db))?);
+    }
     app.initial_load();
+    app.build_search_index(!options.no_ocr);
 
     eframe::run_native(
         "iMessage Reader",
         native_options,
-        Box::new(|_cc| Box::new(app)),
+        Box::new(|cc| {
+            let _ = REPAINT.set(cc.egui_ctx.clone());
+            Box::new(app)
+        }),
     );
 
     Ok(())
 }
 
-#[derive(Clone)]
+/// The window's context, for work done in the background to have it drawn
+/// again once there's something new to show
+static REPAINT: OnceLock<egui::Context> = OnceLock::new();
+
+/// Draw the window again soon, e.g. because a background load finished,
+/// rather than waiting for the mouse to move
+fn repaint() {
+    if let Some(ctx) = REPAINT.get() {
+        ctx.request_repaint();
+    }
+}
+
 struct Handle<T> {
     lock: Arc<Mutex<State<T>>>,
 }
 
+// by hand, since deriving it would need `T: Clone` though only the `Arc` is
+// cloned
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            lock: self.lock.clone(),
+        }
+    }
+}
+
 enum State<T> {
     Empty,
     Fetching,
@@ -66,18 +487,67 @@ impl<T> Handle<T> {
 
     fn set(&self, state: State<T>) {
         *self.lock.lock().unwrap() = state;
+        repaint();
     }
 
-    fn get(&self) -> MutexGuard<State<T>> {
+    fn get(&self) -> MutexGuard<'_, State<T>> {
         self.lock.lock().unwrap()
     }
+
+    /// Modify the value in place if it is ready
+    fn update(&self, f: impl FnOnce(&mut T)) {
+        if let State::Ready(val) = &mut *self.get() {
+            f(val);
+        }
+        repaint();
+    }
+
+    /// Modify the value in place, starting from an empty one if it isn't
+    /// ready yet, for loaders that stream their results in
+    fn extend(&self, f: impl FnOnce(&mut T))
+    where
+        T: Default,
+    {
+        let mut state = self.get();
+        if !matches!(*state, State::Ready(_)) {
+            *state = State::Ready(T::default());
+        }
+        if let State::Ready(val) = &mut *state {
+            f(val);
+        }
+        drop(state);
+        repaint();
+    }
 }
 
 #[derive(Clone)]
 struct Chat {
     name: String,
-    // last_message: String,
+    /// Name given to a group chat, empty otherwise
+    display_name: String,
+    /// Handles of everyone else in the chat
+    participants: Vec<String>,
     last_active: DateTime<Utc>,
+    message_count: i64,
+    /// Start of the last message's text
+    preview: String,
+}
+
+impl Chat {
+    /// Best fuzzy match of `pattern` against the chat's names and handles,
+    /// including the names I gave them
+    fn filter_score(&self, pattern: &str, aliases: &HashMap<String, String>) -> Option<i64> {
+        std::iter::once(&self.display_name)
+            .chain(std::iter::once(&self.name))
+            .chain(&self.participants)
+            .chain(
+                std::iter::once(&self.name)
+                    .chain(&self.participants)
+                    .filter_map(|id| aliases.get(id)),
+            )
+            .filter_map(|candidate| fuzzy::score(pattern, candidate))
+            .max()
+    }
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -86,9 +556,176 @@ enum Sender {
     SomeoneElse(String),
 }
 
+impl Sender {
+    fn new(is_from_me: bool, handle: String) -> Self {
+        if is_from_me {
+            Sender::Me
+        } else {
+            Sender::SomeoneElse(handle)
+        }
+    }
+}
+
+/// A message to scroll to and outline once its chat has loaded
+struct Jump {
+    message: i64,
+    scroll_pending: bool,
+}
+
+/// The chat list, and the config, the most recently active chats' messages
+/// were prefetched with
+struct Prefetched {
+    chats: Arc<[Chat]>,
+    count: usize,
+    rules: Vec<config::IgnoreRule>,
+    changes: Vec<HandleChange>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ChatTab {
+    Messages,
+    /// Grid of everything shared in the chat
+    Media,
+    Stats,
+}
+
+/// A key pressed to move through the chat list
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ChatKey {
+    Up,
+    Down,
+    Open,
+}
+
+/// A key pressed to scroll the open chat
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ScrollKey {
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+}
+
+/// State of the selected chat's message view that is thrown away when
+/// switching chats
+struct ChatView {
+    tab: ChatTab,
+    /// Long messages that have been expanded in full, by ID
+    expanded: HashSet<i64>,
+    /// Collapsed messages that have been clicked to show them, by ID
+    uncollapsed: HashSet<i64>,
+    images: Images,
+    audio: Audio,
+    /// Messages asked to be copied this frame, by index; the end may be past
+    /// the last message
+    copy: Option<Range<usize>>,
+    /// The selected messages asked to be copied this frame
+    copy_selection: bool,
+    /// Messages clicked to select them, by ID
+    selected: HashSet<i64>,
+    /// The message last clicked, which a shift-click selects from
+    anchor: Option<i64>,
+    /// A message asked to be shown in the info window this frame, by ID
+    info: Option<i64>,
+    /// Only show messages from this person, picked from the participants list
+    only_from: Option<Sender>,
+    /// Whether the chat has more than one other person in it
+    group: bool,
+    /// Handles of everyone else in the chat
+    members: Vec<String>,
+    /// Only show messages that @mention me
+    only_mentions: bool,
+    /// Contact photos of everyone else in the chat who has one, by handle
+    photos: HashMap<String, PathBuf>,
+    /// How tall each message was when it was last laid out, by ID, so those
+    /// scrolled out of view can be skipped
+    heights: HashMap<i64, f32>,
+    /// The width `heights` were measured at
+    heights_width: f32,
+    /// Asked this frame to load the newest messages, when they aren't loaded
+    latest: bool,
+    /// Whether a page of older or newer messages was loading as of this frame
+    loading_page: bool,
+    /// Pressed this frame to scroll the messages
+    scroll_key: Option<ScrollKey>,
+    /// Where to scroll the messages to when next drawn, from a key pressed
+    /// last frame or coming back to the chat's tab
+    scroll_offset: Option<f32>,
+    /// How far the messages were scrolled when last drawn
+    offset: f32,
+}
+
+impl ChatView {
+    fn new(rt: &Runtime) -> Self {
+        Self {
+            tab: ChatTab::Messages,
+            expanded: HashSet::new(),
+            uncollapsed: HashSet::new(),
+            images: Images::new(rt.handle().clone()),
+            audio: Audio::new(rt.handle().clone()),
+            copy: None,
+            copy_selection: false,
+            selected: HashSet::new(),
+            anchor: None,
+            info: None,
+            only_from: None,
+            group: false,
+            members: Vec::new(),
+            only_mentions: false,
+            photos: HashMap::new(),
+            heights: HashMap::new(),
+            heights_width: 0.0,
+            latest: false,
+            loading_page: false,
+            scroll_key: None,
+            scroll_offset: None,
+            offset: 0.0,
+        }
+    }
+}
+
 #[derive(Clone)]
 struct Message {
+    /// ROWID in the source database
+    id: i64,
+    guid: String,
+    /// For a sticker stuck onto another message, that message's GUID
+    sticker_on: Option<String>,
+    /// For a tapback, the GUID of the message it's on, the tapback and
+    /// whether it's being added rather than taken off
+    tapback_on: Option<(String, tapback::Tapback, bool)>,
     text: String,
+    /// Names of the files sent with the message
+    attachments: Vec<String>,
+    /// Image attachments, drawn in the bubble
+    images: Vec<Attachment>,
+    /// Audio attachments, playable from the bubble
+    audio: Vec<PathBuf>,
+    /// Apple's transcription of an audio message
+    transcription: Option<String>,
+    /// Stickers other messages stuck onto this one, drawn over the bubble
+    stickers: Vec<Attachment>,
+    /// Tapbacks added to and taken off this message, drawn as badges under
+    /// the bubble
+    reactions: Vec<tapback::Reaction>,
+    /// "iMessage" or "SMS", as it was sent
+    service: String,
+    /// `expressive_send_style_id` of a message sent with a bubble or screen
+    /// effect, e.g. `com.apple.MobileSMS.expressivesend.impact`
+    effect: Option<String>,
+    /// A game, poll, payment etc. from an iMessage app, drawn as a card
+    balloon: Option<balloon::Balloon>,
+    /// A place shared from Maps, drawn as a card
+    location: Option<location::Location>,
+    /// Contacts shared as `.vcf` files
+    contacts: Vec<vcard::Contact>,
+    /// A rename, someone joining or leaving etc., drawn as a line across the
+    /// chat instead of a bubble
+    event: Option<event::Event>,
+    /// People @mentioned in the text, drawn highlighted
+    mentions: Vec<mention::Mention>,
+    /// Matched by an ignore rule, drawn as a single line until clicked
+    collapsed: bool,
     sender: Sender,
     date: DateTime<Utc>,
 }
@@ -96,201 +733,3193 @@ struct Message {
 struct MyEguiApp {
     rt: Runtime,
     db: SqlitePool,
-    chats: Handle<Vec<Chat>>,
+    /// The same database as `db`, for exports, stats and other long scans,
+    /// so they never hold up the queries the UI is waiting on
+    bulk: SqlitePool,
+    cache: SqlitePool,
+    config: Config,
+    settings_open: bool,
+    /// Shared rather than cloned each frame to draw the chat list
+    chats: Handle<Arc<[Chat]>>,
+    /// Weekly message counts for the sidebar sparklines, loaded the first
+    /// time the chat list is shown
+    activity: Handle<Arc<activity::Activity>>,
     selected_chat: Option<Chat>,
-    selected_chat_messages: Handle<Vec<Message>>,
+    selected_chat_messages: Handle<Window>,
+    /// Set while a page of older or newer messages is loading
+    loading_page: Arc<AtomicBool>,
+    /// Audio/video metadata for the selected chat, keyed by message
+    selected_chat_media: Handle<HashMap<i64, Vec<MediaInfo>>>,
+    /// Every attachment in the selected chat, loaded when the media tab is
+    /// first opened
+    selected_chat_gallery: Handle<Vec<MediaItem>>,
+    selected_chat_stats: Handle<stats::Stats>,
+    /// Messages per month in the selected chat, for the strip beside its
+    /// messages
+    selected_chat_density: Handle<minimap::Density>,
+    /// How many messages the selected chat has, for its header
+    selected_chat_count: Handle<usize>,
+    /// Everyone in the selected group chat, loaded when the participants
+    /// list is first opened
+    selected_chat_participants: Handle<Vec<participants::Participant>>,
+    /// Stops what's still loading for the selected chat when it's replaced
+    loads: cancel::Loads,
+    /// Newest messages of the most recently active chats, loaded before
+    /// they're opened, by chat identifier
+    prefetched: HashMap<String, paging::Prefetch>,
+    /// What `prefetched` was loaded for, to load it again when that changes
+    prefetched_for: Option<Prefetched>,
+    /// Stops prefetches that are no longer wanted when new ones start
+    prefetch_loads: cancel::Loads,
+    search_query: String,
+    search_open: bool,
+    search_results: Handle<SearchResults>,
+    search_index_ready: Arc<AtomicBool>,
+    search_regex: bool,
+    search_error: Option<String>,
+    /// What the last search looked for, to highlight in its results
+    search_terms: Terms,
+    saved_search_name: String,
+    export_status: Handle<PathBuf>,
+    /// (chats exported, chats to export) by the running profile export
+    export_progress: Handle<(usize, usize)>,
+    /// Dates typed into the time capsule menu, `YYYY-MM-DD` or empty for no
+    /// limit
+    capsule_after: String,
+    capsule_before: String,
+    /// Date typed into the chat's "jump to date" box
+    jump_date: String,
+    /// First day of the month the "jump to date" calendar is showing
+    jump_month: NaiveDate,
+    /// Path of another copy of the database to compare against
+    merge_path: String,
+    merge_preview: Handle<merge::Preview>,
+    merge_open: bool,
+    /// Raw metadata of the message last asked for with "get info"
+    message_info: Handle<info::Info>,
+    info_open: bool,
+    /// Set in `--watch` mode
+    watch: Option<watch::Watch>,
+    /// Shown at the bottom of the window when a background load fails
+    last_error: LastError,
+    find: Option<Find>,
+    view: ChatView,
+    jump: Option<Jump>,
+    /// Chats open in tabs other than the selected chat's. The selected chat
+    /// sits between `tabs[..active_tab]` and `tabs[active_tab..]`.
+    tabs: Vec<tabs::Tab>,
+    active_tab: usize,
+    /// A chat shown beside the selected one, in split view
+    split: Option<tabs::Tab>,
+    /// Only list chats with this label in the sidebar
+    label_filter: Option<ChatLabel>,
+    /// Fuzzy filter over the chat list
+    chat_filter: String,
+    /// The chat moved to in the sidebar with the arrow keys, opened with
+    /// Enter
+    chat_cursor: Option<String>,
+    /// Pressed this frame to move through the chat list
+    chat_key: Option<ChatKey>,
+    /// How tall each chat in the sidebar was when it was last laid out, by
+    /// chat identifier, so those scrolled out of view can be skipped
+    chat_heights: HashMap<String, f32>,
+    /// The width `chat_heights` were measured at
+    chat_heights_width: f32,
+    /// New handle and date typed into a chat's "changed number" menu
+    handle_change: (String, String),
+    /// Another handle of the person in a chat, as typed into its menu
+    same_person: String,
+    /// A name for a handle or chat, as typed into its menu
+    alias: String,
+    /// Path of the open database, to compare it with another copy
+    database_file: String,
+    /// What the open database's aliases are saved under in the config
+    database_key: String,
+    perf: perf::Overlay,
+    shortcuts_open: bool,
+    /// Set while the command palette (Cmd+K) is open
+    palette: Option<Palette>,
+    clipboard: Clipboard,
+}
+
+impl Message {
+    /// Rough number of bytes this message takes up in memory
+    fn size(&self) -> usize {
+        let sender = match &self.sender {
+            Sender::Me => 0,
+            Sender::SomeoneElse(handle) => handle.len(),
+        };
+
+        std::mem::size_of::<Self>()
+            + self.text.len()
+            + self.attachments.iter().map(String::len).sum::<usize>()
+            + self
+                .images
+                .iter()
+                .chain(&self.stickers)
+                .map(|a| a.guid.len() + a.name.len() + a.path.as_os_str().len())
+                .sum::<usize>()
+            + self
+                .audio
+                .iter()
+                .map(|p| p.as_os_str().len())
+                .sum::<usize>()
+            + self.transcription.as_ref().map_or(0, String::len)
+            + self.guid.len()
+            + self.sticker_on.as_ref().map_or(0, String::len)
+            + self.tapback_on.as_ref().map_or(0, |(guid, ..)| guid.len())
+            + self.reactions.len() * std::mem::size_of::<tapback::Reaction>()
+            + sender
+    }
+}
+
+/// `message.associated_message_type` of a sticker stuck onto a message
+const STICKER: i64 = 1000;
+
+/// The GUID of the message an associated message (sticker, tapback) is on,
+/// from `associated_message_guid`, which looks like `p:0/<guid>` (the part
+/// of the message it's on) or `bp:<guid>`
+fn associated_target(raw: &str) -> &str {
+    match raw.split_once('/') {
+        Some((_, guid)) => guid,
+        None => raw.strip_prefix("bp:").unwrap_or(raw),
+    }
+}
+
+/// Move stickers stuck onto other messages onto those messages' bubbles,
+/// and tapbacks onto the messages they react to. Ones on a message that
+/// isn't in the chat are left as messages of their own, so they don't
+/// disappear.
+fn place_associated(mut messages: Vec<Message>) -> Vec<Message> {
+    let by_guid: HashMap<String, usize> = messages
+        .iter()
+        .enumerate()
+        .map(|(i, message)| (message.guid.clone(), i))
+        .collect();
+
+    let mut placed = vec![false; messages.len()];
+    for i in 0..messages.len() {
+        let target = messages[i]
+            .sticker_on
+            .as_ref()
+            .or(messages[i].tapback_on.as_ref().map(|(guid, ..)| guid));
+        let target = match target.and_then(|g| by_guid.get(g)) {
+            Some(&target) if target != i => target,
+            _ => continue,
+        };
+
+        if let Some((_, tapback, added)) = messages[i].tapback_on.clone() {
+            let reaction = tapback::Reaction {
+                tapback,
+                added,
+                sender: messages[i].sender.clone(),
+                date: messages[i].date,
+            };
+            messages[target].reactions.push(reaction);
+        } else {
+            let stickers = std::mem::take(&mut messages[i].images);
+            messages[target].stickers.extend(stickers);
+        }
+        placed[i] = true;
+    }
+
+    messages
+        .into_iter()
+        .zip(placed)
+        .filter(|(_, placed)| !placed)
+        .map(|(message, _)| message)
+        .collect()
 }
 
+/// Seconds between the unix epoch and Apple's 2001-01-01 epoch, for queries
+/// that work with dates in SQL
+const APPLE_EPOCH: i64 = 978307200;
+
 /// Turn Apple's ridiculous time format into a chrono datetime
 fn time(raw: i64) -> DateTime<Utc> {
-    let epoch_correction = NaiveDate::from_ymd(2001, 1, 1).and_hms(0, 0, 0).timestamp();
-    let seconds = (raw / 1000000000) + epoch_correction;
+    let seconds = (raw / 1000000000) + APPLE_EPOCH;
 
     NaiveDateTime::from_timestamp(seconds, 0)
         .and_local_timezone(Utc)
         .unwrap()
 }
 
+/// The inverse of [`time`], for comparing against dates in queries
+fn apple_time(date: DateTime<Utc>) -> i64 {
+    (date.timestamp() - APPLE_EPOCH) * 1000000000
+}
+
 impl MyEguiApp {
-    fn new(rt: Runtime, db: SqlitePool) -> Self {
+    fn new(
+        rt: Runtime,
+        db: SqlitePool,
+        bulk: SqlitePool,
+        cache: SqlitePool,
+        config: Config,
+    ) -> Self {
+        let view = ChatView::new(&rt);
+
         Self {
             rt,
             db,
+            bulk,
+            cache,
+            config,
+            settings_open: false,
             chats: Handle::new(),
+            activity: Handle::new(),
             selected_chat: None,
             selected_chat_messages: Handle::new(),
+            loading_page: Arc::new(AtomicBool::new(false)),
+            selected_chat_media: Handle::new(),
+            selected_chat_gallery: Handle::new(),
+            selected_chat_stats: Handle::new(),
+            selected_chat_density: Handle::new(),
+            selected_chat_count: Handle::new(),
+            selected_chat_participants: Handle::new(),
+            loads: cancel::Loads::new(),
+            prefetched: HashMap::new(),
+            prefetched_for: None,
+            prefetch_loads: cancel::Loads::new(),
+            search_query: String::new(),
+            search_open: false,
+            search_results: Handle::new(),
+            search_index_ready: Arc::new(AtomicBool::new(false)),
+            search_regex: false,
+            search_error: None,
+            search_terms: Terms::Words(Vec::new()),
+            saved_search_name: String::new(),
+            export_status: Handle::new(),
+            export_progress: Handle::new(),
+            capsule_after: String::new(),
+            capsule_before: String::new(),
+            jump_date: String::new(),
+            jump_month: calendar::month_of(Utc::now().date_naive()),
+            merge_path: String::new(),
+            merge_preview: Handle::new(),
+            merge_open: false,
+            message_info: Handle::new(),
+            info_open: false,
+            watch: None,
+            last_error: LastError::default(),
+            find: None,
+            view,
+            jump: None,
+            tabs: Vec::new(),
+            active_tab: 0,
+            split: None,
+            label_filter: None,
+            handle_change: Default::default(),
+            same_person: String::new(),
+            alias: String::new(),
+            database_file: String::new(),
+            database_key: String::new(),
+            chat_filter: String::new(),
+            chat_cursor: None,
+            chat_key: None,
+            chat_heights: HashMap::new(),
+            chat_heights_width: 0.0,
+            perf: perf::Overlay::default(),
+            shortcuts_open: false,
+            palette: None,
+            clipboard: Clipboard::default(),
         }
     }
 
-    fn load<T>(&self, handle: Handle<T>, f: impl 'static + Send + Future<Output = Result<T>>)
-    where
+    /// Run `f` in the background and put its result in `handle`. `name` is
+    /// what the performance overlay lists its latency under.
+    fn load<T>(
+        &self,
+        name: &'static str,
+        handle: Handle<T>,
+        f: impl 'static + Send + Future<Output = Result<T>>,
+    ) where
         T: 'static + Send + Sync,
     {
         handle.set(State::Fetching);
+        self.refresh(name, handle, f);
+    }
 
+    /// Like [`MyEguiApp::load`], but whatever is in `handle` stays on screen
+    /// until the new value is ready
+    fn refresh<T>(
+        &self,
+        name: &'static str,
+        handle: Handle<T>,
+        f: impl 'static + Send + Future<Output = Result<T>>,
+    ) where
+        T: 'static + Send + Sync,
+    {
+        let last_error = self.last_error.clone();
         self.rt.spawn(async move {
-            match f.await {
+            let start = Instant::now();
+            let result = f.await;
+            perf::record_load(name, start.elapsed());
+
+            match result {
                 Ok(val) => handle.set(State::Ready(val)),
-                Err(e) => eprintln!("{e}"),
+                Err(e) => last_error.set(e),
+            }
+        });
+    }
+
+    /// Like [`MyEguiApp::load`], for `f` that puts what it has into the
+    /// handle it's given as it goes, so the first of a long result is on
+    /// screen before the rest has been read
+    fn stream<T, F>(&self, name: &'static str, handle: Handle<T>, f: impl FnOnce(Handle<T>) -> F)
+    where
+        T: 'static + Send + Sync,
+        F: 'static + Send + Future<Output = Result<()>>,
+    {
+        handle.set(State::Fetching);
+        let loaded = f(handle);
+        let last_error = self.last_error.clone();
+        self.rt.spawn(async move {
+            let start = Instant::now();
+            let result = loaded.await;
+            perf::record_load(name, start.elapsed());
+
+            if let Err(e) = result {
+                last_error.set(e);
             }
         });
     }
 
-    fn load_messages(&self, chat_id: String) {
+    fn load_messages(&mut self, chat_id: String, page: Page) {
+        // a fresh handle so that a page still loading for a previous chat
+        // can't land in this one
+        self.selected_chat_messages = Handle::new();
+        self.loading_page = Arc::new(AtomicBool::new(false));
+        if let Some(find) = &mut self.find {
+            find.invalidate();
+        }
+
+        // only a finished prefetch, since one still streaming is stopped if
+        // the chat list changes
+        let prefetched = self.prefetched.remove(&chat_id);
+        if let (Page::Newest, Some(window)) = (page, prefetched.and_then(|p| p.finished())) {
+            self.selected_chat_messages = window;
+            return;
+        }
+
+        self.load_window(
+            &self.loads,
+            self.selected_chat_messages.clone(),
+            chat_id,
+            page,
+        );
+    }
+
+    /// Load the messages of a chat at `page` into `window`, as one of its
+    /// `loads`
+    fn load_window(
+        &self,
+        loads: &cancel::Loads,
+        window: Handle<Window>,
+        chat_id: String,
+        page: Page,
+    ) {
         let db = self.db.clone();
+        let rules = self.config.ignore_rules.clone();
+        let changes = self.config.changes();
 
-        self.load(self.selected_chat_messages.clone(), async move {
-            let messages = sqlx::query_as::<_, (String, i64, String, bool)>(
-                r#"
-                    SELECT
-                        m.text, m.date, h.id, m.is_from_me
-                    from message m
-                    join chat_message_join cmj
-                        on m.ROWID = cmj.message_id
-                    join chat c
-                        on cmj.chat_id = c.ROWID
-                    left join handle h
-                        on m.handle_id = h.ROWID
-                    where c.chat_identifier=$1
-                    order by date
-                    ;
-                "#,
-            )
-            .bind(chat_id)
-            .fetch_all(&db)
-            .await?
-            .into_iter()
-            .map(|(text, timestamp, sender, is_from_me)| Message {
-                text,
-                date: time(timestamp),
-                sender: if is_from_me {
-                    Sender::Me
-                } else {
-                    Sender::SomeoneElse(sender)
-                },
+        self.stream("messages", window, |window| {
+            loads.run(async move {
+                let rules = ignore::Rules::compile(&rules)?;
+                paging::load(&db, &rules, &changes, &chat_id, page, &window).await
             })
-            .collect::<Vec<_>>();
+        });
+    }
 
-            Ok(messages)
+    /// Start loading the newest messages of the most recently active of
+    /// `chats`, as many as the config says, unless they already are
+    fn prefetch(&mut self, chats: &Arc<[Chat]>) {
+        let count = self.config.prefetch.chats;
+        let changes = self.config.changes();
+        let started = self.prefetched_for.as_ref().is_some_and(|p| {
+            Arc::ptr_eq(&p.chats, chats)
+                && p.count == count
+                && p.rules == self.config.ignore_rules
+                && p.changes == changes
+        });
+        if started {
+            return;
+        }
+        self.prefetched_for = Some(Prefetched {
+            chats: chats.clone(),
+            count,
+            rules: self.config.ignore_rules.clone(),
+            changes,
         });
+        self.prefetched.clear();
+        self.prefetch_loads = cancel::Loads::new();
+
+        for chat in chats.iter().take(count) {
+            let db = self.bulk.clone();
+            let rules = self.config.ignore_rules.clone();
+            let changes = self.config.changes();
+            let chat_id = chat.name.clone();
+            let prefetch = paging::Prefetch::new();
+            let loading = prefetch.clone();
+
+            self.stream("prefetch", prefetch.window.clone(), |_| {
+                self.prefetch_loads.run(async move {
+                    let rules = ignore::Rules::compile(&rules)?;
+                    loading.load(&db, &rules, &changes, &chat_id).await
+                })
+            });
+            self.prefetched.insert(chat.name.clone(), prefetch);
+        }
     }
 
-    fn initial_load(&self) {
-        let db = self.db.clone();
+    /// Load the next page of the open chat towards `edge`, unless one is
+    /// already loading
+    fn load_page(&self, edge: Edge) {
+        self.extend_window(
+            &self.loads,
+            self.selected_chat_messages.clone(),
+            self.loading_page.clone(),
+            edge,
+        );
+    }
 
-        self.load(self.chats.clone(), async move {
-            let chats = sqlx::query_as::<_, (i64, String)>(
-                r#"SELECT
-            max(m.date), c.chat_identifier
-        from message m
-        join chat_message_join cmj
-            on m.ROWID = cmj.message_id
-        join chat c
-            on cmj.chat_id = c.ROWID
-        group by c.chat_identifier
-        order by max(m.date) desc
-        ;
-        "#,
-            )
-            .fetch_all(&db)
-            .await?
-            .into_iter()
-            .map(|(timestamp, name)| Chat {
-                name,
-                last_active: time(timestamp),
-            })
-            .collect();
+    /// Load the next page of `window` towards `edge` as one of `loads`,
+    /// unless `loading` says one already is
+    fn extend_window(
+        &self,
+        loads: &cancel::Loads,
+        window: Handle<Window>,
+        loading: Arc<AtomicBool>,
+        edge: Edge,
+    ) {
+        if loading.swap(true, Ordering::SeqCst) {
+            return;
+        }
 
-            Ok(chats)
+        let db = self.db.clone();
+        let rules = self.config.ignore_rules.clone();
+        let last_error = self.last_error.clone();
+        let loaded = loads.run(async move {
+            let rules = ignore::Rules::compile(&rules)?;
+            paging::extend(&db, &rules, &window, edge).await
+        });
+
+        self.rt.spawn(async move {
+            if let Err(e) = loaded.await {
+                last_error.set(e);
+            }
+            loading.store(false, Ordering::SeqCst);
+            repaint();
         });
     }
-}
 
-impl eframe::App for MyEguiApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::SidePanel::left("my_left_panel").show(ctx, |ui| {
-            let guard = self.chats.get();
-
-            match &*guard {
-                State::Empty => {
-                    ui.heading("no chats found");
-                }
-                State::Fetching => {
-                    ui.heading("loading...");
-                }
-                State::Ready(chats) => {
-                    let chats = chats.to_owned();
-                    drop(guard);
-                    egui::ScrollArea::vertical().show(ui, |ui| {
-                        for chat in chats {
-                            let mut frame = Frame::group(ui.style());
-
-                            if let Some(c) = &self.selected_chat {
-                                if c.name == chat.name {
-                                    frame = frame.fill(*BLUE);
-                                }
-                            }
+    fn build_search_index(&self, ocr: bool) {
+        let db = self.bulk.clone();
+        let cache = self.cache.clone();
+        let ready = self.search_index_ready.clone();
+        let last_error = self.last_error.clone();
 
-                            let response = frame
-                                .show(ui, |ui| {
-                                    ui.visuals_mut().override_text_color = Some(Color32::WHITE);
-                                    ui.label(&chat.name);
-                                    ui.label(format!("{:?}", chat.last_active));
-                                })
-                                .response;
-
-                            if response.interact(egui::Sense::click()).clicked() {
-                                self.selected_chat = Some(chat.clone());
-                                self.load_messages(chat.name.clone());
-                            }
-                        }
-                    });
+        self.rt.spawn(async move {
+            if let Err(e) = search::build_index(&db, &cache).await {
+                last_error.set(e);
+                return;
+            }
+            ready.store(true, Ordering::SeqCst);
+
+            if ocr {
+                if let Err(e) = ocr::run(&db, &cache).await {
+                    last_error.set(e);
                 }
             }
         });
+    }
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            if let Some(chat) = &self.selected_chat {
-                ui.heading(&chat.name);
+    fn search(&mut self, input: String) {
+        // a fresh handle per search so that a slow scan from a previous query
+        // can't write into this one's results
+        self.search_results = Handle::new();
+        self.search_error = None;
 
-                match &*self.selected_chat_messages.get() {
-                    State::Empty => {
-                        ui.label("no messages found");
-                    }
-                    State::Fetching => {
-                        ui.label("loading...");
-                    }
-                    State::Ready(messages) => render_messages(ui, messages),
+        let mut query = match Query::parse(&input) {
+            Ok(query) => query,
+            Err(e) => {
+                self.search_error = Some(e);
+                return;
+            }
+        };
+        query.resolve_names(&self.config);
+
+        self.search_terms = Terms::Words(query.words.clone());
+
+        if self.search_regex {
+            match search::compile_regex(&search::fold::normalize(&query.text())) {
+                Ok(regex) => {
+                    let db = self.bulk.clone();
+                    self.search_terms = Terms::Regex(regex.clone());
+
+                    self.load("regex search", self.search_results.clone(), async move {
+                        search::regex_search(&db, &query, regex).await
+                    });
                 }
-            } else {
-                ui.heading("select a chat on the left");
+                Err(e) => self.search_error = Some(e.to_string()),
             }
+        } else if self.search_index_ready.load(Ordering::SeqCst) {
+            let cache = self.cache.clone();
+
+            self.load("search", self.search_results.clone(), async move {
+                search::search(&cache, &query).await
+            });
+        } else {
+            let db = self.bulk.clone();
+            let handle = self.search_results.clone();
+            handle.set(State::Fetching);
+            let last_error = self.last_error.clone();
+
+            self.rt.spawn(async move {
+                let start = Instant::now();
+                if let Err(e) = search::scan(db, query, handle).await {
+                    last_error.set(e);
+                }
+                perf::record_load("scan", start.elapsed());
+            });
+        }
+    }
+
+    fn save_config(&self) {
+        if let Err(e) = self.config.save() {
+            eprintln!("{e}");
+        }
+    }
+
+    /// Save the open database's aliases after they were edited
+    fn save_aliases(&mut self) {
+        let aliases = &self.config.me.aliases;
+        if aliases.is_empty() {
+            self.config.aliases.remove(&self.database_key);
+        } else {
+            self.config
+                .aliases
+                .insert(self.database_key.clone(), aliases.clone());
+        }
+        self.save_config();
+    }
+
+    fn export_contact_sheet(&self, chat_id: String) {
+        let db = self.bulk.clone();
+        let identity = self.config.me.clone();
+        let heic = self.config.heic_export;
+
+        self.load(
+            "contact sheet export",
+            self.export_status.clone(),
+            async move {
+                let scope = export::Scope::default();
+                let dir = export::default_dir();
+                export::contact_sheet(&db, &identity, &chat_id, &scope, heic, &dir).await
+            },
+        );
+    }
+
+    fn export_messages(&self, chat_id: String, format: export::Format) {
+        let db = self.bulk.clone();
+        let identity = self.config.me.clone();
+        let changes = self.config.changes();
+
+        self.load("messages export", self.export_status.clone(), async move {
+            let scope = export::Scope::default();
+            export::messages(
+                &db,
+                &identity,
+                &changes,
+                &chat_id,
+                &scope,
+                format,
+                &export::default_dir(),
+            )
+            .await
         });
     }
-}
 
-fn render_messages(ui: &mut Ui, messages: &[Message]) {
-    egui::ScrollArea::vertical().show(ui, |ui| {
-        for msg in messages {
-            let (layout, bg) = if msg.sender == Sender::Me {
-                (egui::Layout::right_to_left(egui::Align::TOP), *BLUE)
+    fn export_time_capsule(&self, chat_id: String, scope: export::Scope) {
+        let db = self.bulk.clone();
+        let config = self.config.clone();
+
+        self.load("time capsule", self.export_status.clone(), async move {
+            export::time_capsule(&db, &config, &chat_id, &scope, &export::default_dir()).await
+        });
+    }
+
+    fn export_attachments(&self, chat_id: String, kinds: Vec<export::Kind>) {
+        let db = self.bulk.clone();
+        let identity = self.config.me.clone();
+        let heic = self.config.heic_export;
+
+        self.load(
+            "attachments export",
+            self.export_status.clone(),
+            async move {
+                let scope = export::Scope::default();
+                let selection = export::Selection {
+                    kinds,
+                    convert_heic: Some(heic),
+                    name_by_date: true,
+                    ..Default::default()
+                };
+                export::attachments(
+                    &db,
+                    &identity,
+                    &chat_id,
+                    &scope,
+                    &selection,
+                    &export::default_dir(),
+                )
+                .await
+            },
+        );
+    }
+
+    fn export_timeline(&self, chat_id: String) {
+        let db = self.bulk.clone();
+        let chapters = self
+            .config
+            .chapters
+            .get(&chat_id)
+            .cloned()
+            .unwrap_or_default();
+
+        self.load("timeline export", self.export_status.clone(), async move {
+            let scope = export::Scope::default();
+            export::timeline(&db, &chat_id, &chapters, &scope, &export::default_dir()).await
+        });
+    }
+
+    fn export_each_contact(&self) {
+        let db = self.bulk.clone();
+        let identity = self.config.me.clone();
+        let changes = self.config.changes();
+
+        self.load("contacts export", self.export_status.clone(), async move {
+            let scope = export::Scope::default();
+            export::each_contact(&db, &identity, &changes, &scope, &export::default_dir()).await
+        });
+    }
+
+    fn run_export_profile(&self, profile: ExportProfile) {
+        let db = self.bulk.clone();
+        let config = self.config.clone();
+        let progress = self.export_progress.clone();
+        progress.set(State::Empty);
+
+        self.load("profile export", self.export_status.clone(), async move {
+            export::profile::run(&db, &config, &profile, &progress).await
+        });
+    }
+
+    fn load_info(&mut self, message: i64) {
+        let db = self.db.clone();
+        self.info_open = true;
+
+        self.load("message info", self.message_info.clone(), async move {
+            info::load(&db, message).await
+        });
+    }
+
+    fn preview_merge(&mut self) {
+        let current = self.database_file.clone();
+        let path = PathBuf::from(self.merge_path.trim());
+        self.merge_open = true;
+
+        self.load("merge preview", self.merge_preview.clone(), async move {
+            merge::preview(&current, &path).await
+        });
+    }
+
+    /// Load the open chat again if it's the chat with `chat_id`, or one shown
+    /// along with it, e.g. after its person changed number
+    fn reload_if_open(&mut self, chat_id: &str) {
+        let open = match &self.selected_chat {
+            Some(open) => open.name.clone(),
+            None => return,
+        };
+        if continuity::handles(&self.config.changes(), chat_id).contains(&open) {
+            self.load_messages(open.clone(), Page::Newest);
+            self.selected_chat_stats = Handle::new();
+            self.selected_chat_density = Handle::new();
+            self.selected_chat_count = Handle::new();
+        }
+    }
+
+    /// Open a chat, loading the messages at `page`
+    fn select_chat(&mut self, chat: Chat, page: Page) {
+        self.view = self.chat_view(&chat);
+        self.jump_month = calendar::month_of(chat.last_active.date_naive());
+        self.jump = None;
+
+        // whatever's still loading for the chat this replaces is no longer
+        // wanted
+        self.loads = cancel::Loads::new();
+        self.load_messages(chat.name.clone(), page);
+        // a fresh handle so that probing a previous chat can't land in this one
+        self.selected_chat_media = Handle::new();
+        self.load_media_info(
+            &self.loads,
+            self.selected_chat_media.clone(),
+            chat.name.clone(),
+        );
+        self.selected_chat_gallery = Handle::new();
+        self.selected_chat_stats = Handle::new();
+        self.selected_chat_density = Handle::new();
+        self.selected_chat_count = Handle::new();
+        self.selected_chat_participants = Handle::new();
+        self.selected_chat = Some(chat);
+    }
+
+    /// A fresh view of `chat`'s messages
+    fn chat_view(&self, chat: &Chat) -> ChatView {
+        let mut view = ChatView::new(&self.rt);
+        view.group = chat.participants.len() > 1;
+        view.members = chat.participants.clone();
+        view.photos = chat
+            .participants
+            .iter()
+            .filter_map(|handle| Some((handle.clone(), self.config.photo(handle)?.to_owned())))
+            .collect();
+        view
+    }
+
+    /// Show `chat` beside the selected chat, scrolled separately
+    fn open_beside(&mut self, chat: Chat) {
+        let pane = tabs::Tab {
+            view: self.chat_view(&chat),
+            messages: Handle::new(),
+            loading_page: Arc::new(AtomicBool::new(false)),
+            media: Handle::new(),
+            gallery: Handle::new(),
+            stats: Handle::new(),
+            participants: Handle::new(),
+            density: Handle::new(),
+            count: Handle::new(),
+            jump: None,
+            find: None,
+            loads: cancel::Loads::new(),
+            chat,
+        };
+        self.load_window(
+            &pane.loads,
+            pane.messages.clone(),
+            pane.chat.name.clone(),
+            Page::Newest,
+        );
+        self.load_media_info(&pane.loads, pane.media.clone(), pane.chat.name.clone());
+        self.split = Some(pane);
+    }
+
+    /// Take the selected chat and everything loaded for it out of the app,
+    /// to keep in a tab
+    fn stash_tab(&mut self) -> Option<tabs::Tab> {
+        let chat = self.selected_chat.take()?;
+
+        Some(tabs::Tab {
+            chat,
+            messages: std::mem::replace(&mut self.selected_chat_messages, Handle::new()),
+            loading_page: std::mem::take(&mut self.loading_page),
+            media: std::mem::replace(&mut self.selected_chat_media, Handle::new()),
+            gallery: std::mem::replace(&mut self.selected_chat_gallery, Handle::new()),
+            stats: std::mem::replace(&mut self.selected_chat_stats, Handle::new()),
+            participants: std::mem::replace(&mut self.selected_chat_participants, Handle::new()),
+            density: std::mem::replace(&mut self.selected_chat_density, Handle::new()),
+            count: std::mem::replace(&mut self.selected_chat_count, Handle::new()),
+            view: std::mem::replace(&mut self.view, ChatView::new(&self.rt)),
+            jump: self.jump.take(),
+            find: self.find.take(),
+            loads: std::mem::take(&mut self.loads),
+        })
+    }
+
+    /// Put a tab's chat back on screen, scrolled to where it was left
+    fn restore_tab(&mut self, tab: tabs::Tab) {
+        self.selected_chat = Some(tab.chat);
+        self.selected_chat_messages = tab.messages;
+        self.loading_page = tab.loading_page;
+        self.selected_chat_media = tab.media;
+        self.selected_chat_gallery = tab.gallery;
+        self.selected_chat_stats = tab.stats;
+        self.selected_chat_participants = tab.participants;
+        self.selected_chat_density = tab.density;
+        self.selected_chat_count = tab.count;
+        self.view = tab.view;
+        self.view.scroll_offset = Some(self.view.offset);
+        self.jump = tab.jump;
+        self.find = tab.find;
+        self.loads = tab.loads;
+    }
+
+    /// Open a chat in a tab of its own, after the selected chat's
+    fn open_in_new_tab(&mut self, chat: Chat) {
+        if let Some(tab) = self.stash_tab() {
+            self.tabs.insert(self.active_tab, tab);
+            self.active_tab += 1;
+        }
+        self.select_chat(chat, Page::Newest);
+    }
+
+    /// Show the tab at `index`, counting the selected chat's
+    fn switch_tab(&mut self, index: usize) {
+        if index == self.active_tab {
+            return;
+        }
+        if let Some(tab) = self.stash_tab() {
+            self.tabs.insert(self.active_tab, tab);
+        }
+        if index < self.tabs.len() {
+            let tab = self.tabs.remove(index);
+            self.active_tab = index;
+            self.restore_tab(tab);
+        }
+    }
+
+    /// Close the tab at `index`, counting the selected chat's, showing the
+    /// one after it (or before it, if it was last) when it's the selected one
+    fn close_tab(&mut self, index: usize) {
+        if index != self.active_tab {
+            let index = if index < self.active_tab {
+                self.active_tab -= 1;
+                index
             } else {
-                (egui::Layout::left_to_right(egui::Align::TOP), *GREY)
+                index - 1
             };
+            if index < self.tabs.len() {
+                self.tabs.remove(index);
+            }
+            return;
+        }
 
-            ui.with_layout(layout, |ui| {
-                ui.visuals_mut().override_text_color = Some(Color32::WHITE);
+        self.stash_tab();
+        if self.tabs.is_empty() {
+            self.active_tab = 0;
+            return;
+        }
+        if self.active_tab == self.tabs.len() {
+            self.active_tab -= 1;
+        }
+        let tab = self.tabs.remove(self.active_tab);
+        self.restore_tab(tab);
+    }
 
-                Frame::group(ui.style())
-                    .fill(bg)
-                    .stroke(Stroke::none())
-                    .rounding(Rounding::same(2.0 * 3.14))
-                    .show(ui, |ui| {
-                        ui.set_max_width(250.0);
-                        ui.style_mut().wrap = Some(true);
-                        ui.label(&msg.text);
+    /// Names of the chats open in tabs, in order, for the tab bar
+    fn tab_names(&self) -> Vec<String> {
+        let name = |chat: &Chat| {
+            self.config
+                .me
+                .chat_name(&chat.name, &chat.display_name)
+                .to_owned()
+        };
+        let mut names: Vec<String> = self.tabs.iter().map(|tab| name(&tab.chat)).collect();
+        if let Some(chat) = &self.selected_chat {
+            names.insert(self.active_tab.min(names.len()), name(chat));
+        }
+        names
+    }
+
+    fn load_media_info(
+        &self,
+        loads: &cancel::Loads,
+        media: Handle<HashMap<i64, Vec<MediaInfo>>>,
+        chat_id: String,
+    ) {
+        let db = self.db.clone();
+
+        self.stream("media info", media, |media| {
+            loads.run(async move { media_info::chat_media_info(&db, &chat_id, media).await })
+        });
+    }
+
+    fn load_activity(&self) {
+        let db = self.bulk.clone();
+        let cache = self.cache.clone();
+
+        self.load("activity", self.activity.clone(), async move {
+            Ok(Arc::new(activity::load(&db, &cache).await?))
+        });
+    }
+
+    fn load_stats(&self, chat_id: String) {
+        let db = self.bulk.clone();
+        let identity = self.config.me.clone();
+        let rules = self.config.ignore_rules.clone();
+        let changes = self.config.changes();
+
+        self.load(
+            "stats",
+            self.selected_chat_stats.clone(),
+            self.loads.run(async move {
+                let rules = ignore::Rules::compile(&rules)?;
+                let scope = export::Scope::default();
+                stats::chat(&db, &identity, &rules, &changes, &scope, &chat_id).await
+            }),
+        );
+    }
+
+    fn load_density(&self, chat_id: &str) {
+        let db = self.db.clone();
+        let chats = continuity::handles(&self.config.changes(), chat_id);
+
+        self.load(
+            "density",
+            self.selected_chat_density.clone(),
+            self.loads
+                .run(async move { minimap::load(&db, &chats).await }),
+        );
+    }
+
+    fn load_count(&self, chat_id: &str) {
+        let db = self.db.clone();
+        let chats = continuity::handles(&self.config.changes(), chat_id);
+
+        self.load(
+            "message count",
+            self.selected_chat_count.clone(),
+            self.loads.run(async move {
+                counts::messages(&db, &chats, export::Scope::default().bounds()).await
+            }),
+        );
+    }
+
+    /// Load the open chat around the first message on or after `date`
+    fn jump_to_date(&mut self, chat_id: String, date: NaiveDate) {
+        let date = apple_time(Utc.from_utc_datetime(&date.and_hms(0, 0, 0)));
+        self.jump = None;
+        self.load_messages(chat_id, Page::AroundDate(date));
+    }
+
+    fn load_participants(&self, chat: &Chat) {
+        let db = self.db.clone();
+        let changes = self.config.changes();
+        let chats = continuity::handles(&changes, &chat.name);
+        let members = chat.participants.clone();
+
+        self.load(
+            "participants",
+            self.selected_chat_participants.clone(),
+            self.loads
+                .run(async move { participants::counts(&db, &changes, &chats, &members).await }),
+        );
+    }
+
+    fn load_gallery(&self, chat_id: String) {
+        let db = self.db.clone();
+
+        self.stream("gallery", self.selected_chat_gallery.clone(), |gallery| {
+            self.loads
+                .run(async move { attachment::stream_chat_media(&db, &chat_id, "", gallery).await })
+        });
+    }
+
+    fn find_chat(&self, name: &str) -> Chat {
+        if let State::Ready(chats) = &*self.chats.get() {
+            if let Some(chat) = chats.iter().find(|c| c.name == name) {
+                return chat.clone();
+            }
+        }
+
+        Chat {
+            name: name.to_owned(),
+            display_name: String::new(),
+            participants: Vec::new(),
+            last_active: Utc.timestamp(0, 0),
+            message_count: 0,
+            preview: String::new(),
+        }
+    }
+
+    /// Everything the command palette offers, with the open chat's commands
+    /// and, when `query` is a date, jumping to it first
+    fn palette_entries(&self, query: &str) -> Vec<palette::Entry> {
+        let entry = |label: String, command| palette::Entry { label, command };
+        let mut entries = Vec::new();
+
+        if self.selected_chat.is_some() {
+            if let Ok(date) = NaiveDate::parse_from_str(query.trim(), "%Y-%m-%d") {
+                entries.push(entry(
+                    i18n::tf("jump to {date}", &[("date", &date)]),
+                    PaletteCommand::JumpToDate(date),
+                ));
+            }
+        }
+        entries.push(entry(
+            i18n::t("settings").to_owned(),
+            PaletteCommand::Settings,
+        ));
+        entries.push(entry(
+            i18n::t("keyboard shortcuts").to_owned(),
+            PaletteCommand::Shortcuts,
+        ));
+        entries.push(entry(i18n::t("zoom in").to_owned(), PaletteCommand::ZoomIn));
+        entries.push(entry(
+            i18n::t("zoom out").to_owned(),
+            PaletteCommand::ZoomOut,
+        ));
+        entries.push(entry(
+            i18n::t("reset zoom").to_owned(),
+            PaletteCommand::ResetZoom,
+        ));
+        if self.selected_chat.is_some() {
+            for (format, label) in [
+                (export::Format::Text, i18n::t("text")),
+                (export::Format::Json, "JSON"),
+                (export::Format::Csv, "CSV"),
+                (export::Format::Html, "HTML"),
+            ] {
+                entries.push(entry(
+                    i18n::tf("export this chat as {format}", &[("format", &label)]),
+                    PaletteCommand::ExportMessages(format),
+                ));
+            }
+        }
+        entries.push(entry(
+            i18n::t("export every contact as its own file").to_owned(),
+            PaletteCommand::ExportEachContact,
+        ));
+        for (i, profile) in self.config.export_profiles.iter().enumerate() {
+            entries.push(entry(
+                i18n::tf("export profile {name}", &[("name", &profile.name)]),
+                PaletteCommand::ExportProfile(i),
+            ));
+        }
+
+        if let State::Ready(chats) = &*self.chats.get() {
+            for chat in chats.iter() {
+                let name = self.config.me.chat_name(&chat.name, &chat.display_name);
+                let label = if name == chat.name {
+                    i18n::tf("go to {name}", &[("name", &name)])
+                } else {
+                    i18n::tf(
+                        "go to {name} ({handle})",
+                        &[("name", &name), ("handle", &chat.name)],
+                    )
+                };
+                entries.push(entry(label, PaletteCommand::OpenChat(chat.name.clone())));
+            }
+        }
+
+        entries
+    }
+
+    fn run_command(&mut self, command: PaletteCommand) {
+        match command {
+            PaletteCommand::OpenChat(name) => {
+                self.search_open = false;
+                self.select_chat(self.find_chat(&name), Page::Newest);
+            }
+            PaletteCommand::Settings => self.settings_open = true,
+            PaletteCommand::Shortcuts => self.shortcuts_open = true,
+            PaletteCommand::ExportEachContact => self.export_each_contact(),
+            PaletteCommand::ExportProfile(i) => {
+                if let Some(profile) = self.config.export_profiles.get(i) {
+                    self.run_export_profile(profile.clone());
+                }
+            }
+            PaletteCommand::ExportMessages(format) => {
+                if let Some(chat) = &self.selected_chat {
+                    self.export_messages(chat.name.clone(), format);
+                }
+            }
+            PaletteCommand::JumpToDate(date) => {
+                if let Some(chat) = &self.selected_chat {
+                    self.view.tab = ChatTab::Messages;
+                    self.jump_to_date(chat.name.clone(), date);
+                }
+            }
+            PaletteCommand::ZoomIn => self.set_zoom(self.config.appearance.zoom + theme::ZOOM_STEP),
+            PaletteCommand::ZoomOut => {
+                self.set_zoom(self.config.appearance.zoom - theme::ZOOM_STEP)
+            }
+            PaletteCommand::ResetZoom => self.set_zoom(1.0),
+        }
+    }
+
+    /// Draw everything `zoom` times its normal size, remembered for next time
+    fn set_zoom(&mut self, zoom: f32) {
+        // rounded, so that steps in and out come back to exactly 1
+        let zoom = (zoom * 10.0).round() / 10.0;
+        self.config.appearance.zoom = zoom.clamp(theme::MIN_ZOOM, theme::MAX_ZOOM);
+        self.save_config();
+    }
+
+    /// Rough memory used by each of the loaded caches, for the performance
+    /// overlay
+    fn memory_usage(&self) -> Vec<(&'static str, usize)> {
+        let chats = match &*self.chats.get() {
+            State::Ready(chats) => chats
+                .iter()
+                .map(|c| {
+                    std::mem::size_of::<Chat>()
+                        + c.name.len()
+                        + c.display_name.len()
+                        + c.participants.iter().map(String::len).sum::<usize>()
+                        + c.preview.len()
+                })
+                .sum(),
+            _ => 0,
+        };
+        let messages = match &*self.selected_chat_messages.get() {
+            State::Ready(window) => window.messages.iter().map(Message::size).sum(),
+            _ => 0,
+        };
+        let results = match &*self.search_results.get() {
+            State::Ready(results) => results
+                .chats
+                .iter()
+                .flat_map(|c| &c.messages)
+                .map(Message::size)
+                .sum(),
+            _ => 0,
+        };
+        let media = match &*self.selected_chat_media.get() {
+            State::Ready(media) => media
+                .values()
+                .map(|infos| {
+                    std::mem::size_of::<i64>() + infos.len() * std::mem::size_of::<MediaInfo>()
+                })
+                .sum(),
+            _ => 0,
+        };
+        let find = self
+            .find
+            .as_ref()
+            .map_or(0, |f| f.hits.len() * std::mem::size_of::<usize>());
+
+        vec![
+            ("chats", chats),
+            ("messages", messages),
+            ("search results", results),
+            ("media info", media),
+            ("find hits", find),
+            ("thumbnails", self.view.images.size()),
+        ]
+    }
+
+    /// Show the chat list saved by the last run straight away, and refresh it
+    /// from the database in the background
+    fn initial_load(&self) {
+        match self.rt.block_on(cache::load_chats(&self.cache)) {
+            Ok(chats) if !chats.is_empty() => self.chats.set(State::Ready(chats.into())),
+            Ok(_) => self.chats.set(State::Fetching),
+            Err(e) => {
+                eprintln!("{e}");
+                self.chats.set(State::Fetching);
+            }
+        }
+
+        self.load_chats();
+    }
+
+    /// Bring the chat list up to date with the database, keeping the current
+    /// one on screen until it's done
+    fn load_chats(&self) {
+        let db = self.bulk.clone();
+        let cache = self.cache.clone();
+
+        self.refresh("chats", self.chats.clone(), async move {
+            Ok(summary::load(&db, &cache).await?.into())
+        });
+    }
+
+    /// In `--watch` mode, check for new messages every few seconds, notifying
+    /// about them and adding them to the chat list and the open chat
+    fn check_for_new_messages(&mut self, ctx: &egui::Context) {
+        let watch = match &mut self.watch {
+            Some(watch) => watch,
+            None => return,
+        };
+        ctx.request_repaint_after(watch::INTERVAL);
+
+        let check = match &mut *watch.check.get() {
+            State::Ready(check) => Some(watch::Check {
+                last: check.last,
+                arrivals: std::mem::take(&mut check.arrivals),
+            }),
+            _ => None,
+        };
+        if let Some(watch::Check { last, arrivals }) = check {
+            watch.check.set(State::Empty);
+            watch.last = watch.last.max(last);
+
+            let open = self.selected_chat.as_ref().map_or(Vec::new(), |chat| {
+                continuity::handles(&self.config.changes(), &chat.name)
+            });
+            if arrivals.iter().any(|arrival| open.contains(&arrival.chat)) {
+                let mut follow = false;
+                self.selected_chat_messages
+                    .update(|window| follow = window.follow());
+                if follow {
+                    self.load_page(Edge::Newer);
+                }
+            }
+            if !arrivals.is_empty() {
+                self.load_chats();
+            }
+            return;
+        }
+
+        let idle = matches!(&*watch.check.get(), State::Empty);
+        if idle && watch.checked.elapsed() >= watch::INTERVAL {
+            watch.checked = Instant::now();
+            let db = self.db.clone();
+            let last = watch.last;
+            let settings = self.config.notifications.clone();
+            let identity = self.config.me.clone();
+            let check = watch.check.clone();
+            let last_error = self.last_error.clone();
+
+            // not `self.load`, which would leave the check `Fetching` after
+            // an error (e.g. the database being busy) and stop watching
+            check.set(State::Fetching);
+            self.rt.spawn(async move {
+                let start = Instant::now();
+                let result = async {
+                    let found = watch::since(&db, last).await?;
+                    let found = tokio::task::spawn_blocking(move || {
+                        for arrival in &found.arrivals {
+                            watch::notify(&settings, &identity, arrival);
+                        }
+                        found
+                    })
+                    .await?;
+                    Ok::<_, eyre::Report>(found)
+                }
+                .await;
+                perf::record_load("new messages", start.elapsed());
+
+                match result {
+                    Ok(found) => check.set(State::Ready(found)),
+                    Err(e) => {
+                        check.set(State::Empty);
+                        last_error.set(e);
+                    }
+                }
+            });
+        }
+    }
+
+    /// Search box, saved searches, exports and the chat list
+    fn render_sidebar(&mut self, ui: &mut Ui) {
+        if ui.button(i18n::t("settings")).clicked() {
+            self.settings_open = !self.settings_open;
+        }
+
+        let response = ui
+            .horizontal(|ui| {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.search_query).hint_text(i18n::t(
+                        "search, e.g. from:me after:2021-01-01 has:image beach",
+                    )),
+                );
+                ui.checkbox(&mut self.search_regex, i18n::t("regex"));
+                response
+            })
+            .inner;
+        if response.lost_focus() && shortcuts::SEARCH.pressed(&ui.input()) {
+            if self.search_query.trim().is_empty() {
+                self.search_open = false;
+            } else {
+                self.search_open = true;
+                self.search(self.search_query.clone());
+            }
+        }
+
+        let mut run = None;
+        let mut forget = None;
+        if !self.config.saved_searches.is_empty() {
+            egui::CollapsingHeader::new(i18n::t("saved searches"))
+                .default_open(true)
+                .show(ui, |ui| {
+                    for (i, saved) in self.config.saved_searches.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            if ui.link(&saved.name).on_hover_text(&saved.query).clicked() {
+                                run = Some(saved.clone());
+                            }
+                            if ui
+                                .small_button("x")
+                                .on_hover_text(i18n::t("forget"))
+                                .clicked()
+                            {
+                                forget = Some(i);
+                            }
+                        });
+                    }
+                });
+        }
+        if let Some(saved) = run {
+            self.search_query = saved.query;
+            self.search_regex = saved.regex;
+            self.search_open = true;
+            self.search(self.search_query.clone());
+        }
+        if let Some(i) = forget {
+            self.config.saved_searches.remove(i);
+            self.save_config();
+        }
+        egui::CollapsingHeader::new(i18n::t("export")).show(ui, |ui| {
+            if ui
+                .button(i18n::t("every contact as its own file"))
+                .clicked()
+            {
+                self.export_each_contact();
+            }
+            for profile in &self.config.export_profiles {
+                if ui.button(&profile.name).clicked() {
+                    self.run_export_profile(profile.clone());
+                }
+            }
+
+            match &*self.export_status.get() {
+                State::Empty => {}
+                State::Fetching => match &*self.export_progress.get() {
+                    State::Ready((done, total)) => {
+                        ui.add(
+                            egui::ProgressBar::new(*done as f32 / (*total).max(1) as f32).text(
+                                i18n::tf(
+                                    "exported {done} of {total} chats...",
+                                    &[("done", done), ("total", total)],
+                                ),
+                            ),
+                        );
+                    }
+                    _ => {
+                        ui.label(i18n::t("exporting..."));
+                    }
+                },
+                State::Ready(path) => {
+                    ui.label(i18n::tf("exported to {path}", &[("path", &path.display())]));
+                }
+            }
+        });
+
+        egui::CollapsingHeader::new(i18n::t("merge preview")).show(ui, |ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.merge_path)
+                    .hint_text(i18n::t("path to another copy of chat.db")),
+            );
+            let ready = !self.merge_path.trim().is_empty();
+            if ui
+                .add_enabled(ready, egui::Button::new(i18n::t("compare")))
+                .clicked()
+            {
+                self.preview_merge();
+            }
+            if let State::Fetching = &*self.merge_preview.get() {
+                ui.label(i18n::t("comparing..."));
+            }
+        });
+
+        ui.add(
+            egui::TextEdit::singleline(&mut self.chat_filter).hint_text(i18n::t("filter chats")),
+        );
+        labels::filter_bar(ui, &self.config.labels, &mut self.label_filter);
+        ui.separator();
+
+        let chats = match &*self.chats.get() {
+            State::Empty => {
+                ui.heading(i18n::t("no chats found"));
+                return;
+            }
+            State::Fetching => {
+                ui.heading(i18n::t("loading..."));
+                return;
+            }
+            State::Ready(chats) => chats.clone(),
+        };
+        self.prefetch(&chats);
+
+        if matches!(*self.activity.get(), State::Empty) {
+            self.load_activity();
+        }
+        let activity = match &*self.activity.get() {
+            State::Ready(activity) => Some(activity.clone()),
+            _ => None,
+        };
+
+        let mut chats: Vec<&Chat> = chats.iter().collect();
+        if !self.chat_filter.trim().is_empty() {
+            let mut scored: Vec<_> = chats
+                .into_iter()
+                .filter_map(|chat| {
+                    let score = chat.filter_score(&self.chat_filter, &self.config.me.aliases)?;
+                    Some((score, chat))
+                })
+                .collect();
+            // stable, so equally good matches stay most recent first
+            scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+            chats = scored.into_iter().map(|(_, chat)| chat).collect();
+        }
+
+        // someone's old number is shown as part of the chat with their new
+        // one, and their other handles with their first
+        let changes = self.config.changes();
+        let names: HashSet<&str> = chats.iter().map(|&c| c.name.as_str()).collect();
+        chats.retain(|chat| {
+            let current = continuity::current(&changes, &chat.name);
+            current == chat.name || !names.contains(current)
+        });
+
+        if self.label_filter.is_some() {
+            chats.retain(|chat| self.config.labels.get(&chat.name) == self.label_filter.as_ref());
+        }
+
+        // wrapping changes with the width, and with it every chat's height
+        let width = ui.available_width();
+        if width != self.chat_heights_width {
+            self.chat_heights.clear();
+            self.chat_heights_width = width;
+        }
+
+        egui::ScrollArea::vertical().show_viewport(ui, |ui, viewport| {
+            let origin = ui.min_rect().top();
+            // group chats are never from strangers
+            let (known, unknown): (Vec<_>, Vec<_>) = chats.into_iter().partition(|chat| {
+                chat.participants.len() > 1
+                    || continuity::handles(&changes, &chat.name)
+                        .iter()
+                        .any(|handle| self.config.is_known(handle))
+            });
+            let hide_unknown = self.config.layout.hide_unknown_senders;
+            let order = known
+                .iter()
+                .chain(if hide_unknown { &[][..] } else { &unknown[..] })
+                .map(|chat| chat.name.as_str());
+            self.move_chat_cursor(order.collect());
+
+            let shown = (origin, viewport);
+            self.render_chat_entries(ui, &known, shown, activity.as_deref(), &changes);
+            if !unknown.is_empty() && !hide_unknown {
+                egui::CollapsingHeader::new(i18n::tf(
+                    "unknown senders ({count})",
+                    &[("count", &unknown.len())],
+                ))
+                .id_source("unknown senders")
+                .show(ui, |ui| {
+                    self.render_chat_entries(ui, &unknown, shown, activity.as_deref(), &changes);
+                });
+            }
+        });
+    }
+
+    /// `chats` in the sidebar, laying out only those on screen: the rest take
+    /// up the space they did when they last were. `shown` is where the top of
+    /// the sidebar's scroll area is, and the part of it that's in view.
+    fn render_chat_entries(
+        &mut self,
+        ui: &mut Ui,
+        chats: &[&Chat],
+        (origin, viewport): (f32, egui::Rect),
+        activity: Option<&activity::Activity>,
+        changes: &[HandleChange],
+    ) {
+        let spacing = ui.spacing().item_spacing.y;
+
+        for &chat in chats {
+            let top = ui.cursor().top();
+            // the cursor is scrolled to, and opened, wherever it is
+            let target = self.chat_key.is_some() && self.chat_cursor.as_ref() == Some(&chat.name);
+            if let Some(&height) = self.chat_heights.get(&chat.name) {
+                let y = top - origin;
+                let visible =
+                    y + height >= viewport.top() - OVERSCAN && y <= viewport.bottom() + OVERSCAN;
+                if !visible && !target {
+                    ui.allocate_space(egui::vec2(ui.available_width(), height));
+                    continue;
+                }
+            }
+
+            self.render_chat_entry(ui, chat, activity, changes);
+            self.chat_heights.insert(
+                chat.name.clone(),
+                (ui.cursor().top() - top - spacing).max(0.0),
+            );
+        }
+    }
+
+    /// Move the sidebar's cursor up or down `order`, the chats in the order
+    /// they're listed, for a key pressed this frame
+    fn move_chat_cursor(&mut self, order: Vec<&str>) {
+        let step = match self.chat_key {
+            Some(ChatKey::Up) => -1,
+            Some(ChatKey::Down) => 1,
+            _ => return,
+        };
+        if order.is_empty() {
+            return;
+        }
+
+        // starting from the open chat, if the cursor hasn't been moved yet
+        let from = self
+            .chat_cursor
+            .as_ref()
+            .or(self.selected_chat.as_ref().map(|chat| &chat.name))
+            .and_then(|name| order.iter().position(|c| *c == name.as_str()));
+        let to = match from {
+            Some(from) => (from as isize + step).clamp(0, order.len() as isize - 1) as usize,
+            None => 0,
+        };
+        self.chat_cursor = order.get(to).map(|name| name.to_string());
+    }
+
+    /// One chat in the sidebar, opened when clicked
+    fn render_chat_entry(
+        &mut self,
+        ui: &mut Ui,
+        chat: &Chat,
+        activity: Option<&activity::Activity>,
+        changes: &[HandleChange],
+    ) {
+        let mut label = self
+            .config
+            .labels
+            .get(&chat.name)
+            .cloned()
+            .unwrap_or_default();
+
+        let mut frame = Frame::group(ui.style());
+
+        let selected = self
+            .selected_chat
+            .as_ref()
+            .is_some_and(|c| c.name == chat.name);
+        if selected {
+            frame = frame.fill(theme::Colors::of(ui).me);
+        }
+        let cursor = self.chat_cursor.as_ref() == Some(&chat.name);
+        if cursor {
+            frame = frame.stroke(ui.visuals().selection.stroke);
+        }
+
+        let response = frame
+            .show(ui, |ui| {
+                if selected {
+                    ui.visuals_mut().override_text_color =
+                        Some(theme::text_on(theme::Colors::of(ui).me));
+                }
+                let name = self.config.me.chat_name(&chat.name, &chat.display_name);
+                let photo = match chat.participants.len() {
+                    1 => self.config.photo(&chat.name),
+                    _ => None,
+                };
+                ui.horizontal(|ui| {
+                    avatar::show(
+                        ui,
+                        &mut self.view.images,
+                        name,
+                        &chat.name,
+                        photo,
+                        AVATAR_SIZE,
+                    );
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui| {
+                            labels::badge(ui, &label);
+                            if name != chat.name {
+                                ui.label(name);
+                            }
+                            ui.label(&chat.name);
+                            let handles = continuity::handles(changes, &chat.name);
+                            let history = continuity::history(changes, &chat.name);
+                            let (was, also): (Vec<String>, Vec<String>) = handles[1..]
+                                .iter()
+                                .cloned()
+                                .partition(|h| history.iter().any(|c| c.from == *h));
+                            if !was.is_empty() {
+                                ui.weak(i18n::tf("was {handles}", &[("handles", &was.join(", "))]));
+                            }
+                            if !also.is_empty() {
+                                ui.weak(i18n::tf(
+                                    "also {handles}",
+                                    &[("handles", &also.join(", "))],
+                                ));
+                            }
+
+                            if let Some(counts) = activity.and_then(|a| a.get(&chat.name)) {
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::Center),
+                                    |ui| activity::sparkline(ui, counts),
+                                );
+                            }
+                        });
+                        if !chat.preview.is_empty() {
+                            ui.weak(&chat.preview);
+                        }
+                        ui.label(i18n::tf(
+                            "{date} · {count} messages",
+                            &[
+                                ("date", &format!("{:?}", chat.last_active)),
+                                ("count", &chat.message_count),
+                            ],
+                        ));
+                    });
+                });
+            })
+            .response
+            .interact(egui::Sense::click());
+        response.widget_info(|| {
+            let name = self.config.me.chat_name(&chat.name, &chat.display_name);
+            egui::WidgetInfo::selected(egui::WidgetType::Button, selected, name)
+        });
+        if cursor && self.chat_key.is_some() {
+            response.scroll_to_me(None);
+        }
+
+        let mut new_tab = false;
+        let mut beside = false;
+        let response = response.context_menu(|ui| {
+            if ui.button(i18n::t("open in new tab")).clicked() {
+                new_tab = true;
+                ui.close_menu();
+            }
+            let other = self
+                .selected_chat
+                .as_ref()
+                .is_some_and(|selected| selected.name != chat.name);
+            if other && ui.button(i18n::t("open beside this chat")).clicked() {
+                beside = true;
+                ui.close_menu();
+            }
+            ui.separator();
+            if labels::edit(ui, &mut label) {
+                if label.is_empty() {
+                    self.config.labels.remove(&chat.name);
+                } else {
+                    self.config.labels.insert(chat.name.clone(), label);
+                }
+                self.save_config();
+            }
+            if alias::edit(ui, &chat.name, &mut self.config.me.aliases, &mut self.alias) {
+                self.save_aliases();
+            }
+            let notifying = &mut self.config.notifications.chats;
+            let mut notify = notifying.contains(&chat.name);
+            if ui
+                .checkbox(&mut notify, i18n::t("notify about new messages"))
+                .changed()
+            {
+                notifying.retain(|c| *c != chat.name);
+                if notify {
+                    notifying.push(chat.name.clone());
+                }
+                self.save_config();
+            }
+            if chat.participants.len() == 1 {
+                ui.separator();
+                let changed = continuity::edit(
+                    ui,
+                    &chat.name,
+                    &mut self.config.handle_changes,
+                    &mut self.handle_change,
+                );
+                if changed {
+                    self.save_config();
+                    self.reload_if_open(&chat.name);
+                }
+                ui.separator();
+                let changed = people::edit(
+                    ui,
+                    &chat.name,
+                    &self.config.contacts,
+                    &mut self.config.people,
+                    &mut self.same_person,
+                );
+                if changed {
+                    self.save_config();
+                    self.reload_if_open(&chat.name);
+                }
+            }
+        });
+
+        if beside {
+            self.open_beside(chat.clone());
+            return;
+        }
+        let opened = cursor && self.chat_key == Some(ChatKey::Open);
+        if response.clicked() || opened || new_tab {
+            self.search_open = false;
+            self.chat_cursor = None;
+            // Cmd-click, like a link in a browser
+            if new_tab || (response.clicked() && ui.input().modifiers.command) {
+                self.open_in_new_tab(chat.clone());
+            } else {
+                self.select_chat(chat.clone(), Page::Newest);
+            }
+        }
+    }
+
+    /// Results of the last search, with a box for saving it
+    fn render_search(&mut self, ui: &mut Ui) {
+        if let Some(error) = &self.search_error {
+            ui.label(i18n::tf("invalid search: {error}", &[("error", &error)]));
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.saved_search_name)
+                    .hint_text(i18n::t("name this search")),
+            );
+
+            let name = self.saved_search_name.trim();
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new(i18n::t("save search")))
+                .clicked()
+            {
+                self.config.saved_searches.push(SavedSearch {
+                    name: name.to_owned(),
+                    query: self.search_query.clone(),
+                    regex: self.search_regex,
+                });
+                self.saved_search_name.clear();
+                self.save_config();
+            }
+
+            let docked = !self.config.layout.is_detached(Panel::SearchResults);
+            if docked && layout::controls(ui, &mut self.config.layout, Panel::SearchResults) {
+                self.save_config();
+            }
+        });
+
+        let guard = self.search_results.get();
+
+        match &*guard {
+            State::Empty => {
+                ui.label(i18n::t("no results"));
+            }
+            State::Fetching => {
+                ui.label(i18n::t("searching..."));
+            }
+            State::Ready(results) => {
+                let results = results.to_owned();
+                drop(guard);
+                if let Some((chat, message)) =
+                    render_search_results(ui, &self.config.me, &results, &self.search_terms)
+                {
+                    self.search_open = false;
+                    let page = message.map_or(Page::Newest, Page::Around);
+                    self.select_chat(self.find_chat(&chat), page);
+                    self.jump = message.map(|message| Jump {
+                        message,
+                        scroll_pending: true,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Collapsible list of everyone in a group chat, for showing only one
+    /// person's messages
+    fn render_participants(&mut self, ui: &mut Ui, chat: &Chat) {
+        let people = chat.participants.len() + 1;
+        let title = match &self.view.only_from {
+            Some(sender) => i18n::tf(
+                "{count} people - only showing {name}'s messages",
+                &[("count", &people), ("name", &self.config.me.name(sender))],
+            ),
+            None => i18n::tf("{count} people", &[("count", &people)]),
+        };
+
+        egui::CollapsingHeader::new(title)
+            .id_source(("participants", &chat.name))
+            .show(ui, |ui| {
+                if matches!(*self.selected_chat_participants.get(), State::Empty) {
+                    self.load_participants(chat);
+                }
+                let mut renamed = false;
+                match &*self.selected_chat_participants.get() {
+                    State::Ready(participants) => {
+                        let identity = self.config.me.clone();
+                        renamed = participants::show(
+                            ui,
+                            &identity,
+                            participants,
+                            &mut self.view.only_from,
+                            &mut self.config.me.aliases,
+                            &mut self.alias,
+                        );
+                    }
+                    _ => {
+                        ui.label(i18n::t("loading..."));
+                    }
+                }
+                if renamed {
+                    self.save_aliases();
+                }
+            });
+    }
+
+    /// The chat shown beside the selected one in split view, with a button to
+    /// close it
+    fn render_split(&mut self, ctx: &egui::Context, ui: &mut Ui) {
+        let mut pane = match self.split.take() {
+            Some(pane) => pane,
+            None => return,
+        };
+
+        let mut close = false;
+        ui.horizontal(|ui| {
+            ui.heading(
+                self.config
+                    .me
+                    .chat_name(&pane.chat.name, &pane.chat.display_name),
+            );
+            if a11y::icon(ui.small_button("×"), i18n::t("close split view")).clicked() {
+                close = true;
+            }
+        });
+
+        let mut edge = None;
+        match &mut *pane.messages.get() {
+            State::Empty => {
+                ui.label(i18n::t("no messages found"));
+            }
+            State::Fetching => {
+                ui.label(i18n::t("loading..."));
+            }
+            State::Ready(window) => {
+                pane.view.loading_page = pane.loading_page.load(Ordering::SeqCst);
+                // a scroll position of its own
+                edge = ui
+                    .push_id("split", |ui| {
+                        render_messages(
+                            ui,
+                            &self.config.me,
+                            window,
+                            match &*pane.media.get() {
+                                State::Ready(media) => Some(media),
+                                _ => None,
+                            },
+                            None,
+                            pane.jump.as_mut(),
+                            &mut pane.view,
+                        )
+                    })
+                    .inner;
+
+                if let Some(messages) = to_copy(&mut pane.view, &window.messages) {
+                    self.clipboard
+                        .copy(ctx, &self.config.clipboard, &self.config.me, &messages);
+                }
+            }
+        }
+        if let Some(edge) = edge {
+            self.extend_window(
+                &pane.loads,
+                pane.messages.clone(),
+                pane.loading_page.clone(),
+                edge,
+            );
+        }
+        if std::mem::take(&mut pane.view.latest) {
+            pane.messages = Handle::new();
+            pane.loading_page = Arc::new(AtomicBool::new(false));
+            self.load_window(
+                &pane.loads,
+                pane.messages.clone(),
+                pane.chat.name.clone(),
+                Page::Newest,
+            );
+        }
+        if let Some(message) = pane.view.info.take() {
+            self.load_info(message);
+        }
+
+        if !close {
+            self.split = Some(pane);
+        }
+    }
+
+    /// Gallery of a chat's photos and videos; clicking one jumps to it
+    fn render_media(&mut self, ui: &mut Ui, chat_id: &str) {
+        if matches!(*self.selected_chat_gallery.get(), State::Empty) {
+            self.load_gallery(chat_id.to_owned());
+        }
+
+        let clicked = match &*self.selected_chat_gallery.get() {
+            State::Ready(items) => gallery::show(ui, &self.config.me, items, &mut self.view.images),
+            _ => {
+                ui.label(i18n::t("loading..."));
+                None
+            }
+        };
+        if let Some(message) = clicked {
+            self.view.tab = ChatTab::Messages;
+            let loaded = match &*self.selected_chat_messages.get() {
+                State::Ready(window) => window.messages.iter().any(|m| m.id == message),
+                _ => false,
+            };
+            if !loaded {
+                self.load_messages(chat_id.to_owned(), Page::Around(message));
+            }
+            self.jump = Some(Jump {
+                message,
+                scroll_pending: true,
+            });
+        }
+    }
+}
+
+impl eframe::App for MyEguiApp {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        puffin::GlobalProfiler::lock().new_frame();
+        puffin::profile_function!();
+
+        theme::apply(ctx, &self.config, &frame.info());
+        i18n::set(self.config.appearance.language);
+        // egui only describes what's focused or clicked with this on
+        ctx.options().screen_reader = self.config.accessibility.speak_focus;
+
+        let typing = ctx.wants_keyboard_input();
+        let (open_find, toggle_perf, toggle_shortcuts, toggle_palette, clear, close_tab, zoom) = {
+            let input = ctx.input();
+
+            // the keys below also move the cursor in text boxes
+            self.chat_key = None;
+            self.view.scroll_key = None;
+            if !typing {
+                self.chat_key = [
+                    (&shortcuts::CHAT_UP, ChatKey::Up),
+                    (&shortcuts::CHAT_DOWN, ChatKey::Down),
+                    (&shortcuts::CHAT_OPEN, ChatKey::Open),
+                ]
+                .into_iter()
+                .find_map(|(shortcut, key)| shortcut.pressed(&input).then_some(key));
+                self.view.scroll_key = [
+                    (&shortcuts::PAGE_UP, ScrollKey::PageUp),
+                    (&shortcuts::PAGE_DOWN, ScrollKey::PageDown),
+                    (&shortcuts::TOP, ScrollKey::Top),
+                    (&shortcuts::BOTTOM, ScrollKey::Bottom),
+                ]
+                .into_iter()
+                .find_map(|(shortcut, key)| shortcut.pressed(&input).then_some(key));
+            }
+
+            (
+                shortcuts::FIND.pressed(&input),
+                shortcuts::PERF.pressed(&input),
+                // "?" is also typed into text boxes
+                !typing && shortcuts::CHEAT_SHEET.pressed(&input),
+                shortcuts::PALETTE.pressed(&input),
+                // Esc closes the lightbox or find bar first
+                !typing
+                    && shortcuts::CLEAR.pressed(&input)
+                    && !self.view.images.lightbox_open()
+                    && self.find.is_none(),
+                shortcuts::CLOSE_TAB.pressed(&input),
+                [
+                    (
+                        &shortcuts::ZOOM_IN,
+                        self.config.appearance.zoom + theme::ZOOM_STEP,
+                    ),
+                    (
+                        &shortcuts::ZOOM_OUT,
+                        self.config.appearance.zoom - theme::ZOOM_STEP,
+                    ),
+                    (&shortcuts::ZOOM_RESET, 1.0),
+                ]
+                .into_iter()
+                .find_map(|(shortcut, zoom)| shortcut.pressed(&input).then_some(zoom)),
+            )
+        };
+        if let Some(zoom) = zoom {
+            self.set_zoom(zoom);
+        }
+        if close_tab && self.selected_chat.is_some() {
+            self.close_tab(self.active_tab);
+        }
+        if clear {
+            if self.search_open {
+                self.search_open = false;
+            } else if !self.view.selected.is_empty() {
+                self.view.selected.clear();
+                self.view.anchor = None;
+            } else if self.selected_chat.is_some() {
+                self.close_tab(self.active_tab);
+            }
+            self.chat_cursor = None;
+        }
+        if toggle_perf {
+            self.perf.open = !self.perf.open;
+        }
+        if toggle_shortcuts {
+            self.shortcuts_open = !self.shortcuts_open;
+        }
+        if toggle_palette {
+            self.palette = match self.palette {
+                Some(_) => None,
+                None => Some(Palette::default()),
+            };
+        }
+        if open_find && self.selected_chat.is_some() {
+            match &mut self.find {
+                Some(find) => find.focus_pending = true,
+                None => self.find = Some(Find::open()),
+            }
+        }
+
+        self.check_for_new_messages(ctx);
+        let dropped: Vec<_> = ctx
+            .input()
+            .raw
+            .dropped_files
+            .iter()
+            .filter_map(|file| file.path.clone())
+            .collect();
+        for path in dropped {
+            if let Err(e) = open::in_new_window(&path) {
+                self.last_error.set(e.into());
+            }
+        }
+        if let Some(mut palette) = self.palette.take() {
+            let entries = self.palette_entries(&palette.query);
+            let (picked, close) = palette::show(ctx, &mut palette, &entries);
+            if !close {
+                self.palette = Some(palette);
+            }
+            if let Some(command) = picked {
+                self.run_command(command);
+            }
+        }
+        settings::show(ctx, &mut self.config, &mut self.settings_open);
+        shortcuts::show(ctx, &mut self.shortcuts_open);
+        self.clipboard.show(ctx);
+        self.perf.show(ctx, frame, &self.memory_usage());
+        self.view.images.show_lightbox(ctx);
+        if let Some(split) = &mut self.split {
+            split.view.images.show_lightbox(ctx);
+        }
+        self.last_error.show(ctx);
+        if let State::Ready(preview) = &*self.merge_preview.get() {
+            merge::show(ctx, preview, &mut self.merge_open);
+        }
+        let mut linked = None;
+        if let State::Ready(info) = &*self.message_info.get() {
+            linked = info::show(ctx, info, &mut self.info_open);
+        }
+        if let Some((message, chat)) = linked {
+            self.select_chat(self.find_chat(&chat), Page::Around(message));
+            self.jump = Some(Jump {
+                message,
+                scroll_pending: true,
+            });
+        }
+
+        let panels = &self.config.layout;
+        if panels.is_detached(Panel::Chats) {
+            let mut open = true;
+            layout::window(Panel::Chats, &mut open).show(ctx, |ui| self.render_sidebar(ui));
+            if !open {
+                self.config.layout.set_detached(Panel::Chats, false);
+                self.save_config();
+            }
+        } else if panels.sidebar_collapsed {
+            egui::SidePanel::left("collapsed_left_panel")
+                .resizable(false)
+                .show(ctx, |ui| {
+                    if a11y::icon(ui.small_button("»"), i18n::t("show chats")).clicked() {
+                        self.config.layout.sidebar_collapsed = false;
+                        self.save_config();
+                    }
+                });
+        } else {
+            let width = egui::SidePanel::left("my_left_panel")
+                .default_width(panels.sidebar_width)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        if layout::controls(ui, &mut self.config.layout, Panel::Chats) {
+                            self.save_config();
+                        }
+                    });
+                    self.render_sidebar(ui);
+                })
+                .response
+                .rect
+                .width();
+
+            // saved once the user lets go, rather than on every frame of a drag
+            let resized = (width - self.config.layout.sidebar_width).abs() >= 1.0;
+            if resized && !ctx.input().pointer.any_down() {
+                self.config.layout.sidebar_width = width;
+                self.save_config();
+            }
+        }
+
+        if let Some(chat) = &self.selected_chat {
+            if self.config.layout.is_detached(Panel::Media) {
+                let chat_id = chat.name.clone();
+                let mut open = true;
+                layout::window(Panel::Media, &mut open)
+                    .show(ctx, |ui| self.render_media(ui, &chat_id));
+                if !open {
+                    self.config.layout.set_detached(Panel::Media, false);
+                    self.save_config();
+                }
+            }
+        }
+
+        let search_detached = self.config.layout.is_detached(Panel::SearchResults);
+        if self.search_open && search_detached {
+            let mut open = true;
+            layout::window(Panel::SearchResults, &mut open).show(ctx, |ui| self.render_search(ui));
+            if !open {
+                self.config.layout.set_detached(Panel::SearchResults, false);
+                self.save_config();
+            }
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if self.search_open && !search_detached {
+                self.render_search(ui);
+            } else if let Some(chat) = self.selected_chat.clone() {
+                if self.split.is_some() {
+                    egui::SidePanel::right("split")
+                        .default_width(ui.available_width() / 2.0)
+                        .show_inside(ui, |ui| self.render_split(ctx, ui));
+                }
+                if !self.tabs.is_empty() {
+                    let action = tabs::bar(ui, &self.tab_names(), self.active_tab);
+                    if let Some(action) = action {
+                        match action {
+                            tabs::Action::Switch(i) => self.switch_tab(i),
+                            tabs::Action::Close(i) => self.close_tab(i),
+                        }
+                        // `chat` is the tab that was on screen, so draw the
+                        // new one next frame
+                        ui.ctx().request_repaint();
+                        return;
+                    }
+                    ui.separator();
+                }
+                // the media tab moves out with a detached gallery
+                let media_detached = self.config.layout.is_detached(Panel::Media);
+                let mut tab = match self.view.tab {
+                    ChatTab::Media if media_detached => ChatTab::Messages,
+                    tab => tab,
+                };
+                let mut copy_chat = false;
+                let mut jump_to = None;
+                ui.horizontal(|ui| {
+                    ui.heading(self.config.me.chat_name(&chat.name, &chat.display_name));
+                    if matches!(*self.selected_chat_count.get(), State::Empty) {
+                        self.load_count(&chat.name);
+                    }
+                    if let State::Ready(count) = &*self.selected_chat_count.get() {
+                        ui.weak(i18n::tf("{count} messages", &[("count", count)]));
+                    }
+
+                    ui.selectable_value(&mut tab, ChatTab::Messages, i18n::t("messages"));
+                    if !media_detached {
+                        ui.selectable_value(&mut tab, ChatTab::Media, i18n::t("media"));
+                    }
+                    ui.selectable_value(&mut tab, ChatTab::Stats, i18n::t("stats"));
+                    if tab == ChatTab::Media
+                        && layout::controls(ui, &mut self.config.layout, Panel::Media)
+                    {
+                        self.save_config();
+                        tab = ChatTab::Messages;
+                    }
+
+                    if ui.button(i18n::t("export photo contact sheet")).clicked() {
+                        self.export_contact_sheet(chat.name.clone());
+                    }
+                    if ui.button(i18n::t("export timeline (.ics)")).clicked() {
+                        self.export_timeline(chat.name.clone());
+                    }
+                    if tab == ChatTab::Messages {
+                        ui.menu_button(i18n::t("jump to date"), |ui| {
+                            let typed =
+                                NaiveDate::parse_from_str(self.jump_date.trim(), "%Y-%m-%d").ok();
+                            if let Some(date) = calendar::show(ui, &mut self.jump_month, typed) {
+                                jump_to = Some(date);
+                                self.jump_date = date.format("%Y-%m-%d").to_string();
+                                ui.close_menu();
+                            }
+                            ui.separator();
+
+                            let entered = ui
+                                .add(
+                                    egui::TextEdit::singleline(&mut self.jump_date)
+                                        .hint_text(i18n::t("YYYY-MM-DD"))
+                                        .desired_width(90.0),
+                                )
+                                .lost_focus()
+                                && ui.input().key_pressed(egui::Key::Enter);
+                            match typed {
+                                Some(date) => {
+                                    if ui.button(i18n::t("go")).clicked() || entered {
+                                        jump_to = Some(date);
+                                        ui.close_menu();
+                                    }
+                                }
+                                None => {
+                                    ui.label(i18n::t("dates should look like 2020-12-31"));
+                                }
+                            }
+                        });
+                    }
+                    if tab == ChatTab::Messages && ui.button(i18n::t("copy chat")).clicked() {
+                        copy_chat = true;
+                    }
+                    ui.menu_button(i18n::t("export messages"), |ui| {
+                        for (format, label) in [
+                            (export::Format::Text, i18n::t("text")),
+                            (export::Format::Json, "JSON"),
+                            (export::Format::Csv, "CSV"),
+                            (export::Format::Html, "HTML"),
+                        ] {
+                            if ui.button(label).clicked() {
+                                self.export_messages(chat.name.clone(), format);
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                    ui.menu_button(i18n::t("time capsule"), |ui| {
+                        ui.label(i18n::t("a zip of this chat to keep or give"));
+                        let mut dates = [None, None];
+                        for (i, (label, text)) in [
+                            (i18n::t("from"), &mut self.capsule_after),
+                            (i18n::t("until"), &mut self.capsule_before),
+                        ]
+                        .into_iter()
+                        .enumerate()
+                        {
+                            ui.horizontal(|ui| {
+                                ui.label(label);
+                                ui.add(
+                                    egui::TextEdit::singleline(text)
+                                        .hint_text(i18n::t("YYYY-MM-DD"))
+                                        .desired_width(90.0),
+                                );
+                            });
+                            dates[i] = match text.trim() {
+                                "" => Some(None),
+                                text => NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().map(Some),
+                            };
+                        }
+
+                        match dates {
+                            [Some(after), Some(before)] => {
+                                if ui.button(i18n::t("make time capsule")).clicked() {
+                                    let scope = export::Scope {
+                                        after,
+                                        before,
+                                        ..Default::default()
+                                    };
+                                    self.export_time_capsule(chat.name.clone(), scope);
+                                    ui.close_menu();
+                                }
+                            }
+                            _ => {
+                                ui.label(i18n::t("dates should look like 2020-12-31"));
+                            }
+                        }
                     });
+                    ui.menu_button(i18n::t("export attachments"), |ui| {
+                        if ui.button(i18n::t("everything")).clicked() {
+                            self.export_attachments(chat.name.clone(), Vec::new());
+                            ui.close_menu();
+                        }
+                        for kind in export::Kind::ALL {
+                            if ui.button(i18n::t(kind.label())).clicked() {
+                                self.export_attachments(chat.name.clone(), vec![kind]);
+                                ui.close_menu();
+                            }
+                        }
+                    });
+
+                    match &*self.export_status.get() {
+                        State::Empty => {}
+                        State::Fetching => {
+                            ui.label(i18n::t("exporting..."));
+                        }
+                        State::Ready(path) => {
+                            ui.label(i18n::tf("exported to {path}", &[("path", &path.display())]));
+                        }
+                    }
+                });
+
+                self.view.tab = tab;
+                if copy_chat {
+                    self.view.copy = Some(0..usize::MAX);
+                }
+                if let Some(date) = jump_to {
+                    self.jump_to_date(chat.name.clone(), date);
+                }
+
+                if tab == ChatTab::Media {
+                    self.render_media(ui, &chat.name);
+                    return;
+                }
+                if tab == ChatTab::Stats {
+                    if matches!(*self.selected_chat_stats.get(), State::Empty) {
+                        self.load_stats(chat.name.clone());
+                    }
+                    match &*self.selected_chat_stats.get() {
+                        State::Ready(stats) => stats::show(ui, stats),
+                        _ => {
+                            ui.label(i18n::t("loading..."));
+                        }
+                    }
+                    return;
+                }
+
+                if chat.participants.len() > 1 {
+                    self.render_participants(ui, &chat);
+                    ui.checkbox(
+                        &mut self.view.only_mentions,
+                        i18n::t("only messages that mention me"),
+                    );
+                }
+                if let Some(find) = &mut self.find {
+                    if render_find_bar(ui, find) {
+                        self.find = None;
+                    }
+                }
+
+                if matches!(*self.selected_chat_density.get(), State::Empty) {
+                    self.load_density(&chat.name);
+                }
+
+                let mut edge = None;
+                let mut scrub = None;
+                match &mut *self.selected_chat_messages.get() {
+                    State::Empty => {
+                        ui.label(i18n::t("no messages found"));
+                    }
+                    State::Fetching => {
+                        ui.label(i18n::t("loading..."));
+                    }
+                    State::Ready(window) => {
+                        self.view.loading_page = self.loading_page.load(Ordering::SeqCst);
+                        let area = ui.available_rect_before_wrap();
+                        let (list, strip) = minimap::split(area, ui.spacing().item_spacing.x);
+                        let mut list = ui.child_ui(list, *ui.layout());
+                        edge = render_messages(
+                            &mut list,
+                            &self.config.me,
+                            window,
+                            match &*self.selected_chat_media.get() {
+                                State::Ready(media) => Some(media),
+                                _ => None,
+                            },
+                            self.find.as_mut(),
+                            self.jump.as_mut(),
+                            &mut self.view,
+                        );
+                        ui.expand_to_include_rect(area);
+
+                        if let State::Ready(density) = &*self.selected_chat_density.get() {
+                            let loaded = window
+                                .messages
+                                .first()
+                                .zip(window.messages.last())
+                                .map(|(first, last)| (first.date, last.date));
+                            scrub = minimap::show(ui, strip, density, loaded);
+                        }
+
+                        if let Some(messages) = to_copy(&mut self.view, &window.messages) {
+                            self.clipboard.copy(
+                                ctx,
+                                &self.config.clipboard,
+                                &self.config.me,
+                                &messages,
+                            );
+                        }
+                    }
+                }
+                if let Some(edge) = edge {
+                    self.load_page(edge);
+                }
+                if let Some(date) = scrub {
+                    self.jump_to_date(chat.name.clone(), date);
+                }
+                if std::mem::take(&mut self.view.latest) {
+                    self.jump = None;
+                    self.load_messages(chat.name.clone(), Page::Newest);
+                }
+                if let Some(message) = self.view.info.take() {
+                    self.load_info(message);
+                }
+            } else {
+                ui.heading(i18n::t("select a chat on the left"));
+            }
+        });
+
+        if self.config.accessibility.speak_focus {
+            a11y::speak_focus_changes(ctx);
+        }
+    }
+}
+
+/// Draw the find-in-chat bar, returning true if the user closed it
+fn render_find_bar(ui: &mut Ui, find: &mut Find) -> bool {
+    let mut close = false;
+
+    ui.horizontal(|ui| {
+        let response =
+            ui.add(egui::TextEdit::singleline(&mut find.query).hint_text(i18n::t("find in chat")));
+
+        if find.focus_pending {
+            response.request_focus();
+            find.focus_pending = false;
+        }
+
+        if response.lost_focus() {
+            if shortcuts::FIND_PREV.pressed(&ui.input()) {
+                find.prev();
+                response.request_focus();
+            } else if shortcuts::FIND_NEXT.pressed(&ui.input()) {
+                find.next();
+                response.request_focus();
+            }
+        }
+
+        if ui.button(i18n::t("previous")).clicked() {
+            find.prev();
+        }
+        if ui.button(i18n::t("next")).clicked() {
+            find.next();
+        }
+
+        if !find.query.is_empty() {
+            if find.hits.is_empty() {
+                ui.label(i18n::t("no matches"));
+            } else {
+                ui.label(i18n::tf(
+                    "{current} of {total}",
+                    &[
+                        ("current", &(find.current + 1)),
+                        ("total", &find.hits.len()),
+                    ],
+                ));
+            }
+        }
+
+        if ui.button(i18n::t("done")).clicked() || shortcuts::FIND_CLOSE.pressed(&ui.input()) {
+            close = true;
+        }
+    });
+
+    close
+}
+
+/// Draw the loaded messages of a chat. Returns the edge to load another page
+/// from when it has been scrolled to the top or bottom.
+fn render_messages(
+    ui: &mut Ui,
+    identity: &Identity,
+    window: &mut Window,
+    media: Option<&HashMap<i64, Vec<MediaInfo>>>,
+    find: Option<&mut Find>,
+    jump: Option<&mut Jump>,
+    view: &mut ChatView,
+) -> Option<Edge> {
+    puffin::profile_function!();
+
+    let messages = &window.messages;
+    let mut query = "";
+    let mut current = None;
+    let mut scroll_to = window
+        .scroll_to
+        .take()
+        .and_then(|(id, align)| Some((messages.iter().position(|m| m.id == id)?, align)));
+
+    if let Some(find) = find {
+        find.update_hits(messages);
+        current = find.current_message();
+        if find.scroll_pending {
+            scroll_to = current.map(|i| (i, egui::Align::Center));
+            find.scroll_pending = false;
+        }
+
+        let find: &Find = find;
+        query = &find.query;
+    }
+
+    let mut jumped = None;
+    if let Some(jump) = jump {
+        jumped = messages.iter().position(|m| m.id == jump.message);
+        if jump.scroll_pending && jumped.is_some() {
+            scroll_to = scroll_to.or(jumped.map(|i| (i, egui::Align::Center)));
+            jump.scroll_pending = false;
+        }
+    }
+    let scrolling = scroll_to.is_some();
+    // a page asked for with the button at the top or bottom
+    let mut clicked = None;
+
+    // someone's iMessage and SMS chats are shown as one, with each message
+    // marked with how it was sent
+    let mut services = messages
+        .iter()
+        .map(|m| m.service.as_str())
+        .filter(|s| !s.is_empty());
+    let first_service = services.next();
+    let mixed = services.any(|s| Some(s) != first_service);
+
+    // wrapping changes with the width, and with it every message's height
+    let width = ui.available_width();
+    if width != view.heights_width {
+        view.heights.clear();
+        view.heights_width = width;
+    }
+
+    if !view.selected.is_empty() {
+        ui.horizontal(|ui| {
+            ui.label(i18n::tf(
+                "{count} selected",
+                &[("count", &view.selected.len())],
+            ));
+            if ui.button(i18n::t("copy")).clicked() {
+                view.copy_selection = true;
+            }
+            if ui.button(i18n::t("clear")).clicked() {
+                view.selected.clear();
+                view.anchor = None;
+            }
+        });
+    }
+
+    let mut area = egui::ScrollArea::vertical();
+    if let Some(offset) = view.scroll_offset.take() {
+        area = area.vertical_scroll_offset(offset);
+    }
+    let output = area.show_viewport(ui, |ui, viewport| {
+        let origin = ui.min_rect().top();
+        let spacing = ui.spacing().item_spacing.y;
+        // the last message drawn and where it started, measured once the
+        // next one starts
+        let mut drawn: Option<(i64, f32)> = None;
+
+        if window.older && more_row(ui, view.loading_page, i18n::t("load earlier messages")) {
+            clicked = Some(Edge::Older);
+        }
+
+        for (i, msg) in messages.iter().enumerate() {
+            if let Some((id, top)) = drawn.take() {
+                view.heights
+                    .insert(id, (ui.cursor().top() - top - spacing).max(0.0));
+            }
+
+            if view
+                .only_from
+                .as_ref()
+                .is_some_and(|only| *only != msg.sender)
+            {
+                continue;
+            }
+            if view.only_mentions && !mention::mentions_me(identity, &view.members, &msg.mentions) {
+                continue;
+            }
+
+            // only messages on screen are laid out, the rest take up the
+            // space they did when they last were
+            let top = ui.cursor().top();
+            let target = scroll_to.is_some_and(|(to, _)| to == i);
+            if let Some(&height) = view.heights.get(&msg.id) {
+                let y = top - origin;
+                let visible =
+                    y + height >= viewport.top() - OVERSCAN && y <= viewport.bottom() + OVERSCAN;
+                if !visible && !target {
+                    if height > 0.0 {
+                        ui.allocate_space(egui::vec2(ui.available_width(), height));
+                    }
+                    continue;
+                }
+            }
+            drawn = Some((msg.id, top));
+
+            let previous = i.checked_sub(1).map(|i| &messages[i]);
+            if let Some(previous) = previous {
+                if let Some(notice) = service_notice(&previous.service, &msg.service) {
+                    ui.vertical_centered(|ui| ui.weak(egui::RichText::new(notice).small()));
+                }
+            }
+            // a change before the first loaded message may be in a page
+            // that isn't loaded yet
+            if previous.is_some() || !window.older {
+                let previous = previous.map(|previous| previous.date);
+                for change in continuity::between(&window.history, previous, msg.date) {
+                    let notice = continuity::notice(change);
+                    ui.vertical_centered(|ui| ui.weak(egui::RichText::new(notice).small()));
+                }
+            }
+
+            // shown in full anyway when find or a jump lands on it
+            let folded = msg.collapsed && !view.uncollapsed.contains(&msg.id);
+            if folded && current != Some(i) && jumped != Some(i) {
+                let text = i18n::tf(
+                    "ignored message from {name} - click to show",
+                    &[("name", &identity.name(&msg.sender))],
+                );
+                let clicked = ui
+                    .vertical_centered(|ui| {
+                        ui.add(
+                            egui::Label::new(egui::RichText::new(text).small().weak())
+                                .sense(egui::Sense::click()),
+                        )
+                    })
+                    .inner
+                    .clicked();
+                if clicked {
+                    view.uncollapsed.insert(msg.id);
+                }
+                continue;
+            }
+
+            if let Some(event) = &msg.event {
+                let description = event.describe(identity, &msg.sender);
+                let response = ui
+                    .vertical_centered(|ui| ui.weak(egui::RichText::new(description).small()))
+                    .inner;
+                if let Some((_, align)) = scroll_to.filter(|(to, _)| *to == i) {
+                    response.scroll_to_me(Some(align));
+                }
+                response.context_menu(|ui| {
+                    if ui.button(i18n::t("get info")).clicked() {
+                        view.info = Some(msg.id);
+                        ui.close_menu();
+                    }
+                });
+                continue;
+            }
+
+            let colors = theme::Colors::of(ui);
+            // mirrored, mine go on the left and everyone else's on the right
+            let right = identity.is_me(&msg.sender) != bidi::mirrored(ui);
+            let layout = if right {
+                egui::Layout::right_to_left(egui::Align::TOP)
+            } else {
+                egui::Layout::left_to_right(egui::Align::TOP)
+            };
+            let bg = if !identity.is_me(&msg.sender) {
+                colors.them
+            } else if msg.service == "SMS" {
+                colors.sms
+            } else {
+                colors.me
+            };
+            // in a group, everyone else gets a colour of their own, and their
+            // name over the first of a run of their messages
+            let color = match &msg.sender {
+                Sender::SomeoneElse(handle) if view.group && !identity.is_me(&msg.sender) => {
+                    Some(sender_color(handle))
+                }
+                _ => None,
+            };
+            let continues = previous
+                .is_some_and(|previous| previous.sender == msg.sender && previous.event.is_none());
+            // the tail goes on the last of the run
+            let ends = messages
+                .get(i + 1)
+                .is_none_or(|next| next.sender != msg.sender || next.event.is_some());
+            if previous.is_some() && !continues {
+                ui.add_space(bubble::CLUSTER_SPACING);
+            }
+            // in high contrast, everyone's named, so colour isn't the only
+            // way to tell who's who
+            if (color.is_some() || colors.high_contrast) && !continues {
+                let name_color = match color {
+                    Some(color) if !colors.high_contrast => color,
+                    _ => ui.visuals().strong_text_color(),
+                };
+                ui.with_layout(layout, |ui| {
+                    ui.label(
+                        egui::RichText::new(identity.name(&msg.sender))
+                            .small()
+                            .color(name_color),
+                    );
+                });
+            }
+
+            ui.with_layout(layout, |ui| {
+                ui.visuals_mut().override_text_color = Some(theme::text_on(bg));
+
+                // everyone else's picture beside the first of a run of their
+                // messages, with the rest lined up under it
+                if let Sender::SomeoneElse(handle) = &msg.sender {
+                    if !identity.is_me(&msg.sender) {
+                        if continues {
+                            ui.allocate_space(egui::Vec2::splat(AVATAR_SIZE));
+                        } else {
+                            avatar::show(
+                                ui,
+                                &mut view.images,
+                                identity.name(&msg.sender),
+                                handle,
+                                view.photos.get(handle).map(PathBuf::as_path),
+                                AVATAR_SIZE,
+                            )
+                            .on_hover_text(identity.name(&msg.sender));
+                        }
+                    }
+                }
+
+                let stroke = if current == Some(i) || jumped == Some(i) {
+                    Stroke::new(colors.outline, colors.highlight)
+                } else if view.selected.contains(&msg.id) {
+                    Stroke::new(colors.outline, ui.visuals().selection.stroke.color)
+                } else {
+                    Stroke::new(1.5, color.unwrap_or(colors.border))
+                };
+
+                let tail = if right {
+                    bubble::Tail::Right
+                } else {
+                    bubble::Tail::Left
+                };
+                // one to three emoji on their own go big, without a bubble
+                let jumbo = emoji::jumbo_size(&msg.text).filter(|_| {
+                    msg.images.is_empty()
+                        && msg.audio.is_empty()
+                        && msg.transcription.is_none()
+                        && msg.balloon.is_none()
+                        && msg.location.is_none()
+                        && msg.contacts.is_empty()
+                });
+                // painted once the bubble's size is known, behind it
+                let background = ui.painter().add(egui::Shape::Noop);
+                let response = Frame::none()
+                    .inner_margin(bubble::MARGIN)
+                    .stroke(stroke)
+                    .rounding(Rounding::same(bubble::ROUNDING))
+                    .show(ui, |ui| {
+                        ui.set_max_width(250.0);
+                        ui.style_mut().wrap = Some(true);
+
+                        for image in &msg.images {
+                            if view.images.show_inline(ui, image) {
+                                let paths: Vec<_> = messages
+                                    .iter()
+                                    .flat_map(|m| m.images.iter().map(|a| a.path.clone()))
+                                    .collect();
+                                let index =
+                                    paths.iter().position(|p| *p == image.path).unwrap_or(0);
+                                view.images.open(paths, index);
+                            }
+                        }
+                        for path in &msg.audio {
+                            view.audio.show(ui, path);
+                        }
+                        if let Some(transcription) = &msg.transcription {
+                            ui.label(egui::RichText::new(format!("🎤 {transcription}")).italics());
+                        }
+                        if let Some(balloon) = &msg.balloon {
+                            balloon::show(ui, balloon, &msg.guid, &mut view.images);
+                        }
+                        if let Some(location) = &msg.location {
+                            location::show(ui, location);
+                        }
+                        for contact in &msg.contacts {
+                            vcard::show(ui, contact);
+                        }
+
+                        let ranges = find::matches(&msg.text, query);
+
+                        // the current find match may be past the preview
+                        let was_expanded = view.expanded.contains(&msg.id) || current == Some(i);
+                        let mut is_expanded = was_expanded;
+                        if let Some(size) = jumbo {
+                            // not on a bubble, so in the usual text colour
+                            ui.visuals_mut().override_text_color = None;
+                            ui.label(egui::RichText::new(msg.text.trim()).size(size));
+                        } else if !msg.text.is_empty() {
+                            let mentions: Vec<_> =
+                                msg.mentions.iter().map(|m| m.range.clone()).collect();
+                            long_text::show(ui, &msg.text, &ranges, &mentions, &mut is_expanded);
+                        }
+                        for info in media.and_then(|m| m.get(&msg.id)).into_iter().flatten() {
+                            ui.horizontal(|ui| {
+                                for badge in info.badges() {
+                                    ui.label(egui::RichText::new(badge).small().monospace());
+                                }
+                            });
+                        }
+                        if mixed && !msg.service.is_empty() {
+                            ui.label(egui::RichText::new(&msg.service).small().weak());
+                        }
+                        if is_expanded != was_expanded {
+                            if is_expanded {
+                                view.expanded.insert(msg.id);
+                            } else {
+                                view.expanded.remove(&msg.id);
+                            }
+                        }
+                    })
+                    .response;
+                if jumbo.is_none() {
+                    ui.painter().set(
+                        background,
+                        bubble::background(response.rect, bg, ends.then_some(tail)),
+                    );
+                }
+
+                // a little overlap onto the top corner, like in Messages
+                for (n, sticker) in msg.stickers.iter().enumerate() {
+                    let center = response.rect.right_top()
+                        + egui::vec2(-STICKER_SIZE / 2.0 - n as f32 * STICKER_SIZE * 0.6, 0.0);
+                    let rect =
+                        egui::Rect::from_center_size(center, egui::Vec2::splat(STICKER_SIZE));
+                    view.images.overlay(ui, sticker, rect);
+                }
+
+                if let Some((_, align)) = scroll_to.filter(|(to, _)| *to == i) {
+                    response.scroll_to_me(Some(align));
+                }
+
+                let response = response.interact(egui::Sense::click());
+                response.widget_info(|| {
+                    egui::WidgetInfo::selected(
+                        egui::WidgetType::Other,
+                        view.selected.contains(&msg.id),
+                        a11y::message(identity, msg),
+                    )
+                });
+                if response.clicked() {
+                    let modifiers = ui.input().modifiers;
+                    select(view, messages, i, modifiers);
+                }
+                response.context_menu(|ui| {
+                    if !view.selected.is_empty() && ui.button(i18n::t("copy selected")).clicked() {
+                        view.copy_selection = true;
+                        ui.close_menu();
+                    }
+                    if ui.button(i18n::t("copy")).clicked() {
+                        view.copy = Some(i..i + 1);
+                        ui.close_menu();
+                    }
+                    if ui.button(i18n::t("copy from here to the end")).clicked() {
+                        view.copy = Some(i..usize::MAX);
+                        ui.close_menu();
+                    }
+                    if ui.button(i18n::t("get info")).clicked() {
+                        view.info = Some(msg.id);
+                        ui.close_menu();
+                    }
+                });
             });
+
+            let reactions = tapback::summarize(&msg.reactions);
+            if !reactions.is_empty() {
+                ui.with_layout(layout, |ui| {
+                    // under the bubble, not the picture beside it
+                    if !identity.is_me(&msg.sender) && matches!(msg.sender, Sender::SomeoneElse(_))
+                    {
+                        ui.add_space(AVATAR_SIZE + ui.spacing().item_spacing.x);
+                    }
+                    tapback::badges(ui, identity, msg.id, &reactions);
+                });
+            }
+            if let Some(effect) = &msg.effect {
+                ui.with_layout(layout, |ui| {
+                    ui.weak(egui::RichText::new(effect_caption(effect)).small());
+                });
+            }
+        }
+        if let Some((id, top)) = drawn {
+            view.heights
+                .insert(id, (ui.cursor().top() - top - spacing).max(0.0));
+        }
+
+        if window.newer && more_row(ui, view.loading_page, i18n::t("load later messages")) {
+            clicked = Some(Edge::Newer);
+        }
+
+        ui.min_rect().height()
+    });
+
+    let offset = output.state.offset.y;
+    let bottom = (output.inner - output.inner_rect.height()).max(0.0);
+    view.offset = offset;
+
+    // a little of the last page stays on screen
+    let page = output.inner_rect.height() * 0.9;
+    match view.scroll_key.take() {
+        Some(ScrollKey::PageUp) => view.scroll_offset = Some((offset - page).max(0.0)),
+        Some(ScrollKey::PageDown) => view.scroll_offset = Some((offset + page).min(bottom)),
+        Some(ScrollKey::Top) => view.scroll_offset = Some(0.0),
+        Some(ScrollKey::Bottom) if window.newer => view.latest = true,
+        Some(ScrollKey::Bottom) => view.scroll_offset = Some(bottom),
+        None => {}
+    }
+    if view.scroll_offset.is_some() {
+        ui.ctx().request_repaint();
+    }
+
+    // floating over the bottom corner once scrolled away from the newest
+    // message
+    if window.newer || offset < bottom - EDGE_DISTANCE {
+        let rect = egui::Rect::from_min_size(
+            output.inner_rect.right_bottom() - LATEST_BUTTON - egui::vec2(16.0, 8.0),
+            LATEST_BUTTON,
+        );
+        if ui
+            .put(rect, egui::Button::new(i18n::t("jump to latest ↓")))
+            .clicked()
+        {
+            match window.messages.last() {
+                Some(last) if !window.newer => {
+                    window.scroll_to = Some((last.id, egui::Align::Max));
+                    ui.ctx().request_repaint();
+                }
+                _ => view.latest = true,
+            }
+        }
+    }
+
+    if clicked.is_some() {
+        return clicked;
+    }
+    // wait for a scroll to land before looking at where it ended up
+    if scrolling {
+        return None;
+    }
+    if window.older && offset <= EDGE_DISTANCE {
+        Some(Edge::Older)
+    } else if window.newer && offset >= bottom - EDGE_DISTANCE {
+        Some(Edge::Newer)
+    } else {
+        None
+    }
+}
+
+/// Select the message at `i` for a click on it: on its own, or, holding
+/// Cmd, as well as the others or no longer, or, holding shift, along with
+/// everything between it and the message last clicked
+fn select(view: &mut ChatView, messages: &[Message], i: usize, modifiers: egui::Modifiers) {
+    let id = messages[i].id;
+    let anchor = view
+        .anchor
+        .and_then(|anchor| messages.iter().position(|m| m.id == anchor));
+
+    match anchor {
+        Some(anchor) if modifiers.shift => {
+            let range = anchor.min(i)..=anchor.max(i);
+            view.selected.extend(messages[range].iter().map(|m| m.id));
+            // later shift-clicks still count from the same message
+            return;
+        }
+        _ if modifiers.command => {
+            if !view.selected.remove(&id) {
+                view.selected.insert(id);
+            }
+        }
+        // clicking the only selected message again unselects it
+        _ if view.selected.len() == 1 && view.selected.contains(&id) => view.selected.clear(),
+        _ => {
+            view.selected.clear();
+            view.selected.insert(id);
+        }
+    }
+    view.anchor = Some(id);
+}
+
+/// The messages asked to be copied this frame, in the order they were sent
+fn to_copy(view: &mut ChatView, messages: &[Message]) -> Option<Vec<Message>> {
+    if std::mem::take(&mut view.copy_selection) {
+        return Some(
+            messages
+                .iter()
+                .filter(|m| view.selected.contains(&m.id))
+                .cloned()
+                .collect(),
+        );
+    }
+
+    let range = view.copy.take()?;
+    let end = range.end.min(messages.len());
+    Some(messages[range.start.min(end)..end].to_vec())
+}
+
+/// The row at either end of a chat with more history that way: a spinner
+/// while a page is loading, otherwise a button to load one. True if the
+/// button was clicked.
+fn more_row(ui: &mut Ui, loading: bool, label: &str) -> bool {
+    ui.vertical_centered(|ui| {
+        if loading {
+            ui.add(egui::Spinner::new());
+            false
+        } else {
+            ui.button(label).clicked()
+        }
+    })
+    .inner
+}
+
+/// How close to the top or bottom of a chat, in points, the next page starts
+/// loading. Scrolled further up than this, "jump to latest" is shown.
+const EDGE_DISTANCE: f32 = 200.0;
+
+/// Size of the "jump to latest" button
+const LATEST_BUTTON: egui::Vec2 = egui::vec2(120.0, 24.0);
+
+/// How far past the top and bottom of the message view and chat list, in
+/// points, messages and chats are still laid out
+const OVERSCAN: f32 = 400.0;
+
+/// Width and height of someone's picture beside their chats and messages
+const AVATAR_SIZE: f32 = 28.0;
+
+/// Width and height of a sticker stuck onto a bubble
+const STICKER_SIZE: f32 = 56.0;
+
+/// A colour for someone in a group chat, the same every time for the same
+/// handle
+fn sender_color(handle: &str) -> Color32 {
+    let hash = cache::stable_hash(handle.as_bytes());
+    let hue = (hash % 360) as f32 / 360.0;
+
+    egui::color::Hsva::new(hue, 0.55, 0.9, 1.0).into()
+}
+
+/// The caption under a bubble sent with an effect, e.g. "Sent with Slam"
+fn effect_caption(style_id: &str) -> String {
+    let name = match style_id.rsplit('.').next().unwrap_or_default() {
+        "impact" => "Slam",
+        "loud" => "Loud",
+        "gentle" => "Gentle",
+        "invisibleink" => "Invisible Ink",
+        "CKEchoEffect" => "Echo",
+        "CKSpotlightEffect" => "Spotlight",
+        "CKHappyBirthdayEffect" => "Balloons",
+        "CKConfettiEffect" => "Confetti",
+        "CKHeartEffect" => "Love",
+        "CKLasersEffect" => "Lasers",
+        "CKFireworksEffect" => "Fireworks",
+        "CKShootingStarEffect" => "Shooting Star",
+        "CKSparklesEffect" => "Celebration",
+        // newer effects, under names that aren't known yet
+        _ => return i18n::t("Sent with an effect").to_owned(),
+    };
+
+    i18n::tf("Sent with {effect}", &[("effect", &i18n::t(name))])
+}
+
+/// What the Messages app says between two messages sent over different
+/// services, e.g. when iMessage falls back to SMS
+fn service_notice(previous: &str, current: &str) -> Option<String> {
+    if previous.is_empty() || current.is_empty() || previous == current {
+        return None;
+    }
+
+    Some(match current {
+        "SMS" => i18n::t("sent as text message").to_owned(),
+        service => i18n::tf("sent as {service}", &[("service", &service)]),
+    })
+}
+
+/// Draw global search results grouped by chat, returning the chat the user
+/// clicked on (if any), and the message if they clicked on a hit
+fn render_search_results(
+    ui: &mut Ui,
+    identity: &Identity,
+    results: &SearchResults,
+    terms: &Terms,
+) -> Option<(String, Option<i64>)> {
+    puffin::profile_function!();
+
+    let mut clicked = None;
+
+    if let Some((done, total)) = results.progress {
+        ui.label(i18n::tf(
+            "searched {done} of {total} chats...",
+            &[("done", &done), ("total", &total)],
+        ));
+    } else if results.timed_out {
+        ui.label(i18n::t("search took too long, showing partial results"));
+    } else if results.chats.is_empty() {
+        ui.label(i18n::t("no results"));
+        return None;
+    }
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for result in &results.chats {
+            if ui
+                .add(
+                    egui::Label::new(
+                        egui::RichText::new(identity.chat_name(&result.chat, "")).heading(),
+                    )
+                    .sense(egui::Sense::click()),
+                )
+                .clicked()
+            {
+                clicked = Some((result.chat.clone(), None));
+            }
+
+            for msg in &result.messages {
+                let prefix = format!(
+                    "{} {}: ",
+                    msg.date.format("%Y-%m-%d %H:%M"),
+                    identity.name(&msg.sender)
+                );
+                // voice messages match on their transcription rather than
+                // the placeholder text
+                let mut text = &msg.text;
+                let mut ranges = terms.ranges(text);
+                if let Some(transcription) = &msg.transcription {
+                    let transcription_ranges = terms.ranges(transcription);
+                    if ranges.is_empty() && !transcription_ranges.is_empty() {
+                        text = transcription;
+                        ranges = transcription_ranges;
+                    }
+                }
+                let (snippet, ranges) = search::snippet(text, &ranges);
+
+                let mut job = snippet_job(ui, &prefix, &snippet, &ranges);
+                if !msg.attachments.is_empty() {
+                    job.append(
+                        &format!("\n📎 {}", msg.attachments.join(", ")),
+                        0.0,
+                        TextFormat {
+                            font_id: egui::TextStyle::Body.resolve(ui.style()),
+                            color: ui.visuals().weak_text_color(),
+                            ..Default::default()
+                        },
+                    );
+                }
+
+                if ui
+                    .add(egui::Label::new(job).sense(egui::Sense::click()))
+                    .on_hover_cursor(egui::CursorIcon::PointingHand)
+                    .clicked()
+                {
+                    clicked = Some((result.chat.clone(), Some(msg.id)));
+                }
+            }
+
+            ui.separator();
         }
     });
+
+    clicked
+}
+
+/// Lay out a search result line with the matches in `snippet` emphasised
+fn snippet_job(ui: &Ui, prefix: &str, snippet: &str, ranges: &[Range<usize>]) -> LayoutJob {
+    let normal = TextFormat {
+        font_id: egui::TextStyle::Body.resolve(ui.style()),
+        color: ui.visuals().text_color(),
+        ..Default::default()
+    };
+    let strong = TextFormat {
+        color: ui.visuals().strong_text_color(),
+        underline: Stroke::new(1.0, ui.visuals().strong_text_color()),
+        ..normal.clone()
+    };
+
+    let mut job = LayoutJob::default();
+    job.append(prefix, 0.0, normal.clone());
+
+    let mut last = 0;
+    for range in ranges {
+        job.append(&snippet[last..range.start], 0.0, normal.clone());
+        job.append(&snippet[range.clone()], 0.0, strong.clone());
+        last = range.end;
+    }
+    job.append(&snippet[last..], 0.0, normal);
+
+    job
 }
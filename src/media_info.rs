@@ -0,0 +1,434 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    time::Duration,
+};
+
+use eyre::Result;
+use futures_util::TryStreamExt;
+use sqlx::SqlitePool;
+
+use crate::{attachment::expand_path, Handle};
+
+/// Largest `moov` box that will be read into memory
+const MAX_MOOV_LEN: u64 = 64 << 20;
+
+/// Attachments probed at a time, and put on screen once they are
+const BATCH: usize = 50;
+
+/// What's known about an audio or video attachment without playing it
+#[derive(Clone, Default)]
+pub struct MediaInfo {
+    pub duration: Option<Duration>,
+    /// Width and height in pixels, for video
+    pub resolution: Option<(u32, u32)>,
+    /// File size in bytes
+    pub size: Option<u64>,
+}
+
+impl MediaInfo {
+    /// Short labels for each known property, e.g. `0:03`, `1920×1080`,
+    /// `4.2 MB`
+    pub fn badges(&self) -> Vec<String> {
+        let mut badges = Vec::new();
+
+        if let Some(duration) = self.duration {
+            let secs = duration.as_secs();
+            badges.push(if secs >= 3600 {
+                format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+            } else {
+                format!("{}:{:02}", secs / 60, secs % 60)
+            });
+        }
+
+        if let Some((width, height)) = self.resolution {
+            badges.push(format!("{width}×{height}"));
+        }
+
+        if let Some(size) = self.size {
+            badges.push(human_size(size));
+        }
+
+        badges
+    }
+}
+
+/// Metadata for every audio and video attachment in a chat, keyed by the
+/// ROWID of the message it was sent in, put into `handle` a batch at a time.
+/// Files are probed on the blocking thread pool.
+pub async fn chat_media_info(
+    db: &SqlitePool,
+    chat_id: &str,
+    handle: Handle<HashMap<i64, Vec<MediaInfo>>>,
+) -> Result<()> {
+    let mut batches = sqlx::query_as::<_, (i64, String, i64)>(
+        r#"
+            SELECT
+                maj.message_id, a.filename, a.total_bytes
+            from attachment a
+            join message_attachment_join maj
+                on a.ROWID = maj.attachment_id
+            join chat_message_join cmj
+                on maj.message_id = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            where c.chat_identifier=$1
+                and (a.mime_type like 'video/%' or a.mime_type like 'audio/%')
+                and a.filename is not null
+            ;
+        "#,
+    )
+    .bind(chat_id)
+    .fetch(db)
+    .try_chunks(BATCH);
+
+    while let Some(attachments) = batches.try_next().await? {
+        let probed = tokio::task::spawn_blocking(move || {
+            attachments
+                .into_iter()
+                .map(|(message_id, filename, total_bytes)| {
+                    let size = u64::try_from(total_bytes).ok().filter(|&size| size > 0);
+                    (message_id, probe(&expand_path(&filename), size))
+                })
+                .collect::<Vec<_>>()
+        })
+        .await?;
+
+        handle.extend(|info| {
+            for (message_id, probed) in probed {
+                info.entry(message_id).or_default().push(probed);
+            }
+        });
+    }
+    // ready even if there was nothing to probe
+    handle.extend(|_| ());
+
+    Ok(())
+}
+
+/// Read a file's metadata. Only MP4/QuickTime containers (.mov, .mp4, .m4a)
+/// are parsed for duration and resolution; anything else just gets a size.
+pub fn probe(path: &Path, size: Option<u64>) -> MediaInfo {
+    puffin::profile_function!();
+
+    let mut info = MediaInfo {
+        size,
+        ..MediaInfo::default()
+    };
+
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return info,
+    };
+    if info.size.is_none() {
+        info.size = file.metadata().ok().map(|m| m.len());
+    }
+
+    let len = file.metadata().map_or(0, |m| m.len());
+    if let Ok(Some(moov)) = read_moov(&mut file, len) {
+        parse_moov(&moov, &mut info);
+    }
+
+    info
+}
+
+/// Find the top level `moov` box, which may be at either end of a file `len`
+/// bytes long, and return its contents
+fn read_moov(file: &mut (impl Read + Seek), len: u64) -> io::Result<Option<Vec<u8>>> {
+    let mut pos: u64 = 0;
+
+    while pos + 8 <= len {
+        file.seek(SeekFrom::Start(pos))?;
+
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let mut size = u64::from(u32::from_be_bytes(header[..4].try_into().unwrap()));
+        let mut header_len = 8;
+
+        if size == 1 {
+            let mut large = [0; 8];
+            file.read_exact(&mut large)?;
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        } else if size == 0 {
+            size = len - pos;
+        }
+        if size < header_len {
+            return Ok(None);
+        }
+
+        if &header[4..] == b"moov" {
+            let body_len = size - header_len;
+            if body_len > MAX_MOOV_LEN {
+                return Ok(None);
+            }
+
+            let mut body = vec![0; body_len as usize];
+            file.read_exact(&mut body)?;
+            return Ok(Some(body));
+        }
+
+        pos = match pos.checked_add(size) {
+            Some(next) => next,
+            None => return Ok(None),
+        };
+    }
+
+    Ok(None)
+}
+
+/// Iterate over the boxes directly inside `data`, as (type, contents)
+fn boxes(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 8 {
+            return None;
+        }
+
+        let size = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        let (size, header_len) = match size {
+            0 => (data.len(), 8),
+            1 if data.len() >= 16 => (
+                u64::from_be_bytes(data[8..16].try_into().unwrap()) as usize,
+                16,
+            ),
+            _ => (size, 8),
+        };
+        if size < header_len || size > data.len() {
+            return None;
+        }
+
+        let kind = &data[4..8];
+        let body = &data[header_len..size];
+        data = &data[size..];
+
+        Some((kind, body))
+    })
+}
+
+fn parse_moov(moov: &[u8], info: &mut MediaInfo) {
+    for (kind, body) in boxes(moov) {
+        match kind {
+            b"mvhd" => info.duration = movie_duration(body),
+            b"trak" => {
+                let size = boxes(body)
+                    .find(|(kind, _)| kind == b"tkhd")
+                    .and_then(|(_, tkhd)| track_size(tkhd));
+
+                // audio tracks have a zero size, keep the largest video track
+                if let Some((width, height)) = size.filter(|&(w, h)| w > 0 && h > 0) {
+                    let area = |(w, h): (u32, u32)| u64::from(w) * u64::from(h);
+                    if info
+                        .resolution
+                        .is_none_or(|largest| area((width, height)) > area(largest))
+                    {
+                        info.resolution = Some((width, height));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Duration from a movie header (`mvhd`) box
+fn movie_duration(mvhd: &[u8]) -> Option<Duration> {
+    let version = *mvhd.first()?;
+
+    let (timescale, duration) = if version == 1 {
+        (read_u32(mvhd, 20)?, read_u64(mvhd, 24)?)
+    } else {
+        (read_u32(mvhd, 12)?, u64::from(read_u32(mvhd, 16)?))
+    };
+    // all ones means the duration isn't known
+    let unknown = if version == 1 {
+        u64::MAX
+    } else {
+        u64::from(u32::MAX)
+    };
+    if timescale == 0 || duration == unknown {
+        return None;
+    }
+
+    Duration::try_from_secs_f64(duration as f64 / f64::from(timescale)).ok()
+}
+
+/// Presentation width and height from a track header (`tkhd`) box, stored as
+/// 16.16 fixed point
+fn track_size(tkhd: &[u8]) -> Option<(u32, u32)> {
+    let version = *tkhd.first()?;
+    let offset = if version == 1 { 88 } else { 76 };
+
+    Some((
+        read_u32(tkhd, offset)? >> 16,
+        read_u32(tkhd, offset + 4)? >> 16,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+    if bytes < 1000 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// A box of type `kind` around `body`
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = (body.len() as u32 + 8).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(body);
+        data
+    }
+
+    /// A version 0 movie header with `timescale` and `duration`
+    fn mvhd(timescale: u32, duration: u32) -> Vec<u8> {
+        let mut body = vec![0; 100];
+        body[12..16].copy_from_slice(&timescale.to_be_bytes());
+        body[16..20].copy_from_slice(&duration.to_be_bytes());
+        mp4_box(b"mvhd", &body)
+    }
+
+    /// A version 1 movie header, with a 64-bit duration
+    fn mvhd_v1(timescale: u32, duration: u64) -> Vec<u8> {
+        let mut body = vec![0; 112];
+        body[0] = 1;
+        body[20..24].copy_from_slice(&timescale.to_be_bytes());
+        body[24..32].copy_from_slice(&duration.to_be_bytes());
+        mp4_box(b"mvhd", &body)
+    }
+
+    /// A track with a version 0 header for a `width` by `height` picture
+    fn trak(width: u32, height: u32) -> Vec<u8> {
+        let mut tkhd = vec![0; 84];
+        tkhd[76..80].copy_from_slice(&(width << 16).to_be_bytes());
+        tkhd[80..84].copy_from_slice(&(height << 16).to_be_bytes());
+        mp4_box(b"trak", &mp4_box(b"tkhd", &tkhd))
+    }
+
+    fn movie(moov: &[u8]) -> Vec<u8> {
+        let mut file = mp4_box(b"ftyp", b"qt  \0\0\0\0qt  ");
+        file.extend(mp4_box(b"mdat", &[0; 32]));
+        file.extend(mp4_box(b"moov", moov));
+        file
+    }
+
+    fn info(file: &[u8]) -> MediaInfo {
+        let mut info = MediaInfo::default();
+        let moov = read_moov(&mut Cursor::new(file), file.len() as u64).unwrap();
+        parse_moov(&moov.expect("no moov"), &mut info);
+        info
+    }
+
+    #[test]
+    fn duration_and_largest_video_track() {
+        let mut moov = mvhd(600, 1800);
+        moov.extend(trak(0, 0));
+        moov.extend(trak(1920, 1080));
+        moov.extend(trak(640, 480));
+
+        let info = info(&movie(&moov));
+        assert_eq!(info.duration, Some(Duration::from_secs(3)));
+        assert_eq!(info.resolution, Some((1920, 1080)));
+    }
+
+    #[test]
+    fn version_1_durations() {
+        assert_eq!(
+            info(&movie(&mvhd_v1(1000, 90_500))).duration,
+            Some(Duration::from_millis(90_500))
+        );
+    }
+
+    #[test]
+    fn unknown_and_impossible_durations() {
+        assert_eq!(info(&movie(&mvhd_v1(1, u64::MAX))).duration, None);
+        assert_eq!(info(&movie(&mvhd_v1(1, u64::MAX - 1))).duration, None);
+        assert_eq!(info(&movie(&mvhd(600, u32::MAX))).duration, None);
+        assert_eq!(info(&movie(&mvhd(0, 600))).duration, None);
+    }
+
+    #[test]
+    fn truncated_boxes() {
+        let mut file = movie(&mvhd(600, 1800));
+        file.truncate(file.len() - 10);
+        assert!(read_moov(&mut Cursor::new(&file), file.len() as u64).is_err());
+
+        // a header cut short is ignored rather than read past
+        let mut info = MediaInfo::default();
+        parse_moov(&mvhd(600, 1800)[..50], &mut info);
+        assert_eq!(info.duration, None);
+        parse_moov(&mp4_box(b"mvhd", &[0; 8]), &mut info);
+        assert_eq!(info.duration, None);
+    }
+
+    #[test]
+    fn oversized_boxes() {
+        // a box claiming to run past the end of the file
+        let mut file = mp4_box(b"ftyp", b"qt  ");
+        let mut huge = mp4_box(b"mdat", &[0; 8]);
+        huge[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        file.extend(huge);
+        assert_eq!(
+            read_moov(&mut Cursor::new(&file), file.len() as u64).unwrap(),
+            None
+        );
+
+        // a 64-bit size that would wrap around the position
+        let mut file = mp4_box(b"ftyp", b"qt  ");
+        file.extend(1u32.to_be_bytes());
+        file.extend(b"mdat");
+        file.extend(u64::MAX.to_be_bytes());
+        file.extend(mp4_box(b"moov", &mvhd(600, 1800)));
+        assert_eq!(
+            read_moov(&mut Cursor::new(&file), file.len() as u64).unwrap(),
+            None
+        );
+
+        // smaller than its own header
+        let mut file = mp4_box(b"ftyp", b"qt  ");
+        file.extend(4u32.to_be_bytes());
+        file.extend(b"mdat");
+        assert_eq!(
+            read_moov(&mut Cursor::new(&file), file.len() as u64).unwrap(),
+            None
+        );
+
+        // inside the moov, a child too big for its parent stops the walk
+        let mut moov = mvhd(600, 1800);
+        let mut trak = trak(1920, 1080);
+        trak[..4].copy_from_slice(&1000u32.to_be_bytes());
+        moov.extend(trak);
+        let info = info(&movie(&moov));
+        assert_eq!(info.duration, Some(Duration::from_secs(3)));
+        assert_eq!(info.resolution, None);
+    }
+}
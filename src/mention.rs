@@ -0,0 +1,232 @@
+use std::{collections::HashMap, ops::Range};
+
+use eyre::{eyre, Result};
+use sqlx::SqlitePool;
+
+use crate::{
+    config::Identity,
+    typedstream::{self, Value},
+    Sender,
+};
+
+/// Attribute set on the part of an attributed body that mentions someone,
+/// with their handle as its value
+const MENTION: &str = "__kIMMentionConfirmedMention";
+
+/// Someone @mentioned in a message, and where in its text their name is
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mention {
+    /// Byte range in the message's text
+    pub range: Range<usize>,
+    pub handle: String,
+}
+
+/// The mentions in an `attributedBody`: each mentioned name as written in
+/// the message, and the handle it mentions, in order
+pub fn decode(body: &[u8]) -> Result<Vec<(String, String)>> {
+    let stream = typedstream::read(body)?;
+    let string = stream
+        .groups
+        .first()
+        .and_then(|group| group.first())
+        .and_then(|value| stream.object(value))
+        .ok_or_else(|| eyre!("attributedBody isn't an attributed string"))?;
+
+    let mut groups = string.groups.iter();
+    let text = groups
+        .next()
+        .and_then(|group| stream.string(group.first()?))
+        .ok_or_else(|| eyre!("attributedBody has no text"))?;
+    let text: Vec<u16> = text.encode_utf16().collect();
+
+    // the string is followed by runs of (attributes id, length in UTF-16
+    // units), each with its attributes the first time they're used
+    let mut attributes = HashMap::new();
+    let mut runs = Vec::new();
+    let mut last = None;
+    for group in groups {
+        match group.as_slice() {
+            [Value::Int(id), Value::Int(len)] => {
+                let len = usize::try_from(*len)
+                    .map_err(|_| eyre!("attributedBody has a run of length {len}"))?;
+                runs.push((*id, len));
+                last = Some(*id);
+            }
+            [dictionary @ Value::Object(_)] => {
+                if let Some(id) = last {
+                    attributes.insert(id, dictionary);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut mentions = Vec::new();
+    let mut start = 0usize;
+    for (id, len) in runs {
+        let end = start.saturating_add(len).min(text.len());
+        let handle = attributes
+            .get(&id)
+            .and_then(|dictionary| stream.object(dictionary))
+            .and_then(|dictionary| {
+                // a count, then alternating keys and values
+                let entries: Vec<_> = dictionary.groups.iter().skip(1).flatten().collect();
+                entries.chunks(2).find_map(|entry| match entry {
+                    [key, value] if stream.string(key).as_deref() == Some(MENTION) => {
+                        stream.string(value)
+                    }
+                    _ => None,
+                })
+            });
+        if let Some(handle) = handle {
+            mentions.push((String::from_utf16_lossy(&text[start..end]), handle));
+        }
+        start = end;
+    }
+
+    Ok(mentions)
+}
+
+/// Find each mentioned name in `text`, which may have been trimmed since it
+/// was written, in order
+pub fn locate(text: &str, mentions: &[(String, String)]) -> Vec<Mention> {
+    let mut from = 0;
+    mentions
+        .iter()
+        .filter_map(|(name, handle)| {
+            let start = from + text[from..].find(name.as_str())?;
+            from = start + name.len();
+            Some(Mention {
+                range: start..from,
+                handle: handle.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Whether any of `mentions` is of me. Mentions of me are of whichever of my
+/// handles the sender knows me by, which the database doesn't list, so
+/// that's any handle that isn't one of the chat's other `members`.
+pub fn mentions_me(identity: &Identity, members: &[String], mentions: &[Mention]) -> bool {
+    mentions.iter().any(|mention| {
+        identity.is_me(&Sender::SomeoneElse(mention.handle.clone()))
+            || (identity.is_me(&Sender::Me) && !members.contains(&mention.handle))
+    })
+}
+
+/// The mentions in each message of a chat that has any, by message id.
+/// Messages whose `attributedBody` can't be read are left out.
+pub async fn chat_mentions(
+    db: &SqlitePool,
+    chat_id: &str,
+) -> Result<HashMap<i64, Vec<(String, String)>>> {
+    let rows = sqlx::query_as::<_, (i64, Vec<u8>)>(
+        r#"
+            SELECT
+                m.ROWID, m.attributedBody
+            from message m
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            where c.chat_identifier=$1
+                and instr(m.attributedBody, cast($2 as blob)) > 0
+            ;
+        "#,
+    )
+    .bind(chat_id)
+    .bind(MENTION)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(id, body)| Some((id, decode(&body).ok()?)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A typedstream's `@` group holding a string object of `class` (a
+    /// reference to a class written earlier) with the bytes of `text`,
+    /// using the `+` encoding written earlier
+    fn string(class: u8, text: &str) -> Vec<u8> {
+        let mut data = vec![0x92, 0x84, class, 0x96, text.len() as u8];
+        data.extend_from_slice(text.as_bytes());
+        data.push(0x86);
+        data
+    }
+
+    /// The `attributedBody` of "hi @Bob", with "@Bob" mentioning `handle`
+    /// and then running for `len` UTF-16 units
+    fn body(handle: &str, len: u8) -> Vec<u8> {
+        let mut data = b"\x04\x0bstreamtyped\x81\xe8\x03".to_vec();
+        // the attributed string, a subclass of NSObject
+        data.extend_from_slice(b"\x84\x01@\x84\x84\x84\x12NSAttributedString\x00");
+        data.extend_from_slice(b"\x84\x84\x08NSObject\x00\x85");
+        // its text
+        data.extend_from_slice(b"\x92\x84\x84\x84\x08NSString\x01\x94");
+        data.extend_from_slice(b"\x84\x01+\x07hi @Bob\x86");
+        // "hi " with no attributes of note
+        data.extend_from_slice(b"\x84\x02ii\x01\x03");
+        data.extend_from_slice(b"\x92\x84\x84\x84\x0cNSDictionary\x00\x94\x84\x01i\x00\x86");
+        // "@Bob", with the mention attribute
+        data.extend_from_slice(&[0x97, 0x02, len]);
+        data.extend_from_slice(b"\x92\x84\x98\x99\x01");
+        data.extend(string(0x96, MENTION));
+        data.extend(string(0x96, handle));
+        data.extend_from_slice(b"\x86\x86");
+        data
+    }
+
+    #[test]
+    fn decodes_mentions() {
+        assert_eq!(
+            decode(&body("+15555550100", 4)).unwrap(),
+            vec![("@Bob".to_owned(), "+15555550100".to_owned())]
+        );
+    }
+
+    #[test]
+    fn runs_past_the_text() {
+        // cut off at the end of the text
+        assert_eq!(
+            decode(&body("+15555550100", 100)).unwrap(),
+            vec![("@Bob".to_owned(), "+15555550100".to_owned())]
+        );
+        // a negative length
+        assert!(decode(&body("+15555550100", 0xff)).is_err());
+    }
+
+    #[test]
+    fn not_an_attributed_string() {
+        assert!(decode(b"").is_err());
+        assert!(decode(b"\x04\x0bstreamtyped\x81\xe8\x03").is_err());
+        assert!(decode(b"\x04\x0bstreamtyped\x81\xe8\x03\x84\x01i\x05").is_err());
+    }
+
+    #[test]
+    fn locates_names_in_order() {
+        let mentions = vec![
+            ("@Bob".to_owned(), "bob".to_owned()),
+            ("@Bob".to_owned(), "bob2".to_owned()),
+            ("@Eve".to_owned(), "eve".to_owned()),
+        ];
+        let found = locate("@Bob and @Bob", &mentions);
+        assert_eq!(
+            found,
+            vec![
+                Mention {
+                    range: 0..4,
+                    handle: "bob".to_owned()
+                },
+                Mention {
+                    range: 9..13,
+                    handle: "bob2".to_owned()
+                },
+            ]
+        );
+    }
+}
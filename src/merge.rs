@@ -0,0 +1,257 @@
+use std::path::Path;
+
+use chrono::prelude::*;
+use eyre::Result;
+
+use crate::{audit, i18n, schema, time};
+
+/// How many messages merging would add to one chat
+#[derive(Clone)]
+pub struct ChatPreview {
+    pub chat: String,
+    pub display_name: String,
+    pub new: usize,
+}
+
+/// A message that is in both databases with different text, usually because
+/// it was edited after one of them was copied
+#[derive(Clone)]
+pub struct Conflict {
+    pub guid: String,
+    pub chat: String,
+    pub date: DateTime<Utc>,
+    pub current: String,
+    pub incoming: String,
+}
+
+/// The difference between the open database and another copy of it
+#[derive(Clone)]
+pub struct Preview {
+    pub source: String,
+    pub chats: Vec<ChatPreview>,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Preview {
+    /// Messages only in the other copy, and messages in both with different
+    /// text
+    pub fn totals(&self) -> (usize, usize) {
+        let new = self.chats.iter().map(|c| c.new).sum::<usize>();
+
+        (new, self.conflicts.len())
+    }
+}
+
+/// Compare the open database at `current` with `incoming` (an older backup,
+/// or a copy from another Mac) by message GUID. Nothing is written to either.
+pub async fn preview(current: &str, incoming: &Path) -> Result<Preview> {
+    let source = incoming.to_string_lossy().into_owned();
+    // no authorizer, as it refuses `ATTACH`. Both files are still read-only:
+    // attached databases are opened with the same flags as the main one.
+    let other = audit::connect(&source, audit::Audit::default(), audit::Pool::Bulk).await?;
+    schema::check(&other).await?;
+
+    let rows: Vec<(String, String, i64, String, String, Option<String>)> = {
+        let mut conn = other.acquire().await?;
+        sqlx::query("ATTACH DATABASE $1 AS viewed;")
+            .bind(current)
+            .execute(&mut conn)
+            .await?;
+
+        // the object replacement character is stripped when displaying, so
+        // don't call a difference in it a conflict
+        sqlx::query_as(
+            r#"
+                SELECT
+                    m.guid,
+                    coalesce(m.text, ''),
+                    m.date,
+                    c.chat_identifier,
+                    coalesce(c.display_name, ''),
+                    iif(v.ROWID is null, null, coalesce(v.text, ''))
+                from message as m
+                inner join chat_message_join as cmj on cmj.message_id = m.ROWID
+                inner join chat as c on c.ROWID = cmj.chat_id
+                left join viewed.message as v on v.guid = m.guid
+                where v.ROWID is null
+                    or replace(coalesce(v.text, ''), char(65532), '')
+                        != replace(coalesce(m.text, ''), char(65532), '')
+                order by c.chat_identifier, m.date
+                ;
+            "#,
+        )
+        .fetch_all(&mut conn)
+        .await?
+    };
+    other.close().await;
+
+    let mut chats: Vec<ChatPreview> = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for (guid, text, date, chat, display_name, existing) in rows {
+        match existing {
+            None => match chats.last_mut() {
+                Some(last) if last.chat == chat => last.new += 1,
+                _ => chats.push(ChatPreview {
+                    chat,
+                    display_name,
+                    new: 1,
+                }),
+            },
+            Some(existing) => conflicts.push(Conflict {
+                guid,
+                chat,
+                date: time(date),
+                current: existing,
+                incoming: text,
+            }),
+        }
+    }
+
+    chats.sort_by_key(|c| std::cmp::Reverse(c.new));
+
+    Ok(Preview {
+        source,
+        chats,
+        conflicts,
+    })
+}
+
+/// Window listing what a merge would add per chat and the messages whose text
+/// differs between the two copies
+pub fn show(ctx: &egui::Context, preview: &Preview, open: &mut bool) {
+    egui::Window::new(i18n::t("merge preview"))
+        .id(egui::Id::new("merge preview"))
+        .open(open)
+        .default_width(520.0)
+        .show(ctx, |ui| {
+            ui.label(i18n::tf(
+                "comparing with {path}",
+                &[("path", &preview.source)],
+            ));
+
+            let (added, differing) = preview.totals();
+            ui.label(i18n::tf(
+                "merging would add {added} messages, and {differing} have different text",
+                &[("added", &added), ("differing", &differing)],
+            ));
+            ui.separator();
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::CollapsingHeader::new(i18n::tf(
+                    "new messages ({count} chats)",
+                    &[("count", &preview.chats.len())],
+                ))
+                .default_open(true)
+                .show(ui, |ui| {
+                    egui::Grid::new("merge_chats").striped(true).show(ui, |ui| {
+                        for chat in &preview.chats {
+                            if chat.display_name.is_empty() {
+                                ui.label(&chat.chat);
+                            } else {
+                                ui.label(&chat.display_name);
+                            }
+                            ui.label(format!("+{}", chat.new));
+                            ui.end_row();
+                        }
+                    });
+                });
+
+                egui::CollapsingHeader::new(i18n::tf(
+                    "conflicts ({count})",
+                    &[("count", &preview.conflicts.len())],
+                ))
+                .default_open(true)
+                .show(ui, |ui| {
+                    if preview.conflicts.is_empty() {
+                        ui.label(i18n::t("none"));
+                        return;
+                    }
+
+                    for conflict in &preview.conflicts {
+                        ui.separator();
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "{} {}",
+                                conflict.chat,
+                                conflict.date.format("%Y-%m-%d %H:%M")
+                            ))
+                            .small(),
+                        )
+                        .on_hover_text(&conflict.guid);
+                        ui.label(i18n::tf("current: {text}", &[("text", &conflict.current)]));
+                        ui.label(i18n::tf(
+                            "incoming: {text}",
+                            &[("text", &conflict.incoming)],
+                        ));
+                    }
+                });
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Demo;
+
+    #[tokio::test]
+    async fn previews_new_and_edited_messages() {
+        let (current, incoming) = (Demo::generate().await, Demo::generate().await);
+
+        let other = incoming.writable().await;
+        let (edited, attached): (String, String) = sqlx::query_as(
+            r#"
+                SELECT min(guid), max(guid) from (
+                    SELECT guid from message where text is not null order by ROWID limit 2
+                );
+            "#,
+        )
+        .fetch_one(&other)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"
+                UPDATE message set text = 'edited' where guid = $1;
+                UPDATE message set text = text || char(65532) where guid = $2;
+                INSERT INTO message (ROWID, guid, text, date) VALUES
+                    (10001, 'new-1', 'one', 1), (10002, 'new-2', 'two', 2), (10003, 'new-3', 'three', 3);
+                INSERT INTO chat_message_join (chat_id, message_id, message_date)
+                SELECT c.ROWID, m.ROWID, m.date
+                from chat c, message m
+                where m.guid like 'new-%'
+                    and c.guid = iif(
+                        m.guid = 'new-1',
+                        'iMessage;-;+15555550100',
+                        'iMessage;+;chat100'
+                    );
+            "#,
+        )
+        .bind(&edited)
+        .bind(&attached)
+        .execute(&other)
+        .await
+        .unwrap();
+        other.close().await;
+
+        let preview = preview(current.path.to_str().unwrap(), &incoming.path)
+            .await
+            .unwrap();
+
+        let chats: Vec<_> = preview
+            .chats
+            .iter()
+            .map(|c| (c.chat.as_str(), c.display_name.as_str(), c.new))
+            .collect();
+        assert_eq!(
+            chats,
+            [("chat100", "Demo Ski Trip", 2), ("+15555550100", "", 1)]
+        );
+        assert_eq!(preview.conflicts.len(), 1);
+        assert_eq!(preview.conflicts[0].guid, edited);
+        assert_eq!(preview.conflicts[0].incoming, "edited");
+        assert_ne!(preview.conflicts[0].current, "edited");
+
+        assert_eq!(preview.totals(), (3, 1));
+    }
+}
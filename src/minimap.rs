@@ -0,0 +1,225 @@
+use chrono::prelude::*;
+use egui::{Align2, Rect, Sense, Stroke, Ui};
+use eyre::Result;
+use sqlx::SqlitePool;
+
+use crate::{sql::Sql, theme, APPLE_EPOCH};
+
+/// Width of the strip beside the messages
+const WIDTH: f32 = 14.0;
+
+/// Messages per month in a chat, from the month of its first message to the
+/// month of its last, including the empty months in between
+pub struct Density {
+    /// First day of the first month
+    pub first: NaiveDate,
+    pub counts: Vec<u32>,
+}
+
+impl Density {
+    /// The first day of the `index`th month
+    fn month(&self, index: usize) -> NaiveDate {
+        let index = self.first.year() * 12 + self.first.month0() as i32 + index as i32;
+        NaiveDate::from_ymd(index.div_euclid(12), index.rem_euclid(12) as u32 + 1, 1)
+    }
+
+    /// How many months after the first `date` is
+    fn offset(&self, date: NaiveDate) -> i32 {
+        (date.year() - self.first.year()) * 12 + date.month0() as i32 - self.first.month0() as i32
+    }
+
+    /// Which month `date` falls in, clamped to the ones covered
+    fn index(&self, date: NaiveDate) -> usize {
+        (self.offset(date).max(0) as usize).min(self.counts.len().saturating_sub(1))
+    }
+}
+
+/// Count the messages in each month of `chats`, the chat and any shown along
+/// with it
+pub async fn load(db: &SqlitePool, chats: &[String]) -> Result<Density> {
+    let mut sql = Sql::new(
+        r#"
+            SELECT
+                strftime('%Y-%m-01', cmj.message_date / 1000000000 + "#,
+    );
+    sql.bind(APPLE_EPOCH).push(
+        r#", 'unixepoch') as month, count(*)
+            from chat_message_join cmj
+            join chat c
+                on cmj.chat_id = c.ROWID
+            where c.chat_identifier in ("#,
+    );
+    for (i, chat) in chats.iter().enumerate() {
+        if i > 0 {
+            sql.push(", ");
+        }
+        sql.bind(chat.clone());
+    }
+    sql.push(") group by month order by month;");
+
+    let rows = sqlx::query_as_with::<_, (String, i64), _>(&sql.text, sql.args)
+        .fetch_all(db)
+        .await?;
+
+    let mut density = Density {
+        first: NaiveDate::from_ymd(2001, 1, 1),
+        counts: Vec::new(),
+    };
+    for (i, (month, count)) in rows.into_iter().enumerate() {
+        let month = NaiveDate::parse_from_str(&month, "%Y-%m-%d")?;
+        if i == 0 {
+            density.first = month;
+        }
+        // rows come in order, so this only ever adds months on the end
+        let index = density.offset(month) as usize;
+        density.counts.resize(index + 1, 0);
+        density.counts[index] = count as u32;
+    }
+
+    Ok(density)
+}
+
+/// Split the space for a chat's messages into the messages and the strip
+/// beside them
+pub fn split(rect: Rect, spacing: f32) -> (Rect, Rect) {
+    let messages = Rect::from_min_max(
+        rect.min,
+        egui::pos2(rect.right() - WIDTH - spacing, rect.bottom()),
+    );
+    let strip = Rect::from_min_max(egui::pos2(rect.right() - WIDTH, rect.top()), rect.max);
+
+    (messages, strip)
+}
+
+/// Draw how busy each month of the chat was as a strip, oldest at the top,
+/// with the loaded messages (between the dates in `loaded`) outlined. Returns
+/// the month clicked, or let go of after dragging along the strip.
+pub fn show(
+    ui: &mut Ui,
+    rect: Rect,
+    density: &Density,
+    loaded: Option<(DateTime<Utc>, DateTime<Utc>)>,
+) -> Option<NaiveDate> {
+    if density.counts.is_empty() {
+        return None;
+    }
+
+    let response = ui.interact(rect, ui.id().with("minimap"), Sense::click_and_drag());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+    let months = density.counts.len();
+    let row = rect.height() / months as f32;
+    let max = density.counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+    let color = ui.visuals().widgets.inactive.fg_stroke.color;
+    for (i, count) in density.counts.iter().enumerate() {
+        if *count == 0 {
+            continue;
+        }
+        // square root, so quiet months still show up next to busy ones
+        let width = (*count as f32 / max).sqrt() * rect.width();
+        let top = rect.top() + i as f32 * row;
+        let bar = Rect::from_min_max(
+            egui::pos2(rect.center().x - width / 2.0, top),
+            egui::pos2(rect.center().x + width / 2.0, top + row.max(1.0)),
+        );
+        painter.rect_filled(bar, 0.0, color);
+    }
+
+    if let Some((first, last)) = loaded {
+        let top = rect.top() + density.index(first.date_naive()) as f32 * row;
+        let bottom = rect.top() + (density.index(last.date_naive()) + 1) as f32 * row;
+        painter.rect_stroke(
+            Rect::from_min_max(
+                egui::pos2(rect.left(), top),
+                egui::pos2(rect.right(), bottom),
+            ),
+            2.0,
+            Stroke::new(1.0, theme::Colors::of(ui).highlight),
+        );
+    }
+
+    let at = |y: f32| ((y - rect.top()) / row).max(0.0) as usize;
+    let pointer = response
+        .interact_pointer_pos()
+        .or_else(|| response.hover_pos());
+    if let Some(pointer) = pointer {
+        let i = at(pointer.y).min(months - 1);
+        let y = pointer.y.clamp(rect.top(), rect.bottom());
+        painter.hline(
+            rect.x_range(),
+            y,
+            Stroke::new(2.0, ui.visuals().strong_text_color()),
+        );
+        // to the left, over the messages, since the strip is too thin
+        ui.painter().text(
+            egui::pos2(rect.left() - 6.0, y),
+            Align2::RIGHT_CENTER,
+            format!(
+                "{} · {} messages",
+                density.month(i).format("%B %Y"),
+                density.counts[i]
+            ),
+            egui::TextStyle::Small.resolve(ui.style()),
+            ui.visuals().strong_text_color(),
+        );
+    }
+
+    if response.clicked() || response.drag_released() {
+        return pointer.map(|pointer| density.month(at(pointer.y).min(months - 1)));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apple_time, test_util};
+
+    #[tokio::test]
+    async fn counts_every_month_between_the_first_and_last() {
+        let db = test_util::memory().await;
+        sqlx::query(
+            r#"
+                CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, chat_identifier TEXT);
+                CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER, message_date INTEGER);
+                INSERT INTO chat VALUES (1, 'ana'), (2, 'ana-old'), (3, 'bo');
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        for (chat, date) in [
+            (1, Utc.ymd(2021, 11, 30).and_hms(23, 59, 59)),
+            (1, Utc.ymd(2021, 11, 1).and_hms(0, 0, 0)),
+            (2, Utc.ymd(2022, 2, 14).and_hms(12, 0, 0)),
+            (1, Utc.ymd(2022, 2, 28).and_hms(8, 0, 0)),
+            (3, Utc.ymd(2020, 1, 1).and_hms(0, 0, 0)),
+        ] {
+            sqlx::query("INSERT INTO chat_message_join (chat_id, message_date) values ($1, $2);")
+                .bind(chat)
+                .bind(apple_time(date))
+                .execute(&db)
+                .await
+                .unwrap();
+        }
+
+        let chats = ["ana".to_owned(), "ana-old".to_owned()];
+        let density = load(&db, &chats).await.unwrap();
+        assert_eq!(density.first, NaiveDate::from_ymd(2021, 11, 1));
+        assert_eq!(density.counts, [2, 0, 0, 2]);
+
+        assert_eq!(density.month(2), NaiveDate::from_ymd(2022, 1, 1));
+        assert_eq!(density.index(NaiveDate::from_ymd(2022, 2, 5)), 3);
+        // clamped to the months there are
+        assert_eq!(density.index(NaiveDate::from_ymd(2019, 6, 1)), 0);
+        assert_eq!(density.index(NaiveDate::from_ymd(2030, 6, 1)), 3);
+
+        assert!(load(&db, &["nobody".to_owned()])
+            .await
+            .unwrap()
+            .counts
+            .is_empty());
+    }
+}
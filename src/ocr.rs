@@ -0,0 +1,190 @@
+use std::{collections::HashSet, io::ErrorKind, path::Path, process::Stdio};
+
+use eyre::Result;
+use sqlx::SqlitePool;
+use tokio::process::Command;
+
+use crate::{attachment::expand_path, heic};
+
+/// Recognise text in every image attachment that hasn't been looked at yet
+/// and add it to the search index, so that screenshots and photos of text
+/// turn up in searches. Images are processed one at a time with tesseract,
+/// and this gives up quietly if it isn't installed. Expects the index to
+/// have been built already.
+pub async fn run(db: &SqlitePool, cache: &SqlitePool) -> Result<()> {
+    let done: HashSet<i64> =
+        sqlx::query_as::<_, (i64,)>("SELECT attachment_id from attachment_ocr;")
+            .fetch_all(cache)
+            .await?
+            .into_iter()
+            .map(|(id,)| id)
+            .collect();
+
+    let images = sqlx::query_as::<_, (i64, i64, String)>(
+        r#"
+            SELECT
+                a.ROWID, maj.message_id, a.filename
+            from attachment a
+            join message_attachment_join maj
+                on a.ROWID = maj.attachment_id
+            where a.mime_type like 'image/%'
+                and a.filename is not null
+            order by a.ROWID
+            ;
+        "#,
+    )
+    .fetch_all(db)
+    .await?;
+
+    for (attachment_id, message_id, filename) in images {
+        if done.contains(&attachment_id) {
+            continue;
+        }
+
+        // tesseract can't read HEIC, leave those for next launch if they
+        // can't be converted
+        let path = expand_path(&filename);
+        let path = match tokio::task::spawn_blocking(move || heic::readable(&path)).await? {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!("{e}");
+                continue;
+            }
+        };
+
+        let text = match recognize(&path).await {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                eprintln!("tesseract not found, photos won't be searchable");
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        save(cache, attachment_id, message_id, &text).await?;
+    }
+
+    Ok(())
+}
+
+/// Keep the text recognised in an attachment, and add it to its message in
+/// the search index along with the text of its other attachments
+async fn save(cache: &SqlitePool, attachment_id: i64, message_id: i64, text: &str) -> Result<()> {
+    let mut tx = cache.begin().await?;
+    sqlx::query(
+        r#"
+            INSERT INTO attachment_ocr (attachment_id, message_id, text)
+            values ($1, $2, $3)
+            ;
+        "#,
+    )
+    .bind(attachment_id)
+    .bind(message_id)
+    .bind(text)
+    .execute(&mut tx)
+    .await?;
+    sqlx::query(
+        r#"
+            UPDATE message_fts
+            set
+                ocr = o.text,
+                folded = fold(
+                    message_fts.text || ' '
+                    || coalesce(message_fts.transcription, '') || ' '
+                    || o.text
+                )
+            from (
+                select group_concat(text, ' ') as text
+                from attachment_ocr
+                where message_id = $1
+            ) o
+            where rowid = $1
+            ;
+        "#,
+    )
+    .bind(message_id)
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Text in an image, on a single line. Images tesseract can't read (missing
+/// files, unsupported formats) come back empty so they aren't retried on
+/// every launch.
+async fn recognize(path: &Path) -> std::io::Result<String> {
+    let output = Command::new("tesseract")
+        .arg(path)
+        .arg("stdout")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Ok(String::new());
+    }
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    Ok(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        search::{self, Query},
+        test_util::{self, Demo},
+    };
+
+    #[tokio::test]
+    async fn recognised_text_is_searchable() {
+        let demo = Demo::generate().await;
+        let db = demo.connect().await;
+        let cache = test_util::cache().await;
+        search::build_index(&db, &cache).await.unwrap();
+
+        let (message_id,): (i64,) = sqlx::query_as(
+            r#"
+                SELECT min(maj.message_id)
+                from message_attachment_join maj
+                join attachment a
+                    on a.ROWID = maj.attachment_id
+                where a.mime_type like 'image/%'
+            "#,
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        let found = |input: &'static str| {
+            let cache = cache.clone();
+            async move {
+                let query = Query::parse(input).unwrap();
+                search::search(&cache, &query)
+                    .await
+                    .unwrap()
+                    .chats
+                    .iter()
+                    .flat_map(|chat| chat.messages.iter().map(|m| m.id))
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert!(found("Zürich").await.is_empty());
+
+        save(&cache, 9001, message_id, "Zürich Hauptbahnhof")
+            .await
+            .unwrap();
+        save(&cache, 9002, message_id, "platform 7").await.unwrap();
+        assert_eq!(found("zurich").await, [message_id]);
+        assert_eq!(found("platform").await, [message_id]);
+
+        // and it survives rebuilding the index
+        sqlx::query("DROP TABLE message_fts;")
+            .execute(&cache)
+            .await
+            .unwrap();
+        search::build_index(&db, &cache).await.unwrap();
+        assert_eq!(found("hauptbahnhof").await, [message_id]);
+    }
+}
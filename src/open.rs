@@ -0,0 +1,101 @@
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use eyre::{eyre, Result};
+
+/// Where the message database is inside the folders people open: a copy of
+/// `~/Library`, of `~/Library/Messages`, or an iPhone backup made by Finder
+/// or iTunes (where it's named after the hash of `HomeDomain-Library/SMS/sms.db`)
+const DATABASES: &[&str] = &[
+    "chat.db",
+    "Messages/chat.db",
+    "Library/Messages/chat.db",
+    "3d/3d0d7e5fb2ce288813306e4d4636395e047a3d28",
+];
+
+/// The command line, without the `-psn_...` process serial number that
+/// older versions of macOS add when an app bundle is opened from Finder
+pub fn args() -> Vec<OsString> {
+    std::env::args_os()
+        .filter(|arg| !arg.to_string_lossy().starts_with("-psn_"))
+        .collect()
+}
+
+/// The database to open for something the viewer was given: the file
+/// itself, or the database inside a folder. For a folder, also returns its
+/// `Attachments` folder, if there's one next to the database.
+pub fn resolve(path: &Path) -> Result<(PathBuf, Option<PathBuf>)> {
+    if !path.is_dir() {
+        return Ok((path.to_owned(), None));
+    }
+
+    let database = DATABASES
+        .iter()
+        .map(|candidate| path.join(candidate))
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| eyre!("couldn't find a message database in {}", path.display()))?;
+    let attachments = database
+        .parent()
+        .map(|dir| dir.join("Attachments"))
+        .filter(|dir| dir.is_dir());
+
+    Ok((database, attachments))
+}
+
+/// Open a database (or a folder with one in it) in a new viewer window,
+/// e.g. one dropped onto this one
+pub fn in_new_window(path: &Path) -> io::Result<()> {
+    Command::new(std::env::current_exe()?)
+        .arg(path)
+        .stdin(Stdio::null())
+        .spawn()
+        .map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn finds_the_database_in_a_folder() {
+        let dir = std::env::temp_dir().join(format!("imessage-viewer-open-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        // a copy of ~/Library, with its attachments
+        let library = dir.join("Library");
+        fs::create_dir_all(library.join("Messages/Attachments")).unwrap();
+        fs::write(library.join("Messages/chat.db"), "").unwrap();
+        assert_eq!(
+            resolve(&library).unwrap(),
+            (
+                library.join("Messages/chat.db"),
+                Some(library.join("Messages/Attachments"))
+            )
+        );
+
+        // an iPhone backup
+        let backup = dir.join("backup");
+        fs::create_dir_all(backup.join("3d")).unwrap();
+        let sms = backup.join("3d/3d0d7e5fb2ce288813306e4d4636395e047a3d28");
+        fs::write(&sms, "").unwrap();
+        assert_eq!(resolve(&backup).unwrap(), (sms.clone(), None));
+
+        // files are opened as they are, whatever they're called
+        assert_eq!(resolve(&sms).unwrap(), (sms, None));
+
+        let empty = dir.join("empty");
+        fs::create_dir_all(&empty).unwrap();
+        assert!(resolve(&empty)
+            .unwrap_err()
+            .to_string()
+            .starts_with("couldn't find a message database"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
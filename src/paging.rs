@@ -0,0 +1,776 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use egui::Align;
+use eyre::Result;
+use futures_util::TryStreamExt;
+use sqlx::SqlitePool;
+
+use crate::{
+    associated_target, attachment, balloon,
+    config::{HandleChange, IgnoreAction},
+    continuity, event, ignore, location, mention, place_associated,
+    sql::Sql,
+    tapback, time, vcard, Handle, Message, Sender, State, STICKER,
+};
+
+/// Messages fetched at a time
+const PAGE: i64 = 200;
+
+/// Messages of a page put on screen at a time as it's read, enough to fill
+/// the screen
+const BATCH: usize = 50;
+
+/// Where a message sits in a chat: its date and ROWID. Ordering by both keeps
+/// messages sent in the same instant in a stable order, so pages can pick up
+/// exactly where the last one stopped. Pages are found with
+/// `chat_message_join.message_date`, which Messages keeps equal to
+/// `message.date` and indexes by chat.
+type Position = (i64, i64);
+
+/// Which messages of a chat to load
+#[derive(Clone, Copy)]
+pub enum Page {
+    /// The most recent messages, scrolled to the bottom
+    Newest,
+    /// Messages either side of this message, e.g. one clicked in search
+    Around(i64),
+    /// Messages either side of this date (in Apple's format), scrolled to
+    /// the first message on or after it
+    AroundDate(i64),
+}
+
+/// More history to load, from scrolling to the top or bottom of the chat
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Older,
+    Newer,
+}
+
+/// The loaded part of a chat: a run of consecutive messages, grown a page at
+/// a time in either direction as it's scrolled
+#[derive(Clone)]
+pub struct Window {
+    pub messages: Vec<Message>,
+    /// Whether there's more history before the first message
+    pub older: bool,
+    /// Whether there's more history after the last message
+    pub newer: bool,
+    /// A message to scroll to when next drawn, and where to put it
+    pub scroll_to: Option<(i64, Align)>,
+    /// Changes of number or email by the person in a one on one chat, to
+    /// note where they happened
+    pub history: Vec<HandleChange>,
+    /// Chat identifiers shown together: the chat that was opened and any
+    /// with the same person's other handles
+    chats: Vec<String>,
+    /// Positions of the first and last messages fetched, counting ones
+    /// hidden by ignore rules, which the next pages start from
+    first: Option<Position>,
+    last: Option<Position>,
+    shared: Arc<Shared>,
+}
+
+/// Locations and contacts shared in the chat, its group events and who
+/// each message mentions, by message id. Read once for a window rather than
+/// for every page.
+struct Shared {
+    locations: HashMap<i64, location::Location>,
+    contacts: HashMap<i64, Vec<vcard::Contact>>,
+    events: HashMap<i64, event::Event>,
+    mentions: HashMap<i64, Vec<(String, String)>>,
+    /// For showing each person's merged handles as one sender
+    changes: Vec<HandleChange>,
+}
+
+/// Where a page of messages that has been read starts and ends
+#[derive(Default)]
+struct Fetched {
+    first: Option<Position>,
+    last: Option<Position>,
+    /// Whether the page was cut off at its limit, so there may be more
+    full: bool,
+}
+
+/// A row of [`fetch`]'s query
+type Row = (
+    i64,
+    String,
+    i64,
+    String,
+    bool,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<Vec<u8>>,
+    String,
+    String,
+    String,
+    i64,
+    Option<String>,
+    Option<String>,
+    Option<Vec<u8>>,
+);
+
+/// A chat's newest messages, loaded before it's opened
+#[derive(Clone)]
+pub struct Prefetch {
+    pub window: Handle<Window>,
+    /// Set once [`Prefetch::load`] has finished. The window is `Ready` from
+    /// its first batch, but only knows where it starts and ends, and whether
+    /// there's more history, once every batch is in.
+    done: Arc<AtomicBool>,
+}
+
+impl Prefetch {
+    pub fn new() -> Self {
+        Self {
+            window: Handle::new(),
+            done: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// [`load`] the newest page of `chat_id` into the window
+    pub async fn load(
+        &self,
+        db: &SqlitePool,
+        rules: &ignore::Rules,
+        changes: &[HandleChange],
+        chat_id: &str,
+    ) -> Result<()> {
+        load(db, rules, changes, chat_id, Page::Newest, &self.window).await?;
+        self.done.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// The window, if it has finished loading. One that's still streaming
+    /// can't be taken over, as it's stopped when the prefetches are.
+    pub fn finished(&self) -> Option<Handle<Window>> {
+        self.done
+            .load(Ordering::Acquire)
+            .then(|| self.window.clone())
+    }
+}
+
+/// Load the first window of a chat into `window`, along with the chats of
+/// anyone in it who has changed handle. The messages nearest where it opens
+/// are put there first, a batch at a time, so they're on screen before the
+/// rest of the page has been read.
+pub async fn load(
+    db: &SqlitePool,
+    rules: &ignore::Rules,
+    changes: &[HandleChange],
+    chat_id: &str,
+    page: Page,
+    window: &Handle<Window>,
+) -> Result<()> {
+    let chats = continuity::handles(changes, chat_id);
+    let history = continuity::history(changes, chat_id);
+
+    let mut shared = Shared {
+        locations: HashMap::new(),
+        contacts: HashMap::new(),
+        events: HashMap::new(),
+        mentions: HashMap::new(),
+        changes: changes.to_vec(),
+    };
+    for chat in &chats {
+        shared
+            .locations
+            .extend(location::chat_locations(db, chat).await?);
+        shared
+            .contacts
+            .extend(vcard::chat_contacts(db, chat).await?);
+        shared.events.extend(event::chat_events(db, chat).await?);
+        shared
+            .mentions
+            .extend(mention::chat_mentions(db, chat).await?);
+    }
+    let shared = Arc::new(shared);
+
+    let split = match page {
+        Page::Newest => None,
+        Page::Around(message) => {
+            let date: Option<(i64,)> = sqlx::query_as("SELECT date from message where ROWID=$1;")
+                .bind(message)
+                .fetch_optional(db)
+                .await?;
+            Some((date.map_or(i64::MAX, |(date,)| date), message))
+        }
+        Page::AroundDate(date) => Some((date, i64::MIN)),
+    };
+
+    // put in the handle along with the first batch, so the chat doesn't show
+    // as empty while that's read
+    let mut fresh = Some(Window {
+        messages: Vec::new(),
+        older: false,
+        newer: false,
+        scroll_to: None,
+        history,
+        chats: chats.clone(),
+        first: None,
+        last: None,
+        shared: shared.clone(),
+    });
+    // the message kept in view as the rest come in around it: the one asked
+    // for, or the first on or after the date asked for, or else the newest
+    let mut anchor = match page {
+        Page::Around(message) => Some((message, Align::Center)),
+        _ => None,
+    };
+    let mut add = |edge: Edge, batch: Vec<Message>| {
+        if edge == Edge::Newer && anchor.is_none() {
+            anchor = batch.first().map(|m| (m.id, Align::Min));
+        }
+        if let Some(fresh) = fresh.take() {
+            window.set(State::Ready(fresh));
+        }
+        window.update(|window| {
+            window.add(edge, batch);
+            window.scroll_to =
+                anchor.or_else(|| window.messages.last().map(|m| (m.id, Align::Max)));
+        });
+    };
+
+    let reader = Reader {
+        db,
+        rules,
+        chats: &chats,
+        shared: &shared,
+    };
+    let (older, newer) = match split {
+        None => {
+            let from = (i64::MAX, i64::MAX);
+            let older = fetch(&reader, from, Edge::Older, PAGE, |batch| {
+                add(Edge::Older, batch)
+            })
+            .await?;
+            (older, Fetched::default())
+        }
+        // the newer side first, as it holds the message to open at
+        Some(split) => {
+            let newer = fetch(&reader, split, Edge::Newer, PAGE / 2, |batch| {
+                add(Edge::Newer, batch)
+            })
+            .await?;
+            let older = fetch(&reader, split, Edge::Older, PAGE / 2, |batch| {
+                add(Edge::Older, batch)
+            })
+            .await?;
+            (older, newer)
+        }
+    };
+
+    // no batches if there were no messages
+    if let Some(fresh) = fresh {
+        window.set(State::Ready(fresh));
+    }
+    window.update(|window| {
+        window.older = older.full;
+        // messages may have arrived while it loaded, see `follow`
+        window.newer |= newer.full;
+        window.first = older.first.or(newer.first);
+        window.last = newer.last.or(older.last);
+    });
+
+    Ok(())
+}
+
+impl Window {
+    /// Where the next page towards `edge` starts, if there's more that way
+    pub fn next(&self, edge: Edge) -> Option<Position> {
+        match edge {
+            Edge::Older if self.older => Some(self.first.unwrap_or((i64::MAX, i64::MAX))),
+            // the newer side is inclusive, so start just past the last message
+            Edge::Newer if self.newer => self.last.map(|(date, id)| (date, id + 1)),
+            _ => None,
+        }
+    }
+
+    /// Let the next page towards the newest message pick up messages that
+    /// arrived after the window was loaded. False if the window doesn't reach
+    /// the newest message, so they'll be loaded as it's scrolled down anyway.
+    pub fn follow(&mut self) -> bool {
+        if self.newer {
+            return false;
+        }
+        self.newer = true;
+        true
+    }
+
+    /// Add a batch of messages read towards `edge`
+    fn add(&mut self, edge: Edge, batch: Vec<Message>) {
+        let messages = std::mem::take(&mut self.messages);
+        let messages = match edge {
+            Edge::Older => {
+                let mut older = batch;
+                older.extend(messages);
+                older
+            }
+            Edge::Newer => {
+                let mut messages = messages;
+                messages.extend(batch);
+                messages
+            }
+        };
+        // a sticker may be stuck onto a message in another batch
+        self.messages = place_associated(messages);
+    }
+
+    /// Note where a page read towards `edge` ended, once all of it is in
+    fn finish(&mut self, edge: Edge, page: Fetched) {
+        match edge {
+            Edge::Older => {
+                self.older = page.full;
+                self.first = page.first.or(self.first);
+            }
+            Edge::Newer => {
+                self.newer = page.full;
+                self.last = page.last.or(self.last);
+            }
+        }
+    }
+}
+
+/// Load the next page towards `edge` and add it to `window`
+pub async fn extend(
+    db: &SqlitePool,
+    rules: &ignore::Rules,
+    window: &crate::Handle<Window>,
+    edge: Edge,
+) -> Result<()> {
+    let next = match &*window.get() {
+        State::Ready(window) => window.next(edge).map(|from| {
+            let top = window.messages.first().map(|m| (m.id, Align::Min));
+            (from, top, window.chats.clone(), window.shared.clone())
+        }),
+        _ => None,
+    };
+    let (from, top, chats, shared) = match next {
+        Some(next) => next,
+        None => return Ok(()),
+    };
+
+    let reader = Reader {
+        db,
+        rules,
+        chats: &chats,
+        shared: &shared,
+    };
+    let page = fetch(&reader, from, edge, PAGE, |batch| {
+        window.update(|window| {
+            window.add(edge, batch);
+            // keep the message that was at the top where it was
+            if edge == Edge::Older {
+                window.scroll_to = top;
+            }
+        })
+    })
+    .await?;
+    window.update(|window| window.finish(edge, page));
+
+    Ok(())
+}
+
+/// What pages of a window's messages are read with
+struct Reader<'a> {
+    db: &'a SqlitePool,
+    rules: &'a ignore::Rules,
+    chats: &'a [String],
+    shared: &'a Shared,
+}
+
+/// Read up to `limit` messages in the chats before `from`, or from `from`
+/// onwards, handing them to `batch` a few at a time: nearest `from` first,
+/// each batch in the order its messages were sent
+async fn fetch(
+    reader: &Reader<'_>,
+    from: Position,
+    edge: Edge,
+    limit: i64,
+    mut batch: impl FnMut(Vec<Message>),
+) -> Result<Fetched> {
+    let Reader { db, chats, .. } = *reader;
+    let mut sql = Sql::new(
+        r#"
+            SELECT
+                m.ROWID, coalesce(m.text, ''), m.date, coalesce(h.id, ''), m.is_from_me,
+                (
+                    select group_concat(coalesce(a.transfer_name, a.filename), char(10))
+                    from message_attachment_join maj
+                    join attachment a
+                        on a.ROWID = maj.attachment_id
+                    where maj.message_id = m.ROWID
+                        and coalesce(a.filename, '') not like '%.pluginPayloadAttachment'
+                ),
+                (
+                    select group_concat(a.guid || char(9) || a.filename, char(10))
+                    from message_attachment_join maj
+                    join attachment a
+                        on a.ROWID = maj.attachment_id
+                    where maj.message_id = m.ROWID
+                        and (
+                            a.mime_type like 'image/%'
+                            or a.is_sticker
+                            or a.filename like '%.pluginPayloadAttachment'
+                        )
+                        and a.filename is not null
+                ),
+                (
+                    select group_concat(a.filename, char(10))
+                    from message_attachment_join maj
+                    join attachment a
+                        on a.ROWID = maj.attachment_id
+                    where maj.message_id = m.ROWID
+                        and a.mime_type like 'audio/%'
+                        and a.filename is not null
+                ),
+                (
+                    select a.user_info
+                    from message_attachment_join maj
+                    join attachment a
+                        on a.ROWID = maj.attachment_id
+                    where maj.message_id = m.ROWID
+                        and a.mime_type like 'audio/%'
+                    limit 1
+                ),
+                coalesce(m.service, c.service_name, ''),
+                m.guid,
+                coalesce(m.associated_message_guid, ''),
+                coalesce(m.associated_message_type, 0),
+                nullif(m.expressive_send_style_id, ''),
+                nullif(m.balloon_bundle_id, ''),
+                case when m.balloon_bundle_id != '' then m.payload_data end
+            from (
+                select cmj.message_id, cmj.chat_id, cmj.message_date
+                from chat_message_join cmj
+                join chat c
+                    on cmj.chat_id = c.ROWID
+                where c.chat_identifier in ("#,
+    );
+    // the page is picked first so the subqueries above only run for its
+    // messages, not every message in the chat. Ordering by its columns
+    // leaves its rows in the order it found them in, so they come back as
+    // they're read instead of all being sorted first.
+    let (comparison, order) = match edge {
+        Edge::Older => ("<", "desc"),
+        Edge::Newer => (">=", "asc"),
+    };
+    for (i, chat) in chats.iter().enumerate() {
+        if i > 0 {
+            sql.push(", ");
+        }
+        sql.bind(chat.clone());
+    }
+    sql.push(&format!(
+        ") and (cmj.message_date, cmj.message_id) {comparison} ("
+    ))
+    .bind(from.0)
+    .push(", ")
+    .bind(from.1)
+    .push(&format!(
+        ") order by cmj.message_date {order}, cmj.message_id {order} limit "
+    ))
+    .bind(limit)
+    .push(&format!(
+        r#"
+            ) page
+            join message m
+                on m.ROWID = page.message_id
+            join chat c
+                on page.chat_id = c.ROWID
+            left join handle h
+                on m.handle_id = h.ROWID
+            order by page.message_date {order}, page.message_id {order}
+            ;
+        "#
+    ));
+
+    let mut rows = sqlx::query_as_with::<_, Row, _>(&sql.text, sql.args)
+        .fetch(db)
+        .try_chunks(BATCH);
+
+    let mut read = 0;
+    let (mut nearest, mut farthest) = (None, None);
+    while let Some(rows) = rows.try_next().await? {
+        read += rows.len();
+        nearest = nearest.or(rows.first().map(|row| (row.2, row.0)));
+        farthest = rows.last().map(|row| (row.2, row.0)).or(farthest);
+
+        let mut messages: Vec<_> = rows
+            .into_iter()
+            .filter_map(|row| message(reader, row))
+            .collect();
+        if edge == Edge::Older {
+            messages.reverse();
+        }
+        batch(messages);
+    }
+
+    let (first, last) = match edge {
+        Edge::Older => (farthest, nearest),
+        Edge::Newer => (nearest, farthest),
+    };
+    Ok(Fetched {
+        first,
+        last,
+        full: read as i64 == limit,
+    })
+}
+
+/// The message read in `row`, unless an ignore rule hides it
+fn message(
+    reader: &Reader<'_>,
+    (
+        id,
+        text,
+        timestamp,
+        sender,
+        is_from_me,
+        files,
+        images,
+        audio,
+        user_info,
+        service,
+        guid,
+        associated_guid,
+        associated_type,
+        effect,
+        bundle_id,
+        payload,
+    ): Row,
+) -> Option<Message> {
+    let shared = reader.shared;
+    let (payloads, images) = attachment::attachments(images)
+        .into_iter()
+        .partition(attachment::is_app_payload);
+    // attachments show up as U+FFFC in the text, they're drawn separately
+    let text = text.replace('\u{fffc}', "").trim().to_owned();
+
+    let mut message = Message {
+        id,
+        guid,
+        sticker_on: (associated_type == STICKER)
+            .then(|| associated_target(&associated_guid).to_owned()),
+        tapback_on: tapback::decode(associated_type).map(|(tapback, added)| {
+            (
+                associated_target(&associated_guid).to_owned(),
+                tapback,
+                added,
+            )
+        }),
+        mentions: shared
+            .mentions
+            .get(&id)
+            .map(|mentions| mention::locate(&text, mentions))
+            .unwrap_or_default(),
+        text,
+        attachments: attachment::names(files),
+        images,
+        audio: attachment::names(audio)
+            .iter()
+            .map(|name| attachment::expand_path(name))
+            .collect(),
+        transcription: attachment::transcription(user_info),
+        stickers: Vec::new(),
+        reactions: Vec::new(),
+        service,
+        effect,
+        balloon: balloon::decode(bundle_id, payload, payloads),
+        location: shared.locations.get(&id).cloned(),
+        contacts: shared.contacts.get(&id).cloned().unwrap_or_default(),
+        event: shared.events.get(&id).cloned(),
+        collapsed: false,
+        date: time(timestamp),
+        sender: Sender::new(is_from_me, sender),
+    };
+
+    let action = reader
+        .chats
+        .iter()
+        .find_map(|chat| reader.rules.check(chat, &message.sender, &message.text));
+    match action {
+        Some(IgnoreAction::Hide) => return None,
+        Some(IgnoreAction::Collapse) => message.collapsed = true,
+        None => {}
+    }
+    if let Sender::SomeoneElse(handle) = &message.sender {
+        let person = continuity::person(&shared.changes, handle).to_owned();
+        message.sender = Sender::SomeoneElse(person);
+    }
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cancel, test_util::Demo};
+
+    /// Messages in the long chat of [`fixture`]
+    const LONG: i64 = 450;
+
+    /// The demo database with a chat `long` of [`LONG`] messages added,
+    /// three sent at each instant, so page edges fall between messages that
+    /// share a date and only their ROWIDs tell them apart.
+    async fn fixture() -> (Demo, SqlitePool) {
+        let demo = Demo::generate().await;
+        let db = demo.writable().await;
+        sqlx::query(
+            r#"
+                INSERT INTO chat (guid, chat_identifier, service_name) VALUES
+                    ('iMessage;-;long', 'long', 'iMessage');
+                WITH RECURSIVE n(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM n WHERE i < $1 - 1)
+                INSERT INTO message (ROWID, guid, text, date, is_from_me)
+                SELECT 10000 + i, 'long-' || i, 'message ' || i, 1000000 * (i / 3), 1 FROM n;
+                INSERT INTO chat_message_join (chat_id, message_id, message_date)
+                SELECT c.ROWID, m.ROWID, m.date
+                from chat c, message m
+                where c.chat_identifier = 'long' and m.guid like 'long-%';
+            "#,
+        )
+        .bind(LONG)
+        .execute(&db)
+        .await
+        .unwrap();
+
+        (demo, db)
+    }
+
+    /// Every message of the long chat, in the order they were sent
+    async fn all(db: &SqlitePool) -> Vec<i64> {
+        sqlx::query_as::<_, (i64,)>(
+            "SELECT ROWID from message where guid like 'long-%' order by date, ROWID;",
+        )
+        .fetch_all(db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(id,)| id)
+        .collect()
+    }
+
+    fn ids(window: &Handle<Window>) -> Vec<i64> {
+        match &*window.get() {
+            State::Ready(window) => window.messages.iter().map(|m| m.id).collect(),
+            _ => panic!("not loaded"),
+        }
+    }
+
+    /// Whether there's more to load towards `edge`
+    fn more(window: &Handle<Window>, edge: Edge) -> bool {
+        match &*window.get() {
+            State::Ready(window) => window.next(edge).is_some(),
+            _ => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn scrolls_back_to_the_start() {
+        let (_demo, db) = fixture().await;
+        let rules = ignore::Rules::default();
+        let all = all(&db).await;
+
+        let window = Handle::new();
+        load(&db, &rules, &[], "long", Page::Newest, &window)
+            .await
+            .unwrap();
+        assert_eq!(ids(&window), all[all.len() - PAGE as usize..]);
+        assert!(!more(&window, Edge::Newer));
+
+        let mut pages = 1;
+        while more(&window, Edge::Older) {
+            extend(&db, &rules, &window, Edge::Older).await.unwrap();
+            pages += 1;
+        }
+        assert_eq!(ids(&window), all);
+        assert_eq!(pages, 3);
+    }
+
+    #[tokio::test]
+    async fn scrolls_both_ways_from_the_middle() {
+        let (_demo, db) = fixture().await;
+        let rules = ignore::Rules::default();
+        let all = all(&db).await;
+        let middle = all[LONG as usize / 2];
+
+        let window = Handle::new();
+        load(&db, &rules, &[], "long", Page::Around(middle), &window)
+            .await
+            .unwrap();
+        let loaded = ids(&window);
+        assert_eq!(loaded.len(), PAGE as usize);
+        assert_eq!(loaded[PAGE as usize / 2], middle);
+
+        while more(&window, Edge::Newer) || more(&window, Edge::Older) {
+            extend(&db, &rules, &window, Edge::Newer).await.unwrap();
+            extend(&db, &rules, &window, Edge::Older).await.unwrap();
+        }
+        assert_eq!(ids(&window), all);
+    }
+
+    #[tokio::test]
+    async fn adopts_only_finished_prefetches() {
+        let (_demo, db) = fixture().await;
+        let rules = ignore::Rules::default();
+
+        let prefetch = Prefetch::new();
+        prefetch.load(&db, &rules, &[], "long").await.unwrap();
+        let finished = prefetch.finished().unwrap();
+        assert!(more(&finished, Edge::Older));
+
+        // ready after its first batch, but still streaming the rest
+        let streaming = Prefetch::new();
+        match &*finished.get() {
+            State::Ready(window) => {
+                let mut first = window.clone();
+                first.messages.truncate(BATCH);
+                first.older = false;
+                first.first = None;
+                streaming.window.set(State::Ready(first));
+            }
+            _ => unreachable!(),
+        }
+        assert!(streaming.finished().is_none());
+
+        // and one stopped partway stays unfinished
+        let stopped = Prefetch::new();
+        let loads = cancel::Loads::new();
+        let load = loads.run(stopped.load(&db, &rules, &[], "long"));
+        drop(loads);
+        assert!(load.await.is_err());
+        assert!(stopped.finished().is_none());
+    }
+
+    #[tokio::test]
+    async fn follows_new_messages() {
+        let (_demo, db) = fixture().await;
+        let rules = ignore::Rules::default();
+
+        let window = Handle::new();
+        load(&db, &rules, &[], "long", Page::Newest, &window)
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"
+                INSERT INTO message (ROWID, guid, text, date, is_from_me)
+                VALUES (20000, 'long-new', 'just in', 1000000000, 0);
+                INSERT INTO chat_message_join (chat_id, message_id, message_date)
+                SELECT ROWID, 20000, 1000000000 from chat where chat_identifier = 'long';
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        window.update(|window| assert!(window.follow()));
+        extend(&db, &rules, &window, Edge::Newer).await.unwrap();
+        assert_eq!(ids(&window).last(), Some(&20000));
+        assert_eq!(ids(&window).len(), PAGE as usize + 1);
+    }
+}
@@ -0,0 +1,175 @@
+use chrono::NaiveDate;
+use egui::{Align2, Context};
+
+use crate::{export, fuzzy, i18n, shortcuts};
+
+/// How many matching commands are listed at once
+const SHOWN: usize = 12;
+
+/// Something the command palette can do
+#[derive(Clone)]
+pub enum Command {
+    OpenChat(String),
+    Settings,
+    Shortcuts,
+    ExportEachContact,
+    /// Run the export profile at this index in the config
+    ExportProfile(usize),
+    /// Export the open chat's messages
+    ExportMessages(export::Format),
+    /// Load the open chat around this day
+    JumpToDate(NaiveDate),
+    ZoomIn,
+    ZoomOut,
+    ResetZoom,
+}
+
+/// A command as listed in the palette
+pub struct Entry {
+    pub label: String,
+    pub command: Command,
+}
+
+/// State of the command palette (Cmd+K) while it's open
+#[derive(Default)]
+pub struct Palette {
+    pub query: String,
+    /// Index into the matching entries of the highlighted one
+    selected: usize,
+}
+
+impl Palette {
+    /// `entries` that fuzzily match the query, best first
+    fn matches<'a>(&self, entries: &'a [Entry]) -> Vec<&'a Entry> {
+        if self.query.trim().is_empty() {
+            return entries.iter().take(SHOWN).collect();
+        }
+
+        let mut scored: Vec<_> = entries
+            .iter()
+            .filter_map(|entry| Some((fuzzy::score(&self.query, &entry.label)?, entry)))
+            .collect();
+        // stable, so equally good matches stay in the order they were given
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.into_iter().take(SHOWN).map(|(_, e)| e).collect()
+    }
+}
+
+/// Draw the palette at the top of the window, with `entries` filtered by
+/// what's typed. Returns the command picked, if any, and whether the palette
+/// should close.
+pub fn show(ctx: &Context, palette: &mut Palette, entries: &[Entry]) -> (Option<Command>, bool) {
+    let mut picked = None;
+    let mut close = false;
+
+    egui::Window::new("command palette")
+        .title_bar(false)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_TOP, [0.0, 40.0])
+        .fixed_size([420.0, 0.0])
+        .show(ctx, |ui| {
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut palette.query)
+                    .hint_text(i18n::t(
+                        "go to a chat, export, settings, or a date (YYYY-MM-DD)",
+                    ))
+                    .desired_width(f32::INFINITY),
+            );
+            response.request_focus();
+            if response.changed() {
+                palette.selected = 0;
+            }
+
+            let matches = palette.matches(entries);
+            let last = matches.len().saturating_sub(1);
+            {
+                let input = ui.input();
+                if shortcuts::PALETTE_UP.pressed(&input) {
+                    palette.selected = palette.selected.saturating_sub(1);
+                } else if shortcuts::PALETTE_DOWN.pressed(&input) {
+                    palette.selected = (palette.selected + 1).min(last);
+                }
+                if shortcuts::PALETTE_RUN.pressed(&input) {
+                    picked = matches.get(palette.selected).map(|e| e.command.clone());
+                }
+                close = shortcuts::PALETTE_CLOSE.pressed(&input);
+            }
+
+            if matches.is_empty() {
+                ui.weak(i18n::t("nothing matches"));
+            }
+            for (i, entry) in matches.iter().enumerate() {
+                if ui
+                    .selectable_label(i == palette.selected, &entry.label)
+                    .clicked()
+                {
+                    picked = Some(entry.command.clone());
+                }
+            }
+        });
+
+    close |= picked.is_some();
+    (picked, close)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(labels: &[&str]) -> Vec<Entry> {
+        labels
+            .iter()
+            .map(|label| Entry {
+                label: label.to_string(),
+                command: Command::Settings,
+            })
+            .collect()
+    }
+
+    /// Labels of the entries listed for `query`
+    fn labels<'a>(query: &str, entries: &'a [Entry]) -> Vec<&'a str> {
+        let palette = Palette {
+            query: query.to_owned(),
+            ..Default::default()
+        };
+        palette
+            .matches(entries)
+            .into_iter()
+            .map(|entry| entry.label.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn best_matches_first() {
+        let entries = entries(&[
+            "settings",
+            "zoom in",
+            "go to Sam Mills",
+            "go to Bob Smith",
+            "go to Rasmus",
+        ]);
+        let found = labels("sm", &entries);
+        assert_eq!(found[0], "go to Bob Smith");
+        assert!(!found.contains(&"zoom in"));
+
+        // equally good matches in the order given
+        assert_eq!(
+            labels("go to", &entries),
+            ["go to Sam Mills", "go to Bob Smith", "go to Rasmus"]
+        );
+
+        assert!(labels("nothing like it", &entries).is_empty());
+    }
+
+    #[test]
+    fn lists_a_screenful() {
+        let many: Vec<String> = (0..SHOWN + 5).map(|i| format!("go to chat {i}")).collect();
+        let entries = entries(&many.iter().map(String::as_str).collect::<Vec<_>>());
+        let found = labels("  ", &entries);
+        assert_eq!(found.len(), SHOWN);
+        assert_eq!(found[0], "go to chat 0");
+
+        assert_eq!(labels("chat", &entries).len(), SHOWN);
+    }
+}
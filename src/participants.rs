@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use egui::Ui;
+use eyre::Result;
+use sqlx::SqlitePool;
+
+use crate::{
+    alias,
+    config::{HandleChange, Identity},
+    continuity,
+    sql::Sql,
+    Sender,
+};
+
+/// Someone in a group chat, and how many messages they've sent in it
+#[derive(Clone)]
+pub struct Participant {
+    pub sender: Sender,
+    pub messages: i64,
+}
+
+/// Everyone in a chat, most messages first: its members from
+/// `chat_handle_join`, anyone else who has sent a message in it (members
+/// who have since left), and me. `chats` are the chat and any others shown
+/// along with it. Someone's merged handles count as one person.
+pub async fn counts(
+    db: &SqlitePool,
+    changes: &[HandleChange],
+    chats: &[String],
+    members: &[String],
+) -> Result<Vec<Participant>> {
+    let mut sql = Sql::new(
+        r#"
+            SELECT
+                coalesce(h.id, ''), m.is_from_me, count(*)
+            from message m
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            left join handle h
+                on m.handle_id = h.ROWID
+            where m.item_type = 0
+                and c.chat_identifier in ("#,
+    );
+    for (i, chat) in chats.iter().enumerate() {
+        if i > 0 {
+            sql.push(", ");
+        }
+        sql.bind(chat.clone());
+    }
+    sql.push(") group by 1, 2;");
+
+    let rows = sqlx::query_as_with::<_, (String, bool, i64), _>(&sql.text, sql.args)
+        .fetch_all(db)
+        .await?;
+
+    let person = |handle: &str| continuity::person(changes, handle).to_owned();
+    let mut participants: Vec<Participant> = Vec::new();
+    for sender in
+        std::iter::once(Sender::Me).chain(members.iter().map(|m| Sender::SomeoneElse(person(m))))
+    {
+        if !participants.iter().any(|p| p.sender == sender) {
+            participants.push(Participant {
+                sender,
+                messages: 0,
+            });
+        }
+    }
+    for (handle, is_from_me, count) in rows {
+        let sender = Sender::new(is_from_me, person(&handle));
+        match participants.iter_mut().find(|p| p.sender == sender) {
+            Some(participant) => participant.messages += count,
+            None => participants.push(Participant {
+                sender,
+                messages: count,
+            }),
+        }
+    }
+    participants.sort_by_key(|p| std::cmp::Reverse(p.messages));
+
+    Ok(participants)
+}
+
+/// The list of participants. Clicking one shows only their messages, and
+/// clicking them again shows everyone's. Right-clicking someone names them;
+/// `draft` is the name as typed. Returns true if `aliases` changed.
+pub fn show(
+    ui: &mut Ui,
+    identity: &Identity,
+    participants: &[Participant],
+    only_from: &mut Option<Sender>,
+    aliases: &mut HashMap<String, String>,
+    draft: &mut String,
+) -> bool {
+    let mut changed = false;
+
+    for participant in participants {
+        let selected = only_from.as_ref() == Some(&participant.sender);
+        let label = format!(
+            "{} · {} messages",
+            identity.name(&participant.sender),
+            participant.messages
+        );
+        let response = ui.selectable_label(selected, label);
+        if response.clicked() {
+            *only_from = if selected {
+                None
+            } else {
+                Some(participant.sender.clone())
+            };
+        }
+        if let Sender::SomeoneElse(handle) = &participant.sender {
+            response.context_menu(|ui| changed |= alias::edit(ui, handle, aliases, draft));
+        }
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[tokio::test]
+    async fn counts_everyone_who_has_sent_messages() {
+        let db = test_util::memory().await;
+        sqlx::query(
+            r#"
+                CREATE TABLE message (
+                    ROWID INTEGER PRIMARY KEY, handle_id INTEGER, is_from_me INTEGER, item_type INTEGER
+                );
+                CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, chat_identifier TEXT);
+                CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+                CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT);
+                INSERT INTO chat VALUES (1, 'group'), (2, 'group-old'), (3, 'other');
+                INSERT INTO handle VALUES (1, 'ana'), (2, 'ana@work'), (3, 'bo'), (4, 'cy');
+                INSERT INTO message VALUES
+                    (1, 1, 0, 0), (2, 2, 0, 0), (3, 2, 0, 0),
+                    (4, 3, 0, 0),
+                    (5, 0, 1, 0), (6, 1, 1, 0),
+                    -- someone leaving isn't a message
+                    (7, 3, 0, 3),
+                    (8, 4, 0, 0);
+                INSERT INTO chat_message_join VALUES
+                    (1, 1), (2, 2), (1, 3), (1, 4), (1, 5), (2, 6), (1, 7), (3, 8);
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let changes = [HandleChange {
+            from: "ana@work".to_owned(),
+            to: "ana".to_owned(),
+            date: None,
+        }];
+        let chats = ["group".to_owned(), "group-old".to_owned()];
+        // cy is a member who hasn't said anything here
+        let members = ["ana".to_owned(), "ana@work".to_owned(), "cy".to_owned()];
+
+        let participants = counts(&db, &changes, &chats, &members).await.unwrap();
+        let messages: Vec<_> = participants
+            .iter()
+            .map(|p| (p.sender.clone(), p.messages))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (Sender::SomeoneElse("ana".to_owned()), 3),
+                (Sender::Me, 2),
+                (Sender::SomeoneElse("bo".to_owned()), 1),
+                (Sender::SomeoneElse("cy".to_owned()), 0),
+            ]
+        );
+
+        // without the merge, ana's handles are two people
+        let participants = counts(&db, &[], &chats[..1], &[]).await.unwrap();
+        assert_eq!(participants.len(), 4);
+        assert!(participants
+            .iter()
+            .any(|p| p.sender == Sender::SomeoneElse("ana@work".to_owned()) && p.messages == 1));
+    }
+}
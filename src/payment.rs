@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use egui::{RichText, Ui};
+use regex::Regex;
+
+use crate::i18n;
+
+/// End of `message.balloon_bundle_id` for Apple Pay and Apple Cash messages
+pub const BUNDLE_ID: &str = "com.apple.PassbookUIService.PeerPaymentMessagesExtension";
+
+lazy_static::lazy_static! {
+    /// An amount with its currency, e.g. `$25.00`, `€5` or `10 GBP`
+    static ref AMOUNT: Regex =
+        Regex::new(r"[$€£¥₹]\s?\d[\d,.]*|\d[\d,.]*\s?[A-Z]{3}\b").unwrap();
+}
+
+/// Words the payment extension uses for where a payment is at
+const STATUSES: &[&str] = &[
+    "pending",
+    "completed",
+    "accepted",
+    "declined",
+    "canceled",
+    "cancelled",
+    "expired",
+    "refunded",
+    "failed",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Requested,
+}
+
+/// What could be read out of a payment message. Anything missing is `None`,
+/// since the payload has changed between OS versions.
+#[derive(Clone, Debug, Default)]
+pub struct Payment {
+    pub amount: Option<String>,
+    pub direction: Option<Direction>,
+    pub status: Option<String>,
+}
+
+/// Make sense of the strings in a payment's payload, whose summary text reads
+/// like "Sent $25.00 with Apple Cash"
+pub fn decode(strings: &HashMap<String, String>) -> Payment {
+    let summary = ["ldtext", "caption"]
+        .iter()
+        .find_map(|key| strings.get(*key))
+        .map_or("", String::as_str);
+
+    let direction = match summary.split_whitespace().next() {
+        Some("Sent") => Some(Direction::Sent),
+        Some("Requested" | "Request") => Some(Direction::Requested),
+        _ => None,
+    };
+
+    let status = strings
+        .values()
+        .find(|value| STATUSES.contains(&value.to_lowercase().as_str()))
+        .cloned();
+
+    Payment {
+        amount: AMOUNT.find(summary).map(|m| m.as_str().to_owned()),
+        direction,
+        status,
+    }
+}
+
+/// Draw the inside of a payment's card
+pub fn show(ui: &mut Ui, payment: &Payment) {
+    ui.label(RichText::new(payment.amount.as_deref().unwrap_or("Apple Pay")).heading());
+
+    let direction = match payment.direction {
+        Some(Direction::Sent) => i18n::t("sent with Apple Pay"),
+        Some(Direction::Requested) => i18n::t("requested with Apple Pay"),
+        None => "Apple Pay",
+    };
+    ui.label(RichText::new(direction).small());
+
+    if let Some(status) = &payment.status {
+        ui.label(RichText::new(status).small().italics());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_summaries() {
+        let payment = decode(&strings(&[
+            ("ldtext", "Sent $25.00 with Apple Cash"),
+            ("status", "Completed"),
+        ]));
+        assert_eq!(payment.amount.as_deref(), Some("$25.00"));
+        assert_eq!(payment.direction, Some(Direction::Sent));
+        assert_eq!(payment.status.as_deref(), Some("Completed"));
+
+        let payment = decode(&strings(&[("caption", "Requested 1,200 GBP")]));
+        assert_eq!(payment.amount.as_deref(), Some("1,200 GBP"));
+        assert_eq!(payment.direction, Some(Direction::Requested));
+        assert_eq!(payment.status, None);
+    }
+
+    #[test]
+    fn leaves_out_what_it_cant_read() {
+        let payment = decode(&strings(&[("ldtext", "Apple Cash")]));
+        assert_eq!(payment.amount, None);
+        assert_eq!(payment.direction, None);
+
+        let payment = decode(&HashMap::new());
+        assert_eq!(payment.amount, None);
+        assert_eq!(payment.direction, None);
+        assert_eq!(payment.status, None);
+    }
+}
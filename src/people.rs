@@ -0,0 +1,477 @@
+use std::path::{Path, PathBuf};
+
+use egui::Ui;
+use eyre::Result;
+use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
+
+use crate::{cache, config::Person, i18n};
+
+/// Name of the address book database in each of the folders macOS keeps one
+pub const ADDRESS_BOOK: &str = "AddressBook-v22.abcddb";
+
+/// The address books under macOS's `AddressBook` folder: one for contacts
+/// kept only on the Mac, and one per account in `Sources`
+fn address_books(dir: &Path) -> Vec<PathBuf> {
+    let mut books = vec![dir.join(ADDRESS_BOOK)];
+    if let Ok(sources) = std::fs::read_dir(dir.join("Sources")) {
+        books.extend(
+            sources
+                .flatten()
+                .map(|source| source.path().join(ADDRESS_BOOK)),
+        );
+    }
+    books.retain(|book| book.is_file());
+    books
+}
+
+/// Everyone in Contacts who has messaged from one or more of the handles in
+/// the message database, with those handles. `path` is an address book, or
+/// a folder of them, instead of the macOS one. Without any address book,
+/// there's no one.
+pub async fn contacts(db: &SqlitePool, path: Option<&Path>) -> Result<Vec<Person>> {
+    let path = match path {
+        Some(path) => path.to_owned(),
+        None => dirs::home_dir()
+            .unwrap_or_default()
+            .join("Library/Application Support/AddressBook"),
+    };
+    let books = if path.is_dir() {
+        address_books(&path)
+    } else if path.is_file() {
+        vec![path]
+    } else {
+        Vec::new()
+    };
+
+    let handles =
+        sqlx::query_as::<_, (String,)>("SELECT id from handle group by id order by min(ROWID);")
+            .fetch_all(db)
+            .await?;
+
+    let mut people = Vec::new();
+    for book in books {
+        for (name, addresses, photo) in read_address_book(&book).await? {
+            let handles: Vec<String> = handles
+                .iter()
+                .map(|(handle,)| handle)
+                .filter(|handle| addresses.iter().any(|a| same_handle(a, handle)))
+                .cloned()
+                .collect();
+            if !handles.is_empty() {
+                people.push(Person {
+                    name,
+                    handles,
+                    photo,
+                });
+            }
+        }
+    }
+
+    Ok(people)
+}
+
+/// A contact's name, their phone numbers and emails, and their picture
+type Contact = (String, Vec<String>, Option<PathBuf>);
+
+async fn read_address_book(path: &Path) -> Result<Vec<Contact>> {
+    let book = SqlitePool::connect_with(SqliteConnectOptions::new().filename(path).read_only(true))
+        .await?;
+
+    // older address books don't keep pictures
+    let pictures = sqlx::query_as::<_, (i64,)>(
+        "SELECT count(*) from pragma_table_info('ZABCDRECORD') where name = 'ZTHUMBNAILIMAGEDATA';",
+    )
+    .fetch_one(&book)
+    .await?
+    .0 > 0;
+    let thumbnail = if pictures {
+        "r.ZTHUMBNAILIMAGEDATA"
+    } else {
+        "null"
+    };
+
+    let rows = sqlx::query_as::<_, (i64, String, String, Option<Vec<u8>>)>(&format!(
+        r#"
+            SELECT
+                r.Z_PK,
+                coalesce(
+                    nullif(trim(coalesce(r.ZFIRSTNAME, '') || ' ' || coalesce(r.ZLASTNAME, '')), ''),
+                    r.ZORGANIZATION,
+                    ''
+                ),
+                a.address,
+                {thumbnail}
+            from ZABCDRECORD r
+            join (
+                SELECT ZOWNER as owner, ZFULLNUMBER as address from ZABCDPHONENUMBER
+                union all
+                SELECT ZOWNER, ZADDRESS from ZABCDEMAILADDRESS
+            ) a
+                on a.owner = r.Z_PK
+            where a.address is not null
+            order by r.Z_PK
+            ;
+        "#
+    ))
+    .fetch_all(&book)
+    .await?;
+    book.close().await;
+
+    let mut contacts: Vec<(i64, Contact)> = Vec::new();
+    for (id, name, address, thumbnail) in rows {
+        match contacts.last_mut() {
+            Some((last, (_, addresses, _))) if *last == id => addresses.push(address),
+            _ => {
+                let photo = thumbnail.and_then(|thumbnail| photo(path, id, &thumbnail));
+                contacts.push((id, (name, vec![address], photo)));
+            }
+        }
+    }
+
+    Ok(contacts.into_iter().map(|(_, contact)| contact).collect())
+}
+
+/// Where a contact's thumbnail is. Contacts keeps it after a marker byte:
+/// small ones inline, which are copied to the cache, and larger ones as the
+/// name of a file next to the address book.
+fn photo(book: &Path, id: i64, thumbnail: &[u8]) -> Option<PathBuf> {
+    match thumbnail.split_first()? {
+        (1, image) => {
+            let path = cache::converted_path("avatars", &book.join(id.to_string()), "jpg");
+            // the photo may have changed since it was last copied
+            if std::fs::read(&path).ok().as_deref() != Some(image) {
+                std::fs::create_dir_all(path.parent()?).ok()?;
+                std::fs::write(&path, image).ok()?;
+            }
+            Some(path)
+        }
+        (2, name) => {
+            let name = String::from_utf8_lossy(name);
+            let path = book
+                .with_file_name(".AddressBook-v22_SUPPORT/_EXTERNAL_DATA")
+                .join(name.trim_end_matches('\0'));
+            path.is_file().then_some(path)
+        }
+        _ => None,
+    }
+}
+
+/// Whether a phone number or email from Contacts is `handle`. Numbers in
+/// Contacts are formatted however they were typed and may leave out the
+/// country code, so only their digits are compared, from the end.
+fn same_handle(address: &str, handle: &str) -> bool {
+    if address.contains('@') || handle.contains('@') {
+        return address.trim().eq_ignore_ascii_case(handle);
+    }
+
+    let digits = |s: &str| -> String { s.chars().filter(char::is_ascii_digit).collect() };
+    let (address, handle) = (digits(address), digits(handle));
+    let (short, long) = if address.len() <= handle.len() {
+        (address, handle)
+    } else {
+        (handle, address)
+    };
+    // short codes have to match exactly
+    !short.is_empty() && (short == long || (short.len() >= 7 && long.ends_with(&short)))
+}
+
+/// Everyone from Contacts, less any handles `manual` lists, and everyone in
+/// `manual`
+pub fn merged(contacts: &[Person], manual: &[Person]) -> Vec<Person> {
+    let overridden = |handle: &String| manual.iter().any(|p| p.handles.contains(handle));
+
+    contacts
+        .iter()
+        .map(|person| Person {
+            name: person.name.clone(),
+            photo: person.photo.clone(),
+            handles: person
+                .handles
+                .iter()
+                .filter(|handle| !overridden(handle))
+                .cloned()
+                .collect(),
+        })
+        .filter(|person| !person.handles.is_empty())
+        .chain(manual.iter().cloned())
+        .collect()
+}
+
+/// Contents of a one on one chat's right-click menu for saying whose other
+/// handle is the same person, or that Contacts got it wrong. `draft` is the
+/// other handle as typed. Returns true if `manual` changed.
+pub fn edit(
+    ui: &mut Ui,
+    chat_id: &str,
+    contacts: &[Person],
+    manual: &mut Vec<Person>,
+    draft: &mut String,
+) -> bool {
+    let people = merged(contacts, manual);
+    let person_with = |handle: &str| {
+        people
+            .iter()
+            .find(|person| person.handles.iter().any(|h| h == handle))
+    };
+    let others: Vec<String> = person_with(chat_id)
+        .map(|person| {
+            person
+                .handles
+                .iter()
+                .filter(|h| *h != chat_id)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if !others.is_empty() {
+        ui.label(i18n::tf(
+            "same person as {handles}",
+            &[("handles", &others.join(", "))],
+        ));
+        if ui.button(i18n::t("not the same person")).clicked() {
+            for person in manual.iter_mut() {
+                person.handles.retain(|h| h != chat_id);
+            }
+            manual.retain(|person| !person.handles.is_empty());
+            manual.push(Person {
+                handles: vec![chat_id.to_owned()],
+                ..Default::default()
+            });
+            ui.close_menu();
+            return true;
+        }
+    }
+
+    ui.label(i18n::t("same person as"));
+    ui.add(egui::TextEdit::singleline(draft).hint_text(i18n::t("their other handle")));
+
+    let other = draft.trim();
+    if other.is_empty() || other == chat_id || others.iter().any(|h| h == other) {
+        return false;
+    }
+    if !ui.button(i18n::t("merge")).clicked() {
+        return false;
+    }
+
+    // both people as they are now, joined into one that overrides Contacts
+    let mut name = String::new();
+    let mut handles: Vec<String> = Vec::new();
+    for handle in [chat_id, other] {
+        match person_with(handle) {
+            Some(person) => {
+                if name.is_empty() {
+                    name = person.name.clone();
+                }
+                handles.extend(person.handles.iter().cloned());
+            }
+            None => handles.push(handle.to_owned()),
+        }
+    }
+    let mut seen = Vec::new();
+    handles.retain(|handle| {
+        let new = !seen.contains(handle);
+        seen.push(handle.clone());
+        new
+    });
+
+    manual.retain(|person| !person.handles.iter().any(|h| handles.contains(h)));
+    manual.push(Person {
+        name,
+        handles,
+        ..Default::default()
+    });
+    *draft = String::new();
+    ui.close_menu();
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// A contact's first name, organization, addresses and thumbnail
+    type Row<'a> = (
+        Option<&'a str>,
+        Option<&'a str>,
+        &'a [&'a str],
+        Option<&'a [u8]>,
+    );
+
+    /// An address book at `path` with `contacts`. Without `thumbnails`, it's laid out like the
+    /// older ones that don't keep pictures.
+    async fn address_book(path: &Path, thumbnails: bool, contacts: &[Row<'_>]) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let _ = std::fs::remove_file(path);
+        let book = SqlitePool::connect_with(
+            SqliteConnectOptions::new()
+                .filename(path)
+                .create_if_missing(true),
+        )
+        .await
+        .unwrap();
+        let thumbnail = if thumbnails {
+            ", ZTHUMBNAILIMAGEDATA BLOB"
+        } else {
+            ""
+        };
+        sqlx::query(&format!(
+            r#"
+                CREATE TABLE ZABCDRECORD (
+                    Z_PK INTEGER PRIMARY KEY,
+                    ZFIRSTNAME VARCHAR,
+                    ZLASTNAME VARCHAR,
+                    ZORGANIZATION VARCHAR{thumbnail}
+                );
+                CREATE TABLE ZABCDPHONENUMBER (Z_PK INTEGER PRIMARY KEY, ZOWNER INTEGER, ZFULLNUMBER VARCHAR);
+                CREATE TABLE ZABCDEMAILADDRESS (Z_PK INTEGER PRIMARY KEY, ZOWNER INTEGER, ZADDRESS VARCHAR);
+            "#
+        ))
+        .execute(&book)
+        .await
+        .unwrap();
+
+        for (i, (first, organization, addresses, photo)) in contacts.iter().enumerate() {
+            let owner = i as i64 + 1;
+            sqlx::query(
+                "INSERT INTO ZABCDRECORD (Z_PK, ZFIRSTNAME, ZORGANIZATION) values ($1, $2, $3);",
+            )
+            .bind(owner)
+            .bind(first)
+            .bind(organization)
+            .execute(&book)
+            .await
+            .unwrap();
+            if let Some(photo) = photo {
+                sqlx::query("UPDATE ZABCDRECORD set ZTHUMBNAILIMAGEDATA = $1 where Z_PK = $2;")
+                    .bind(*photo)
+                    .bind(owner)
+                    .execute(&book)
+                    .await
+                    .unwrap();
+            }
+            for address in *addresses {
+                let table = if address.contains('@') {
+                    "INSERT INTO ZABCDEMAILADDRESS (ZOWNER, ZADDRESS) values ($1, $2);"
+                } else {
+                    "INSERT INTO ZABCDPHONENUMBER (ZOWNER, ZFULLNUMBER) values ($1, $2);"
+                };
+                sqlx::query(table)
+                    .bind(owner)
+                    .bind(address)
+                    .execute(&book)
+                    .await
+                    .unwrap();
+            }
+        }
+        book.close().await;
+    }
+
+    #[tokio::test]
+    async fn finds_contacts_with_handles() {
+        let dir = std::env::temp_dir().join(format!(
+            "imessage-viewer-people-{}-contacts",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        address_book(
+            &dir.join(ADDRESS_BOOK),
+            false,
+            &[
+                (
+                    Some("Ana"),
+                    None,
+                    &["(555) 123-4567", "ana@example.com"],
+                    None,
+                ),
+                (Some("Nobody"), None, &["555 000 0000"], None),
+            ],
+        )
+        .await;
+        let source = dir.join("Sources/account").join(ADDRESS_BOOK);
+        address_book(
+            &source,
+            true,
+            &[(
+                None,
+                Some("Bakery"),
+                &["BAKERY@example.com"],
+                Some(b"\x02bread.jpg\0"),
+            )],
+        )
+        .await;
+        let picture = source.with_file_name(".AddressBook-v22_SUPPORT/_EXTERNAL_DATA/bread.jpg");
+        std::fs::create_dir_all(picture.parent().unwrap()).unwrap();
+        std::fs::write(&picture, b"jpeg").unwrap();
+
+        let db = test_util::memory().await;
+        sqlx::query(
+            r#"
+                CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT);
+                INSERT INTO handle (id) VALUES
+                    ('ana@example.com'), ('+15551234567'), ('bakery@example.com'), ('+15551234567');
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let people = contacts(&db, Some(&dir)).await.unwrap();
+        assert_eq!(people.len(), 2);
+        assert_eq!(people[0].name, "Ana");
+        assert_eq!(people[0].handles, ["ana@example.com", "+15551234567"]);
+        assert_eq!(people[0].photo, None);
+        assert_eq!(people[1].name, "Bakery");
+        assert_eq!(people[1].handles, ["bakery@example.com"]);
+        assert_eq!(people[1].photo, Some(picture));
+
+        // a single address book instead of a folder of them
+        let people = contacts(&db, Some(&source)).await.unwrap();
+        assert_eq!(people.len(), 1);
+        assert_eq!(people[0].name, "Bakery");
+
+        assert!(contacts(&db, Some(&dir.join("missing")))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn matches_handles() {
+        assert!(same_handle("(555) 123-4567", "+15551234567"));
+        assert!(same_handle("+1 555 123 4567", "5551234567"));
+        assert!(same_handle(" Ana@Example.com", "ana@example.com"));
+        assert!(same_handle("12345", "12345"));
+        // short codes and different numbers
+        assert!(!same_handle("12345", "112345"));
+        assert!(!same_handle("555-123-4567", "+15559994567"));
+        assert!(!same_handle("", ""));
+        assert!(!same_handle("5551234567", "ana@example.com"));
+    }
+
+    #[test]
+    fn manual_people_override_contacts() {
+        let person = |name: &str, handles: &[&str]| Person {
+            name: name.to_owned(),
+            handles: handles.iter().map(|h| h.to_string()).collect(),
+            photo: None,
+        };
+        let contacts = [
+            person("Ana", &["+15551234567", "ana@example.com"]),
+            person("Bo", &["bo@example.com"]),
+        ];
+        let manual = [person("Ana Work", &["ana@example.com", "bo@example.com"])];
+
+        assert_eq!(
+            merged(&contacts, &manual),
+            [
+                person("Ana", &["+15551234567"]),
+                person("Ana Work", &["ana@example.com", "bo@example.com"]),
+            ]
+        );
+        assert_eq!(merged(&contacts, &[]), contacts);
+    }
+}
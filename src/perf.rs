@@ -0,0 +1,127 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+
+/// How many of the most recent background loads the overlay lists
+const RECENT_LOADS: usize = 10;
+
+/// How many frames frame time stats are taken over
+const FRAME_WINDOW: usize = 120;
+
+/// Where puffin_viewer can connect to while profiling is on
+const PUFFIN_ADDR: &str = "127.0.0.1:8585";
+
+lazy_static::lazy_static! {
+    static ref LOADS: Mutex<VecDeque<(&'static str, Duration)>> = Mutex::new(VecDeque::new());
+    static ref CACHES: Mutex<BTreeMap<&'static str, (u64, u64)>> = Mutex::new(BTreeMap::new());
+}
+
+/// Record how long a background load took
+pub fn record_load(name: &'static str, took: Duration) {
+    let mut loads = LOADS.lock().unwrap();
+    if loads.len() == RECENT_LOADS {
+        loads.pop_front();
+    }
+    loads.push_back((name, took));
+}
+
+/// Count a lookup in one of the app's in-memory caches
+pub fn cache_lookup(cache: &'static str, hit: bool) {
+    let mut caches = CACHES.lock().unwrap();
+    let (hits, misses) = caches.entry(cache).or_default();
+    if hit {
+        *hits += 1;
+    } else {
+        *misses += 1;
+    }
+}
+
+/// Debug overlay (F12) with frame timings, load latencies and cache stats
+#[derive(Default)]
+pub struct Overlay {
+    pub open: bool,
+    frame_times: VecDeque<f32>,
+    profiler: Option<puffin_http::Server>,
+}
+
+impl Overlay {
+    /// Draw the overlay if it is open. `sizes` is the approximate memory used
+    /// by each of the app's caches, in bytes.
+    pub fn show(&mut self, ctx: &egui::Context, frame: &eframe::Frame, sizes: &[(&str, usize)]) {
+        if self.frame_times.len() == FRAME_WINDOW {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(ctx.input().unstable_dt);
+
+        if !self.open {
+            return;
+        }
+
+        egui::Window::new("performance")
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                let count = self.frame_times.len().max(1) as f32;
+                let mean = self.frame_times.iter().sum::<f32>() / count;
+                let max = self.frame_times.iter().copied().fold(0.0, f32::max);
+
+                ui.heading("frames");
+                ui.label(format!(
+                    "frame time {:.1} ms mean, {:.1} ms max",
+                    mean * 1000.0,
+                    max * 1000.0
+                ));
+                if let Some(cpu) = frame.info().cpu_usage {
+                    ui.label(format!("cpu time {:.1} ms", cpu * 1000.0));
+                }
+
+                ui.heading("recent loads");
+                for (name, took) in LOADS.lock().unwrap().iter().rev() {
+                    ui.label(format!("{name}: {:.1} ms", took.as_secs_f64() * 1000.0));
+                }
+
+                ui.heading("caches");
+                egui::Grid::new("caches").num_columns(3).show(ui, |ui| {
+                    for (name, (hits, misses)) in CACHES.lock().unwrap().iter() {
+                        let rate = *hits as f64 / (hits + misses).max(1) as f64;
+                        ui.label(*name);
+                        ui.label(format!("{hits} hits, {misses} misses"));
+                        ui.label(format!("{:.0}%", rate * 100.0));
+                        ui.end_row();
+                    }
+                });
+
+                ui.heading("memory");
+                egui::Grid::new("memory").num_columns(2).show(ui, |ui| {
+                    for (name, bytes) in sizes {
+                        ui.label(*name);
+                        ui.label(format!("{:.1} KB", *bytes as f64 / 1000.0));
+                        ui.end_row();
+                    }
+                });
+
+                ui.heading("profiler");
+                match &self.profiler {
+                    Some(_) => {
+                        ui.label(format!("connect puffin_viewer to {PUFFIN_ADDR}"));
+                        if ui.button("stop").clicked() {
+                            puffin::set_scopes_on(false);
+                            self.profiler = None;
+                        }
+                    }
+                    None => {
+                        if ui.button("start puffin server").clicked() {
+                            match puffin_http::Server::new(PUFFIN_ADDR) {
+                                Ok(server) => {
+                                    puffin::set_scopes_on(true);
+                                    self.profiler = Some(server);
+                                }
+                                Err(e) => eprintln!("{e}"),
+                            }
+                        }
+                    }
+                }
+            });
+    }
+}
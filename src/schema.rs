@@ -0,0 +1,156 @@
+use eyre::Result;
+use sqlx::SqlitePool;
+
+use crate::error::Error;
+
+/// Every table in the source database that is read from, along with the
+/// columns of it that are used
+const EXPECTED: &[(&str, &[&str])] = &[
+    (
+        "message",
+        &[
+            "ROWID",
+            "guid",
+            "text",
+            "date",
+            "handle_id",
+            "is_from_me",
+            "service",
+            "associated_message_guid",
+            "associated_message_type",
+            "expressive_send_style_id",
+            "balloon_bundle_id",
+            "payload_data",
+            "attributedBody",
+            "item_type",
+            "group_action_type",
+            "other_handle",
+            "group_title",
+        ],
+    ),
+    (
+        "chat",
+        &["ROWID", "chat_identifier", "display_name", "service_name"],
+    ),
+    ("handle", &["ROWID", "id"]),
+    (
+        "chat_message_join",
+        &["chat_id", "message_id", "message_date"],
+    ),
+    ("chat_handle_join", &["chat_id", "handle_id"]),
+    ("message_attachment_join", &["message_id", "attachment_id"]),
+    (
+        "attachment",
+        &[
+            "ROWID",
+            "guid",
+            "filename",
+            "transfer_name",
+            "mime_type",
+            "uti",
+            "total_bytes",
+            "user_info",
+            "is_sticker",
+        ],
+    ),
+];
+
+/// Make sure the database has everything the viewer reads, so that a file
+/// that isn't a message database (or is from an unsupported macOS version)
+/// is reported up front, rather than by whichever query runs first
+pub async fn check(db: &SqlitePool) -> Result<()> {
+    let mut missing = Vec::new();
+
+    for (table, columns) in EXPECTED {
+        let sql = format!("SELECT {} from {table} limit 0;", columns.join(", "));
+
+        if let Err(e) = sqlx::query(&sql).execute(db).await {
+            match Error::from_sqlx(&e) {
+                Some(Error::SchemaMismatch(what)) => missing.push(what),
+                Some(other) => return Err(other.into()),
+                None => return Err(e.into()),
+            }
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(Error::SchemaMismatch(missing.join(", ")).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqliteConnectOptions;
+
+    use super::*;
+    use crate::{
+        error,
+        test_util::{self, Demo},
+    };
+
+    #[tokio::test]
+    async fn accepts_a_message_database() {
+        let demo = Demo::generate().await;
+        let db = demo.connect().await;
+
+        check(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn lists_what_is_missing() {
+        let db = test_util::memory().await;
+        let mut tables = String::new();
+        for (table, columns) in EXPECTED {
+            // as if from an older macOS, without @mentions
+            let columns: Vec<_> = columns
+                .iter()
+                .filter(|column| **column != "attributedBody")
+                .collect();
+            if *table != "chat_handle_join" {
+                tables.push_str(&format!(
+                    "CREATE TABLE {table} ({});\n",
+                    columns
+                        .iter()
+                        .map(|c| c.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+        sqlx::query(&tables).execute(&db).await.unwrap();
+
+        let report = check(&db).await.unwrap_err();
+        match error::classify(&report) {
+            Some(Error::SchemaMismatch(what)) => assert_eq!(
+                what,
+                "no such column: attributedBody, no such table: chat_handle_join"
+            ),
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn not_a_database() {
+        let path = std::env::temp_dir().join(format!(
+            "imessage-viewer-schema-{}-notes.txt",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            "these are not the messages you are looking for\n".repeat(100),
+        )
+        .unwrap();
+        let db = SqlitePool::connect_with(SqliteConnectOptions::new().filename(&path))
+            .await
+            .unwrap();
+
+        let report = check(&db).await.unwrap_err();
+        assert!(
+            matches!(error::classify(&report), Some(Error::Corrupt(_))),
+            "{report}"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,877 @@
+pub mod fold;
+mod query;
+
+use std::{
+    ops::Range,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use eyre::Result;
+use futures_util::TryStreamExt;
+use regex::{Regex, RegexBuilder};
+use sqlx::SqlitePool;
+use tokio::sync::Semaphore;
+
+use crate::{attachment, audit::Pool, find, sql::Sql, time, Handle, Message, Sender, State};
+
+pub use query::Query;
+
+/// Bump when the shape of the index or how text is folded into it changes so
+/// that existing caches are rebuilt
+const INDEX_VERSION: i64 = 6;
+
+/// Number of source rows copied into the index per transaction
+const INDEX_BATCH_SIZE: i64 = 5000;
+
+/// Upper bound on hits returned for a single query
+const MAX_RESULTS: i64 = 1000;
+
+/// Maximum number of per-chat queries [`scan`] runs at once: one for each
+/// connection of the pool it runs on, since more would only queue for them
+const SCAN_CONCURRENCY: usize = Pool::Bulk.connections() as usize;
+
+/// How long a regex search may run before giving up with what it has found
+const REGEX_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Cap on the compiled size of user-supplied patterns
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// Characters kept either side of the first match in a result snippet
+const SNIPPET_CONTEXT: usize = 40;
+
+/// Matching messages from a single chat
+#[derive(Clone)]
+pub struct ChatResults {
+    pub chat: String,
+    pub messages: Vec<Message>,
+}
+
+#[derive(Clone, Default)]
+pub struct SearchResults {
+    pub chats: Vec<ChatResults>,
+    /// Number of chats searched so far and in total while [`scan`] is still
+    /// streaming results in
+    pub progress: Option<(usize, usize)>,
+    /// Set if the search gave up before looking at every message
+    pub timed_out: bool,
+}
+
+impl SearchResults {
+    /// Add a hit to its chat's results. Hits are expected newest first, call
+    /// [`SearchResults::finish`] once they have all been pushed.
+    fn push(&mut self, chat: String, message: Message) {
+        match self.chats.iter_mut().find(|r| r.chat == chat) {
+            Some(r) => r.messages.push(message),
+            None => self.chats.push(ChatResults {
+                chat,
+                messages: vec![message],
+            }),
+        }
+    }
+
+    /// Put each chat's hits back in chronological order
+    fn finish(&mut self) {
+        for r in &mut self.chats {
+            r.messages.reverse();
+        }
+    }
+
+    /// Add a chat's results, keeping the most recently matching chat first
+    fn insert(&mut self, result: ChatResults) {
+        let latest = result.messages.last().map(|m| m.date);
+        let i = self
+            .chats
+            .iter()
+            .position(|c| c.messages.last().map(|m| m.date) < latest)
+            .unwrap_or(self.chats.len());
+
+        self.chats.insert(i, result);
+    }
+}
+
+/// Bring the FTS index in the cache database up to date with the source
+/// database. Only messages newer than the last indexed ROWID are copied, so
+/// this is cheap to run on every startup. The index is rebuilt if `db` no
+/// longer has the messages it was built from, e.g. an older backup opened
+/// after the newer database.
+pub async fn build_index(db: &SqlitePool, cache: &SqlitePool) -> Result<()> {
+    let (version,) = sqlx::query_as::<_, (i64,)>("PRAGMA user_version;")
+        .fetch_one(cache)
+        .await?;
+    // the folding settings are part of the version, as the index holds
+    // folded text
+    let wanted = INDEX_VERSION << 8 | fold::settings_id();
+    if version != wanted {
+        sqlx::query("DROP TABLE IF EXISTS message_fts;")
+            .execute(cache)
+            .await?;
+        sqlx::query(&format!("PRAGMA user_version = {wanted};"))
+            .execute(cache)
+            .await?;
+    }
+
+    // only `folded` (the searchable text of the message run through
+    // `fold()`) is indexed, the rest is there to show and filter results
+    sqlx::query(
+        r#"
+            CREATE VIRTUAL TABLE IF NOT EXISTS message_fts USING fts5(
+                text UNINDEXED,
+                chat_identifier UNINDEXED,
+                sender UNINDEXED,
+                is_from_me UNINDEXED,
+                date UNINDEXED,
+                attachments UNINDEXED,
+                ocr UNINDEXED,
+                files UNINDEXED,
+                utis UNINDEXED,
+                transcription UNINDEXED,
+                folded,
+                tokenize = 'unicode61 remove_diacritics 0'
+            );
+        "#,
+    )
+    .execute(cache)
+    .await?;
+
+    // kept outside of the index so that recognised text survives rebuilds,
+    // it's much slower to produce than the rest
+    sqlx::query(
+        r#"
+            CREATE TABLE IF NOT EXISTS attachment_ocr (
+                attachment_id INTEGER PRIMARY KEY,
+                message_id INTEGER NOT NULL,
+                text TEXT NOT NULL
+            );
+        "#,
+    )
+    .execute(cache)
+    .await?;
+
+    // what the source had up to the newest indexed message, as of the last
+    // time the index was brought up to date
+    sqlx::query(
+        r#"
+            CREATE TABLE IF NOT EXISTS message_fts_state (
+                newest INTEGER NOT NULL,
+                messages INTEGER NOT NULL
+            );
+        "#,
+    )
+    .execute(cache)
+    .await?;
+
+    let state = sqlx::query_as::<_, (i64, i64)>("SELECT newest, messages from message_fts_state;")
+        .fetch_optional(cache)
+        .await?;
+    if let Some((newest, messages)) = state {
+        let (present, max) = sqlx::query_as::<_, (i64, i64)>(
+            r#"
+                SELECT
+                    (select count(*) from message where ROWID <= $1),
+                    (select coalesce(max(ROWID), 0) from message)
+                ;
+            "#,
+        )
+        .bind(newest)
+        .fetch_one(db)
+        .await?;
+        // adding on from the last indexed ROWID would mix in another copy's
+        // messages, or keep ones that have been deleted
+        if present != messages || max < newest {
+            sqlx::query("DELETE FROM message_fts; DELETE FROM message_fts_state;")
+                .execute(cache)
+                .await?;
+        }
+    }
+
+    loop {
+        let (last_indexed,) =
+            sqlx::query_as::<_, (i64,)>("SELECT coalesce(max(rowid), 0) from message_fts;")
+                .fetch_one(cache)
+                .await?;
+
+        let rows = sqlx::query_as::<
+            _,
+            (
+                i64,
+                String,
+                String,
+                String,
+                bool,
+                i64,
+                String,
+                Option<String>,
+                Option<String>,
+                Option<Vec<u8>>,
+            ),
+        >(
+            r#"
+                SELECT
+                    m.ROWID, coalesce(m.text, ''), c.chat_identifier, coalesce(h.id, ''), m.is_from_me, m.date,
+                    (
+                        select group_concat(a.mime_type, ' ')
+                        from message_attachment_join maj
+                        join attachment a
+                            on a.ROWID = maj.attachment_id
+                        where maj.message_id = m.ROWID
+                    ),
+                    (
+                        select group_concat(coalesce(a.transfer_name, a.filename), char(10))
+                        from message_attachment_join maj
+                        join attachment a
+                            on a.ROWID = maj.attachment_id
+                        where maj.message_id = m.ROWID
+                    ),
+                    (
+                        select group_concat(a.uti, ' ')
+                        from message_attachment_join maj
+                        join attachment a
+                            on a.ROWID = maj.attachment_id
+                        where maj.message_id = m.ROWID
+                    ),
+                    (
+                        select a.user_info
+                        from message_attachment_join maj
+                        join attachment a
+                            on a.ROWID = maj.attachment_id
+                        where maj.message_id = m.ROWID
+                            and a.mime_type like 'audio/%'
+                        limit 1
+                    )
+                from message m
+                join chat_message_join cmj
+                    on m.ROWID = cmj.message_id
+                join chat c
+                    on cmj.chat_id = c.ROWID
+                left join handle h
+                    on m.handle_id = h.ROWID
+                where m.ROWID > $1
+                group by m.ROWID
+                order by m.ROWID
+                limit $2
+                ;
+            "#,
+        )
+        .bind(last_indexed)
+        .bind(INDEX_BATCH_SIZE)
+        .fetch_all(db)
+        .await?;
+
+        if rows.is_empty() {
+            let (messages,) =
+                sqlx::query_as::<_, (i64,)>("SELECT count(*) from message where ROWID <= $1;")
+                    .bind(last_indexed)
+                    .fetch_one(db)
+                    .await?;
+            let mut tx = cache.begin().await?;
+            sqlx::query("DELETE FROM message_fts_state;")
+                .execute(&mut tx)
+                .await?;
+            sqlx::query("INSERT INTO message_fts_state (newest, messages) values ($1, $2);")
+                .bind(last_indexed)
+                .bind(messages)
+                .execute(&mut tx)
+                .await?;
+            tx.commit().await?;
+
+            return Ok(());
+        }
+
+        let mut tx = cache.begin().await?;
+        for (rowid, text, chat, sender, is_from_me, date, attachments, files, utis, user_info) in
+            rows
+        {
+            sqlx::query(
+                r#"
+                    INSERT INTO message_fts (
+                        rowid, text, chat_identifier, sender, is_from_me, date, attachments,
+                        files, utis, transcription, ocr, folded
+                    )
+                    SELECT
+                        $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, o.text,
+                        fold($2 || ' ' || coalesce($10, '') || ' ' || coalesce(o.text, ''))
+                    from (
+                        select group_concat(text, ' ') as text
+                        from attachment_ocr
+                        where message_id = $1
+                    ) o
+                    ;
+                "#,
+            )
+            .bind(rowid)
+            .bind(text)
+            .bind(chat)
+            .bind(sender)
+            .bind(is_from_me)
+            .bind(date)
+            .bind(attachments)
+            .bind(files)
+            .bind(utis)
+            .bind(attachment::transcription(user_info))
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+    }
+}
+
+/// What a search looked for, to highlight in its results
+#[derive(Clone)]
+pub enum Terms {
+    Words(Vec<String>),
+    Regex(Regex),
+}
+
+impl Terms {
+    /// Byte ranges of every match in `text`, in order and not overlapping
+    pub fn ranges(&self, text: &str) -> Vec<Range<usize>> {
+        match self {
+            Terms::Words(words) => {
+                let mut ranges: Vec<_> = words
+                    .iter()
+                    .flat_map(|word| find::matches(text, word))
+                    .collect();
+                ranges.sort_by_key(|r| r.start);
+
+                let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+                for range in ranges {
+                    match merged.last_mut() {
+                        Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                        _ => merged.push(range),
+                    }
+                }
+                merged
+            }
+            Terms::Regex(regex) => {
+                let (normalized, origins) = fold::mapped(text, false);
+                regex
+                    .find_iter(&normalized)
+                    .filter(|m| !m.range().is_empty())
+                    .map(|m| origins[m.start()].start..origins[m.end() - 1].end)
+                    .collect()
+            }
+        }
+    }
+}
+
+/// The part of a message around its first match, on one line, along with
+/// the match ranges that fall inside it
+pub fn snippet(text: &str, ranges: &[Range<usize>]) -> (String, Vec<Range<usize>>) {
+    let anchor = ranges.first().cloned().unwrap_or(0..0);
+
+    let start = text[..anchor.start]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT - 1)
+        .map_or(0, |(i, _)| i);
+    let end = text[anchor.end..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT)
+        .map_or(text.len(), |(i, _)| anchor.end + i);
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let offset = snippet.len();
+    // newlines and spaces are both one byte, so the ranges still line up
+    snippet.push_str(&text[start..end].replace('\n', " "));
+    if end < text.len() {
+        snippet.push('…');
+    }
+
+    let ranges = ranges
+        .iter()
+        .filter(|r| r.start < end && r.end > start)
+        .map(|r| r.start.max(start) - start + offset..r.end.min(end) - start + offset)
+        .collect();
+
+    (snippet, ranges)
+}
+
+/// Search every chat for messages containing all of the words in `query`
+/// and passing its filters, grouped by chat with the most recently matching
+/// chat first.
+pub async fn search(cache: &SqlitePool, query: &Query) -> Result<SearchResults> {
+    let mut sql = Sql::new(
+        r#"
+            SELECT
+                rowid, chat_identifier, text, sender, is_from_me, date, files, transcription
+            from message_fts
+            where 1
+        "#,
+    );
+    if !query.words.is_empty() {
+        sql.push(" and message_fts MATCH ")
+            .bind(fts_query(&query.words));
+    }
+    query.index_conditions(&mut sql);
+    sql.push(" order by date desc, rowid desc limit ")
+        .bind(MAX_RESULTS)
+        .push(";");
+
+    let rows = sqlx::query_as_with::<
+        _,
+        (
+            i64,
+            String,
+            String,
+            String,
+            bool,
+            i64,
+            Option<String>,
+            Option<String>,
+        ),
+        _,
+    >(&sql.text, sql.args)
+    .fetch_all(cache)
+    .await?;
+
+    let mut results = SearchResults::default();
+
+    for (id, chat, text, sender, is_from_me, timestamp, files, transcription) in rows {
+        let sender = Sender::new(is_from_me, sender);
+        let message = hit(id, text, timestamp, sender, files, transcription);
+
+        results.push(chat, message);
+    }
+    results.finish();
+
+    Ok(results)
+}
+
+/// Compile a user-supplied pattern, rejecting ones that would be huge
+pub fn compile_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+/// Search every message passing the query's filters for a regex match.
+/// Gives up after [`REGEX_TIMEOUT`] so that an expensive pattern on a huge
+/// database can't run forever, returning whatever was found up to that point.
+pub async fn regex_search(db: &SqlitePool, query: &Query, regex: Regex) -> Result<SearchResults> {
+    let started = Instant::now();
+    let mut results = SearchResults::default();
+    let mut hits = 0;
+
+    let mut sql = Sql::new(
+        r#"
+            SELECT
+                m.ROWID, c.chat_identifier, coalesce(m.text, ''), m.date, coalesce(h.id, ''), m.is_from_me,
+                (
+                    select group_concat(coalesce(a.transfer_name, a.filename), char(10))
+                    from message_attachment_join maj
+                    join attachment a
+                        on a.ROWID = maj.attachment_id
+                    where maj.message_id = m.ROWID
+                ),
+                (
+                    select a.user_info
+                    from message_attachment_join maj
+                    join attachment a
+                        on a.ROWID = maj.attachment_id
+                    where maj.message_id = m.ROWID
+                        and a.mime_type like 'audio/%'
+                    limit 1
+                )
+            from message m
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            left join handle h
+                on m.handle_id = h.ROWID
+            where 1
+        "#,
+    );
+    query.source_conditions(&mut sql);
+    sql.push(" order by m.date desc, m.ROWID desc;");
+
+    let mut rows = sqlx::query_as_with::<
+        _,
+        (
+            i64,
+            String,
+            String,
+            i64,
+            String,
+            bool,
+            Option<String>,
+            Option<Vec<u8>>,
+        ),
+        _,
+    >(&sql.text, sql.args)
+    .fetch(db);
+
+    while let Some((id, chat, text, timestamp, sender, is_from_me, files, user_info)) =
+        rows.try_next().await?
+    {
+        if started.elapsed() > REGEX_TIMEOUT {
+            results.timed_out = true;
+            break;
+        }
+
+        let transcription = attachment::transcription(user_info);
+        let matches = |text: &str| regex.is_match(&fold::normalize(text));
+        if !matches(&text) && !transcription.as_deref().is_some_and(matches) {
+            continue;
+        }
+
+        let sender = Sender::new(is_from_me, sender);
+        let message = hit(id, text, timestamp, sender, files, transcription);
+        results.push(chat, message);
+
+        hits += 1;
+        if hits >= MAX_RESULTS {
+            break;
+        }
+    }
+    results.finish();
+
+    Ok(results)
+}
+
+/// Search without the FTS index (e.g. while it is still being built) by
+/// running a query per chat concurrently on the runtime. Results are
+/// published into `handle` as each chat finishes rather than all at once.
+pub async fn scan(db: SqlitePool, query: Query, handle: Handle<SearchResults>) -> Result<()> {
+    let query = Arc::new(query);
+
+    let chats = sqlx::query_as::<_, (String,)>("SELECT DISTINCT chat_identifier from chat;")
+        .fetch_all(&db)
+        .await?;
+
+    let total = chats.len();
+    handle.set(State::Ready(SearchResults {
+        progress: Some((0, total)),
+        ..SearchResults::default()
+    }));
+
+    let semaphore = Arc::new(Semaphore::new(SCAN_CONCURRENCY));
+    let mut tasks = Vec::with_capacity(total);
+
+    for (chat,) in chats {
+        let db = db.clone();
+        let query = query.clone();
+        let handle = handle.clone();
+        let semaphore = semaphore.clone();
+
+        tasks.push(tokio::spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            let messages = scan_chat(&db, &chat, &query).await?;
+
+            handle.update(|results| {
+                if !messages.is_empty() {
+                    results.insert(ChatResults { chat, messages });
+                }
+                if let Some((done, _)) = &mut results.progress {
+                    *done += 1;
+                }
+            });
+
+            Ok::<_, eyre::Report>(())
+        }));
+    }
+
+    for task in tasks {
+        task.await??;
+    }
+
+    handle.update(|results| results.progress = None);
+
+    Ok(())
+}
+
+/// Messages in one chat containing every word in the query and passing its
+/// filters
+async fn scan_chat(db: &SqlitePool, chat: &str, query: &Query) -> Result<Vec<Message>> {
+    let mut sql = Sql::new(
+        r#"
+            SELECT
+                m.ROWID, coalesce(m.text, ''), m.date, coalesce(h.id, ''), m.is_from_me,
+                (
+                    select group_concat(coalesce(a.transfer_name, a.filename), char(10))
+                    from message_attachment_join maj
+                    join attachment a
+                        on a.ROWID = maj.attachment_id
+                    where maj.message_id = m.ROWID
+                ),
+                (
+                    select a.user_info
+                    from message_attachment_join maj
+                    join attachment a
+                        on a.ROWID = maj.attachment_id
+                    where maj.message_id = m.ROWID
+                        and a.mime_type like 'audio/%'
+                    limit 1
+                )
+            from message m
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            left join handle h
+                on m.handle_id = h.ROWID
+            where c.chat_identifier=
+        "#,
+    );
+    sql.bind(chat.to_owned());
+    for word in &query.words {
+        sql.push(" and instr(fold(m.text), ")
+            .bind(fold::fold(word))
+            .push(") > 0");
+    }
+    query.source_conditions(&mut sql);
+    sql.push(" order by m.date, m.ROWID limit ")
+        .bind(MAX_RESULTS)
+        .push(";");
+
+    let messages = sqlx::query_as_with::<
+        _,
+        (
+            i64,
+            String,
+            i64,
+            String,
+            bool,
+            Option<String>,
+            Option<Vec<u8>>,
+        ),
+        _,
+    >(&sql.text, sql.args)
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(
+        |(id, text, timestamp, sender, is_from_me, files, user_info)| {
+            let sender = Sender::new(is_from_me, sender);
+            let transcription = attachment::transcription(user_info);
+            hit(id, text, timestamp, sender, files, transcription)
+        },
+    )
+    .collect();
+
+    Ok(messages)
+}
+
+/// A message as search results show it: its text, the names of its files and
+/// any transcription, without the images, reactions etc. drawn in the chat
+fn hit(
+    id: i64,
+    text: String,
+    timestamp: i64,
+    sender: Sender,
+    files: Option<String>,
+    transcription: Option<String>,
+) -> Message {
+    Message {
+        id,
+        guid: String::new(),
+        sticker_on: None,
+        tapback_on: None,
+        text,
+        attachments: attachment::names(files),
+        images: Vec::new(),
+        audio: Vec::new(),
+        service: String::new(),
+        effect: None,
+        balloon: None,
+        location: None,
+        contacts: Vec::new(),
+        event: None,
+        mentions: Vec::new(),
+        collapsed: false,
+        stickers: Vec::new(),
+        reactions: Vec::new(),
+        transcription,
+        date: time(timestamp),
+        sender,
+    }
+}
+
+/// Turn free text into an FTS5 query: every word must appear (as a prefix),
+/// and FTS operators typed by the user are treated as plain text.
+fn fts_query(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|word| format!("\"{}\"*", fold::fold(word).replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, Demo};
+
+    /// Each chat with the ROWIDs of its hits, in the order they're shown
+    fn hits(results: &SearchResults) -> Vec<(String, Vec<i64>)> {
+        results
+            .chats
+            .iter()
+            .map(|chat| {
+                let ids = chat.messages.iter().map(|m| m.id).collect();
+                (chat.chat.clone(), ids)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn index_and_scan_agree() {
+        let demo = Demo::generate().await;
+        let db = demo.connect().await;
+        let cache = test_util::cache().await;
+        build_index(&db, &cache).await.unwrap();
+
+        for input in [
+            "beach",
+            "cafe",
+            "CAFÉ tomorrow",
+            "beach after:2021-01-01",
+            "nothing",
+        ] {
+            let query = Query::parse(input).unwrap();
+            let indexed = search(&cache, &query).await.unwrap();
+
+            let handle = Handle::new();
+            scan(db.clone(), query, handle.clone()).await.unwrap();
+            let scanned = match &*handle.get() {
+                State::Ready(results) => results.clone(),
+                _ => panic!("{input}: scan finished without results"),
+            };
+            assert_eq!(scanned.progress, None, "{input}");
+
+            assert_eq!(hits(&indexed), hits(&scanned), "{input}");
+            assert_eq!(hits(&indexed).is_empty(), input == "nothing", "{input}");
+        }
+
+        // accents and case fold away
+        let query = Query::parse("cafe").unwrap();
+        let found = search(&cache, &query).await.unwrap();
+        let texts: Vec<_> = found.chats[0].messages.iter().map(|m| &m.text).collect();
+        assert_eq!(texts.len(), 2, "{texts:?}");
+    }
+
+    #[tokio::test]
+    async fn indexes_only_new_messages() {
+        let demo = Demo::generate().await;
+        let db = demo.connect().await;
+        let cache = test_util::cache().await;
+        let count = || async {
+            sqlx::query_as::<_, (i64,)>("SELECT count(*) from message_fts;")
+                .fetch_one(&cache)
+                .await
+                .unwrap()
+                .0
+        };
+
+        build_index(&db, &cache).await.unwrap();
+        let indexed = count().await;
+        let (messages,) = sqlx::query_as::<_, (i64,)>(
+            "SELECT count(DISTINCT message_id) from chat_message_join;",
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(indexed, messages);
+
+        build_index(&db, &cache).await.unwrap();
+        assert_eq!(count().await, indexed);
+
+        // voice messages are found by what was said
+        let query = Query::parse("avalanche").unwrap();
+        let found = search(&cache, &query).await.unwrap();
+        assert_eq!(found.chats.len(), 1);
+        assert!(found.chats[0].messages[0].transcription.is_some());
+    }
+
+    #[tokio::test]
+    async fn rebuilds_the_index_for_an_older_copy() {
+        let demo = Demo::generate().await;
+        let db = demo.connect().await;
+        let cache = test_util::cache().await;
+        build_index(&db, &cache).await.unwrap();
+
+        // the same database as it was before its newest messages arrived
+        let writable = demo.writable().await;
+        sqlx::query("DELETE FROM message where ROWID > (SELECT max(ROWID) - 10 from message);")
+            .execute(&writable)
+            .await
+            .unwrap();
+        build_index(&db, &cache).await.unwrap();
+
+        let (indexed,) = sqlx::query_as::<_, (i64,)>("SELECT max(rowid) from message_fts;")
+            .fetch_one(&cache)
+            .await
+            .unwrap();
+        let (newest,) = sqlx::query_as::<_, (i64,)>(
+            "SELECT max(message_id) from chat_message_join where message_id in (SELECT ROWID from message);",
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(indexed, newest);
+    }
+
+    #[tokio::test]
+    async fn regex_searches_text_and_transcriptions() {
+        let demo = Demo::generate().await;
+        let db = demo.connect().await;
+
+        let regex = compile_regex(r"\bski(s|\s+trip)\b").unwrap();
+        let found = regex_search(&db, &Query::default(), regex).await.unwrap();
+        let texts: Vec<_> = found
+            .chats
+            .iter()
+            .flat_map(|chat| chat.messages.iter().map(|m| m.text.as_str()))
+            .collect();
+        assert!(!texts.is_empty());
+        assert!(texts.iter().all(|text| text.contains("ski")), "{texts:?}");
+
+        let regex = compile_regex("avalanche beacon").unwrap();
+        let found = regex_search(&db, &Query::default(), regex).await.unwrap();
+        assert_eq!(found.chats.len(), 1);
+        assert!(!found.timed_out);
+    }
+
+    #[test]
+    fn quotes_fts_words() {
+        let words = ["Café".to_owned(), "say \"hi\"".to_owned(), "OR".to_owned()];
+        assert_eq!(fts_query(&words), r#""cafe"* "say ""hi"""* "or"*"#);
+    }
+
+    #[test]
+    fn merges_overlapping_ranges() {
+        let terms = Terms::Words(vec!["ski".to_owned(), "skis".to_owned(), "the".to_owned()]);
+        assert_eq!(
+            terms.ranges("bring the skis, the ski"),
+            [6..9, 10..14, 16..19, 20..23]
+        );
+
+        let terms = Terms::Regex(compile_regex("cafe").unwrap());
+        let text = "un CAFÉ, un café";
+        let ranges = terms.ranges(text);
+        let found: Vec<_> = ranges.iter().map(|r| &text[r.clone()]).collect();
+        assert_eq!(found, ["CAFÉ", "café"]);
+    }
+
+    #[test]
+    fn snippets_around_the_first_match() {
+        let text = format!("{}\nmatch here{}", "a".repeat(60), "b".repeat(60));
+        let start = text.find("match").unwrap();
+        let matched = start..start + 5;
+        let (snippet, ranges) = snippet(&text, std::slice::from_ref(&matched));
+
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        assert!(!snippet.contains('\n'));
+        assert_eq!(&snippet[ranges[0].clone()], "match");
+
+        let (snippet, ranges) = super::snippet("short", &[]);
+        assert_eq!(snippet, "short");
+        assert!(ranges.is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    ops::Range,
+    os::raw::{c_char, c_int},
+    sync::RwLock,
+};
+
+use libsqlite3_sys as ffi;
+use unicode_normalization::{
+    char::{decompose_canonical, decompose_compatible, is_combining_mark},
+    UnicodeNormalization,
+};
+
+use crate::config::{CaseFolding, Normalization, SearchConfig};
+
+lazy_static::lazy_static! {
+    static ref SETTINGS: RwLock<SearchConfig> = RwLock::new(SearchConfig::default());
+}
+
+/// Set how text is folded from the user's config. Call before anything is
+/// searched or indexed.
+pub fn configure(settings: &SearchConfig) {
+    *SETTINGS.write().unwrap() = settings.clone();
+}
+
+/// A number identifying the current settings, so that an index built with
+/// different ones can be spotted
+pub fn settings_id() -> i64 {
+    let settings = SETTINGS.read().unwrap();
+
+    i64::from(settings.ignore_diacritics)
+        | (settings.normalization as i64) << 1
+        | (settings.case_folding as i64) << 2
+}
+
+/// Text in the form search terms and messages are compared in
+pub fn fold(text: &str) -> String {
+    mapped(text, true).0
+}
+
+/// Like [`fold`] but keeping case, for regex searches which handle case
+/// themselves
+pub fn normalize(text: &str) -> String {
+    mapped(text, false).0
+}
+
+/// Fold `text`, along with the byte range in `text` that each byte of the
+/// result came from
+pub fn mapped(text: &str, fold_case: bool) -> (String, Vec<Range<usize>>) {
+    let settings = SETTINGS.read().unwrap();
+    let mut folded = String::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(text.len());
+
+    for (i, c) in text.char_indices() {
+        fold_char(c, &settings, fold_case, &mut folded);
+        origins.resize(folded.len(), i..i + c.len_utf8());
+    }
+
+    (folded, origins)
+}
+
+fn fold_char(c: char, settings: &SearchConfig, fold_case: bool, out: &mut String) {
+    let turkic_i = fold_case && settings.case_folding == CaseFolding::Turkic && c == 'I';
+    if c.is_ascii() && !turkic_i {
+        out.push(if fold_case { c.to_ascii_lowercase() } else { c });
+        return;
+    }
+
+    let c = match (fold_case, settings.case_folding, c) {
+        (true, CaseFolding::Turkic, 'I') => 'ı',
+        (true, CaseFolding::Turkic, 'İ') => 'i',
+        _ => c,
+    };
+
+    let mut decomposed = String::new();
+    let mut push = |d: char| {
+        if !(settings.ignore_diacritics && is_combining_mark(d)) {
+            decomposed.push(d);
+        }
+    };
+    match settings.normalization {
+        Normalization::Nfc => decompose_canonical(c, &mut push),
+        Normalization::Nfkc => decompose_compatible(c, &mut push),
+    }
+
+    let cased = if fold_case {
+        decomposed
+            .chars()
+            .flat_map(|d| match (settings.case_folding, d) {
+                (CaseFolding::Simple, _) => d.to_lowercase().collect::<Vec<_>>(),
+                (_, 'ß' | 'ẞ') => vec!['s', 's'],
+                (_, 'ς') => vec!['σ'],
+                _ => d.to_lowercase().collect(),
+            })
+            .collect()
+    } else {
+        decomposed
+    };
+
+    if settings.ignore_diacritics {
+        out.push_str(&cased);
+    } else {
+        // an accent written as a separate mark composes with the letter
+        // before it, the same as the precomposed letter would
+        let mut start = out.len();
+        if cased.starts_with(is_combining_mark) {
+            start -= out.chars().next_back().map_or(0, char::len_utf8);
+        }
+        let composed: String = out[start..].chars().chain(cased.chars()).nfc().collect();
+        out.truncate(start);
+        out.push_str(&composed);
+    }
+}
+
+/// Make `fold(text)` available to SQL on a connection
+///
+/// # Safety
+///
+/// `handle` must be a valid, open connection not in use by another thread
+pub unsafe fn register(handle: *mut ffi::sqlite3) {
+    ffi::sqlite3_create_function_v2(
+        handle,
+        c"fold".as_ptr(),
+        1,
+        ffi::SQLITE_UTF8 | ffi::SQLITE_DETERMINISTIC,
+        std::ptr::null_mut(),
+        Some(fold_sql),
+        None,
+        None,
+        None,
+    );
+}
+
+unsafe extern "C" fn fold_sql(
+    ctx: *mut ffi::sqlite3_context,
+    _: c_int,
+    argv: *mut *mut ffi::sqlite3_value,
+) {
+    let value = *argv;
+    let text = ffi::sqlite3_value_text(value);
+    if text.is_null() {
+        ffi::sqlite3_result_null(ctx);
+        return;
+    }
+
+    let bytes = std::slice::from_raw_parts(text, ffi::sqlite3_value_bytes(value) as usize);
+    let folded = fold(&String::from_utf8_lossy(bytes));
+
+    ffi::sqlite3_result_text(
+        ctx,
+        folded.as_ptr() as *const c_char,
+        folded.len() as c_int,
+        ffi::SQLITE_TRANSIENT(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fold `text` with `settings`, leaving the global ones alone for tests
+    /// running alongside
+    fn fold_with(text: &str, settings: &SearchConfig) -> String {
+        let mut out = String::new();
+        for c in text.chars() {
+            fold_char(c, settings, true, &mut out);
+        }
+        out
+    }
+
+    fn settings(
+        ignore_diacritics: bool,
+        normalization: Normalization,
+        case_folding: CaseFolding,
+    ) -> SearchConfig {
+        SearchConfig {
+            ignore_diacritics,
+            normalization,
+            case_folding,
+        }
+    }
+
+    #[test]
+    fn diacritics() {
+        assert_eq!(fold("café"), "cafe");
+        assert_eq!(fold("CAFÉ"), "cafe");
+        // a combining acute accent rather than a precomposed é
+        assert_eq!(fold("cafe\u{301}"), "cafe");
+        assert_eq!(fold("Ångström"), "angstrom");
+
+        let keep = settings(false, Normalization::Nfkc, CaseFolding::Full);
+        assert_eq!(fold_with("café", &keep), "café");
+        assert_eq!(fold_with("cafe\u{301}", &keep), "café");
+    }
+
+    #[test]
+    fn case_folding() {
+        assert_eq!(fold("Straße"), "strasse");
+        assert_eq!(fold("STRAẞE"), "strasse");
+        assert_eq!(fold("ὈΔΥΣΣΕΎΣ"), fold("ὀδυσσεύς"));
+
+        let simple = settings(true, Normalization::Nfkc, CaseFolding::Simple);
+        assert_eq!(fold_with("Straße", &simple), "straße");
+
+        let turkic = settings(true, Normalization::Nfkc, CaseFolding::Turkic);
+        assert_eq!(fold_with("DİYARBAKIR", &turkic), "diyarbakır");
+    }
+
+    #[test]
+    fn compatibility_forms() {
+        assert_eq!(fold("ﬁle"), "file");
+        assert_eq!(fold("ＡＢＣ"), "abc");
+
+        let canonical = settings(true, Normalization::Nfc, CaseFolding::Full);
+        assert_eq!(fold_with("ﬁle", &canonical), "ﬁle");
+    }
+
+    #[test]
+    fn origins_point_back_into_the_text() {
+        let text = "Éa";
+        let (folded, origins) = mapped(text, true);
+        assert_eq!(folded, "ea");
+        assert_eq!(origins, vec![0..2, 2..3]);
+    }
+}
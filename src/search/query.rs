@@ -0,0 +1,411 @@
+use chrono::prelude::*;
+
+use crate::{apple_time, config::Config, people, sql::Sql};
+
+/// A search as typed by the user: free text plus optional filters, e.g.
+/// `from:mom after:2021-01-01 has:image beach`. Double quotes keep spaces in
+/// a word or a filter's value, as in `"boarding pass"` or `from:"aunt may"`.
+#[derive(Clone, Default)]
+pub struct Query {
+    pub words: Vec<String>,
+    /// `from:` - "me", or part of the sender's handle or name
+    pub from: Option<String>,
+    /// Handles of everyone whose name contains `from`, filled in by
+    /// [`Query::resolve_names`]
+    pub from_handles: Vec<String>,
+    /// `before:` - only messages sent before this day
+    pub before: Option<NaiveDate>,
+    /// `after:` - only messages sent on or after this day
+    pub after: Option<NaiveDate>,
+    /// `has:` - only messages with this kind of content
+    pub has: Vec<Has>,
+    /// `file:` - only messages with an attachment whose name, mime type or
+    /// UTI contains this, e.g. `file:pdf` or `file:IMG_2041`
+    pub file: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Has {
+    Attachment,
+    Image,
+    Video,
+    Audio,
+    Link,
+}
+
+impl Has {
+    fn parse(value: &str) -> Result<Self, String> {
+        Ok(match value {
+            "attachment" => Has::Attachment,
+            "image" | "photo" => Has::Image,
+            "video" => Has::Video,
+            "audio" => Has::Audio,
+            "link" => Has::Link,
+            _ => {
+                return Err(format!(
+                    "unknown has:{value} - expected attachment, image, video, audio or link"
+                ))
+            }
+        })
+    }
+
+    /// Prefix of the attachment mime types this matches
+    fn mime_prefix(self) -> Option<&'static str> {
+        match self {
+            Has::Attachment => Some(""),
+            Has::Image => Some("image/"),
+            Has::Video => Some("video/"),
+            Has::Audio => Some("audio/"),
+            Has::Link => None,
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut query = Query::default();
+
+        for token in tokens(input)? {
+            match token.split_once(':') {
+                Some(("from", value)) => query.from = Some(value.to_lowercase()),
+                Some(("before", value)) => query.before = Some(parse_date(value)?),
+                Some(("after", value)) => query.after = Some(parse_date(value)?),
+                Some(("has", value)) => query.has.push(Has::parse(value)?),
+                Some(("file", value)) => query.file = Some(value.to_lowercase()),
+                _ => query.words.push(token),
+            }
+        }
+
+        Ok(query)
+    }
+
+    /// Find the handles of the people `from:` names, by the names Contacts,
+    /// merged people and aliases give them
+    pub fn resolve_names(&mut self, config: &Config) {
+        let from = match self.from.as_ref().filter(|_| !self.sent_by_me()) {
+            Some(from) => from,
+            None => return,
+        };
+
+        let people = people::merged(&config.contacts, &config.people);
+        let mut handles: Vec<String> = people
+            .iter()
+            .filter(|person| person.name.to_lowercase().contains(from.as_str()))
+            .flat_map(|person| person.handles.iter().cloned())
+            .chain(
+                config
+                    .me
+                    .aliases
+                    .iter()
+                    .filter(|(_, alias)| alias.to_lowercase().contains(from.as_str()))
+                    .map(|(handle, _)| handle.clone()),
+            )
+            .collect();
+        handles.sort();
+        handles.dedup();
+        self.from_handles = handles;
+    }
+
+    /// The free text part of the query
+    pub fn text(&self) -> String {
+        self.words.join(" ")
+    }
+
+    fn sent_by_me(&self) -> bool {
+        self.from.as_deref() == Some("me")
+    }
+
+    /// Append the filters as `and ...` conditions over the source database,
+    /// where the message table is aliased `m` and the handle table `h`
+    pub fn source_conditions(&self, sql: &mut Sql) {
+        self.common_conditions(sql, "m.date", "m.is_from_me", "m.text");
+
+        if let Some(from) = self.from.as_ref().filter(|_| !self.sent_by_me()) {
+            sql.push(" and m.is_from_me = 0 and ");
+            self.sender_condition(sql, "h.id", from);
+        }
+
+        for has in &self.has {
+            if let Some(prefix) = has.mime_prefix() {
+                sql.push(
+                    r#" and exists (
+                        select 1 from message_attachment_join maj
+                        join attachment a on a.ROWID = maj.attachment_id
+                        where maj.message_id = m.ROWID and a.mime_type like "#,
+                )
+                .bind(format!("{prefix}%"))
+                .push(")");
+            }
+        }
+
+        if let Some(file) = &self.file {
+            let pattern = containing(file);
+            sql.push(
+                r#" and exists (
+                    select 1 from message_attachment_join maj
+                    join attachment a on a.ROWID = maj.attachment_id
+                    where maj.message_id = m.ROWID and (
+                        lower(coalesce(a.transfer_name, a.filename)) like "#,
+            )
+            .bind(pattern.clone())
+            .push(ESCAPE)
+            .push(" or lower(a.mime_type) like ")
+            .bind(pattern.clone())
+            .push(ESCAPE)
+            .push(" or lower(a.uti) like ")
+            .bind(pattern)
+            .push(ESCAPE)
+            .push("))");
+        }
+    }
+
+    /// Append the filters as `and ...` conditions over the FTS index table
+    pub fn index_conditions(&self, sql: &mut Sql) {
+        self.common_conditions(sql, "date", "is_from_me", "text");
+
+        if let Some(from) = self.from.as_ref().filter(|_| !self.sent_by_me()) {
+            sql.push(" and is_from_me = 0 and ");
+            self.sender_condition(sql, "sender", from);
+        }
+
+        for has in &self.has {
+            if let Some(prefix) = has.mime_prefix() {
+                sql.push(" and (' ' || attachments) like ")
+                    .bind(format!("% {prefix}%"));
+            }
+        }
+
+        if let Some(file) = &self.file {
+            let pattern = containing(file);
+            sql.push(" and (lower(files) like ")
+                .bind(pattern.clone())
+                .push(ESCAPE)
+                .push(" or lower(attachments) like ")
+                .bind(pattern.clone())
+                .push(ESCAPE)
+                .push(" or lower(utis) like ")
+                .bind(pattern)
+                .push(ESCAPE)
+                .push(")");
+        }
+    }
+
+    /// `sender`, a column of handles, contains `from` or is the handle of
+    /// someone whose name does
+    fn sender_condition(&self, sql: &mut Sql, sender: &str, from: &str) {
+        sql.push(&format!("(lower({sender}) like "))
+            .bind(containing(from))
+            .push(ESCAPE);
+        for handle in &self.from_handles {
+            sql.push(&format!(" or {sender} = ")).bind(handle.clone());
+        }
+        sql.push(")");
+    }
+
+    /// Conditions that are written the same way for both databases
+    fn common_conditions(&self, sql: &mut Sql, date: &str, is_from_me: &str, text: &str) {
+        if self.sent_by_me() {
+            sql.push(&format!(" and {is_from_me} = 1"));
+        }
+
+        if let Some(before) = self.before {
+            sql.push(&format!(" and {date} < ")).bind(day_start(before));
+        }
+
+        if let Some(after) = self.after {
+            sql.push(&format!(" and {date} >= ")).bind(day_start(after));
+        }
+
+        if self.has.contains(&Has::Link) {
+            sql.push(&format!(" and {text} like '%http%'"));
+        }
+    }
+}
+
+/// Escape character for `LIKE` patterns made by [`containing`]
+const ESCAPE: &str = r" escape '\'";
+
+/// A `LIKE` pattern for text containing `text`, with any `%` and `_` typed
+/// in it matched as themselves
+fn containing(text: &str) -> String {
+    let escaped = text
+        .replace('\\', r"\\")
+        .replace('%', r"\%")
+        .replace('_', r"\_");
+    format!("%{escaped}%")
+}
+
+/// Split a query into words and filters at spaces, except inside double
+/// quotes, which are dropped
+fn tokens(input: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    // so that `""` is kept as an empty word rather than dropped
+    let mut started = false;
+
+    for c in input.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    tokens.push(std::mem::take(&mut token));
+                    started = false;
+                }
+            }
+            c => {
+                token.push(c);
+                started = true;
+            }
+        }
+    }
+    if quoted {
+        return Err("missing closing quote".to_owned());
+    }
+    if started {
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("invalid date {value:?} - expected YYYY-MM-DD"))
+}
+
+/// Midnight at the start of a day where the viewer is, in Apple's time
+/// format
+fn day_start(date: NaiveDate) -> i64 {
+    let midnight = date.and_hms(0, 0, 0);
+    // midnight can be skipped by a change of clocks, and then the day starts
+    // when it would have been in UTC
+    let start = match Local.from_local_datetime(&midnight).earliest() {
+        Some(start) => start.with_timezone(&Utc),
+        None => Utc.from_utc_datetime(&midnight),
+    };
+    apple_time(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_and_filters() {
+        let query = Query::parse(
+            "beach from:Mom after:2021-01-01 before:2021-02-01 has:photo has:link file:PDF day",
+        )
+        .unwrap();
+
+        assert_eq!(query.words, ["beach", "day"]);
+        assert_eq!(query.from.as_deref(), Some("mom"));
+        assert_eq!(query.after, NaiveDate::from_ymd_opt(2021, 1, 1));
+        assert_eq!(query.before, NaiveDate::from_ymd_opt(2021, 2, 1));
+        assert_eq!(query.has, [Has::Image, Has::Link]);
+        assert_eq!(query.file.as_deref(), Some("pdf"));
+        assert_eq!(query.text(), "beach day");
+    }
+
+    #[test]
+    fn from_me() {
+        let query = Query::parse("from:me").unwrap();
+        assert!(query.sent_by_me());
+        assert!(query.words.is_empty());
+    }
+
+    #[test]
+    fn every_kind_of_has() {
+        let query = Query::parse("has:attachment has:image has:video has:audio has:link").unwrap();
+        assert_eq!(
+            query.has,
+            [
+                Has::Attachment,
+                Has::Image,
+                Has::Video,
+                Has::Audio,
+                Has::Link
+            ]
+        );
+        assert!(Query::parse("has:gif").is_err());
+    }
+
+    #[test]
+    fn quotes_keep_spaces() {
+        let query = Query::parse(r#""boarding pass" from:"Aunt May" gate"#).unwrap();
+        assert_eq!(query.words, ["boarding pass", "gate"]);
+        assert_eq!(query.from.as_deref(), Some("aunt may"));
+
+        let query = Query::parse(r#""from:mom""#).unwrap();
+        assert_eq!(query.from.as_deref(), Some("mom"));
+
+        assert!(Query::parse(r#"from:"aunt may"#).is_err());
+    }
+
+    #[test]
+    fn unknown_filters_are_words() {
+        let query = Query::parse("to:mom 10:30").unwrap();
+        assert_eq!(query.words, ["to:mom", "10:30"]);
+        assert!(query.from.is_none());
+    }
+
+    #[test]
+    fn invalid_dates() {
+        for input in [
+            "after:2021-13-01",
+            "before:2021-02-30",
+            "after:yesterday",
+            "before:01/02/2021",
+            "after:",
+        ] {
+            let error = Query::parse(input).err().unwrap_or_default();
+            assert!(error.contains("expected YYYY-MM-DD"), "{input}: {error:?}");
+        }
+    }
+
+    #[test]
+    fn like_patterns_escape_wildcards() {
+        assert_eq!(containing("mom"), "%mom%");
+        assert_eq!(containing("50%_off"), r"%50\%\_off%");
+        assert_eq!(containing(r"a\b"), r"%a\\b%");
+    }
+
+    #[test]
+    fn days_start_at_local_midnight() {
+        let day = NaiveDate::from_ymd_opt(2021, 6, 1).unwrap();
+        let midnight = Local
+            .from_local_datetime(&day.and_hms(0, 0, 0))
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(day_start(day), apple_time(midnight));
+    }
+
+    #[test]
+    fn names_resolve_to_handles() {
+        let mut config = Config::default();
+        config.people.push(crate::config::Person {
+            name: "Aunt May".to_owned(),
+            handles: vec!["+15555550100".to_owned(), "may@example.com".to_owned()],
+            photo: None,
+        });
+        config
+            .me
+            .aliases
+            .insert("+15555550142".to_owned(), "Landlord".to_owned());
+
+        let mut query = Query::parse("from:may").unwrap();
+        query.resolve_names(&config);
+        assert_eq!(query.from_handles, ["+15555550100", "may@example.com"]);
+
+        let mut query = Query::parse("from:land").unwrap();
+        query.resolve_names(&config);
+        assert_eq!(query.from_handles, ["+15555550142"]);
+
+        let mut query = Query::parse("from:me").unwrap();
+        query.resolve_names(&config);
+        assert!(query.from_handles.is_empty());
+    }
+}
@@ -0,0 +1,305 @@
+use std::path::PathBuf;
+
+use egui::Color32;
+
+use crate::{
+    config::{Config, Direction, HeicFormat, IgnoreAction, IgnoreRule, Language, Theme},
+    i18n, theme,
+};
+
+/// Draw the settings window. Changes apply immediately and are written to
+/// the config file when the user clicks save.
+pub fn show(ctx: &egui::Context, config: &mut Config, open: &mut bool) {
+    egui::Window::new(i18n::t("settings"))
+        .id(egui::Id::new("settings"))
+        .open(open)
+        .show(ctx, |ui| {
+            ui.heading(i18n::t("me"));
+
+            egui::Grid::new("identity").num_columns(2).show(ui, |ui| {
+                ui.label(i18n::t("display name"));
+                ui.text_edit_singleline(&mut config.me.display_name);
+                ui.end_row();
+
+                ui.label(i18n::t("avatar"));
+                let mut avatar = config
+                    .me
+                    .avatar
+                    .as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default();
+                if ui.text_edit_singleline(&mut avatar).changed() {
+                    config.me.avatar =
+                        Some(PathBuf::from(avatar)).filter(|p| !p.as_os_str().is_empty());
+                }
+                ui.end_row();
+
+                ui.label(i18n::t("my handles")).on_hover_text(i18n::t(
+                    "only needed for a database that belongs to someone else, one per line",
+                ));
+                let mut handles = config.me.handles.join("\n");
+                if ui.text_edit_multiline(&mut handles).changed() {
+                    config.me.handles = handles.split('\n').map(String::from).collect();
+                }
+                ui.end_row();
+
+                ui.label(i18n::t("database owner's name"));
+                ui.text_edit_singleline(&mut config.me.owner_name);
+                ui.end_row();
+            });
+
+            ui.heading(i18n::t("exports"));
+
+            egui::Grid::new("exports").num_columns(2).show(ui, |ui| {
+                let heic = &mut config.heic_export;
+
+                ui.label(i18n::t("convert HEIC photos to"))
+                    .on_hover_text(i18n::t(
+                        "for photo exports, so they open without an Apple device",
+                    ));
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut heic.format, HeicFormat::Jpeg, "JPEG");
+                    ui.radio_value(&mut heic.format, HeicFormat::Png, "PNG");
+                });
+                ui.end_row();
+
+                ui.label(i18n::t("JPEG quality"));
+                ui.add_enabled(
+                    heic.format == HeicFormat::Jpeg,
+                    egui::Slider::new(&mut heic.quality, 1..=100),
+                );
+                ui.end_row();
+            });
+
+            ui.heading(i18n::t("appearance"));
+
+            ui.horizontal(|ui| {
+                ui.label(i18n::t("theme"));
+                let theme = &mut config.appearance.theme;
+                ui.radio_value(theme, Theme::System, i18n::t("system"))
+                    .on_hover_text(i18n::t(
+                        "whichever the system was using when the app started",
+                    ));
+                ui.radio_value(theme, Theme::Light, i18n::t("light"));
+                ui.radio_value(theme, Theme::Dark, i18n::t("dark"));
+            });
+            ui.horizontal(|ui| {
+                ui.label(i18n::t("direction"));
+                let direction = &mut config.appearance.direction;
+                ui.radio_value(direction, Direction::Auto, i18n::t("system"))
+                    .on_hover_text(i18n::t(
+                        "right to left for Arabic, Hebrew, Persian and Urdu",
+                    ));
+                ui.radio_value(direction, Direction::Ltr, i18n::t("left to right"));
+                ui.radio_value(direction, Direction::Rtl, i18n::t("right to left"))
+                    .on_hover_text(i18n::t(
+                        "my messages on the left, everyone else's on the right",
+                    ));
+            });
+            ui.horizontal(|ui| {
+                ui.label(i18n::t("language"));
+                let language = &mut config.appearance.language;
+                ui.radio_value(language, Language::Auto, i18n::t("system"))
+                    .on_hover_text(i18n::t(
+                        "English unless the system's language is one listed here",
+                    ));
+                // each in its own language, so it can be found whatever's showing
+                ui.radio_value(language, Language::En, "English");
+                ui.radio_value(language, Language::De, "Deutsch");
+                ui.radio_value(language, Language::Es, "Español");
+            });
+
+            egui::Grid::new("text").num_columns(2).show(ui, |ui| {
+                ui.label(i18n::t("text size"));
+                ui.add(
+                    egui::Slider::new(&mut config.appearance.text_size, 10.0..=32.0).suffix(" pt"),
+                );
+                ui.end_row();
+
+                ui.label(i18n::t("zoom"))
+                    .on_hover_text(i18n::t("also Cmd + and Cmd -, or from the command palette"));
+                ui.add(
+                    egui::Slider::new(
+                        &mut config.appearance.zoom,
+                        theme::MIN_ZOOM..=theme::MAX_ZOOM,
+                    )
+                    .step_by(theme::ZOOM_STEP as f64)
+                    .suffix("×"),
+                );
+                ui.end_row();
+            });
+
+            let dark = ctx.style().visuals.dark_mode;
+            let defaults = theme::Colors::defaults(dark, config.accessibility.high_contrast);
+            let visuals = if dark {
+                egui::Visuals::dark()
+            } else {
+                egui::Visuals::light()
+            };
+            let colors = &mut config.appearance.colors;
+            egui::Grid::new("colors").num_columns(2).show(ui, |ui| {
+                color(ui, i18n::t("my iMessages"), &mut colors.me, defaults.me);
+                color(
+                    ui,
+                    i18n::t("my text messages"),
+                    &mut colors.sms,
+                    defaults.sms,
+                );
+                color(
+                    ui,
+                    i18n::t("everyone else's"),
+                    &mut colors.them,
+                    defaults.them,
+                );
+                color(
+                    ui,
+                    i18n::t("background"),
+                    &mut colors.background,
+                    visuals.window_fill(),
+                );
+                color(
+                    ui,
+                    i18n::t("accent"),
+                    &mut colors.accent,
+                    visuals.selection.bg_fill,
+                );
+            });
+
+            ui.heading(i18n::t("accessibility"));
+
+            ui.checkbox(
+                &mut config.accessibility.speak_focus,
+                i18n::t("speak focus changes"),
+            )
+            .on_hover_text(i18n::t(
+                "says what's focused or clicked through the system's text-to-speech; this isn't \
+                 screen reader support. Tab moves between chats, messages and buttons.",
+            ));
+            ui.checkbox(
+                &mut config.accessibility.high_contrast,
+                i18n::t("high contrast"),
+            );
+
+            ui.heading(i18n::t("layout"));
+
+            if ui.button(i18n::t("dock every panel")).clicked() {
+                config.layout.sidebar_collapsed = false;
+                config.layout.detached.clear();
+            }
+            ui.checkbox(
+                &mut config.layout.hide_unknown_senders,
+                i18n::t("hide chats from unknown senders"),
+            )
+            .on_hover_text(i18n::t(
+                "handles that aren't in Contacts and haven't been given a name",
+            ));
+
+            ui.horizontal(|ui| {
+                ui.label(i18n::t("load the newest messages of the"));
+                ui.add(egui::DragValue::new(&mut config.prefetch.chats).clamp_range(0..=50));
+                ui.label(i18n::t("most recent chats ahead of time"));
+            })
+            .response
+            .on_hover_text(i18n::t(
+                "so they open straight away; 0 to only load a chat when it's opened",
+            ));
+
+            ui.heading(i18n::t("ignore rules")).on_hover_text(i18n::t(
+                "automated messages to collapse or hide; applies to chats opened after saving",
+            ));
+
+            let mut remove = None;
+            egui::Grid::new("ignore_rules")
+                .num_columns(5)
+                .show(ui, |ui| {
+                    for (i, rule) in config.ignore_rules.iter_mut().enumerate() {
+                        optional_text(ui, &mut rule.chat, i18n::t("in every chat"));
+                        optional_text(ui, &mut rule.sender, i18n::t("from anyone"));
+                        optional_text(ui, &mut rule.text, i18n::t("any text"));
+                        ui.horizontal(|ui| {
+                            ui.radio_value(
+                                &mut rule.action,
+                                IgnoreAction::Collapse,
+                                i18n::t("collapse"),
+                            );
+                            ui.radio_value(&mut rule.action, IgnoreAction::Hide, i18n::t("hide"));
+                        });
+                        if ui
+                            .small_button("x")
+                            .on_hover_text(i18n::t("remove"))
+                            .clicked()
+                        {
+                            remove = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+            if let Some(i) = remove {
+                config.ignore_rules.remove(i);
+            }
+            if ui.button(i18n::t("add rule")).clicked() {
+                config.ignore_rules.push(IgnoreRule::default());
+            }
+
+            ui.heading(i18n::t("clipboard"));
+
+            ui.horizontal(|ui| {
+                let clipboard = &mut config.clipboard;
+                ui.checkbox(
+                    &mut clipboard.confirm,
+                    i18n::t("ask before copying more than"),
+                );
+                ui.add_enabled(
+                    clipboard.confirm,
+                    egui::DragValue::new(&mut clipboard.max_messages),
+                );
+                ui.label(i18n::t("messages or any attachment"));
+            });
+
+            ui.heading(i18n::t("notifications"));
+
+            ui.checkbox(
+                &mut config.notifications.preview,
+                i18n::t("show who sent new messages and what they say"),
+            );
+            ui.weak(i18n::t(
+                "right-click a chat to be notified about it while running with --watch",
+            ));
+
+            if ui.button(i18n::t("save")).clicked() {
+                config.me.handles.retain(|h| !h.trim().is_empty());
+
+                if let Err(e) = config.save() {
+                    eprintln!("{e}");
+                }
+            }
+        });
+}
+
+/// A text box for a setting that is left out when empty
+/// A row of the colours grid: a button to pick `value`, showing `default`
+/// until one is picked, and one to go back to the default
+fn color(ui: &mut egui::Ui, label: &str, value: &mut Option<[u8; 3]>, default: Color32) {
+    ui.label(label);
+    ui.horizontal(|ui| {
+        let [r, g, b, _] = default.to_array();
+        let mut rgb = value.unwrap_or([r, g, b]);
+        if ui.color_edit_button_srgb(&mut rgb).changed() {
+            *value = Some(rgb);
+        }
+        if value.is_some() && ui.small_button(i18n::t("reset")).clicked() {
+            *value = None;
+        }
+    });
+    ui.end_row();
+}
+
+fn optional_text(ui: &mut egui::Ui, value: &mut Option<String>, hint: &str) {
+    let mut text = value.clone().unwrap_or_default();
+    if ui
+        .add(egui::TextEdit::singleline(&mut text).hint_text(hint))
+        .changed()
+    {
+        *value = Some(text).filter(|t| !t.is_empty());
+    }
+}
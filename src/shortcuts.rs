@@ -0,0 +1,313 @@
+use egui::{Context, Event, InputState, Key, Modifiers};
+
+use crate::i18n;
+
+/// A keyboard shortcut. Everything that handles a key press goes through one
+/// of these, so that the cheat sheet lists exactly what is bound.
+pub struct Shortcut {
+    /// Heading the cheat sheet groups it under
+    pub area: &'static str,
+    pub description: &'static str,
+    trigger: Trigger,
+}
+
+enum Trigger {
+    Key(Modifiers, Key),
+    /// A typed character, for keys egui doesn't name like `?`
+    Text(&'static str),
+    /// A character typed with the command key held. Not every platform
+    /// passes these on; macOS doesn't.
+    CommandText(&'static str),
+}
+
+const fn key(area: &'static str, description: &'static str, key: Key) -> Shortcut {
+    with(area, description, Modifiers::NONE, key)
+}
+
+const fn with(
+    area: &'static str,
+    description: &'static str,
+    modifiers: Modifiers,
+    key: Key,
+) -> Shortcut {
+    Shortcut {
+        area,
+        description,
+        trigger: Trigger::Key(modifiers, key),
+    }
+}
+
+const SHIFT: Modifiers = Modifiers {
+    shift: true,
+    ..Modifiers::NONE
+};
+
+const COMMAND: Modifiers = Modifiers {
+    command: true,
+    ..Modifiers::NONE
+};
+
+/// What the command and alt keys are called on this platform
+const COMMAND_LABEL: &str = if cfg!(target_os = "macos") {
+    "⌘"
+} else {
+    "Ctrl"
+};
+const ALT_LABEL: &str = if cfg!(target_os = "macos") {
+    "⌥"
+} else {
+    "Alt"
+};
+
+pub const CHEAT_SHEET: Shortcut = Shortcut {
+    area: "anywhere",
+    description: "show or hide this list",
+    trigger: Trigger::Text("?"),
+};
+pub const PERF: Shortcut = key("anywhere", "performance overlay", Key::F12);
+pub const PALETTE: Shortcut = with("anywhere", "command palette", COMMAND, Key::K);
+pub const ZOOM_IN: Shortcut = Shortcut {
+    area: "anywhere",
+    description: "zoom in",
+    trigger: Trigger::CommandText("="),
+};
+pub const ZOOM_OUT: Shortcut = Shortcut {
+    area: "anywhere",
+    description: "zoom out",
+    trigger: Trigger::CommandText("-"),
+};
+pub const ZOOM_RESET: Shortcut = with("anywhere", "reset zoom", COMMAND, Key::Num0);
+pub const CLEAR: Shortcut = key(
+    "anywhere",
+    "close search results, or the open chat",
+    Key::Escape,
+);
+
+pub const SEARCH: Shortcut = key("search box", "search", Key::Enter);
+
+pub const CHAT_UP: Shortcut = key("chat list", "previous chat", Key::ArrowUp);
+pub const CHAT_DOWN: Shortcut = key("chat list", "next chat", Key::ArrowDown);
+pub const CHAT_OPEN: Shortcut = key("chat list", "open the chat", Key::Enter);
+
+pub const CLOSE_TAB: Shortcut = with("chat", "close the chat's tab", COMMAND, Key::W);
+pub const PAGE_UP: Shortcut = key("chat", "scroll up a page", Key::PageUp);
+pub const PAGE_DOWN: Shortcut = key("chat", "scroll down a page", Key::PageDown);
+pub const TOP: Shortcut = key("chat", "scroll to the top", Key::Home);
+pub const BOTTOM: Shortcut = key("chat", "jump to the latest message", Key::End);
+pub const FIND: Shortcut = with("chat", "find in chat", COMMAND, Key::F);
+pub const FIND_NEXT: Shortcut = key("find in chat", "next match", Key::Enter);
+pub const FIND_PREV: Shortcut = with("find in chat", "previous match", SHIFT, Key::Enter);
+pub const FIND_CLOSE: Shortcut = key("find in chat", "close", Key::Escape);
+
+pub const PALETTE_UP: Shortcut = key("command palette", "previous command", Key::ArrowUp);
+pub const PALETTE_DOWN: Shortcut = key("command palette", "next command", Key::ArrowDown);
+pub const PALETTE_RUN: Shortcut = key("command palette", "run the command", Key::Enter);
+pub const PALETTE_CLOSE: Shortcut = key("command palette", "close", Key::Escape);
+
+pub const LIGHTBOX_PREV: Shortcut = key("photo lightbox", "previous photo", Key::ArrowLeft);
+pub const LIGHTBOX_NEXT: Shortcut = key("photo lightbox", "next photo", Key::ArrowRight);
+pub const LIGHTBOX_CLOSE: Shortcut = key("photo lightbox", "close", Key::Escape);
+
+/// Every shortcut, in the order the cheat sheet shows them
+const ALL: &[&Shortcut] = &[
+    &CHEAT_SHEET,
+    &PERF,
+    &PALETTE,
+    &ZOOM_IN,
+    &ZOOM_OUT,
+    &ZOOM_RESET,
+    &CLEAR,
+    &SEARCH,
+    &CHAT_UP,
+    &CHAT_DOWN,
+    &CHAT_OPEN,
+    &CLOSE_TAB,
+    &PAGE_UP,
+    &PAGE_DOWN,
+    &TOP,
+    &BOTTOM,
+    &FIND,
+    &FIND_NEXT,
+    &FIND_PREV,
+    &FIND_CLOSE,
+    &PALETTE_UP,
+    &PALETTE_DOWN,
+    &PALETTE_RUN,
+    &PALETTE_CLOSE,
+    &LIGHTBOX_PREV,
+    &LIGHTBOX_NEXT,
+    &LIGHTBOX_CLOSE,
+];
+
+impl Shortcut {
+    /// Whether it was pressed this frame, with exactly its modifiers held
+    pub fn pressed(&self, input: &InputState) -> bool {
+        match &self.trigger {
+            Trigger::Key(modifiers, key) => {
+                let held = input.modifiers;
+                held.shift == modifiers.shift
+                    && held.command == modifiers.command
+                    && held.alt == modifiers.alt
+                    && input.key_pressed(*key)
+            }
+            Trigger::Text(text) => input
+                .events
+                .iter()
+                .any(|event| matches!(event, Event::Text(typed) if typed == text)),
+            Trigger::CommandText(text) => {
+                input.modifiers.command
+                    && input
+                        .events
+                        .iter()
+                        .any(|event| matches!(event, Event::Text(typed) if typed == text))
+            }
+        }
+    }
+
+    /// How the cheat sheet writes it, e.g. `⌘ F`
+    fn keys(&self) -> String {
+        let (modifiers, key) = match &self.trigger {
+            Trigger::Key(modifiers, key) => (modifiers, key),
+            Trigger::Text(text) => return text.to_string(),
+            Trigger::CommandText(text) => return format!("{COMMAND_LABEL} {text}"),
+        };
+
+        let mut keys = Vec::new();
+        if modifiers.command {
+            keys.push(COMMAND_LABEL.to_owned());
+        }
+        if modifiers.alt {
+            keys.push(ALT_LABEL.to_owned());
+        }
+        if modifiers.shift {
+            keys.push("Shift".to_owned());
+        }
+        keys.push(match key {
+            Key::ArrowLeft => "←".to_owned(),
+            Key::ArrowRight => "→".to_owned(),
+            Key::ArrowUp => "↑".to_owned(),
+            Key::ArrowDown => "↓".to_owned(),
+            Key::Escape => "Esc".to_owned(),
+            Key::PageUp => "PgUp".to_owned(),
+            Key::PageDown => "PgDn".to_owned(),
+            key => format!("{key:?}"),
+        });
+
+        keys.join(" ")
+    }
+}
+
+/// Window listing every shortcut, grouped by where it works
+pub fn show(ctx: &Context, open: &mut bool) {
+    egui::Window::new(i18n::t("keyboard shortcuts"))
+        .id(egui::Id::new("keyboard shortcuts"))
+        .open(open)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            let mut area = "";
+            for shortcut in ALL {
+                if shortcut.area != area {
+                    area = shortcut.area;
+                    ui.add_space(4.0);
+                    ui.strong(i18n::t(area));
+                }
+
+                ui.horizontal(|ui| {
+                    ui.add_sized(
+                        [120.0, ui.spacing().interact_size.y],
+                        egui::Label::new(egui::RichText::new(shortcut.keys()).monospace()),
+                    );
+                    ui.label(i18n::t(shortcut.description));
+                });
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::RawInput;
+
+    use super::*;
+
+    /// Whether `shortcut` counts as pressed in a frame with `events`, with
+    /// `modifiers` held
+    fn pressed(shortcut: &Shortcut, modifiers: Modifiers, events: Vec<Event>) -> bool {
+        let ctx = Context::default();
+        ctx.begin_frame(RawInput {
+            modifiers,
+            events,
+            ..RawInput::default()
+        });
+        let pressed = shortcut.pressed(&ctx.input());
+        let _ = ctx.end_frame();
+        pressed
+    }
+
+    fn press(key: Key, modifiers: Modifiers) -> Vec<Event> {
+        vec![Event::Key {
+            key,
+            pressed: true,
+            modifiers,
+        }]
+    }
+
+    fn text(text: &str) -> Vec<Event> {
+        vec![Event::Text(text.to_owned())]
+    }
+
+    #[test]
+    fn needs_exactly_its_modifiers() {
+        assert!(pressed(
+            &FIND_NEXT,
+            Modifiers::NONE,
+            press(Key::Enter, Modifiers::NONE)
+        ));
+        assert!(!pressed(&FIND_NEXT, SHIFT, press(Key::Enter, SHIFT)));
+        assert!(pressed(&FIND_PREV, SHIFT, press(Key::Enter, SHIFT)));
+        assert!(!pressed(
+            &FIND,
+            Modifiers::NONE,
+            press(Key::F, Modifiers::NONE)
+        ));
+        assert!(pressed(&FIND, COMMAND, press(Key::F, COMMAND)));
+        assert!(!pressed(&FIND, Modifiers::NONE, Vec::new()));
+    }
+
+    #[test]
+    fn typed_characters() {
+        assert!(pressed(&CHEAT_SHEET, SHIFT, text("?")));
+        assert!(!pressed(&CHEAT_SHEET, Modifiers::NONE, text("/")));
+        assert!(pressed(&ZOOM_IN, COMMAND, text("=")));
+        assert!(!pressed(&ZOOM_IN, Modifiers::NONE, text("=")));
+    }
+
+    #[test]
+    fn writes_keys() {
+        assert_eq!(FIND.keys(), format!("{COMMAND_LABEL} F"));
+        assert_eq!(FIND_PREV.keys(), "Shift Enter");
+        assert_eq!(CHAT_UP.keys(), "↑");
+        assert_eq!(CHEAT_SHEET.keys(), "?");
+        assert_eq!(ZOOM_OUT.keys(), format!("{COMMAND_LABEL} -"));
+    }
+
+    #[test]
+    fn cheat_sheet_groups_areas() {
+        // `show` starts a new heading whenever the area changes, so each
+        // area's shortcuts have to be listed together, and each written
+        // differently
+        let mut seen: Vec<&str> = Vec::new();
+        for pair in ALL.windows(2) {
+            if pair[0].area != pair[1].area {
+                seen.push(pair[0].area);
+                assert!(!seen.contains(&pair[1].area), "{} is split", pair[1].area);
+            }
+        }
+        for shortcut in ALL {
+            let same = ALL
+                .iter()
+                .filter(|other| other.area == shortcut.area && other.keys() == shortcut.keys());
+            assert_eq!(same.count(), 1, "{} is bound twice", shortcut.keys());
+        }
+    }
+}
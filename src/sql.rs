@@ -0,0 +1,60 @@
+use sqlx::{sqlite::SqliteArguments, Arguments, Encode, Sqlite, Type};
+
+/// A SQL statement assembled at runtime, for queries whose shape depends on
+/// user input (search filters etc.)
+#[derive(Default)]
+pub struct Sql {
+    pub text: String,
+    pub args: SqliteArguments<'static>,
+    count: usize,
+}
+
+impl Sql {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    pub fn push(&mut self, text: &str) -> &mut Self {
+        self.text.push_str(text);
+        self
+    }
+
+    /// Append a placeholder bound to `value`
+    pub fn bind<T>(&mut self, value: T) -> &mut Self
+    where
+        T: 'static + Send + Encode<'static, Sqlite> + Type<Sqlite>,
+    {
+        self.count += 1;
+        self.text.push_str(&format!("${}", self.count));
+        self.args.add(value);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[tokio::test]
+    async fn numbers_placeholders_in_order() {
+        let db = test_util::memory().await;
+
+        let mut sql = Sql::new("SELECT ");
+        sql.bind(1i64)
+            .push(" + ")
+            .bind(2i64)
+            .push(", ")
+            .bind("three");
+        assert_eq!(sql.text, "SELECT $1 + $2, $3");
+
+        let row = sqlx::query_as_with::<_, (i64, String), _>(&sql.text, sql.args)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(row, (3, "three".to_owned()));
+    }
+}
@@ -0,0 +1,542 @@
+use std::collections::HashMap;
+
+use chrono::prelude::*;
+use egui::Ui;
+use eyre::Result;
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::{
+    config::{HandleChange, Identity},
+    continuity, counts,
+    export::{html_escape, Kind, Scope},
+    i18n, ignore,
+    sql::Sql,
+    time, Sender,
+};
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Hours without a message after which the next one starts a new
+/// conversation
+const CONVERSATION_GAP: i64 = 6;
+
+/// Numbers about one chat, shown in its stats tab and written by `stats`
+#[derive(Clone, Serialize)]
+pub struct Stats {
+    pub chat: String,
+    pub messages: usize,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+    /// Messages and words per person, most messages first
+    pub senders: Vec<SenderStats>,
+    /// Attachments of each kind, e.g. `photos`
+    pub attachments: Vec<(String, usize)>,
+    /// Conversations, each starting with the first message after
+    /// [`CONVERSATION_GAP`] hours of silence
+    pub conversations: usize,
+    /// The day with the most messages, and how many
+    pub busiest_day: Option<(NaiveDate, usize)>,
+    /// Messages sent in each hour of the day, UTC
+    pub by_hour: [usize; 24],
+    /// Messages sent on each day of the week, Monday first
+    pub by_weekday: [usize; 7],
+}
+
+#[derive(Clone, Serialize)]
+pub struct SenderStats {
+    pub name: String,
+    pub messages: usize,
+    pub words: usize,
+    /// Conversations they sent the first message of
+    pub started: usize,
+}
+
+/// Formats the `stats` command can write
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+    Html,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Text => "txt",
+            Self::Json => "json",
+            Self::Html => "html",
+        }
+    }
+}
+
+/// Count up everything in the report for one chat, leaving out group events
+/// and messages matched by an ignore rule or outside `scope`'s dates. Someone who changed
+/// handle counts as one person, and their chats under each handle as one
+/// chat.
+pub async fn chat(
+    db: &SqlitePool,
+    identity: &Identity,
+    rules: &ignore::Rules,
+    changes: &[HandleChange],
+    scope: &Scope,
+    chat_id: &str,
+) -> Result<Stats> {
+    let (start, end) = scope.bounds();
+    let chats = continuity::handles(changes, chat_id);
+
+    let mut sql = Sql::new(
+        r#"
+            SELECT
+                m.ROWID, coalesce(m.text, ''), m.date, coalesce(h.id, ''), m.is_from_me
+            from message m
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            left join handle h
+                on m.handle_id = h.ROWID
+            where m.item_type = 0
+                and m.date >= "#,
+    );
+    sql.bind(start)
+        .push(" and m.date < ")
+        .bind(end)
+        .push(" and c.chat_identifier in (");
+    for (i, chat) in chats.iter().enumerate() {
+        if i > 0 {
+            sql.push(", ");
+        }
+        sql.bind(chat.clone());
+    }
+    sql.push(") order by m.date;");
+
+    let mut rows =
+        sqlx::query_as_with::<_, (i64, String, i64, String, bool), _>(&sql.text, sql.args)
+            .fetch(db);
+
+    let mut ignored = Vec::new();
+    let mut first = None;
+    let mut last = None;
+    let mut senders: Vec<SenderStats> = Vec::new();
+    let mut days: HashMap<NaiveDate, usize> = HashMap::new();
+    let mut by_hour = [0; 24];
+    let mut by_weekday = [0; 7];
+    let mut conversations = 0;
+
+    // read as they come rather than all at once, since a long chat's text
+    // can be a lot to hold
+    while let Some((id, text, timestamp, sender, is_from_me)) = rows.try_next().await? {
+        let hidden = {
+            let sender = Sender::new(is_from_me, sender.clone());
+            chats
+                .iter()
+                .any(|chat| rules.check(chat, &sender, &text).is_some())
+        };
+        if hidden {
+            ignored.push(id);
+            continue;
+        }
+
+        let date = time(timestamp);
+        let starts = match last {
+            Some(previous) => date - previous >= chrono::Duration::hours(CONVERSATION_GAP),
+            None => true,
+        };
+        first.get_or_insert(date);
+        last = Some(date);
+        conversations += starts as usize;
+        *days.entry(date.date_naive()).or_default() += 1;
+        by_hour[date.hour() as usize] += 1;
+        by_weekday[date.weekday().num_days_from_monday() as usize] += 1;
+
+        let handle = continuity::current(changes, &sender).to_owned();
+        let sender = Sender::new(is_from_me, handle);
+        let name = identity.name(&sender);
+        let words = text.split_whitespace().count();
+        match senders.iter_mut().find(|s| s.name == name) {
+            Some(stats) => {
+                stats.messages += 1;
+                stats.words += words;
+                stats.started += starts as usize;
+            }
+            None => senders.push(SenderStats {
+                name: name.to_owned(),
+                messages: 1,
+                words,
+                started: starts as usize,
+            }),
+        }
+    }
+    senders.sort_by_key(|s| std::cmp::Reverse(s.messages));
+
+    // counted by SQLite, less the hidden messages and those on them
+    let messages = counts::messages(db, &chats, (start, end)).await? - ignored.len();
+    let mut types = counts::attachment_types(db, &chats, (start, end)).await?;
+    for (mime_type, count) in counts::message_attachment_types(db, &ignored).await? {
+        if let Some(total) = types.get_mut(&mime_type) {
+            *total = total.saturating_sub(count);
+        }
+    }
+    let attachments = Kind::ALL
+        .iter()
+        .map(|kind| {
+            let count = types
+                .iter()
+                .filter(|(mime_type, _)| Kind::of(mime_type) == *kind)
+                .map(|(_, count)| count)
+                .sum();
+            (kind.label().to_owned(), count)
+        })
+        .filter(|(_, count)| *count > 0)
+        .collect();
+
+    // ties go to the earlier day, so the report doesn't change run to run
+    let busiest_day = days
+        .into_iter()
+        .max_by_key(|(day, count)| (*count, std::cmp::Reverse(*day)));
+
+    Ok(Stats {
+        chat: chat_id.to_owned(),
+        messages,
+        first,
+        last,
+        senders,
+        attachments,
+        conversations,
+        busiest_day,
+        by_hour,
+        by_weekday,
+    })
+}
+
+/// A heading (except for the first) and its rows of label and value
+type Section = (Option<&'static str>, Vec<(String, String)>);
+
+impl Stats {
+    /// Everything in the report, in order and in the app's language, for each
+    /// format to lay out
+    fn sections(&self) -> Vec<Section> {
+        let date = |d: Option<DateTime<Utc>>| {
+            d.map_or("-".to_owned(), |d| d.format("%Y-%m-%d").to_string())
+        };
+
+        let mut summary = vec![
+            (i18n::t("messages").to_owned(), self.messages.to_string()),
+            (i18n::t("first").to_owned(), date(self.first)),
+            (i18n::t("last").to_owned(), date(self.last)),
+        ];
+        if let Some((day, count)) = self.busiest_day {
+            summary.push((
+                i18n::t("busiest day").to_owned(),
+                i18n::tf(
+                    "{day} ({count} messages)",
+                    &[("day", &day), ("count", &count)],
+                ),
+            ));
+        }
+
+        let mut sections = vec![
+            (None, summary),
+            (
+                Some(i18n::t("by person")),
+                self.senders
+                    .iter()
+                    .map(|s| {
+                        (
+                            s.name.clone(),
+                            i18n::tf(
+                                "{messages} messages, {words} words",
+                                &[("messages", &s.messages), ("words", &s.words)],
+                            ),
+                        )
+                    })
+                    .collect(),
+            ),
+        ];
+        if self.conversations > 0 {
+            sections.push((
+                Some(i18n::t("who starts conversations")),
+                self.senders
+                    .iter()
+                    .map(|s| {
+                        let percent = 100 * s.started / self.conversations;
+                        (
+                            s.name.clone(),
+                            i18n::tf(
+                                "{started} of {total} ({percent}%)",
+                                &[
+                                    ("started", &s.started),
+                                    ("total", &self.conversations),
+                                    ("percent", &percent),
+                                ],
+                            ),
+                        )
+                    })
+                    .collect(),
+            ));
+        }
+        if !self.attachments.is_empty() {
+            sections.push((
+                Some(i18n::t("attachments")),
+                self.attachments
+                    .iter()
+                    .map(|(kind, count)| {
+                        // kept as the English label, which is what the JSON has
+                        let label = Kind::ALL
+                            .iter()
+                            .find(|k| k.label() == kind)
+                            .map_or(kind.as_str(), |k| i18n::t(k.label()));
+                        (label.to_owned(), count.to_string())
+                    })
+                    .collect(),
+            ));
+        }
+        sections.push((
+            Some(i18n::t("by hour (UTC)")),
+            self.by_hour
+                .iter()
+                .enumerate()
+                .map(|(hour, count)| (format!("{hour:02}"), count.to_string()))
+                .collect(),
+        ));
+        sections.push((
+            Some(i18n::t("by day of the week")),
+            WEEKDAYS
+                .iter()
+                .zip(self.by_weekday)
+                .map(|(day, count)| (i18n::t(day).to_owned(), count.to_string()))
+                .collect(),
+        ));
+
+        sections
+    }
+
+    /// The report as text, one fact per line
+    pub fn text(&self) -> String {
+        let mut text = format!("{}\n", self.chat);
+
+        for (heading, rows) in self.sections() {
+            text.push('\n');
+            let indent = match heading {
+                Some(heading) => {
+                    text.push_str(&format!("{heading}:\n"));
+                    "  "
+                }
+                None => "",
+            };
+            for (label, value) in rows {
+                text.push_str(&format!("{indent}{label}: {value}\n"));
+            }
+        }
+
+        text
+    }
+
+    pub fn json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// A standalone page with a table per section
+    pub fn html(&self) -> String {
+        let mut html = format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; }}
+th {{ text-align: left; padding-right: 1em; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+"#,
+            title = html_escape(&self.chat),
+        );
+
+        for (heading, rows) in self.sections() {
+            if let Some(heading) = heading {
+                html.push_str(&format!("<h2>{}</h2>\n", html_escape(heading)));
+            }
+            html.push_str("<table>\n");
+            for (label, value) in rows {
+                html.push_str(&format!(
+                    "<tr><th>{}</th><td>{}</td></tr>\n",
+                    html_escape(&label),
+                    html_escape(&value)
+                ));
+            }
+            html.push_str("</table>\n");
+        }
+
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    pub fn render(&self, format: Format) -> Result<String> {
+        match format {
+            Format::Text => Ok(self.text()),
+            Format::Json => self.json(),
+            Format::Html => Ok(self.html()),
+        }
+    }
+}
+
+/// The stats tab
+pub fn show(ui: &mut Ui, stats: &Stats) {
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for (i, (heading, rows)) in stats.sections().into_iter().enumerate() {
+            if let Some(heading) = heading {
+                ui.add_space(8.0);
+                ui.strong(heading);
+            }
+            egui::Grid::new(("stats", i)).striped(true).show(ui, |ui| {
+                for (label, value) in rows {
+                    ui.label(label);
+                    ui.label(value);
+                    ui.end_row();
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apple_time, config::IgnoreRule, test_util};
+
+    fn at(day: u32, hour: u32, minute: u32) -> i64 {
+        // 2021-01-04 is a Monday
+        apple_time(Utc.ymd(2021, 1, day).and_hms(hour, minute, 0))
+    }
+
+    /// Bob, who texted from `bob-old` until he changed to `bob`. The last
+    /// two messages are a verification code with a photo, which is ignored,
+    /// and a group event.
+    async fn scratch_db() -> SqlitePool {
+        let db = test_util::memory().await;
+        sqlx::query(
+            r#"
+                CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, chat_identifier TEXT);
+                CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT);
+                CREATE TABLE message (
+                    ROWID INTEGER PRIMARY KEY, text TEXT, date INTEGER, handle_id INTEGER,
+                    is_from_me INTEGER, item_type INTEGER
+                );
+                CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+                CREATE TABLE attachment (ROWID INTEGER PRIMARY KEY, filename TEXT, mime_type TEXT);
+                CREATE TABLE message_attachment_join (message_id INTEGER, attachment_id INTEGER);
+
+                INSERT INTO chat VALUES (1, 'bob-old'), (2, 'bob');
+                INSERT INTO handle VALUES (1, 'bob-old'), (2, 'bob');
+                INSERT INTO chat_message_join VALUES (1, 1), (1, 2), (2, 3), (2, 4), (2, 5);
+                INSERT INTO attachment VALUES
+                    (1, 'a.jpeg', 'image/jpeg'), (2, 'b.jpeg', 'image/jpeg');
+                INSERT INTO message_attachment_join VALUES (3, 1), (4, 2);
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let messages = [
+            (1, "hi there", at(4, 9, 0), 1, false, 0),
+            (2, "hello back to you", at(4, 9, 30), 0, true, 0),
+            (3, "your code is 123", at(4, 20, 0), 2, false, 0),
+            (4, "new day", at(5, 10, 0), 2, false, 0),
+            (5, "", at(5, 11, 0), 2, false, 1),
+        ];
+        for (id, text, date, handle, is_from_me, item_type) in messages {
+            sqlx::query("INSERT INTO message VALUES ($1, $2, $3, $4, $5, $6);")
+                .bind(id)
+                .bind(text)
+                .bind(date)
+                .bind(handle)
+                .bind(is_from_me)
+                .bind(item_type)
+                .execute(&db)
+                .await
+                .unwrap();
+        }
+
+        db
+    }
+
+    async fn stats(scope: &Scope) -> Stats {
+        let db = scratch_db().await;
+        let rules = ignore::Rules::compile(&[IgnoreRule {
+            text: Some("code".to_owned()),
+            ..IgnoreRule::default()
+        }])
+        .unwrap();
+        let changes = [HandleChange {
+            from: "bob-old".to_owned(),
+            to: "bob".to_owned(),
+            date: Some(NaiveDate::from_ymd(2021, 1, 4)),
+        }];
+
+        chat(&db, &Identity::default(), &rules, &changes, scope, "bob")
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn counts_a_chat() {
+        let stats = stats(&Scope::default()).await;
+
+        assert_eq!(stats.messages, 3);
+        assert_eq!(stats.first, Some(time(at(4, 9, 0))));
+        assert_eq!(stats.last, Some(time(at(5, 10, 0))));
+        assert_eq!(stats.conversations, 2);
+        assert_eq!(
+            stats.busiest_day,
+            Some((NaiveDate::from_ymd(2021, 1, 4), 2))
+        );
+        assert_eq!(stats.attachments, [("photos".to_owned(), 1)]);
+        assert_eq!(stats.by_hour[9], 2);
+        assert_eq!(stats.by_hour[10], 1);
+        assert_eq!(stats.by_hour.iter().sum::<usize>(), 3);
+        assert_eq!(stats.by_weekday, [2, 1, 0, 0, 0, 0, 0]);
+
+        let senders: Vec<_> = stats
+            .senders
+            .iter()
+            .map(|s| (s.name.as_str(), s.messages, s.words, s.started))
+            .collect();
+        assert_eq!(senders, [("bob", 2, 4, 2), ("Me", 1, 4, 0)]);
+    }
+
+    #[tokio::test]
+    async fn counts_within_scope() {
+        let stats = stats(&Scope {
+            after: Some(NaiveDate::from_ymd(2021, 1, 5)),
+            ..Scope::default()
+        })
+        .await;
+
+        assert_eq!(stats.messages, 1);
+        assert_eq!(stats.conversations, 1);
+        assert_eq!(stats.attachments, [("photos".to_owned(), 1)]);
+    }
+
+    #[tokio::test]
+    async fn writes_reports() {
+        let stats = stats(&Scope::default()).await;
+
+        let text = stats.text();
+        assert!(text.starts_with("bob\n\nmessages: 3\n"), "{text}");
+        assert!(text.contains("\nby person:\n  bob: 2 messages, 4 words\n"));
+        assert!(text.contains("\nwho starts conversations:\n  bob: 2 of 2 (100%)\n"));
+
+        let json: serde_json::Value = serde_json::from_str(&stats.json().unwrap()).unwrap();
+        assert_eq!(json["messages"], 3);
+        assert_eq!(json["senders"][1]["name"], "Me");
+
+        let html = stats.html();
+        assert!(html.contains("<tr><th>busiest day</th><td>2021-01-04 (2 messages)</td></tr>"));
+    }
+}
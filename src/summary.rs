@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+
+use eyre::Result;
+use sqlx::SqlitePool;
+
+use crate::{cache, time, Chat};
+
+/// Most characters of a chat's last message shown under its name
+const PREVIEW_LEN: usize = 80;
+
+/// Every chat with messages in it, most recently active first, with how many
+/// messages it has and the last one. Chats are summarized once into the
+/// cache; after that only messages added since are read from `db`, rather
+/// than going over every message in it again.
+pub async fn load(db: &SqlitePool, cache: &SqlitePool) -> Result<Vec<Chat>> {
+    let (newest, messages): (i64, i64) =
+        sqlx::query_as("SELECT coalesce(max(ROWID), 0), count(*) from message;")
+            .fetch_one(db)
+            .await?;
+
+    let mut chats = HashMap::new();
+    let mut from = 0;
+    if let Some((summarized, counted)) = cache::summarized(cache).await? {
+        let (added,): (i64,) = sqlx::query_as("SELECT count(*) from message where ROWID > $1;")
+            .bind(summarized)
+            .fetch_one(db)
+            .await?;
+        // otherwise messages have been deleted since, or this isn't the
+        // database that was summarized (an older backup restored over it, a
+        // copy with other messages under the same ROWIDs), so start again
+        if summarized <= newest && counted + added == messages {
+            from = summarized;
+            chats = cache::load_chats(cache)
+                .await?
+                .into_iter()
+                .map(|chat| (chat.name.clone(), chat))
+                .collect();
+        }
+    }
+
+    // the text picked is from the row with the max(m.date), which SQLite
+    // guarantees for a query with a single max()
+    let rows = sqlx::query_as::<_, (String, i64, i64, Option<String>)>(
+        r#"
+            SELECT
+                c.chat_identifier, max(m.date), count(*), m.text
+            from message m
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            where m.ROWID > $1
+                and m.ROWID <= $2
+            group by c.chat_identifier
+            ;
+        "#,
+    )
+    .bind(from)
+    .bind(newest)
+    .fetch_all(db)
+    .await?;
+
+    for (name, timestamp, count, text) in rows {
+        let date = time(timestamp);
+        let chat = chats.entry(name.clone()).or_insert_with(|| Chat {
+            name,
+            display_name: String::new(),
+            participants: Vec::new(),
+            last_active: date,
+            message_count: 0,
+            preview: String::new(),
+        });
+        chat.message_count += count;
+        if date >= chat.last_active {
+            chat.last_active = date;
+            chat.preview = preview(text.as_deref().unwrap_or_default());
+        }
+    }
+
+    // names and members can change without a new message, and are cheap to
+    // read for every chat
+    let names = sqlx::query_as::<_, (String, String, Option<String>)>(
+        r#"
+            SELECT
+                c.chat_identifier, coalesce(max(c.display_name), ''),
+                (
+                    select group_concat(h.id, ' ')
+                    from chat_handle_join chj
+                    join handle h
+                        on chj.handle_id = h.ROWID
+                    where chj.chat_id = c.ROWID
+                )
+            from chat c
+            group by c.chat_identifier
+            ;
+        "#,
+    )
+    .fetch_all(db)
+    .await?;
+    for (name, display_name, participants) in names {
+        if let Some(chat) = chats.get_mut(&name) {
+            chat.display_name = display_name;
+            chat.participants = participants
+                .unwrap_or_default()
+                .split(' ')
+                .filter(|handle| !handle.is_empty())
+                .map(String::from)
+                .collect();
+        }
+    }
+
+    let mut chats: Vec<Chat> = chats.into_values().collect();
+    chats.sort_by_key(|chat| std::cmp::Reverse(chat.last_active));
+
+    cache::save_chats(cache, &chats, newest, messages).await?;
+
+    Ok(chats)
+}
+
+/// The start of the first line of a message, for the chat list
+fn preview(text: &str) -> String {
+    // attachments show up as U+FFFC in the text
+    let text = text.replace('\u{fffc}', "");
+    let line = text.trim().lines().next().unwrap_or_default();
+    match line.char_indices().nth(PREVIEW_LEN) {
+        Some((end, _)) => format!("{}…", line[..end].trim_end()),
+        None => line.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// Two chats, `ana` with three messages and a group with two
+    async fn fixture() -> SqlitePool {
+        let db = test_util::memory().await;
+        sqlx::query(
+            r#"
+                CREATE TABLE message (ROWID INTEGER PRIMARY KEY, text TEXT, date INTEGER);
+                CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, chat_identifier TEXT, display_name TEXT);
+                CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+                CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT);
+                CREATE TABLE chat_handle_join (chat_id INTEGER, handle_id INTEGER);
+                INSERT INTO chat VALUES (1, 'ana', ''), (2, 'chat123', 'Trip');
+                INSERT INTO handle VALUES (1, 'ana'), (2, 'bo');
+                INSERT INTO chat_handle_join VALUES (1, 1), (2, 1), (2, 2);
+                INSERT INTO message VALUES
+                    (1, 'hi', 1000000000000),
+                    (2, 'who is going?', 2000000000000),
+                    (3, 'me', 3000000000000),
+                    (4, 'not me', 2500000000000),
+                    (5, 'later', 4000000000000);
+                INSERT INTO chat_message_join VALUES (1, 1), (2, 2), (2, 3), (1, 4), (1, 5);
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        db
+    }
+
+    /// Each chat's name, message count, last message and members
+    fn summary(chats: &[Chat]) -> Vec<(&str, i64, &str, Vec<&str>)> {
+        chats
+            .iter()
+            .map(|chat| {
+                let participants = chat.participants.iter().map(String::as_str).collect();
+                (
+                    chat.name.as_str(),
+                    chat.message_count,
+                    chat.preview.as_str(),
+                    participants,
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn summarizes_chats() {
+        let db = fixture().await;
+        let cache = test_util::cache().await;
+
+        let chats = load(&db, &cache).await.unwrap();
+        assert_eq!(
+            summary(&chats),
+            [
+                ("ana", 3, "later", vec!["ana"]),
+                ("chat123", 2, "me", vec!["ana", "bo"]),
+            ]
+        );
+        assert_eq!(chats[0].last_active, time(4000000000000));
+        assert_eq!(chats[1].display_name, "Trip");
+        assert_eq!(cache::summarized(&cache).await.unwrap(), Some((5, 5)));
+    }
+
+    #[tokio::test]
+    async fn adds_new_messages_to_the_cached_summary() {
+        let db = fixture().await;
+        let cache = test_util::cache().await;
+        load(&db, &cache).await.unwrap();
+
+        // one newer and one that arrived late, with an older date
+        sqlx::query(
+            r#"
+                INSERT INTO message VALUES
+                    (6, 'on my way', 5000000000000),
+                    (7, 'late', 1500000000000);
+                INSERT INTO chat_message_join VALUES (2, 6), (1, 7);
+                UPDATE chat set display_name = 'Road trip' where ROWID = 2;
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let incremental = load(&db, &cache).await.unwrap();
+        let fresh = load(&db, &test_util::memory().await).await.unwrap();
+        assert_eq!(summary(&incremental), summary(&fresh));
+        assert_eq!(
+            summary(&incremental),
+            [
+                ("chat123", 3, "on my way", vec!["ana", "bo"]),
+                ("ana", 4, "later", vec!["ana"]),
+            ]
+        );
+        assert_eq!(incremental[0].display_name, "Road trip");
+    }
+
+    #[tokio::test]
+    async fn starts_again_after_deletions() {
+        let db = fixture().await;
+        let cache = test_util::cache().await;
+        load(&db, &cache).await.unwrap();
+
+        sqlx::query(
+            r#"
+                DELETE FROM message where ROWID = 1;
+                DELETE FROM chat_message_join where message_id = 1;
+                INSERT INTO message VALUES (6, 'again', 500000000000);
+                INSERT INTO chat_message_join VALUES (1, 6);
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        // as many messages as before, but not the same ones
+        let chats = load(&db, &cache).await.unwrap();
+        assert_eq!(
+            summary(&chats),
+            summary(&load(&db, &test_util::memory().await).await.unwrap())
+        );
+        assert_eq!(chats[0].message_count, 3);
+    }
+
+    #[test]
+    fn previews_the_first_line() {
+        assert_eq!(preview("  \u{fffc}look at this\nand this"), "look at this");
+        assert_eq!(preview("\u{fffc}"), "");
+        let long = format!("{} end", "a".repeat(PREVIEW_LEN - 1));
+        assert_eq!(preview(&long), format!("{}…", "a".repeat(PREVIEW_LEN - 1)));
+        assert_eq!(preview(&"é".repeat(PREVIEW_LEN)), "é".repeat(PREVIEW_LEN));
+    }
+}
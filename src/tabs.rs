@@ -0,0 +1,59 @@
+use std::{
+    collections::HashMap,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use egui::Ui;
+
+use crate::{
+    a11y, attachment::MediaItem, cancel, find::Find, i18n, media_info::MediaInfo, minimap,
+    paging::Window, participants, stats, Chat, ChatView, Handle, Jump,
+};
+
+/// A chat open in a tab that isn't the one on screen, with everything loaded
+/// for it kept as it was, down to how far it was scrolled
+pub struct Tab {
+    pub chat: Chat,
+    pub messages: Handle<Window>,
+    pub loading_page: Arc<AtomicBool>,
+    pub media: Handle<HashMap<i64, Vec<MediaInfo>>>,
+    pub gallery: Handle<Vec<MediaItem>>,
+    pub stats: Handle<stats::Stats>,
+    pub participants: Handle<Vec<participants::Participant>>,
+    pub density: Handle<minimap::Density>,
+    pub count: Handle<usize>,
+    pub view: ChatView,
+    pub jump: Option<Jump>,
+    pub find: Option<Find>,
+    /// Kept with the tab so what's loading for it carries on while it's
+    /// hidden, and stops when it's closed
+    pub loads: cancel::Loads,
+}
+
+/// Something done to a tab from the tab bar
+pub enum Action {
+    Switch(usize),
+    Close(usize),
+}
+
+/// A row of tabs named `names`, with `active` highlighted, each with a button
+/// to close it
+pub fn bar(ui: &mut Ui, names: &[String], active: usize) -> Option<Action> {
+    let mut action = None;
+
+    ui.horizontal_wrapped(|ui| {
+        for (i, name) in names.iter().enumerate() {
+            ui.group(|ui| {
+                ui.spacing_mut().item_spacing.x = 2.0;
+                if ui.selectable_label(i == active, name).clicked() {
+                    action = Some(Action::Switch(i));
+                }
+                if a11y::icon(ui.small_button("×"), i18n::t("close tab")).clicked() {
+                    action = Some(Action::Close(i));
+                }
+            });
+        }
+    });
+
+    action
+}
@@ -0,0 +1,181 @@
+use chrono::{DateTime, Utc};
+use egui::Ui;
+
+use crate::{config::Identity, Sender};
+
+/// `message.associated_message_type` of the first kind of tapback. Removing
+/// one is [`REMOVED`] more than adding it.
+const FIRST: i64 = 2000;
+const REMOVED: i64 = 1000;
+
+/// A reaction from long-pressing a message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Tapback {
+    Love,
+    Like,
+    Dislike,
+    Laugh,
+    Emphasize,
+    Question,
+}
+
+impl Tapback {
+    const ALL: [Self; 6] = [
+        Self::Love,
+        Self::Like,
+        Self::Dislike,
+        Self::Laugh,
+        Self::Emphasize,
+        Self::Question,
+    ];
+
+    pub fn emoji(self) -> &'static str {
+        match self {
+            Self::Love => "❤",
+            Self::Like => "👍",
+            Self::Dislike => "👎",
+            Self::Laugh => "😂",
+            Self::Emphasize => "‼",
+            Self::Question => "❓",
+        }
+    }
+}
+
+/// The tapback an associated message adds, or takes back off when the bool
+/// is false, if it is one
+pub fn decode(associated_type: i64) -> Option<(Tapback, bool)> {
+    let (offset, added) = match associated_type - FIRST {
+        offset if offset >= REMOVED => (offset - REMOVED, false),
+        offset => (offset, true),
+    };
+    let tapback = *Tapback::ALL.get(usize::try_from(offset).ok()?)?;
+
+    Some((tapback, added))
+}
+
+/// A tapback being added to or taken off a message, which is a message of
+/// its own pointing at the one it's on
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reaction {
+    pub tapback: Tapback,
+    /// False when taking the tapback back off
+    pub added: bool,
+    pub sender: Sender,
+    /// Later reactions replace earlier ones
+    pub date: DateTime<Utc>,
+}
+
+/// Who has each tapback on a message now, from every reaction to it in any
+/// order: everyone has at most one, the last they picked, unless they took
+/// it off again
+pub fn summarize(reactions: &[Reaction]) -> Vec<(Tapback, Vec<Sender>)> {
+    let mut sorted: Vec<&Reaction> = reactions.iter().collect();
+    sorted.sort_by_key(|reaction| reaction.date);
+
+    let mut current: Vec<(&Sender, Tapback)> = Vec::new();
+    for reaction in sorted {
+        current.retain(|(sender, tapback)| {
+            *sender != &reaction.sender || (!reaction.added && *tapback != reaction.tapback)
+        });
+        if reaction.added {
+            current.push((&reaction.sender, reaction.tapback));
+        }
+    }
+
+    let mut summary: Vec<(Tapback, Vec<Sender>)> = Vec::new();
+    for (sender, tapback) in current {
+        match summary.iter_mut().find(|(t, _)| *t == tapback) {
+            Some((_, senders)) => senders.push(sender.clone()),
+            None => summary.push((tapback, vec![sender.clone()])),
+        }
+    }
+    summary.sort_by_key(|(tapback, _)| *tapback);
+    summary
+}
+
+/// Draw a message's tapbacks, from [`summarize`], as badges with how many of
+/// each there are. Hovering over one lists who sent it, and clicking one
+/// keeps that list open.
+pub fn badges(
+    ui: &mut Ui,
+    identity: &Identity,
+    message_id: i64,
+    summary: &[(Tapback, Vec<Sender>)],
+) {
+    for (tapback, senders) in summary {
+        let names: Vec<&str> = senders.iter().map(|s| identity.name(s)).collect();
+        let text = match senders.len() {
+            1 => tapback.emoji().to_owned(),
+            n => format!("{} {n}", tapback.emoji()),
+        };
+
+        let response = ui.small_button(text).on_hover_text(names.join("\n"));
+        let popup = ui.make_persistent_id(("tapback", message_id, tapback));
+        if response.clicked() {
+            ui.memory().toggle_popup(popup);
+        }
+        egui::popup::popup_below_widget(ui, popup, &response, |ui| {
+            ui.set_min_width(120.0);
+            ui.label(tapback.emoji());
+            for name in &names {
+                ui.label(*name);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn reaction(tapback: Tapback, added: bool, sender: &str, second: i64) -> Reaction {
+        Reaction {
+            tapback,
+            added,
+            sender: Sender::SomeoneElse(sender.to_owned()),
+            date: Utc.timestamp_opt(second, 0).unwrap(),
+        }
+    }
+
+    fn senders(names: &[&str]) -> Vec<Sender> {
+        names
+            .iter()
+            .map(|name| Sender::SomeoneElse(name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn decodes_types() {
+        assert_eq!(decode(2000), Some((Tapback::Love, true)));
+        assert_eq!(decode(2005), Some((Tapback::Question, true)));
+        assert_eq!(decode(3001), Some((Tapback::Like, false)));
+        assert_eq!(decode(0), None);
+        assert_eq!(decode(1000), None);
+        assert_eq!(decode(2006), None);
+        assert_eq!(decode(3006), None);
+    }
+
+    #[test]
+    fn keeps_each_senders_last_pick() {
+        let summary = summarize(&[
+            // out of order: bob loved it, then changed to a laugh
+            reaction(Tapback::Laugh, true, "bob", 2),
+            reaction(Tapback::Love, true, "bob", 1),
+            reaction(Tapback::Love, true, "eve", 1),
+            // taking off a tapback they don't have changes nothing
+            reaction(Tapback::Like, false, "eve", 3),
+            reaction(Tapback::Laugh, true, "ann", 1),
+            reaction(Tapback::Laugh, false, "ann", 2),
+        ]);
+        assert_eq!(
+            summary,
+            vec![
+                (Tapback::Love, senders(&["eve"])),
+                (Tapback::Laugh, senders(&["bob"])),
+            ]
+        );
+        assert!(summarize(&[]).is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+//! Databases for the tests to run against
+
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    SqlitePool,
+};
+
+use crate::{
+    audit::{self, Audit, Pool},
+    cache, demo,
+};
+
+/// An empty in-memory database. It has a single connection, since each
+/// connection to `:memory:` gets a database of its own.
+pub async fn memory() -> SqlitePool {
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap()
+}
+
+/// An empty in-memory cache database, set up like the one [`cache::open`]
+/// opens
+pub async fn cache() -> SqlitePool {
+    cache::pool_options()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap()
+}
+
+/// A freshly generated demo database in a directory of its own, which is
+/// removed along with anything else written to it when this is dropped
+pub struct Demo {
+    pub dir: PathBuf,
+    pub path: PathBuf,
+}
+
+impl Demo {
+    pub async fn generate() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "imessage-viewer-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let path = dir.join("chat.db");
+        demo::generate(&path).await.unwrap();
+
+        Self { dir, path }
+    }
+
+    /// Connect read-only, the way the app connects for long scans
+    pub async fn connect(&self) -> SqlitePool {
+        self.connect_with(Audit::default(), Pool::Bulk).await
+    }
+
+    pub async fn connect_with(&self, audit: Audit<'_>, pool: Pool) -> SqlitePool {
+        audit::connect(self.path.to_str().unwrap(), audit, pool)
+            .await
+            .unwrap()
+    }
+
+    /// Connect without any of the app's restrictions, to change the database
+    /// before a test reads it
+    pub async fn writable(&self) -> SqlitePool {
+        SqlitePool::connect_with(SqliteConnectOptions::new().filename(&self.path))
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for Demo {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,220 @@
+use std::{process::Command, sync::OnceLock};
+
+use egui::{Color32, Id, Stroke, Ui, Visuals};
+
+use crate::{
+    bidi,
+    config::{Config, Theme},
+};
+
+/// Colours the app picks itself rather than taking from egui's visuals, for
+/// one theme
+#[derive(Clone, Copy)]
+pub struct Colors {
+    /// My iMessages, and the open chat in the chat list
+    pub me: Color32,
+    /// My text messages, as opposed to iMessages
+    pub sms: Color32,
+    /// Everyone else's messages
+    pub them: Color32,
+    /// Outline of the message found or jumped to
+    pub highlight: Color32,
+    /// Outline of every bubble, so they stand out from the background
+    pub border: Color32,
+    /// Width of the outlines around found, jumped to and selected messages
+    pub outline: f32,
+    pub high_contrast: bool,
+}
+
+const DARK: Colors = Colors {
+    me: Color32::from_rgb(65, 136, 247),
+    sms: Color32::from_rgb(52, 168, 83),
+    them: Color32::from_rgb(59, 59, 61),
+    highlight: Color32::YELLOW,
+    border: Color32::TRANSPARENT,
+    outline: 2.0,
+    high_contrast: false,
+};
+
+const LIGHT: Colors = Colors {
+    me: Color32::from_rgb(10, 122, 255),
+    sms: Color32::from_rgb(52, 168, 83),
+    them: Color32::from_rgb(229, 229, 234),
+    highlight: Color32::from_rgb(230, 150, 0),
+    border: Color32::TRANSPARENT,
+    outline: 2.0,
+    high_contrast: false,
+};
+
+/// Dark and light, with darker bubbles that white text stands out on, and
+/// everyone else's as black or white as the background but outlined
+const HIGH_CONTRAST_DARK: Colors = Colors {
+    me: Color32::from_rgb(0, 64, 221),
+    sms: Color32::from_rgb(0, 110, 40),
+    them: Color32::BLACK,
+    highlight: Color32::YELLOW,
+    border: Color32::WHITE,
+    outline: 3.0,
+    high_contrast: true,
+};
+
+const HIGH_CONTRAST_LIGHT: Colors = Colors {
+    me: Color32::from_rgb(0, 64, 221),
+    sms: Color32::from_rgb(0, 110, 40),
+    them: Color32::WHITE,
+    highlight: Color32::from_rgb(180, 0, 180),
+    border: Color32::BLACK,
+    outline: 3.0,
+    high_contrast: true,
+};
+
+impl Colors {
+    /// The theme's own colours, before any from the config
+    pub fn defaults(dark: bool, high_contrast: bool) -> Self {
+        match (dark, high_contrast) {
+            (true, false) => DARK,
+            (false, false) => LIGHT,
+            (true, true) => HIGH_CONTRAST_DARK,
+            (false, true) => HIGH_CONTRAST_LIGHT,
+        }
+    }
+
+    /// The colours in use, as last set by `apply`
+    pub fn of(ui: &Ui) -> Self {
+        ui.data()
+            .get_temp(Id::new(COLORS))
+            .unwrap_or_else(|| Self::defaults(ui.visuals().dark_mode, false))
+    }
+}
+
+/// Where `apply` keeps the colours in egui's memory
+const COLORS: &str = "theme colors";
+
+/// egui's own size for body text, which the others are sized relative to
+const BODY_SIZE: f32 = 14.0;
+
+/// How far each zoom in or out goes, and how far it can go
+pub const ZOOM_STEP: f32 = 0.1;
+pub const MIN_ZOOM: f32 = 0.5;
+pub const MAX_ZOOM: f32 = 3.0;
+
+/// Black or white, whichever reads better on `background`
+pub fn text_on(background: Color32) -> Color32 {
+    let [r, g, b, _] = background.to_array();
+    let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+    if luma > 160.0 {
+        Color32::BLACK
+    } else {
+        Color32::WHITE
+    }
+}
+
+/// Whether `theme` is dark, given the system's theme if it could be found
+pub fn is_dark(theme: Theme, system: Option<eframe::Theme>) -> bool {
+    match theme {
+        Theme::System => system.or_else(system_theme) != Some(eframe::Theme::Light),
+        Theme::Light => false,
+        Theme::Dark => true,
+    }
+}
+
+/// The system's theme when it's first asked for, where eframe doesn't report
+/// one: on macOS, from the `AppleInterfaceStyle` default, which is only set
+/// in dark mode
+fn system_theme() -> Option<eframe::Theme> {
+    static THEME: OnceLock<Option<eframe::Theme>> = OnceLock::new();
+    *THEME.get_or_init(|| {
+        if !cfg!(target_os = "macos") {
+            return None;
+        }
+        let output = Command::new("defaults")
+            .args(["read", "-g", "AppleInterfaceStyle"])
+            .output()
+            .ok()?;
+        match String::from_utf8_lossy(&output.stdout).trim() {
+            "Dark" => Some(eframe::Theme::Dark),
+            _ => Some(eframe::Theme::Light),
+        }
+    })
+}
+
+/// Switch `ctx` to the look `config` asks for: its theme, in high contrast
+/// if asked, with any colours it picks in place of the theme's, its text
+/// size, its zoom and its direction
+pub fn apply(ctx: &egui::Context, config: &Config, info: &eframe::IntegrationInfo) {
+    let appearance = &config.appearance;
+    let high_contrast = config.accessibility.high_contrast;
+    let dark = is_dark(appearance.theme, info.system_theme);
+    let custom = &appearance.colors;
+    let rgb = |[r, g, b]: [u8; 3]| Color32::from_rgb(r, g, b);
+
+    let mut visuals = if dark {
+        Visuals::dark()
+    } else {
+        Visuals::light()
+    };
+    if high_contrast {
+        raise_contrast(&mut visuals);
+    }
+    if let Some(background) = custom.background {
+        visuals.widgets.noninteractive.bg_fill = rgb(background);
+    }
+    if let Some(accent) = custom.accent {
+        visuals.selection.bg_fill = rgb(accent);
+        visuals.hyperlink_color = rgb(accent);
+    }
+
+    let scale = appearance.text_size / BODY_SIZE;
+    let mut style = (*ctx.style()).clone();
+    style.visuals = visuals;
+    style.text_styles = egui::Style::default().text_styles;
+    for font in style.text_styles.values_mut() {
+        font.size *= scale;
+    }
+    if *ctx.style() != style {
+        ctx.set_style(style);
+    }
+
+    // on top of the display's own scale
+    let pixels_per_point = info.native_pixels_per_point.unwrap_or(1.0) * appearance.zoom;
+    if (ctx.pixels_per_point() - pixels_per_point).abs() > f32::EPSILON {
+        ctx.set_pixels_per_point(pixels_per_point);
+    }
+
+    let defaults = Colors::defaults(dark, high_contrast);
+    let colors = Colors {
+        me: custom.me.map_or(defaults.me, rgb),
+        sms: custom.sms.map_or(defaults.sms, rgb),
+        them: custom.them.map_or(defaults.them, rgb),
+        ..defaults
+    };
+    ctx.data().insert_temp(Id::new(COLORS), colors);
+
+    bidi::apply(ctx, appearance.direction);
+}
+
+/// Pure black on white or white on black, with every widget outlined and
+/// thicker outlines on the one hovered or focused
+fn raise_contrast(visuals: &mut Visuals) {
+    let (background, foreground) = if visuals.dark_mode {
+        (Color32::BLACK, Color32::WHITE)
+    } else {
+        (Color32::WHITE, Color32::BLACK)
+    };
+    visuals.extreme_bg_color = background;
+    visuals.faint_bg_color = background;
+
+    let widgets = &mut visuals.widgets;
+    widgets.noninteractive.bg_fill = background;
+    widgets.noninteractive.bg_stroke = Stroke::new(1.0, foreground);
+    for (widget, width) in [
+        (&mut widgets.noninteractive, 1.0),
+        (&mut widgets.inactive, 1.0),
+        (&mut widgets.hovered, 2.0),
+        (&mut widgets.active, 2.0),
+        (&mut widgets.open, 1.0),
+    ] {
+        widget.fg_stroke.color = foreground;
+        widget.bg_stroke = Stroke::new(width, foreground);
+    }
+}
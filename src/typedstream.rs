@@ -0,0 +1,382 @@
+//! Reading NSArchiver's "typedstream" format, which `message.attributedBody`
+//! (an NSAttributedString of the message's text) is stored in.
+//!
+//! A stream is a series of groups of values, each group led by its Objective-C
+//! type encoding (`@` for an object, `+` for bytes, `i` for an int...).
+//! Objects are their class, then groups of their own until an end marker.
+//! Type encodings, class names and objects are each written in full the
+//! first time and referred to by number after that.
+
+use eyre::{bail, eyre, Result};
+
+/// Leads a value written in full for the first time
+const NEW: u8 = 0x84;
+/// An empty object or string
+const NIL: u8 = 0x85;
+/// Ends an object's contents
+const END: u8 = 0x86;
+/// Leads a 2 byte integer
+const I16: u8 = 0x81;
+/// Leads a 4 byte integer
+const I32: u8 = 0x82;
+/// Leads a float or double
+const FLOAT: u8 = 0x83;
+/// References are numbered from here
+const REFERENCE: i64 = 0x92;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bytes(Vec<u8>),
+    String(Option<String>),
+    /// An object's index in the stream, see [`Stream::object`]
+    Object(usize),
+    Nil,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Object {
+    pub class: String,
+    pub groups: Vec<Vec<Value>>,
+}
+
+/// Classes and objects share one table of references
+#[derive(Clone, Debug)]
+enum Entry {
+    Class(String),
+    Object(Object),
+}
+
+/// A whole stream, read
+#[derive(Debug, Default)]
+pub struct Stream {
+    entries: Vec<Entry>,
+    /// The groups at the top level of the stream
+    pub groups: Vec<Vec<Value>>,
+}
+
+impl Stream {
+    pub fn object(&self, value: &Value) -> Option<&Object> {
+        match value {
+            Value::Object(index) => match self.entries.get(*index)? {
+                Entry::Object(object) => Some(object),
+                Entry::Class(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The text of an NSString (or NSMutableString) object
+    pub fn string(&self, value: &Value) -> Option<String> {
+        let object = self.object(value)?;
+        if !object.class.ends_with("String") {
+            return None;
+        }
+        match object.groups.first()?.first()? {
+            Value::Bytes(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+            _ => None,
+        }
+    }
+}
+
+pub fn read(data: &[u8]) -> Result<Stream> {
+    let mut reader = Reader {
+        data,
+        position: 0,
+        strings: Vec::new(),
+        stream: Stream::default(),
+    };
+    reader.header()?;
+    while reader.position < data.len() {
+        let group = reader.group()?;
+        reader.stream.groups.push(group);
+    }
+
+    Ok(reader.stream)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    /// Type encodings and C strings (including class names) seen so far
+    strings: Vec<Vec<u8>>,
+    stream: Stream,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or_else(|| eyre!("typedstream ended early"))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.position).copied()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&[u8]> {
+        // a negative length read as a `usize` would run off the end
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| eyre!("typedstream ended early"))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn header(&mut self) -> Result<()> {
+        let _version = self.byte()?;
+        let len = self.byte()? as usize;
+        if self.bytes(len)? != b"streamtyped" {
+            bail!("not a typedstream");
+        }
+        let _system_version = self.next_int()?;
+        Ok(())
+    }
+
+    /// An integer following `tag`, which is either the value itself or says
+    /// how long it is
+    fn int(&mut self, tag: u8) -> Result<i64> {
+        Ok(match tag {
+            I16 => i16::from_le_bytes(self.bytes(2)?.try_into()?) as i64,
+            I32 => i32::from_le_bytes(self.bytes(4)?.try_into()?) as i64,
+            tag => tag as i8 as i64,
+        })
+    }
+
+    fn next_int(&mut self) -> Result<i64> {
+        let tag = self.byte()?;
+        self.int(tag)
+    }
+
+    /// The number of something written earlier
+    fn reference(&mut self, tag: u8) -> Result<usize> {
+        let value = match tag {
+            I16 | I32 => self.int(tag)?,
+            tag => tag as i64,
+        };
+        usize::try_from(value - REFERENCE).map_err(|_| eyre!("bad typedstream reference"))
+    }
+
+    /// A shared string: a type encoding or C string
+    fn shared_string(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.byte()? {
+            NIL => Ok(None),
+            NEW => {
+                let len = self.next_int()? as usize;
+                let string = self.bytes(len)?.to_vec();
+                self.strings.push(string.clone());
+                Ok(Some(string))
+            }
+            tag => {
+                let index = self.reference(tag)?;
+                let string = self
+                    .strings
+                    .get(index)
+                    .ok_or_else(|| eyre!("bad typedstream string reference"))?;
+                Ok(Some(string.clone()))
+            }
+        }
+    }
+
+    /// A type encoding and the values it describes
+    fn group(&mut self) -> Result<Vec<Value>> {
+        let encoding = self
+            .shared_string()?
+            .ok_or_else(|| eyre!("typedstream group without a type"))?;
+
+        encoding.iter().map(|&kind| self.value(kind)).collect()
+    }
+
+    fn value(&mut self, kind: u8) -> Result<Value> {
+        Ok(match kind {
+            b'@' => self.object()?,
+            b'+' => {
+                let len = self.next_int()? as usize;
+                Value::Bytes(self.bytes(len)?.to_vec())
+            }
+            b'*' => Value::String(
+                self.shared_string()?
+                    .map(|s| String::from_utf8_lossy(&s).into_owned()),
+            ),
+            b'c' | b'i' | b's' | b'l' | b'q' | b'C' | b'I' | b'S' | b'L' | b'Q' | b'B' => {
+                let tag = self.byte()?;
+                let value = self.int(tag)?;
+                // small unsigned values are written as their byte
+                match kind {
+                    b'C' | b'I' | b'S' | b'L' | b'Q' if tag < I16 => Value::Int(tag as i64),
+                    _ => Value::Int(value),
+                }
+            }
+            b'f' | b'd' => match self.byte()? {
+                FLOAT if kind == b'f' => {
+                    Value::Float(f32::from_le_bytes(self.bytes(4)?.try_into()?) as f64)
+                }
+                FLOAT => Value::Float(f64::from_le_bytes(self.bytes(8)?.try_into()?)),
+                tag => Value::Float(self.int(tag)? as f64),
+            },
+            kind => bail!("unsupported typedstream type {:?}", kind as char),
+        })
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        match self.byte()? {
+            NIL => Ok(Value::Nil),
+            NEW => {
+                let index = self.stream.entries.len();
+                self.stream.entries.push(Entry::Object(Object::default()));
+
+                let class = self.class()?.unwrap_or_default();
+                let mut groups = Vec::new();
+                while self.peek() != Some(END) {
+                    groups.push(self.group()?);
+                }
+                self.position += 1;
+
+                self.stream.entries[index] = Entry::Object(Object { class, groups });
+                Ok(Value::Object(index))
+            }
+            tag => Ok(Value::Object(self.reference(tag)?)),
+        }
+    }
+
+    /// A class and its superclasses, returning the class's name
+    fn class(&mut self) -> Result<Option<String>> {
+        match self.byte()? {
+            NIL => Ok(None),
+            NEW => {
+                let name = self.shared_string()?.unwrap_or_default();
+                let name = String::from_utf8_lossy(&name).into_owned();
+                self.stream.entries.push(Entry::Class(name.clone()));
+                let _version = self.next_int()?;
+                let _superclass = self.class()?;
+                Ok(Some(name))
+            }
+            tag => {
+                let index = self.reference(tag)?;
+                match self.stream.entries.get(index) {
+                    Some(Entry::Class(name)) => Ok(Some(name.clone())),
+                    _ => bail!("bad typedstream class reference"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The header every stream starts with: version 4, "streamtyped", system
+    /// version 1000
+    const HEADER: &[u8] = b"\x04\x0bstreamtyped\x81\xe8\x03";
+
+    fn stream(groups: &[u8]) -> Vec<u8> {
+        let mut data = HEADER.to_vec();
+        data.extend_from_slice(groups);
+        data
+    }
+
+    /// An NSAttributedString of "hello", as `message.attributedBody` has it
+    /// without the attributes
+    fn attributed_string() -> Vec<u8> {
+        let mut groups = Vec::new();
+        // a group of one object, of a new class with NSObject as superclass
+        groups.extend_from_slice(b"\x84\x01@\x84\x84\x84\x12NSAttributedString\x00");
+        groups.extend_from_slice(b"\x84\x84\x08NSObject\x00\x85");
+        // holding an NSString, whose superclass refers back to NSObject
+        groups.extend_from_slice(b"\x92\x84\x84\x84\x08NSString\x01\x94");
+        // of the bytes of "hello"
+        groups.extend_from_slice(b"\x84\x01+\x05hello\x86\x86");
+        stream(&groups)
+    }
+
+    #[test]
+    fn attributed_string_text() {
+        let stream = read(&attributed_string()).unwrap();
+        let outer = stream.object(&stream.groups[0][0]).unwrap();
+        assert_eq!(outer.class, "NSAttributedString");
+        assert_eq!(stream.string(&outer.groups[0][0]).as_deref(), Some("hello"));
+        // only strings have text
+        assert_eq!(stream.string(&stream.groups[0][0]), None);
+    }
+
+    #[test]
+    fn numbers() {
+        // ints of each width, an unsigned char, a float and a double, then
+        // the unsigned char's type encoding again by reference
+        let stream = read(&stream(
+            b"\x84\x04iiii\x05\x81\x00\x01\x82\x00\x00\x01\x00\xfe\
+              \x84\x01C\x7f\
+              \x84\x02fd\x83\x00\x00\xc0\x3f\x83\x00\x00\x00\x00\x00\x00\x04\xc0\
+              \x93\x03",
+        ))
+        .unwrap();
+
+        assert_eq!(
+            stream.groups,
+            vec![
+                vec![
+                    Value::Int(5),
+                    Value::Int(256),
+                    Value::Int(65536),
+                    Value::Int(-2)
+                ],
+                vec![Value::Int(127)],
+                vec![Value::Float(1.5), Value::Float(-2.5)],
+                vec![Value::Int(3)],
+            ]
+        );
+    }
+
+    #[test]
+    fn shared_strings() {
+        let stream = read(&stream(b"\x84\x02**\x84\x03abc\x93\x84\x01*\x85")).unwrap();
+        assert_eq!(
+            stream.groups,
+            vec![
+                vec![
+                    Value::String(Some("abc".to_owned())),
+                    Value::String(Some("abc".to_owned()))
+                ],
+                vec![Value::String(None)],
+            ]
+        );
+    }
+
+    #[test]
+    fn not_a_typedstream() {
+        assert!(read(b"\x04\x0bstreamtypes\x81\xe8\x03").is_err());
+        assert!(read(b"bplist00").is_err());
+        assert!(read(b"").is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        let data = attributed_string();
+        for end in 0..data.len() {
+            let result = read(&data[..end]);
+            // just the header is an empty stream
+            assert_eq!(result.is_ok(), end == HEADER.len(), "cut at {end}");
+        }
+    }
+
+    #[test]
+    fn bad_lengths_and_references() {
+        // bytes with a negative length
+        assert!(read(&stream(b"\x84\x01+\xff")).is_err());
+        assert!(read(&stream(b"\x84\x01+\x82\x00\x00\x00\x80")).is_err());
+        // a type encoding that was never written
+        assert!(read(&stream(b"\x99\x01")).is_err());
+        // an object that refers to a class that was never written
+        assert!(read(&stream(b"\x84\x01@\x84\x95\x86")).is_err());
+        // a type it doesn't know
+        assert!(read(&stream(b"\x84\x01?\x00")).is_err());
+    }
+}
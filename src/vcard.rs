@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+
+use egui::{Color32, Frame, RichText, Rounding, Ui};
+use eyre::Result;
+use sqlx::SqlitePool;
+
+use crate::{attachment::expand_path, i18n};
+
+/// One line of a vCard, e.g. `item1.TEL;type=CELL;type=pref:+1 555 0100`
+pub struct Property {
+    /// Upper case, without the group (`item1.`)
+    pub name: String,
+    /// Values of `type=` parameters, lower case
+    pub types: Vec<String>,
+    /// Still escaped, since some properties are `;` separated lists
+    pub value: String,
+}
+
+/// The properties of every card in a `.vcf` file, one list per card
+pub fn cards(text: &str) -> Vec<Vec<Property>> {
+    let mut cards = Vec::new();
+    let mut card = Vec::new();
+
+    for line in unfold(text) {
+        let (key, value) = match line.split_once(':') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let mut params = key.split(';');
+        let name = params.next().unwrap_or_default();
+        let name = name.rsplit('.').next().unwrap_or(name).to_ascii_uppercase();
+        let types = params
+            .filter_map(|param| {
+                let (key, value) = param.split_once('=').unwrap_or(("type", param));
+                key.eq_ignore_ascii_case("type").then_some(value)
+            })
+            .flat_map(|value| value.split(','))
+            .map(str::to_lowercase)
+            .collect();
+
+        match name.as_str() {
+            "BEGIN" => card = Vec::new(),
+            "END" => cards.push(std::mem::take(&mut card)),
+            _ => card.push(Property {
+                name,
+                types,
+                value: value.to_owned(),
+            }),
+        }
+    }
+
+    cards
+}
+
+/// Join lines that were folded onto the next line with a leading space
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+    lines
+}
+
+pub fn unescape(value: &str) -> String {
+    // in one pass, so that `\\n` is a backslash and an `n`
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n' | 'N')) => out.push('\n'),
+            ('\\', Some(escaped @ (',' | ';' | '\\'))) => out.push(escaped),
+            _ => {
+                out.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+    out
+}
+
+/// A contact someone shared
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Contact {
+    pub name: String,
+    pub organization: Option<String>,
+    /// Numbers with their label (e.g. `cell`), if they have one
+    pub phones: Vec<(Option<String>, String)>,
+    pub emails: Vec<(Option<String>, String)>,
+}
+
+/// Every contact in a `.vcf` file. Cards with no name, number or email are
+/// left out.
+pub fn contacts(text: &str) -> Vec<Contact> {
+    cards(text)
+        .iter()
+        .filter_map(|card| contact(card))
+        .collect()
+}
+
+fn contact(card: &[Property]) -> Option<Contact> {
+    let get = |name: &str| {
+        card.iter()
+            .find(|p| p.name == name)
+            .map(|p| p.value.as_str())
+            .filter(|value| !value.trim().is_empty())
+    };
+
+    // `ORG` and `N` are `;` separated: company;department and
+    // family;given;middle;prefix;suffix
+    let organization = get("ORG")
+        .and_then(|org| org.split(';').next())
+        .map(unescape)
+        .filter(|org| !org.is_empty());
+    let structured = get("N").map(|n| {
+        let parts: Vec<_> = n.split(';').map(unescape).collect();
+        let part = |i: usize| parts.get(i).map_or("", String::as_str);
+        [part(3), part(1), part(2), part(0), part(4)]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+
+    let labelled = |name: &str| {
+        card.iter()
+            .filter(|p| p.name == name && !p.value.trim().is_empty())
+            .map(|p| (label(&p.types), unescape(&p.value)))
+            .collect::<Vec<_>>()
+    };
+    let phones = labelled("TEL");
+    let emails = labelled("EMAIL");
+
+    let name = get("FN")
+        .map(unescape)
+        .or(structured)
+        .filter(|name| !name.is_empty())
+        .or_else(|| organization.clone())
+        .or_else(|| phones.first().map(|(_, phone)| phone.clone()))
+        .or_else(|| emails.first().map(|(_, email)| email.clone()))?;
+
+    Some(Contact {
+        name,
+        organization,
+        phones,
+        emails,
+    })
+}
+
+/// The `type` worth showing next to a number or address, e.g. `cell`
+/// rather than `pref` or `voice`
+fn label(types: &[String]) -> Option<String> {
+    types
+        .iter()
+        .find(|t| !matches!(t.as_str(), "pref" | "voice" | "internet" | "x400"))
+        .cloned()
+}
+
+/// The contacts in every contact card attachment of a chat, keyed by the
+/// ROWID of the message they were sent in. Location cards, which are also
+/// `.vcf` files, are left to [`crate::location`]. Files are read on the
+/// blocking thread pool.
+pub async fn chat_contacts(db: &SqlitePool, chat_id: &str) -> Result<HashMap<i64, Vec<Contact>>> {
+    let attachments = sqlx::query_as::<_, (i64, String)>(
+        r#"
+            SELECT
+                maj.message_id, a.filename
+            from attachment a
+            join message_attachment_join maj
+                on a.ROWID = maj.attachment_id
+            join chat_message_join cmj
+                on maj.message_id = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            where c.chat_identifier=$1
+                and (a.mime_type in ('text/vcard', 'text/x-vcard') or a.filename like '%.vcf')
+                and coalesce(a.mime_type, '') != 'text/x-vlocation'
+                and a.filename not like '%.loc.vcf'
+            ;
+        "#,
+    )
+    .bind(chat_id)
+    .fetch_all(db)
+    .await?;
+
+    let contacts = tokio::task::spawn_blocking(move || {
+        let mut found: HashMap<i64, Vec<Contact>> = HashMap::new();
+        for (message_id, filename) in attachments {
+            // the file may not have been downloaded from iCloud
+            if let Ok(text) = std::fs::read_to_string(expand_path(&filename)) {
+                found.entry(message_id).or_default().extend(contacts(&text));
+            }
+        }
+        found
+    })
+    .await?;
+
+    Ok(contacts)
+}
+
+/// A card with the contact's name, numbers and email addresses
+pub fn show(ui: &mut Ui, contact: &Contact) {
+    Frame::none()
+        .fill(Color32::from_black_alpha(60))
+        .rounding(Rounding::same(6.0))
+        .inner_margin(8.0)
+        .show(ui, |ui| {
+            ui.label(
+                RichText::new(format!("👤 {}", i18n::t("contact")))
+                    .small()
+                    .strong(),
+            );
+            ui.label(RichText::new(&contact.name).strong());
+            if let Some(organization) = &contact.organization {
+                if *organization != contact.name {
+                    ui.label(RichText::new(organization).small());
+                }
+            }
+            for (label, value) in contact.phones.iter().chain(&contact.emails) {
+                ui.horizontal(|ui| {
+                    if let Some(label) = label {
+                        ui.label(RichText::new(label).small().weak());
+                    }
+                    ui.label(value);
+                });
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_cards() {
+        let text = "BEGIN:VCARD\r\nVERSION:3.0\r\nFN:Ada Lovelace\r\n\
+            item1.TEL;type=CELL;type=pref:+1 555\r\n  0100\r\n\
+            EMAIL;TYPE=INTERNET,HOME:ada@example.com\r\nEND:VCARD\r\n\
+            BEGIN:VCARD\r\nN:Babbage;Charles;;Mr;\r\nEND:VCARD\r\n";
+        let cards = cards(text);
+        assert_eq!(cards.len(), 2);
+        let tel = &cards[0][2];
+        assert_eq!(tel.name, "TEL");
+        assert_eq!(tel.types, ["cell", "pref"]);
+        assert_eq!(tel.value, "+1 555 0100");
+        assert_eq!(cards[0][3].types, ["internet", "home"]);
+
+        assert_eq!(
+            contacts(text),
+            vec![
+                Contact {
+                    name: "Ada Lovelace".to_owned(),
+                    organization: None,
+                    phones: vec![(Some("cell".to_owned()), "+1 555 0100".to_owned())],
+                    emails: vec![(Some("home".to_owned()), "ada@example.com".to_owned())],
+                },
+                Contact {
+                    name: "Mr Charles Babbage".to_owned(),
+                    organization: None,
+                    phones: vec![],
+                    emails: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn names_without_a_name() {
+        let names: Vec<_> = contacts(
+            "BEGIN:VCARD\nFN:\nORG:Analytical Engines\\, Ltd;R&D\nEND:VCARD\n\
+             BEGIN:VCARD\nTEL:+1 555 0100\nEND:VCARD\n\
+             BEGIN:VCARD\nEMAIL:ada@example.com\nEND:VCARD\n\
+             BEGIN:VCARD\nNOTE:nothing to go on\nEND:VCARD\n",
+        )
+        .into_iter()
+        .map(|contact| contact.name)
+        .collect();
+        assert_eq!(
+            names,
+            ["Analytical Engines, Ltd", "+1 555 0100", "ada@example.com"]
+        );
+    }
+
+    #[test]
+    fn unescapes() {
+        assert_eq!(unescape(r"a\, b\; c\nd\Ne"), "a, b; c\nd\ne");
+        assert_eq!(unescape(r"C:\\new"), r"C:\new");
+        assert_eq!(unescape(r"trailing\"), r"trailing\");
+    }
+}
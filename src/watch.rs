@@ -0,0 +1,315 @@
+use std::{
+    io::{self, ErrorKind},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use eyre::Result;
+use sqlx::SqlitePool;
+
+use crate::{
+    config::{Identity, NotificationConfig},
+    i18n, Handle, Sender,
+};
+
+/// How often `--watch` checks the database for new messages
+pub const INTERVAL: Duration = Duration::from_secs(5);
+
+/// Following the database for messages as they arrive, e.g. on a machine
+/// that syncs its messages from iCloud
+pub struct Watch {
+    /// ROWID of the newest row seen so far, group events included
+    pub last: i64,
+    /// When the last check started
+    pub checked: Instant,
+    /// What the last check found
+    pub check: Handle<Check>,
+}
+
+/// Everything newer than the last check
+pub struct Check {
+    /// ROWID of the newest row this check read, which is past the last of
+    /// `arrivals` if group events came after it
+    pub last: i64,
+    pub arrivals: Vec<Arrival>,
+}
+
+/// A message that arrived since the last check
+#[derive(Clone)]
+pub struct Arrival {
+    pub chat: String,
+    pub display_name: String,
+    pub sender: Sender,
+    pub text: String,
+}
+
+impl Watch {
+    pub async fn start(db: &SqlitePool) -> Result<Self> {
+        let (last,) = sqlx::query_as::<_, (i64,)>("SELECT coalesce(max(ROWID), 0) from message;")
+            .fetch_one(db)
+            .await?;
+
+        Ok(Self {
+            last,
+            checked: Instant::now(),
+            check: Handle::new(),
+        })
+    }
+}
+
+/// Messages newer than `after`, oldest first, leaving out group events
+pub async fn since(db: &SqlitePool, after: i64) -> Result<Check> {
+    // read up to a fixed ROWID, so a message that lands between the two
+    // queries is left for the next check rather than skipped
+    let (last,) = sqlx::query_as::<_, (i64,)>(
+        "SELECT coalesce(max(ROWID), $1) from message where ROWID > $1;",
+    )
+    .bind(after)
+    .fetch_one(db)
+    .await?;
+
+    let rows = sqlx::query_as::<_, (String, String, String, bool, String)>(
+        r#"
+            SELECT
+                c.chat_identifier, coalesce(c.display_name, ''), coalesce(h.id, ''),
+                m.is_from_me, coalesce(m.text, '')
+            from message m
+            join chat_message_join cmj
+                on m.ROWID = cmj.message_id
+            join chat c
+                on cmj.chat_id = c.ROWID
+            left join handle h
+                on m.handle_id = h.ROWID
+            where m.ROWID > $1
+                and m.ROWID <= $2
+                and m.item_type = 0
+            order by m.ROWID
+            ;
+        "#,
+    )
+    .bind(after)
+    .bind(last)
+    .fetch_all(db)
+    .await?;
+
+    let arrivals = rows
+        .into_iter()
+        .map(|(chat, display_name, sender, is_from_me, text)| Arrival {
+            chat,
+            display_name,
+            sender: Sender::new(is_from_me, sender),
+            // attachments show up as U+FFFC in the text
+            text: text.replace('\u{fffc}', "").trim().to_owned(),
+        })
+        .collect();
+
+    Ok(Check { last, arrivals })
+}
+
+/// Show a desktop notification for a message, if [`notification`] has one
+/// for it. Blocks until the notification is posted.
+pub fn notify(settings: &NotificationConfig, identity: &Identity, arrival: &Arrival) {
+    if let Some((title, body)) = notification(settings, identity, arrival) {
+        if let Err(e) = post(&title, &body) {
+            eprintln!("{e}");
+        }
+    }
+}
+
+/// The title and body of the notification for a message, if it's in one of
+/// the chats `settings` asks about and wasn't sent by me. Without `preview`
+/// it only says which chat has a new message.
+fn notification(
+    settings: &NotificationConfig,
+    identity: &Identity,
+    arrival: &Arrival,
+) -> Option<(String, String)> {
+    if identity.is_me(&arrival.sender) || !settings.chats.contains(&arrival.chat) {
+        return None;
+    }
+
+    let chat = identity.chat_name(&arrival.chat, &arrival.display_name);
+    if settings.preview {
+        let text = match arrival.text.as_str() {
+            "" => i18n::t("(attachment)"),
+            text => text,
+        };
+        Some((
+            chat.to_owned(),
+            format!("{}: {text}", identity.name(&arrival.sender)),
+        ))
+    } else {
+        Some((
+            "iMessage Viewer".to_owned(),
+            i18n::tf("new message in {chat}", &[("chat", &chat)]),
+        ))
+    }
+}
+
+/// Post a notification with whichever tool is installed: `osascript` ships
+/// with macOS, and `notify-send` with most Linux desktops
+fn post(title: &str, body: &str) -> io::Result<()> {
+    let mut osascript = Command::new("osascript");
+    osascript.arg("-e").arg(format!(
+        "display notification {} with title {}",
+        quote(body),
+        quote(title)
+    ));
+
+    let mut notify_send = Command::new("notify-send");
+    notify_send.args(["--app-name", "iMessage Viewer", title, body]);
+
+    for notifier in [&mut osascript, &mut notify_send] {
+        let status = notifier
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+
+        match status {
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            result => return result.map(drop),
+        }
+    }
+
+    Err(io::Error::new(
+        ErrorKind::NotFound,
+        "can't show notifications, install notify-send",
+    ))
+}
+
+/// `s` as an AppleScript string literal
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[tokio::test]
+    async fn finds_new_messages() {
+        let db = test_util::memory().await;
+        sqlx::query(
+            r#"
+                CREATE TABLE message (
+                    ROWID INTEGER PRIMARY KEY, handle_id INTEGER, is_from_me INTEGER,
+                    text TEXT, item_type INTEGER
+                );
+                CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, chat_identifier TEXT, display_name TEXT);
+                CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+                CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT);
+                INSERT INTO chat VALUES (1, 'ana', NULL), (2, 'chat100', 'Trip');
+                INSERT INTO handle VALUES (1, 'ana');
+                INSERT INTO message VALUES (1, 1, 0, 'old', 0);
+                INSERT INTO chat_message_join VALUES (1, 1);
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let watch = Watch::start(&db).await.unwrap();
+        assert_eq!(watch.last, 1);
+        let check = since(&db, watch.last).await.unwrap();
+        assert_eq!(check.last, 1);
+        assert!(check.arrivals.is_empty());
+
+        sqlx::query(
+            r#"
+                INSERT INTO message VALUES
+                    (2, 1, 0, ' look ' || char(65532), 0),
+                    (3, 0, 0, NULL, 1),
+                    (4, 0, 1, NULL, 0);
+                INSERT INTO chat_message_join VALUES (2, 2), (2, 3), (1, 4);
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let check = since(&db, watch.last).await.unwrap();
+        assert_eq!(check.last, 4);
+        let arrivals: Vec<_> = check
+            .arrivals
+            .into_iter()
+            .map(|a| (a.chat, a.display_name, a.sender, a.text))
+            .collect();
+        assert_eq!(
+            arrivals,
+            [
+                (
+                    "chat100".to_owned(),
+                    "Trip".to_owned(),
+                    Sender::SomeoneElse("ana".to_owned()),
+                    "look".to_owned()
+                ),
+                ("ana".to_owned(), String::new(), Sender::Me, String::new()),
+            ]
+        );
+
+        // a group event on its own still moves the next check past it
+        sqlx::query(
+            r#"
+                INSERT INTO message VALUES (5, 0, 0, NULL, 2);
+                INSERT INTO chat_message_join VALUES (2, 5);
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        let check = since(&db, 4).await.unwrap();
+        assert_eq!(check.last, 5);
+        assert!(check.arrivals.is_empty());
+        assert!(since(&db, 5).await.unwrap().arrivals.is_empty());
+    }
+
+    #[test]
+    fn notifies_about_chosen_chats() {
+        let arrival = |chat: &str, sender: Sender, text: &str| Arrival {
+            chat: chat.to_owned(),
+            display_name: String::new(),
+            sender,
+            text: text.to_owned(),
+        };
+        let ana = || Sender::SomeoneElse("ana".to_owned());
+        let mut identity = Identity::default();
+        identity.aliases.insert("ana".to_owned(), "Ana".to_owned());
+        let mut settings = NotificationConfig {
+            chats: vec!["ana".to_owned()],
+            preview: true,
+        };
+
+        assert_eq!(
+            notification(&settings, &identity, &arrival("ana", ana(), "hi")),
+            Some(("Ana".to_owned(), "Ana: hi".to_owned()))
+        );
+        assert_eq!(
+            notification(&settings, &identity, &arrival("ana", ana(), "")),
+            Some(("Ana".to_owned(), "Ana: (attachment)".to_owned()))
+        );
+        assert_eq!(
+            notification(&settings, &identity, &arrival("ana", Sender::Me, "hi")),
+            None
+        );
+        assert_eq!(
+            notification(&settings, &identity, &arrival("bo", ana(), "hi")),
+            None
+        );
+
+        settings.preview = false;
+        assert_eq!(
+            notification(&settings, &identity, &arrival("ana", ana(), "hi")),
+            Some((
+                "iMessage Viewer".to_owned(),
+                "new message in Ana".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn quotes_applescript_strings() {
+        assert_eq!(quote(r#"say "hi" \o/"#), r#""say \"hi\" \\o/""#);
+    }
+}